@@ -1,50 +1,391 @@
-use super::featuredb::{Feature, FeatureDB};
-use super::gfx::camera::Camera;
-use super::gfx::renderer::{BasicRenderer, FeatureRenderer};
+use super::capture::{GifRecording, GIF_HEIGHT, GIF_WIDTH};
+use super::command::{AppCommand, CommandHandler};
+use super::config::Config;
+use super::featuredb::{Feature, FeatureDB, FeatureEvent, WatchHandle};
+use super::gfx::camera::{Camera, CameraBuilder, CameraPath, LoopMode};
+use super::gfx::geometry::{self, Geometry};
+use super::gfx::profiler::GpuProfiler;
+#[cfg(feature = "ssao")]
+use super::gfx::renderer::SsaoPass;
+use super::gfx::renderer::{
+  self, BasicRenderer, BasicRendererConfiguration, EllipsoidRenderer, FeatureDisplay, FeatureRenderer, GridRenderer,
+  InstancedLineRenderer, InstancedLineRendererConfiguration, LodFeatureRenderer, PointCloudRenderer, RenderError,
+  RenderMode, RenderPassBuilder, RendererStats, SkyboxRenderer, ZPrepass,
+};
 use super::gfx::shader::feature::FeatureInstance;
+use super::gfx::text::TextRenderer;
 use super::gfx::texture::Texture;
-use super::net::Client;
-use super::ui::{KeyEvent, MouseEvent, UIEvent, UserInterface};
+use super::metrics::Metrics;
+use super::net::{BinaryFramer, ConnectionState, FramedClient, SimulatorMessage};
+use super::pointcloud::{ExportError, PointCloudWriter};
+use super::raycast::{Ball, Model, PrimitiveKind, Scene};
+use super::replay::{FramePlayer, FrameRecorder};
+use super::sensor::SensorReading;
+use super::stats::{FrameLimiter, FrameTimer, TitleUpdater};
+use super::ui::{KeyEvent, Measurement, MouseEvent, UIEvent, UserInterface};
 
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
 use winit::event::*;
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub struct ApplicationConfiguration {
+  pub dataset: String,
+  pub ssao: bool,
+  pub debug_wireframe: bool,
+  pub use_z_prepass: bool,
+  /// Keep the feature position index, or drop it to speed up inserts
+  pub position_index: bool,
+  /// Start with the mouse pitch inverted
+  pub invert_y: bool,
+  pub record: Option<PathBuf>,
+  /// Animated GIF toggled on and off with G
+  pub gif: Option<PathBuf>,
+  /// Depth snapshot of the first frame
+  pub snapshot_depth: Option<PathBuf>,
+  /// Directory of cubemap faces drawn as the background
+  pub skybox: Option<PathBuf>,
+  pub replay: Option<PathBuf>,
+  pub config: Option<PathBuf>,
+  pub max_fps: Option<f32>,
+  pub feature_display: FeatureDisplay,
+  /// Near and far distances of level of detail feature rendering, off when not given
+  pub lod_thresholds: Option<(f32, f32)>,
+  /// Extra feature databases drawn alongside the main one, by layer name
+  pub layers: Vec<(String, PathBuf)>,
+  /// Density of the distance fog over the features, zero for none
+  pub fog_density: f32,
+  /// Port to serve Prometheus metrics on
+  pub metrics_port: Option<u16>,
+  /// Time render passes on the GPU
+  pub profile: bool,
+  /// Log renderer statistics with every title update
+  pub stats: bool,
+  /// JSON camera waypoints to fly through on startup
+  pub fly_path: Option<PathBuf>,
+  /// Seconds the fly path takes end to end
+  pub fly_duration: f32,
+  pub fly_loop: LoopMode,
+}
+
+/// Meshes cycled through with N to draw each feature
+const FEATURE_MESHES: [fn() -> Geometry; 3] = [
+  || geometry::uv_sphere(20),
+  || geometry::icosphere(3),
+  || geometry::cylinder(16, 1.0, 0.1),
+];
+
+/// Scene of each feature's mean sphere, named by feature id.
+fn picking_scene(features: &[Feature]) -> Scene {
+  let mut scene = Scene::new();
+  for feature in features {
+    let ball = Model::Primitive(PrimitiveKind::Ball(Ball::new(feature.radius_mean)));
+    if let Some(model) = ball.transform_in_place(cgmath::Matrix4::from_translation(feature.position_mean)) {
+      scene.add(&feature.id.to_string(), model);
+    }
+  }
+  scene
+}
+
+/// Size in pixels of features drawn with `--render-mode points`
+const FEATURE_POINT_SIZE: f32 = 4.0;
+
+/// Files of the `--skybox` cubemap faces, in the order `Texture::create_cubemap` takes them
+const SKYBOX_FACES: [&str; 6] = ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"];
+
+fn load_skybox(
+  device: &wgpu::Device,
+  queue: &wgpu::Queue,
+  config: &wgpu::SurfaceConfiguration,
+  dir: &Path,
+) -> Result<SkyboxRenderer, Box<dyn std::error::Error>> {
+  let faces = SKYBOX_FACES
+    .iter()
+    .map(|face| std::fs::read(dir.join(face)))
+    .collect::<Result<Vec<_>, _>>()?;
+  let cubemap = Texture::create_cubemap(device, queue, [0, 1, 2, 3, 4, 5].map(|face| &faces[face][..]))?;
+  Ok(SkyboxRenderer::new(device, config, cubemap))
+}
+
+/// Frame rate of `--gif` recordings
+const GIF_FPS: f32 = 15.0;
+
+/// Color of the outline drawn around selected features
+#[cfg(feature = "outlines")]
+const SELECTION_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+
+/// Color of the outline drawn around the feature under the cursor while nothing is selected
+#[cfg(feature = "outlines")]
+const HOVER_COLOR: [f32; 4] = [0.4, 0.8, 1.0, 1.0];
+
+/// WebSocket messages applied per frame, so a flood of updates cannot stall rendering. The rest wait for the next frame.
+const MAX_MESSAGES_PER_FRAME: usize = 100;
+
+/// Time between the sensor readings sent to the robot
+const SENSOR_READING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Converts touchpad pixel scrolling into mouse wheel lines.
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
+const PATH_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+const MEASUREMENT_COLOR: [f32; 4] = [0.0, 1.0, 1.0, 1.0];
+
+/// Meters the ground grid extends from the origin along X and Z, and its cells each side of an axis
+const GRID_HALF_EXTENT: f32 = 10.0;
+const GRID_DIVISIONS: u32 = 10;
+
+/// Features not seen in this many updates are removed from the database.
+const MAX_FEATURE_AGE: u32 = 200;
+
+/// Clear color of the main pass, which distant features also fade into
+const BACKGROUND_COLOR: wgpu::Color = wgpu::Color {
+  r: 0.1,
+  g: 0.2,
+  b: 0.3,
+  a: 1.0,
+};
+
+/// Newly seen features are drawn at this visibility, brightening them, for `NEW_FEATURE_FLASH_FRAMES` frames.
+const NEW_FEATURE_FLASH: f32 = 2.0;
+const NEW_FEATURE_FLASH_FRAMES: u32 = 15;
+
+fn fog_color() -> [f32; 4] {
+  let wgpu::Color { r, g, b, a } = BACKGROUND_COLOR;
+  [r as f32, g as f32, b as f32, a as f32]
+}
+
+/// Features from another database drawn on top of the main one, such as a second sensor's detections.
+struct FeatureLayer {
+  name: String,
+  _database: FeatureDB,
+  renderer: FeatureRenderer,
+  visible: bool,
+}
+
+/// Why `Application::run_headless_for` stopped before running every frame.
+#[cfg(any(test, feature = "headless"))]
+#[derive(Debug)]
+pub enum ApplicationError {
+  Database(rusqlite::Error),
+  Render(RenderError),
+}
+
+#[cfg(any(test, feature = "headless"))]
+impl std::fmt::Display for ApplicationError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ApplicationError::Database(err) => write!(f, "failed to apply feature update: '{}'", err),
+      ApplicationError::Render(err) => write!(f, "failed to render frame: '{}'", err),
+    }
+  }
+}
+
+#[cfg(any(test, feature = "headless"))]
+impl From<rusqlite::Error> for ApplicationError {
+  fn from(other: rusqlite::Error) -> Self {
+    ApplicationError::Database(other)
+  }
+}
+
+#[cfg(any(test, feature = "headless"))]
+impl From<RenderError> for ApplicationError {
+  fn from(other: RenderError) -> Self {
+    ApplicationError::Render(other)
+  }
+}
+
+/// The kinds of file `Application::handle_drag_drop` loads, by extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DroppedFileKind {
+  Database,
+  Csv,
+  Json,
+}
+
+impl DroppedFileKind {
+  fn classify(path: &Path) -> Option<Self> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+      "sqlite" | "db" => Some(DroppedFileKind::Database),
+      "csv" => Some(DroppedFileKind::Csv),
+      "json" => Some(DroppedFileKind::Json),
+      _ => None,
+    }
+  }
+
+  /// Opens a dropped database to replace `database`, or imports a dropped CSV or JSON file into it, and loads the
+  /// features to draw afterwards: those of `dataset`, or of the first dataset of a dropped database that lacks it.
+  fn load(self, path: &Path, database: &FeatureDB, dataset: &str) -> Result<DroppedFile, String> {
+    let opened = match self {
+      DroppedFileKind::Database => Some(FeatureDB::open(path).map_err(|err| err.to_string())?),
+      DroppedFileKind::Csv => {
+        database.import_csv(path).map_err(|err| err.to_string())?;
+        None
+      }
+      DroppedFileKind::Json => {
+        database.import_json(path).map_err(|err| err.to_string())?;
+        None
+      }
+    };
+    let datasets = opened
+      .as_ref()
+      .map(FeatureDB::list_datasets)
+      .transpose()
+      .map_err(|err| err.to_string())?;
+    let dataset = match datasets {
+      Some(datasets) if !datasets.iter().any(|name| name == dataset) => {
+        datasets.into_iter().next().unwrap_or_else(|| dataset.to_owned())
+      }
+      _ => dataset.to_owned(),
+    };
+    let features = opened
+      .as_ref()
+      .unwrap_or(database)
+      .load_all(Some(&dataset))
+      .map_err(|err| err.to_string())?;
+    Ok(DroppedFile {
+      database: opened,
+      dataset,
+      features,
+    })
+  }
+}
+
+/// What loading a dropped file leaves for the application to draw.
+struct DroppedFile {
+  /// Replaces the application's database when a database was dropped
+  database: Option<FeatureDB>,
+  dataset: String,
+  features: Vec<Feature>,
+}
+
+/// The window frames are presented to and the event loop driving it, which headless applications go without.
+struct Display {
+  window: Window,
+  surface: wgpu::Surface,
+  event_loop: Option<EventLoop<AppCommand>>,
+}
+
 pub struct Application {
   _instance: wgpu::Instance,
   _adapter: wgpu::Adapter,
-  surface: wgpu::Surface,
   device: wgpu::Device,
   queue: wgpu::Queue,
   config: wgpu::SurfaceConfiguration,
   size: winit::dpi::PhysicalSize<u32>,
-  event_loop: Option<EventLoop<()>>,
-  window: Window,
+  display: Option<Display>,
 
   camera: Camera,
+  /// Animation overriding the camera until it finishes
+  fly_path: Option<CameraPath>,
+  /// Background drawn first in the main pass with `--skybox`
+  skybox: Option<SkyboxRenderer>,
   basic_renderer: BasicRenderer,
+  grid_renderer: GridRenderer,
+  debug_wireframe: Option<BasicRenderer>,
   feature_renderer: FeatureRenderer,
-  _database: FeatureDB,
-  websocket: Option<Client>,
+  /// Draws the instances of `feature_renderer` in its place, which still picks and outlines them
+  lod_renderer: Option<LodFeatureRenderer>,
+  /// Draws the instances of `feature_renderer` as dots in its place with `--render-mode points`
+  point_cloud: Option<PointCloudRenderer>,
+  /// Instance generation of `feature_renderer` last uploaded to `point_cloud`
+  point_cloud_generation: Option<u64>,
+  /// Position uncertainty of the rendered features, drawn over them while `show_uncertainty` is set
+  ellipsoid_renderer: EllipsoidRenderer,
+  show_uncertainty: bool,
+  /// Sphere of every rendered feature, named by feature id
+  picking_scene: Scene,
+  feature_layers: Vec<FeatureLayer>,
+  fog_density: f32,
+  z_prepass: Option<ZPrepass>,
+  path_renderers: Vec<InstancedLineRenderer>,
+  /// Last distance measured with M and the line drawn along it
+  measurement: Option<(Measurement, InstancedLineRenderer)>,
+  feature_mesh: usize,
+  #[cfg(feature = "ssao")]
+  ssao_pass: Option<SsaoPass>,
+  database: FeatureDB,
+  /// Set from the database watcher when features are written by anyone, including this application
+  features_changed: Arc<AtomicBool>,
+  /// `database.data_version()` when the features were last reloaded for someone else's write
+  data_version: i64,
+  _feature_watch: WatchHandle,
+  /// Features deleted through `database`, as they are deleted
+  feature_events: Receiver<FeatureEvent>,
+  current_dataset: String,
+  /// File dropped on the window, loaded once the frame showing "Loading…" is presented
+  dropped_file: Option<(PathBuf, DroppedFileKind)>,
+  websocket: Option<FramedClient>,
+  /// When `emit_sensor_readings` is next due
+  sensor_readings_due: Instant,
+  record_path: Option<PathBuf>,
+  recorder: Option<FrameRecorder>,
+  gif_path: Option<PathBuf>,
+  gif_recording: Option<GifRecording>,
+  /// Workers still saving stopped GIF recordings
+  gif_saves: Vec<JoinHandle<()>>,
+  /// Where to save the next depth snapshot, cleared once taken
+  depth_snapshot_path: Option<PathBuf>,
+  player: Option<FramePlayer>,
+  max_feature_age: u32,
+  /// Frames left to flash each newly seen feature for, by id
+  flashes: HashMap<u32, u32>,
+  paused: bool,
+  paused_banner: Option<BasicRenderer>,
+  text_renderer: TextRenderer,
+  /// Whether the frame rate, feature count and connection state are drawn over the scene
+  debug_text: bool,
   user_interface: UserInterface,
+  /// Features outlined in the main pass, chosen by clicking them
+  pub selected_ids: Vec<u32>,
+  /// Feature under the cursor, outlined with `HOVER_COLOR` and named in the debug text
+  hovered_id: Option<u32>,
   depth_texture: Texture,
+  /// Instance under every pixel, drawn by `pick`
+  picking_texture: Texture,
+  frame_timer: FrameTimer,
+  frame_limiter: Option<FrameLimiter>,
+  metrics: Metrics,
+  profiler: GpuProfiler,
+  log_stats: bool,
+  title_updater: TitleUpdater,
 }
 
 impl Application {
-  pub async fn new() -> Self {
+  /// Opens `recognition.sqlite`, connects to the WebSocket server and creates the window on the main thread.
+  pub async fn new(configuration: ApplicationConfiguration) -> Self {
     env_logger::init();
+    let database = FeatureDB::new().unwrap();
+    let event_loop = EventLoop::with_user_event();
+    let websocket = FramedClient::new(BinaryFramer, BinaryFramer, Some(event_loop.create_proxy()))
+      .await
+      .ok();
+    Self::with_parts(configuration, event_loop, database, websocket).await
+  }
 
-    let event_loop = EventLoop::new();
+  /// Builds the application around a database, WebSocket client and event loop made elsewhere.
+  pub async fn with_parts(
+    configuration: ApplicationConfiguration,
+    event_loop: EventLoop<AppCommand>,
+    database: FeatureDB,
+    websocket: Option<FramedClient>,
+  ) -> Self {
     let window = WindowBuilder::new()
       .with_title("Lawny Simulator")
       .build(&event_loop)
       .unwrap();
-
     let size = window.inner_size();
 
     let instance = wgpu::Instance::new(wgpu::Backends::all());
     let surface = unsafe { instance.create_surface(&window) };
-
     let adapter = instance
       .request_adapter(&wgpu::RequestAdapterOptions {
         power_preference: wgpu::PowerPreference::default(),
@@ -53,11 +394,74 @@ impl Application {
       })
       .await
       .unwrap();
+    let format = surface.get_preferred_format(&adapter).unwrap();
+    let display = Display {
+      window,
+      surface,
+      event_loop: Some(event_loop),
+    };
+    Self::build(
+      configuration,
+      instance,
+      adapter,
+      format,
+      size,
+      Some(display),
+      database,
+      websocket,
+    )
+    .await
+  }
 
+  /// Builds the application without a window or event loop, rendering only through `render_to_texture` at `size`.
+  /// `None` if there is no adapter to render with.
+  #[cfg(any(test, feature = "headless"))]
+  pub async fn headless(
+    configuration: ApplicationConfiguration,
+    database: FeatureDB,
+    size: winit::dpi::PhysicalSize<u32>,
+  ) -> Option<Self> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = instance
+      .request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        compatible_surface: None,
+        force_fallback_adapter: false,
+      })
+      .await?;
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    Some(Self::build(configuration, instance, adapter, format, size, None, database, None).await)
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  async fn build(
+    mut configuration: ApplicationConfiguration,
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    format: wgpu::TextureFormat,
+    size: winit::dpi::PhysicalSize<u32>,
+    display: Option<Display>,
+    database: FeatureDB,
+    websocket: Option<FramedClient>,
+  ) -> Self {
+    let file_config = configuration.config.as_deref().map_or_else(
+      || Ok(Config::default()),
+      |path| Config::load(path).map_err(|err| eprintln!("failed to load config '{}': {}", path.display(), err)),
+    );
+    let file_config = file_config.unwrap_or_default();
+    configuration.ssao |= file_config.rendering.ssao;
+    configuration.debug_wireframe |= file_config.rendering.debug_wireframe;
+    configuration.use_z_prepass |= file_config.rendering.z_prepass;
+
+    let features = if configuration.profile {
+      adapter.features() & wgpu::Features::TIMESTAMP_QUERY
+    } else {
+      wgpu::Features::empty()
+    };
     let (device, queue) = adapter
       .request_device(
         &wgpu::DeviceDescriptor {
-          features: wgpu::Features::empty(),
+          features,
           limits: wgpu::Limits::default(),
           label: None,
         },
@@ -68,67 +472,674 @@ impl Application {
 
     let config = wgpu::SurfaceConfiguration {
       usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-      format: surface.get_preferred_format(&adapter).unwrap(),
+      format,
       width: size.width,
       height: size.height,
       present_mode: wgpu::PresentMode::Fifo,
     };
-    surface.configure(&device, &config);
+    if let Some(display) = &display {
+      display.surface.configure(&device, &config);
+    }
 
-    let mut camera = Camera::new(&device);
-    camera.aspect = size.width as f32 / size.height as f32;
-    camera.up = (0.0, 1.0, 0.0).into();
-    camera.eye = (0.0, 0.0, 5.0).into();
-    camera.target = (0.0, 0.0, 0.0).into();
-    camera.update(&device);
+    let camera = CameraBuilder::default().aspect(size.width as f32 / size.height as f32);
+    let camera = camera
+      .clone()
+      .with_toml(&file_config.camera)
+      .unwrap_or_else(|err| {
+        eprintln!("failed to apply camera config: {}", err);
+        camera
+      })
+      .build(&device);
 
-    let database = FeatureDB::new().unwrap();
-    let instances = {
-      let mut stmt = database.all().unwrap();
-      stmt
-        .query_map([], Feature::from_row)
-        .unwrap()
-        .map(|result| result.unwrap())
-        .map(|feature| FeatureInstance {
-          model: feature.transform().into(),
-          color: feature.color.map(|x| x as f32 / 255.0).into(),
-        })
-        .collect()
+    // Restores the index after a run with --no-position-index
+    let index_result = if configuration.position_index {
+      database.create_position_index()
+    } else {
+      database.drop_position_index()
     };
+    if let Err(err) = index_result {
+      eprintln!("failed to update the position index: '{}'", err);
+    }
+    let features_changed = Arc::new(AtomicBool::new(false));
+    let feature_watch = {
+      let features_changed = features_changed.clone();
+      database.watch(move |_, _| features_changed.store(true, Ordering::Relaxed))
+    };
+    let data_version = database.data_version().unwrap();
+    let (feature_sender, feature_events) = mpsc::channel();
+    // Only deletes are used, and ageing would report an update for every feature
+    database
+      .subscribe(feature_sender, |event| matches!(event, FeatureEvent::Deleted(_)))
+      .keep();
+    let features = database.load_all(Some(&configuration.dataset)).unwrap();
+    let instances = features.iter().map(FeatureInstance::from).collect();
 
-    use super::gfx::renderer;
-
-    let basic_renderer = BasicRenderer::new(renderer::BasicRendererConfiguration {
-      device: &device,
-      surface_config: &config,
+    let skybox = configuration.skybox.as_deref().and_then(|dir| {
+      load_skybox(&device, &queue, &config, dir)
+        .map_err(|err| eprintln!("failed to load skybox '{}': '{}'", dir.display(), err))
+        .ok()
     });
+    let basic_renderer = BasicRenderer::new(BasicRendererConfiguration::new(&device, &config));
+    let grid_renderer = GridRenderer::new(&device, &config, GRID_HALF_EXTENT, GRID_DIVISIONS);
+
+    let debug_wireframe = if configuration.debug_wireframe {
+      Some(BasicRenderer::with_geometry(
+        BasicRendererConfiguration {
+          vertex_entry: "vertex_geometry",
+          ..BasicRendererConfiguration::new(&device, &config)
+        },
+        &geometry::uv_sphere(20).to_wireframe_lines(),
+        wgpu::PrimitiveTopology::LineList,
+      ))
+    } else {
+      None
+    };
 
     let feature_renderer = FeatureRenderer::new(renderer::FeatureRendererConfiguration {
-      geometry: super::gfx::geometry::uv_sphere(100),
+      geometry: FEATURE_MESHES[0](),
       instances,
       device: &device,
+      queue: &queue,
       surface_config: &config,
+      use_z_prepass: configuration.use_z_prepass,
+    });
+
+    feature_renderer.set_fog(configuration.fog_density, fog_color(), &queue);
+
+    let lod_renderer = configuration.lod_thresholds.map(|(near, far)| {
+      let mut lod_renderer = LodFeatureRenderer::new(&device, &queue, &config);
+      lod_renderer.set_thresholds(near, far);
+      lod_renderer.set_fog(configuration.fog_density, fog_color(), &queue);
+      lod_renderer
     });
 
+    let point_cloud = match configuration.feature_display {
+      FeatureDisplay::Meshes => None,
+      FeatureDisplay::Points => Some(PointCloudRenderer::new(
+        &device,
+        &config,
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+      )),
+    };
+
+    let mut ellipsoid_renderer = EllipsoidRenderer::new(&device, &config);
+    ellipsoid_renderer.update(&features, &device, &queue);
+
+    // The coarser spheres and the points lie inside the depth a z prepass would lay down for the full mesh
+    let z_prepass = if configuration.use_z_prepass && lod_renderer.is_none() && point_cloud.is_none() {
+      Some(ZPrepass::new(&device, &config))
+    } else {
+      None
+    };
+
+    #[cfg(feature = "ssao")]
+    let ssao_pass = if configuration.ssao {
+      Some(SsaoPass::new(&device, &config, SsaoPass::MAX_SAMPLES))
+    } else {
+      None
+    };
+    #[cfg(not(feature = "ssao"))]
+    if configuration.ssao {
+      eprintln!("SSAO is not available, build with the 'ssao' feature to enable it");
+    }
+
     let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
+    let picking_texture = Texture::create_render_target(
+      &device,
+      config.width,
+      config.height,
+      FeatureRenderer::PICK_FORMAT,
+      "picking_texture",
+    );
 
-    Self {
+    let text_renderer = TextRenderer::new(&device, &queue, config.format);
+
+    let profiler = if configuration.profile {
+      let profiler = GpuProfiler::new(&device, &queue);
+      if !profiler.is_enabled() {
+        eprintln!("GPU profiling is unavailable: the adapter does not support timestamp queries");
+      }
+      profiler
+    } else {
+      GpuProfiler::disabled()
+    };
+
+    let mut user_interface = UserInterface::new(size);
+    user_interface.invert_y = configuration.invert_y;
+    let mut application = Self {
       _instance: instance,
       _adapter: adapter,
-      surface,
       device,
       queue,
       config,
       size,
-      event_loop: Some(event_loop),
-      window,
+      display,
       camera,
+      fly_path: configuration.fly_path.as_deref().and_then(|path| {
+        CameraPath::load(path, configuration.fly_duration)
+          .map(|fly_path| fly_path.with_loop_mode(configuration.fly_loop))
+          .map_err(|err| eprintln!("failed to load fly path '{}': {}", path.display(), err))
+          .ok()
+      }),
+      skybox,
       basic_renderer,
+      grid_renderer,
+      debug_wireframe,
       feature_renderer,
-      _database: database,
-      websocket: Client::new().await.ok(),
-      user_interface: UserInterface::new(size),
+      lod_renderer,
+      point_cloud,
+      point_cloud_generation: None,
+      ellipsoid_renderer,
+      show_uncertainty: false,
+      picking_scene: picking_scene(&features),
+      feature_layers: Vec::new(),
+      fog_density: configuration.fog_density,
+      z_prepass,
+      path_renderers: Vec::new(),
+      measurement: None,
+      feature_mesh: 0,
+      #[cfg(feature = "ssao")]
+      ssao_pass,
+      database,
+      features_changed,
+      data_version,
+      _feature_watch: feature_watch,
+      feature_events,
+      current_dataset: configuration.dataset,
+      dropped_file: None,
+      websocket,
+      sensor_readings_due: Instant::now(),
+      record_path: configuration.record,
+      recorder: None,
+      gif_path: configuration.gif,
+      gif_recording: None,
+      gif_saves: Vec::new(),
+      depth_snapshot_path: configuration.snapshot_depth,
+      player: configuration.replay.and_then(|path| {
+        FramePlayer::load(&path)
+          .map_err(|err| eprintln!("failed to load replay '{}': {}", path.display(), err))
+          .ok()
+      }),
+      max_feature_age: file_config.simulation.max_feature_age.unwrap_or(MAX_FEATURE_AGE),
+      flashes: HashMap::new(),
+      paused: false,
+      paused_banner: None,
+      text_renderer,
+      debug_text: false,
+      user_interface,
+      selected_ids: Vec::new(),
+      hovered_id: None,
       depth_texture,
+      picking_texture,
+      frame_timer: FrameTimer::new(60),
+      frame_limiter: configuration.max_fps.map(FrameLimiter::new),
+      metrics: Metrics::new(),
+      profiler,
+      log_stats: configuration.stats,
+      title_updater: TitleUpdater::new(30),
+    };
+    if let Some(port) = configuration.metrics_port {
+      let metrics = application.metrics.clone();
+      let commands = application
+        .display
+        .as_ref()
+        .and_then(|display| display.event_loop.as_ref())
+        .map(EventLoop::create_proxy);
+      async_std::task::spawn(async move {
+        if let Err(err) = metrics.serve(port, commands).await {
+          eprintln!("failed to serve metrics on port {}: '{}'", port, err);
+        }
+      });
+    }
+    // Frame the stored features unless the config placed the camera
+    if file_config.camera.as_table().is_some_and(|table| table.is_empty()) {
+      application.zoom_to_fit();
+    }
+    for (name, path) in &configuration.layers {
+      if let Err(err) = application.add_layer(name, path) {
+        eprintln!("failed to add layer '{}': '{}'", name, err);
+      }
+    }
+    application
+  }
+
+  /// Opens the feature database at `path` and draws all of its features as the layer `name`.
+  pub fn add_layer(&mut self, name: &str, path: &Path) -> rusqlite::Result<()> {
+    let database = FeatureDB::open(path)?;
+    let instances = database.load_all(None)?.iter().map(FeatureInstance::from).collect();
+    let renderer = FeatureRenderer::new(renderer::FeatureRendererConfiguration {
+      geometry: FEATURE_MESHES[self.feature_mesh](),
+      instances,
+      device: &self.device,
+      queue: &self.queue,
+      surface_config: &self.config,
+      // The z prepass only covers the main features
+      use_z_prepass: false,
+    });
+    renderer.set_fog(self.fog_density, fog_color(), &self.queue);
+    self.feature_layers.push(FeatureLayer {
+      name: name.into(),
+      _database: database,
+      renderer,
+      visible: true,
+    });
+    Ok(())
+  }
+
+  /// Shows or hides the layer `name`.
+  pub fn toggle_layer_visibility(&mut self, name: &str) {
+    if let Some(layer) = self.feature_layers.iter_mut().find(|layer| layer.name == name) {
+      layer.visible = !layer.visible;
+    }
+  }
+
+  /// Writes every feature in the database to `path` as an ASCII PCD point cloud.
+  #[allow(dead_code)]
+  pub fn export_point_cloud(&self, path: &Path) -> Result<(), ExportError> {
+    let features = self.database.load_all(None)?;
+    PointCloudWriter::create(path)?.write_pcd(&features)
+  }
+
+  /// Upserts `features` received from the robot and reloads the current dataset into the feature renderer.
+  pub fn apply_feature_update(&mut self, features: Vec<Feature>) -> rusqlite::Result<()> {
+    self
+      .metrics
+      .db_insert_count
+      .fetch_add(features.len() as u64, Ordering::Relaxed);
+    let flashes = &mut self.flashes;
+    self.database.upsert_with_callback(&features, |id, new| {
+      if new {
+        flashes.insert(id, NEW_FEATURE_FLASH_FRAMES);
+      }
+    })?;
+    let features = self.database.load_all(Some(&self.current_dataset))?;
+    if let Some(recorder) = &mut self.recorder {
+      recorder.record_frame(&features);
+    }
+    self.upload_features(&features);
+    Ok(())
+  }
+
+  /// Replaces the rendered feature instances, flashing the ones seen for the first time.
+  fn upload_features(&mut self, features: &[Feature]) {
+    let instances = features
+      .iter()
+      .map(|feature| {
+        let mut instance = FeatureInstance::from(feature);
+        if self.flashes.contains_key(&feature.id) {
+          instance.visibility = NEW_FEATURE_FLASH;
+        }
+        instance
+      })
+      .collect();
+    self
+      .feature_renderer
+      .update_instances(instances, &self.device, &self.queue);
+    self.ellipsoid_renderer.update(features, &self.device, &self.queue);
+    self.picking_scene = picking_scene(features);
+  }
+
+  /// Counts down the new feature flashes, redrawing the current dataset once any of them ends.
+  fn fade_flashes(&mut self) -> rusqlite::Result<()> {
+    let count = self.flashes.len();
+    self.flashes.retain(|_, frames| {
+      *frames -= 1;
+      *frames > 0
+    });
+    if self.flashes.len() < count {
+      let features = self.database.load_all(Some(&self.current_dataset))?;
+      self.upload_features(&features);
+    }
+    Ok(())
+  }
+
+  /// Starts recording the current dataset to the `--record` file, or stops and saves the recording in progress.
+  pub fn toggle_recording(&mut self) {
+    let path = match &self.record_path {
+      Some(path) => path,
+      None => return,
+    };
+    if let Some(recorder) = self.recorder.take() {
+      if let Err(err) = recorder.save(path) {
+        eprintln!("failed to save recording '{}': {}", path.display(), err);
+      }
+    } else {
+      match self.database.load_all(Some(&self.current_dataset)) {
+        Ok(features) => {
+          let mut recorder = FrameRecorder::new();
+          recorder.record_frame(&features);
+          self.recorder = Some(recorder);
+        }
+        Err(err) => eprintln!("failed to start recording: '{}'", err),
+      }
+    }
+  }
+
+  /// Starts capturing GIF frames at `fps`, replacing any recording in progress without saving it.
+  pub fn start_gif_recording(&mut self, path: PathBuf, fps: f32) {
+    self.gif_recording = Some(GifRecording::new(path, fps));
+  }
+
+  /// Writes the frames captured since `start_gif_recording` to its GIF file in the background.
+  pub fn stop_gif_recording(&mut self) {
+    self.gif_saves.retain(|save| !save.is_finished());
+    if let Some(recording) = self.gif_recording.take() {
+      self.gif_saves.push(recording.finish());
+    }
+  }
+
+  /// Starts recording the `--gif` file, or stops and saves the recording in progress.
+  pub fn toggle_gif_recording(&mut self) {
+    if self.gif_recording.is_some() {
+      self.stop_gif_recording();
+    } else if let Some(path) = self.gif_path.clone() {
+      self.start_gif_recording(path, GIF_FPS);
+    }
+  }
+
+  /// Renders a GIF frame for the recording's worker to quantize when one is due, stopping the recording once it is
+  /// full.
+  fn capture_gif_frame(&mut self) {
+    let due = self
+      .gif_recording
+      .as_mut()
+      .is_some_and(|recording| recording.frame_due(Instant::now()));
+    if !due {
+      return;
+    }
+    match self.render_to_texture(GIF_WIDTH, GIF_HEIGHT) {
+      Ok(pixels) => {
+        if let Some(recording) = &mut self.gif_recording {
+          recording.push(pixels);
+          if recording.is_full() {
+            self.stop_gif_recording();
+          }
+        }
+      }
+      Err(err) => eprintln!("failed to capture GIF frame: '{}'", err),
+    }
+  }
+
+  /// Saves the `--snapshot-depth` file once, after the first frame.
+  fn capture_depth_snapshot(&mut self) {
+    if let Some(path) = self.depth_snapshot_path.take() {
+      if let Err(err) = self.save_depth_snapshot(&path) {
+        eprintln!("failed to save depth snapshot: '{}'", err);
+      }
+    }
+  }
+
+  /// Shows the next frame of the `--replay` file while playback isn't paused.
+  fn play_frame(&mut self) {
+    if let (Some(player), false) = (&mut self.player, self.paused) {
+      if let Some(features) = player.next_frame() {
+        let instances = features.iter().map(FeatureInstance::from).collect();
+        self
+          .feature_renderer
+          .update_instances(instances, &self.device, &self.queue);
+        self.ellipsoid_renderer.update(features, &self.device, &self.queue);
+        self.picking_scene = picking_scene(features);
+      }
+    }
+  }
+
+  /// Ages every stored feature, drops stale ones and applies the features received since the last frame.
+  fn receive_messages(&mut self) -> rusqlite::Result<()> {
+    if self.paused {
+      return Ok(());
+    }
+    self.fade_flashes()?;
+    let mut features = Vec::new();
+    let mut paths = None;
+    let mut colors = Vec::new();
+    let mut commands = Vec::new();
+    if let Some(client) = &self.websocket {
+      for msg in client.drain_messages_bounded(MAX_MESSAGES_PER_FRAME) {
+        self.metrics.ws_messages_received.fetch_add(1, Ordering::Relaxed);
+        match msg {
+          SimulatorMessage::FeatureUpdate(update) => features.extend(update),
+          SimulatorMessage::PathUpdate(update) => paths = Some(update),
+          SimulatorMessage::FeatureColorUpdate { id, r, g, b } => colors.push((id, Vector3::new(r, g, b))),
+          SimulatorMessage::SensorData(_) => (),
+          // Only clients without an event loop to pass commands to queue them
+          SimulatorMessage::Command(command) => commands.push(command),
+        }
+      }
+    }
+    for command in commands {
+      command.dispatch(self);
+    }
+    if let Some(paths) = paths {
+      self.update_paths(&paths);
+    }
+    if !features.is_empty() {
+      self.database.increment_ages()?;
+      // Prune before upserting so the incoming features are never removed
+      self.database.prune_by_age(self.max_feature_age)?;
+      self.apply_feature_update(features)?;
+    }
+    for (id, color) in colors {
+      self.database.update_color(id, color)?;
+      self
+        .feature_renderer
+        .update_instance_color(id, color.map(|x| x as f32 / 255.0).into(), &self.queue);
+    }
+    self.forget_deleted_features();
+    // Picks up writes from other processes, such as generating features from another terminal. The watcher also sees
+    // the writes above, which are already uploaded and leave our own data version alone.
+    if self.features_changed.swap(false, Ordering::Relaxed) {
+      let data_version = self.database.data_version()?;
+      if data_version != self.data_version {
+        self.data_version = data_version;
+        let features = self.database.load_all(Some(&self.current_dataset))?;
+        self.upload_features(&features);
+      }
+    }
+    Ok(())
+  }
+
+  /// Deselects and stops flashing the features deleted since the last call, such as the ones pruned for their age.
+  /// Inserts and updates are left to `apply_feature_update` and never subscribed to, since every upsert is reported as
+  /// an insert and every update ages all features.
+  fn forget_deleted_features(&mut self) {
+    for event in self.feature_events.try_iter() {
+      if let FeatureEvent::Deleted(id) = event {
+        self.selected_ids.retain(|&selected| selected != id);
+        self.hovered_id = self.hovered_id.filter(|&hovered| hovered != id);
+        self.flashes.remove(&id);
+      }
+    }
+  }
+
+  /// Freezes the rendered state: WebSocket updates are left queued, features stop ageing and replay stops.
+  pub fn toggle_pause(&mut self) {
+    self.paused = !self.paused;
+    self.paused_banner = if self.paused {
+      Some(BasicRenderer::overlay(
+        BasicRendererConfiguration {
+          vertex_entry: "vertex_overlay",
+          fragment_entry: "fragment_overlay",
+          ..BasicRendererConfiguration::new(&self.device, &self.config)
+        },
+        &geometry::fullscreen_quad(),
+      ))
+    } else {
+      None
+    };
+  }
+
+  /// Replaces the drawn robot paths, reusing existing line renderers.
+  pub fn update_paths(&mut self, paths: &[Vec<[f32; 3]>]) {
+    self.path_renderers.truncate(paths.len());
+    for (renderer, path) in self.path_renderers.iter_mut().zip(paths) {
+      renderer.update_path(path, &self.device, &self.queue);
+    }
+    for path in &paths[self.path_renderers.len()..] {
+      self
+        .path_renderers
+        .push(InstancedLineRenderer::new(InstancedLineRendererConfiguration {
+          path,
+          color: PATH_COLOR,
+          device: &self.device,
+          surface_config: &self.config,
+        }));
+    }
+  }
+
+  /// Whether M has started measuring, or a measurement is still shown.
+  fn is_measuring(&self) -> bool {
+    self.measurement.is_some() || matches!(self.user_interface.current_state.event, UIEvent::MeasureDistance { .. })
+  }
+
+  /// Shows `measurement` as a line and in the window title.
+  fn set_measurement(&mut self, measurement: Measurement) {
+    let line = InstancedLineRenderer::new(InstancedLineRendererConfiguration {
+      path: &[measurement.start.into(), measurement.end.into()],
+      color: MEASUREMENT_COLOR,
+      device: &self.device,
+      surface_config: &self.config,
+    });
+    self.measurement = Some((measurement, line));
+    self.update_title();
+  }
+
+  /// Leaves measuring, hiding the last measurement.
+  fn clear_measurement(&mut self) {
+    self.measurement = None;
+    self.user_interface.current_state.event = UIEvent::None;
+    self.update_title();
+  }
+
+  /// Switches features to the next mesh in `FEATURE_MESHES`.
+  pub fn next_feature_mesh(&mut self) {
+    self.feature_mesh = (self.feature_mesh + 1) % FEATURE_MESHES.len();
+    self
+      .feature_renderer
+      .set_geometry(FEATURE_MESHES[self.feature_mesh](), &self.device);
+    for layer in &mut self.feature_layers {
+      layer
+        .renderer
+        .set_geometry(FEATURE_MESHES[self.feature_mesh](), &self.device);
+    }
+  }
+
+  /// Queues a file dropped on the window for `load_dropped_file`. Feature databases replace the current one and CSV or
+  /// JSON files are imported into it; the window title shows "Loading…" until the features are reloaded.
+  pub fn handle_drag_drop(&mut self, path: &Path) {
+    let kind = match DroppedFileKind::classify(path) {
+      Some(kind) => kind,
+      None => {
+        eprintln!("ignoring dropped file '{}': unsupported extension", path.display());
+        return;
+      }
+    };
+    self.set_title("Loading…");
+    self.dropped_file = Some((path.to_owned(), kind));
+  }
+
+  /// Loads the file queued by `handle_drag_drop` and draws its features. Called after a frame is presented, so the
+  /// "Loading…" title is up while the file loads.
+  fn load_dropped_file(&mut self) {
+    let (path, kind) = match self.dropped_file.take() {
+      Some(dropped) => dropped,
+      None => return,
+    };
+    match kind.load(&path, &self.database, &self.current_dataset) {
+      Ok(dropped) => {
+        if let Some(database) = dropped.database {
+          self.replace_database(database);
+        }
+        self.current_dataset = dropped.dataset;
+        self.upload_features(&dropped.features);
+      }
+      Err(err) => eprintln!("failed to load dropped file '{}': '{}'", path.display(), err),
+    }
+    self.update_title();
+  }
+
+  /// Swaps in `database`, moving the change watch and subscription over.
+  fn replace_database(&mut self, database: FeatureDB) {
+    self._feature_watch = {
+      let features_changed = self.features_changed.clone();
+      database.watch(move |_, _| features_changed.store(true, Ordering::Relaxed))
+    };
+    let (feature_sender, feature_events) = mpsc::channel();
+    database
+      .subscribe(feature_sender, |event| matches!(event, FeatureEvent::Deleted(_)))
+      .keep();
+    self.feature_events = feature_events;
+    // A failure only costs a reload when the watcher next fires
+    self.data_version = database.data_version().unwrap_or(self.data_version);
+    self.database = database;
+    self.flashes.clear();
+  }
+
+  /// Switches to the next dataset in the database, wrapping around, and reloads the rendered features from it.
+  pub fn next_dataset(&mut self) {
+    let datasets = match self.database.list_datasets() {
+      Ok(datasets) => datasets,
+      Err(err) => {
+        eprintln!("failed to list datasets: '{}'", err);
+        return;
+      }
+    };
+    let next = datasets
+      .iter()
+      .position(|dataset| *dataset == self.current_dataset)
+      .map_or(0, |idx| (idx + 1) % datasets.len());
+    if let Some(dataset) = datasets.get(next) {
+      match self.database.load_all(Some(dataset)) {
+        Ok(features) => {
+          let instances = features.iter().map(FeatureInstance::from).collect();
+          self
+            .feature_renderer
+            .update_instances(instances, &self.device, &self.queue);
+          self.ellipsoid_renderer.update(&features, &self.device, &self.queue);
+          self.picking_scene = picking_scene(&features);
+          self.current_dataset = dataset.clone();
+        }
+        Err(err) => eprintln!("failed to load dataset '{}': '{}'", dataset, err),
+      }
+    }
+  }
+
+  /// Shows the feature count, frame rate, connection state and latency in the window title.
+  pub fn update_title(&mut self) {
+    let feature_count = match self.database.count(Some(&self.current_dataset)) {
+      Ok(count) => count,
+      Err(err) => {
+        eprintln!("failed to count features: '{}'", err);
+        return;
+      }
+    };
+    let connection_state = self
+      .websocket
+      .as_ref()
+      .map_or(ConnectionState::Disconnected, FramedClient::state);
+    let latency_ms = self
+      .websocket
+      .as_ref()
+      .filter(|client| client.state() == ConnectionState::Connected)
+      .map(FramedClient::latency_ms)
+      .filter(|&latency| latency > 0.0);
+    let mut title = self
+      .title_updater
+      .format(feature_count, self.frame_timer.fps(), connection_state, latency_ms);
+    let render_mode = self.feature_renderer.render_mode();
+    if render_mode != RenderMode::Solid {
+      title.push_str(&format!(" | {}", render_mode));
+    }
+    if let Some((measurement, _)) = &self.measurement {
+      title.push_str(&format!(" | {}", measurement));
+    }
+    self.set_title(&title);
+  }
+
+  fn set_title(&self, title: &str) {
+    if let Some(display) = &self.display {
+      display.window.set_title(title);
     }
   }
 
@@ -137,8 +1148,20 @@ impl Application {
       self.size = new_size;
       self.config.width = new_size.width;
       self.config.height = new_size.height;
-      self.surface.configure(&self.device, &self.config);
-      self.depth_texture = Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+      if let Some(display) = &self.display {
+        display.surface.configure(&self.device, &self.config);
+      }
+      self.depth_texture.resize(&self.device, new_size.width, new_size.height);
+      self
+        .picking_texture
+        .resize(&self.device, new_size.width, new_size.height);
+      if let Some(point_cloud) = &self.point_cloud {
+        point_cloud.resize(new_size.width, new_size.height, &self.queue);
+      }
+      #[cfg(feature = "ssao")]
+      if let Some(ssao_pass) = &mut self.ssao_pass {
+        ssao_pass.resize(&self.device, &self.config);
+      }
     }
   }
 
@@ -146,6 +1169,9 @@ impl Application {
     let current = &mut self.user_interface.current_state;
     match event {
       WindowEvent::MouseInput { button, state, .. } => {
+        if let ElementState::Pressed = state {
+          current.click_position = current.position;
+        }
         match button {
           MouseButton::Left => current.left = MouseEvent::from(*state),
           MouseButton::Middle => current.middle = MouseEvent::from(*state),
@@ -154,8 +1180,16 @@ impl Application {
         };
         true
       }
+      WindowEvent::MouseWheel { delta, .. } => {
+        current.scroll += match delta {
+          MouseScrollDelta::LineDelta(_, y) => *y,
+          MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_SCROLL_LINE,
+        };
+        true
+      }
       WindowEvent::CursorMoved { position, .. } => {
         current.position = *position;
+        self.hover_feature();
         true
       }
       WindowEvent::KeyboardInput { input, .. } => {
@@ -166,6 +1200,10 @@ impl Application {
           false
         }
       }
+      WindowEvent::DroppedFile(path) => {
+        self.handle_drag_drop(path);
+        true
+      }
       _ => false,
     }
   }
@@ -178,8 +1216,49 @@ impl Application {
     // let last_ray = last.ray(&self.camera, self.window.inner_size());
     // let current_ray = current.ray(&self.camera, self.window.inner_size());
 
+    self.user_interface.update(&mut self.camera);
+    if let Some(fly_path) = &mut self.fly_path {
+      let (eye, target) = fly_path.tick(self.frame_timer.last_frame_time().as_secs_f32());
+      self.camera.eye = eye;
+      self.camera.target = target;
+      if fly_path.is_finished() {
+        self.fly_path = None;
+      }
+    }
+
+    // Buttons stay clicked until the cursor moves far enough to count as a drag
+    let dragging = current.is_dragging(self.user_interface.drag_threshold);
+
+    // Clicking without dragging selects, or picks the next point while measuring
+    if matches!(
+      (self.user_interface.last_state.left, current.left),
+      (MouseEvent::Click, MouseEvent::Release)
+    ) {
+      match current.event {
+        UIEvent::MeasureDistance { from, to } => {
+          if let Some(point) = self.pick_point() {
+            next.event = match (from, to) {
+              (Some(from), None) => {
+                self.set_measurement(Measurement::new(from, point));
+                UIEvent::MeasureDistance {
+                  from: Some(from),
+                  to: Some(point),
+                }
+              }
+              // Starts over after a finished measurement
+              _ => UIEvent::MeasureDistance {
+                from: Some(point),
+                to: None,
+              },
+            };
+          }
+        }
+        _ => self.pick(),
+      }
+    }
+
     match current.left {
-      MouseEvent::Click => {
+      MouseEvent::Click if dragging => {
         next.left = MouseEvent::Move;
       }
       MouseEvent::Release => {
@@ -189,7 +1268,7 @@ impl Application {
     };
 
     match current.middle {
-      MouseEvent::Click => {
+      MouseEvent::Click if dragging => {
         next.middle = MouseEvent::Move;
       }
       MouseEvent::Release => {
@@ -201,11 +1280,8 @@ impl Application {
     match current.right {
       MouseEvent::Click => {
         next.event = UIEvent::FreeMoveCamera;
-        next.right = MouseEvent::Move;
-      }
-      MouseEvent::Move => {
-        if let UIEvent::FreeMoveCamera = current.event {
-          self.user_interface.free_move(&mut self.camera);
+        if dragging {
+          next.right = MouseEvent::Move;
         }
       }
       MouseEvent::Release => {
@@ -215,6 +1291,69 @@ impl Application {
       _ => (),
     }
 
+    if current.key_just_pressed(VirtualKeyCode::Tab) {
+      self.next_dataset();
+    }
+    if current.key_just_pressed(VirtualKeyCode::M) {
+      if self.is_measuring() {
+        self.clear_measurement();
+        next.event = UIEvent::None;
+      } else {
+        next.event = UIEvent::MeasureDistance { from: None, to: None };
+      }
+    }
+    if current.key_just_pressed(VirtualKeyCode::N) {
+      self.next_feature_mesh();
+    }
+    if current.key_just_pressed(VirtualKeyCode::F) {
+      self.zoom_to_fit();
+    }
+    let layer_keys = [
+      VirtualKeyCode::Key1,
+      VirtualKeyCode::Key2,
+      VirtualKeyCode::Key3,
+      VirtualKeyCode::Key4,
+    ];
+    for (&key, idx) in layer_keys.iter().zip(0..) {
+      if current.key_just_pressed(key) {
+        if let Some(name) = self.feature_layers.get(idx).map(|layer| layer.name.clone()) {
+          self.toggle_layer_visibility(&name);
+        }
+      }
+    }
+    if current.key_just_pressed(VirtualKeyCode::R) {
+      self.toggle_recording();
+    }
+    if current.key_just_pressed(VirtualKeyCode::G) {
+      self.toggle_gif_recording();
+    }
+    if current.key_just_pressed(VirtualKeyCode::V) {
+      let mode = self.feature_renderer.render_mode().next();
+      self.feature_renderer.set_render_mode(mode);
+      if let Some(lod_renderer) = &mut self.lod_renderer {
+        lod_renderer.set_render_mode(mode);
+      }
+    }
+    if current.key_just_pressed(VirtualKeyCode::U) {
+      self.show_uncertainty = !self.show_uncertainty;
+    }
+    if current.key_just_pressed(VirtualKeyCode::H) {
+      self.grid_renderer.set_visible(!self.grid_renderer.is_visible());
+    }
+    if current.key_just_pressed(VirtualKeyCode::I) {
+      self.user_interface.invert_y = !self.user_interface.invert_y;
+    }
+    if current.key_just_pressed(VirtualKeyCode::F3) {
+      self.debug_text = !self.debug_text;
+    }
+    // Space also moves the camera up while free moving
+    if current.key_just_pressed(VirtualKeyCode::Space) && matches!(current.event, UIEvent::None) {
+      self.toggle_pause();
+    }
+
+    // Each scroll is only zoomed once
+    next.scroll = 0.0;
+
     for (_, event) in next.keys.iter_mut() {
       match event {
         KeyEvent::Press => *event = KeyEvent::Hold,
@@ -228,53 +1367,440 @@ impl Application {
     self.camera.update(&self.device);
   }
 
+  /// Draws a frame to the window. Headless applications have none and draw with `render_to_texture` instead.
   pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-    let output = self.surface.get_current_texture()?;
+    let output = match &self.display {
+      Some(display) => display.surface.get_current_texture()?,
+      None => return Ok(()),
+    };
     let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
     let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
       label: Some("Render Encoder"),
     });
 
+    self.encode_frame(&mut encoder, &view, true);
+    self.profiler.resolve(&mut encoder);
+
+    self.queue.submit(std::iter::once(encoder.finish()));
+    output.present();
+    self.profiler.collect(&self.device);
+
+    Ok(())
+  }
+
+  /// Renders one frame at `width`×`height` into an offscreen texture instead of the window and returns its pixels
+  /// as tightly packed RGBA8 rows. SSAO is skipped unless the size matches the window.
+  pub fn render_to_texture(&mut self, width: u32, height: u32) -> Result<Vec<u8>, RenderError> {
+    let target = Texture::create_render_target(&self.device, width, height, self.config.format, "Offscreen Target");
+    let offscreen_config = wgpu::SurfaceConfiguration {
+      width,
+      height,
+      ..self.config.clone()
+    };
+    let depth_texture = Texture::create_depth_texture(&self.device, &offscreen_config, "offscreen_depth_texture");
+    let window_depth_texture = std::mem::replace(&mut self.depth_texture, depth_texture);
+    let window_aspect = self.camera.aspect;
+    self.camera.aspect = width as f32 / height as f32;
+    self.camera.update(&self.device);
+
+    let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Offscreen Render Encoder"),
+    });
+    let ssao = width == self.config.width && height == self.config.height;
+    self.encode_frame(&mut encoder, &target.view, ssao);
+    self.profiler.resolve(&mut encoder);
+    self.queue.submit(std::iter::once(encoder.finish()));
+    self.profiler.collect(&self.device);
+
+    self.depth_texture = window_depth_texture;
+    self.camera.aspect = window_aspect;
+    self.camera.update(&self.device);
+
+    let mut pixels = renderer::read_texture(&self.device, &self.queue, &target, width, height)?;
+    if matches!(
+      self.config.format,
+      wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    ) {
+      for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+      }
+    }
+    Ok(pixels)
+  }
+
+  /// Records every pass of a frame into `view`, using the current depth texture.
+  #[cfg_attr(not(feature = "ssao"), allow(unused_variables))]
+  fn encode_frame(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, ssao: bool) {
+    self.feature_renderer.sort_transparent(&self.camera, &self.queue);
+    let generation = self.feature_renderer.instance_generation();
+    match (&mut self.point_cloud, &mut self.lod_renderer) {
+      (Some(point_cloud), _) if self.point_cloud_generation != Some(generation) => {
+        let (points, colors): (Vec<_>, Vec<_>) = self
+          .feature_renderer
+          .instances()
+          .map(|instance| {
+            let position: [f32; 3] = instance.position().into();
+            let [r, g, b] = instance.color;
+            (position, [r, g, b, instance.visibility])
+          })
+          .unzip();
+        let sizes = vec![FEATURE_POINT_SIZE; points.len()];
+        point_cloud.update_points(&points, &colors, &sizes, &self.device, &self.queue);
+        self.point_cloud_generation = Some(generation);
+      }
+      (Some(_), _) => {}
+      (None, Some(lod_renderer)) => lod_renderer.update(
+        self.feature_renderer.instances(),
+        &self.camera,
+        &self.device,
+        &self.queue,
+      ),
+      (None, None) => {}
+    }
+    for layer in self.feature_layers.iter_mut().filter(|layer| layer.visible) {
+      layer.renderer.sort_transparent(&self.camera, &self.queue);
+    }
+    if let Some(z_prepass) = &self.z_prepass {
+      self.profiler.begin_scope("Z Prepass", encoder);
+      z_prepass.render(encoder, &self.depth_texture, &self.feature_renderer, &self.camera);
+      self.profiler.end_scope(encoder);
+    }
+
+    // One outline color per frame, so the selection hides the hover highlight
+    #[cfg(feature = "outlines")]
+    match (self.selected_ids.is_empty(), self.hovered_id) {
+      (true, Some(id)) => self
+        .feature_renderer
+        .set_outline(&[id], HOVER_COLOR, &self.device, &self.queue),
+      _ => self
+        .feature_renderer
+        .set_outline(&self.selected_ids, SELECTION_COLOR, &self.device, &self.queue),
+    }
+
+    self.profiler.begin_scope("Main Pass", encoder);
     {
-      let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-        label: Some("Render Pass"),
-        color_attachments: &[wgpu::RenderPassColorAttachment {
-          view: &view,
-          resolve_target: None,
-          ops: wgpu::Operations {
-            load: wgpu::LoadOp::Clear(wgpu::Color {
-              r: 0.1,
-              g: 0.2,
-              b: 0.3,
-              a: 1.0,
-            }),
-            store: true,
-          },
-        }],
-        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-          view: &self.depth_texture.view,
-          depth_ops: Some(wgpu::Operations {
-            load: wgpu::LoadOp::Clear(1.0),
-            store: true,
-          }),
-          stencil_ops: None,
-        }),
-      });
+      let builder = RenderPassBuilder::new(encoder, "Main Pass")
+        .color(view)
+        .clear_color(BACKGROUND_COLOR)
+        .depth(&self.depth_texture.view)
+        .clear_stencil(0);
+      // The z prepass has already laid down the feature depth
+      let mut render_pass = if self.z_prepass.is_some() {
+        builder.build()
+      } else {
+        builder.clear_depth(1.0).build()
+      };
 
+      if let Some(skybox) = &self.skybox {
+        skybox.render(&mut render_pass, &self.camera);
+      }
       self.basic_renderer.render(&mut render_pass, &self.camera);
-      self.feature_renderer.render(&mut render_pass, &self.camera);
+      self.grid_renderer.render(&mut render_pass, &self.camera);
+      match (&self.point_cloud, &self.lod_renderer) {
+        (Some(point_cloud), _) => point_cloud.render(&mut render_pass, &self.camera),
+        (None, Some(lod_renderer)) => lod_renderer.render_opaque(&mut render_pass, &self.camera),
+        (None, None) => self.feature_renderer.render_opaque(&mut render_pass, &self.camera),
+      }
+      for layer in self.feature_layers.iter().filter(|layer| layer.visible) {
+        layer.renderer.render_opaque(&mut render_pass, &self.camera);
+      }
+      for path_renderer in &self.path_renderers {
+        path_renderer.render(&mut render_pass, &self.camera);
+      }
+      if let Some((_, line)) = &self.measurement {
+        line.render(&mut render_pass, &self.camera);
+      }
+      if let Some(debug_wireframe) = &self.debug_wireframe {
+        debug_wireframe.render(&mut render_pass, &self.camera);
+      }
+      match (&self.point_cloud, &self.lod_renderer) {
+        // Points are blended as they are drawn
+        (Some(_), _) => (),
+        (None, Some(lod_renderer)) => lod_renderer.render_transparent(&mut render_pass, &self.camera),
+        (None, None) => self.feature_renderer.render_transparent(&mut render_pass, &self.camera),
+      }
+      for layer in self.feature_layers.iter().filter(|layer| layer.visible) {
+        layer.renderer.render_transparent(&mut render_pass, &self.camera);
+      }
+      if self.show_uncertainty {
+        self.ellipsoid_renderer.render(&mut render_pass, &self.camera);
+      }
+      #[cfg(feature = "outlines")]
+      self.feature_renderer.render_outline(&mut render_pass, &self.camera);
+      if let Some(paused_banner) = &self.paused_banner {
+        paused_banner.render(&mut render_pass, &self.camera);
+      }
+    }
+    self.profiler.end_scope(encoder);
+
+    #[cfg(feature = "ssao")]
+    if let (Some(ssao_pass), true) = (&mut self.ssao_pass, ssao) {
+      self.profiler.begin_scope("SSAO", encoder);
+      ssao_pass.render(&self.device, &self.queue, encoder, &self.depth_texture, &self.camera);
+      ssao_pass.resolve(encoder, view);
+      self.profiler.end_scope(encoder);
+    }
+
+    // Drawn last so SSAO never darkens it
+    if self.debug_text {
+      let connection_state = self
+        .websocket
+        .as_ref()
+        .map_or(ConnectionState::Disconnected, FramedClient::state);
+      let mut text = format!(
+        "{:.1} FPS\n{} features\n{:?}",
+        self.frame_timer.fps(),
+        self.feature_renderer.instance_count(),
+        connection_state
+      );
+      if let Some(id) = self.hovered_id {
+        text.push_str(&format!("\nfeature #{}", id));
+      }
+      self.text_renderer.queue_text(8.0, 8.0, &text, [1.0, 1.0, 1.0, 1.0]);
+      let mut render_pass = RenderPassBuilder::new(encoder, "Debug Text Pass").color(view).build();
+      self.text_renderer.flush(
+        &self.device,
+        &self.queue,
+        &mut render_pass,
+        [self.config.width as f32, self.config.height as f32],
+      );
     }
+  }
 
+  /// Renders a frame the size of the window and saves it to `path`, in the format given by its extension.
+  pub fn save_screenshot(&mut self, path: &Path) -> Result<(), RenderError> {
+    let (width, height) = (self.config.width, self.config.height);
+    let pixels = self.render_to_texture(width, height)?;
+    image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)?;
+    Ok(())
+  }
+
+  /// Renders a frame the size of the window and returns the distance along the view direction to the nearest surface
+  /// of each pixel, row by row from the top left. Pixels without any surface are `zfar` away.
+  pub fn take_depth_snapshot(&mut self) -> Result<Vec<f32>, RenderError> {
+    let (width, height) = (self.config.width, self.config.height);
+    let target = Texture::create_render_target(&self.device, width, height, self.config.format, "Snapshot Target");
+    let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Snapshot Render Encoder"),
+    });
+    self.encode_frame(&mut encoder, &target.view, false);
+    self.profiler.resolve(&mut encoder);
     self.queue.submit(std::iter::once(encoder.finish()));
-    output.present();
+    self.profiler.collect(&self.device);
+
+    let depths = renderer::read_depth(&self.device, &self.queue, &self.depth_texture, width, height)?;
+    Ok(
+      depths
+        .into_iter()
+        .map(|depth| self.camera.linear_depth(depth))
+        .collect(),
+    )
+  }
 
+  /// Writes `take_depth_snapshot` to `path` as a headerless array of little-endian f32s.
+  pub fn save_depth_snapshot(&mut self, path: &Path) -> Result<(), RenderError> {
+    let depths = self.take_depth_snapshot()?;
+    let bytes: Vec<u8> = depths.iter().flat_map(|depth| depth.to_le_bytes()).collect();
+    std::fs::write(path, bytes)?;
     Ok(())
   }
 
+  /// GPU time of each render pass in the last frame, by pass label. Empty unless profiling with timestamp queries.
+  #[allow(dead_code)]
+  pub fn gpu_timings(&self) -> HashMap<&str, Duration> {
+    self.profiler.timings().clone()
+  }
+
+  /// Frames the features of the current dataset using the database statistics, without loading the features. The
+  /// framed radius covers two standard deviations plus the mean feature radius.
+  pub fn zoom_to_fit(&mut self) {
+    match self.database.aggregate_statistics(Some(&self.current_dataset)) {
+      Ok(stats) if stats.count > 0 => {
+        let radius = 2.0 * stats.position_stddev.magnitude() + stats.radius_mean + stats.radius_stddev;
+        UserInterface::frame(Point3::from_vec(stats.position_centroid), radius, &mut self.camera);
+        self.camera.update(&self.device);
+      }
+      Ok(_) => (),
+      Err(err) => eprintln!("failed to compute feature statistics: '{}'", err),
+    }
+  }
+
+  /// Highlights the rendered feature under the cursor, nearest first, and returns its id.
+  fn hover_feature(&mut self) -> Option<u32> {
+    let ray = self.user_interface.current_state.ray(&self.camera, self.size);
+    self.metrics.raycast_count.fetch_add(1, Ordering::Relaxed);
+    self.hovered_id = UserInterface::pick_feature(self.feature_renderer.instances(), &ray).map(|instance| instance.id);
+    self.hovered_id
+  }
+
+  /// Emits sensor readings once `SENSOR_READING_INTERVAL` has passed since the last ones. Intervals missed by slow
+  /// frames are skipped rather than caught up on.
+  fn emit_due_sensor_readings(&mut self) {
+    let now = Instant::now();
+    if now >= self.sensor_readings_due {
+      self.sensor_readings_due = now + SENSOR_READING_INTERVAL;
+      self.emit_sensor_readings();
+    }
+  }
+
+  /// Mock sensor readings of every rendered feature from the camera, also sent to the robot while it is connected.
+  fn emit_sensor_readings(&self) -> Vec<SensorReading> {
+    let timestamp_us = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_micros() as u64;
+    let readings: Vec<SensorReading> = self
+      .feature_renderer
+      .instances()
+      .map(|instance| instance.to_sensor_reading(&self.camera, timestamp_us))
+      .collect();
+    if let Some(client) = self
+      .websocket
+      .as_ref()
+      .filter(|client| client.state() == ConnectionState::Connected)
+    {
+      if let Err(err) = client.send(SimulatorMessage::SensorData(readings.clone())) {
+        eprintln!("failed to send sensor readings: '{}'", err);
+      }
+    }
+    readings
+  }
+
+  /// Point under the cursor on the nearest feature.
+  fn pick_point(&self) -> Option<Point3<f32>> {
+    let ray = self.user_interface.current_state.ray(&self.camera, self.size);
+    self.metrics.raycast_count.fetch_add(1, Ordering::Relaxed);
+    self.picking_scene.intersect_named(&ray).map(|(_, hit)| hit.position)
+  }
+
+  /// Selects the rendered feature under the cursor, or clears the selection if there is none.
+  pub fn pick(&mut self) {
+    let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Pick Encoder"),
+    });
+    // The depth texture is cleared again by the next frame before it is drawn
+    self
+      .feature_renderer
+      .render_picking(&mut encoder, &self.picking_texture, &self.depth_texture, &self.camera);
+    self.queue.submit(std::iter::once(encoder.finish()));
+    let position = self.user_interface.current_state.position;
+    self.selected_ids = self
+      .feature_renderer
+      .read_pick_result(
+        &self.device,
+        &self.queue,
+        &self.picking_texture,
+        position.x as u32,
+        position.y as u32,
+      )
+      .and_then(|idx| self.feature_renderer.instance_id(idx as usize))
+      .into_iter()
+      .collect();
+  }
+
+  /// Draw calls, triangles and instances of every renderer drawn in the main pass, including visible layers.
+  pub fn total_stats(&self) -> RendererStats {
+    let basic = [
+      Some(&self.basic_renderer),
+      self.debug_wireframe.as_ref(),
+      self.paused_banner.as_ref(),
+    ]
+    .iter()
+    .flatten()
+    .map(|renderer| renderer.stats())
+    .sum::<RendererStats>();
+    let skybox = self
+      .skybox
+      .as_ref()
+      .map_or_else(RendererStats::default, SkyboxRenderer::stats);
+    let features = match (&self.point_cloud, &self.lod_renderer) {
+      (Some(point_cloud), _) => point_cloud.stats(),
+      (None, Some(lod_renderer)) => lod_renderer.stats(),
+      (None, None) => self.feature_renderer.stats(),
+    } + self
+      .feature_layers
+      .iter()
+      .filter(|layer| layer.visible)
+      .map(|layer| layer.renderer.stats())
+      .sum();
+    let uncertainty = if self.show_uncertainty {
+      self.ellipsoid_renderer.stats()
+    } else {
+      RendererStats::default()
+    };
+    basic + skybox + features + uncertainty
+  }
+
+  fn record_frame_metrics(&self) {
+    let visible = self.feature_renderer.instance_count()
+      + self
+        .feature_layers
+        .iter()
+        .filter(|layer| layer.visible)
+        .map(|layer| layer.renderer.instance_count())
+        .sum::<usize>();
+    self.metrics.features_visible.store(visible as u64, Ordering::Relaxed);
+    self.metrics.frames_rendered.fetch_add(1, Ordering::Relaxed);
+    let stats = self.total_stats();
+    self
+      .metrics
+      .draw_calls_total
+      .fetch_add(stats.draw_calls as u64, Ordering::Relaxed);
+    self
+      .metrics
+      .triangles_total
+      .fetch_add(stats.triangles as u64, Ordering::Relaxed);
+    if let Some(client) = &self.websocket {
+      let client_stats = client.stats();
+      self
+        .metrics
+        .ws_bytes_received
+        .store(client_stats.bytes_received, Ordering::Relaxed);
+      self
+        .metrics
+        .ws_bytes_sent
+        .store(client_stats.bytes_sent, Ordering::Relaxed);
+    }
+  }
+
+  /// Runs `frames` iterations of the update and render cycle of `run` without the event loop, rendering each frame
+  /// into an offscreen texture the size of the window instead of presenting it, so tests and scripts can step the
+  /// application.
+  #[cfg(any(test, feature = "headless"))]
+  pub fn run_headless_for(&mut self, frames: u32) -> Result<(), ApplicationError> {
+    for _ in 0..frames {
+      self.receive_messages()?;
+      self.play_frame();
+      self.update();
+      self.render_to_texture(self.size.width, self.size.height)?;
+      self.load_dropped_file();
+      self.emit_due_sensor_readings();
+      self.record_frame_metrics();
+      self.frame_timer.tick();
+    }
+    Ok(())
+  }
+
+  /// Runs the event loop until the window closes. Panics for applications made with `headless`, which have none.
   pub async fn run(mut self) {
-    let event_loop = self.event_loop.take().unwrap();
+    let display = self.display.as_mut().expect("a window to run");
+    let event_loop = display.event_loop.take().unwrap();
+    let window_id = display.window.id();
     event_loop.run(move |event, _, control_flow| match event {
-      Event::WindowEvent { ref event, window_id } if window_id == self.window.id() => match event {
+      Event::WindowEvent {
+        ref event,
+        window_id: id,
+      } if id == window_id => match event {
+        // Escape leaves measuring before it quits
+        WindowEvent::KeyboardInput {
+          input:
+            KeyboardInput {
+              state: ElementState::Pressed,
+              virtual_keycode: Some(VirtualKeyCode::Escape),
+              ..
+            },
+          ..
+        } if self.is_measuring() => self.clear_measurement(),
         WindowEvent::CloseRequested
         | WindowEvent::KeyboardInput {
           input:
@@ -284,7 +1810,10 @@ impl Application {
               ..
             },
           ..
-        } => *control_flow = ControlFlow::Exit,
+        } => {
+          self.shutdown();
+          *control_flow = ControlFlow::Exit
+        }
         WindowEvent::Resized(physical_size) => {
           self.resize(*physical_size);
           self.camera.aspect = physical_size.width as f32 / physical_size.height as f32;
@@ -294,14 +1823,17 @@ impl Application {
         }
       },
       Event::RedrawRequested(_) => {
-        if let Some(client) = &self.websocket {
-          while let Ok(_msg) = client.stream().try_next() {
-            println!("WS message received");
-          }
+        if let Err(err) = self.receive_messages() {
+          eprintln!("failed to apply feature update: '{}'", err);
         }
+        self.play_frame();
         self.update();
         match self.render() {
-          Ok(_) => {}
+          Ok(_) => {
+            self.record_frame_metrics();
+            self.capture_gif_frame();
+            self.capture_depth_snapshot();
+          }
           // Reconfigure the surface if lost
           Err(wgpu::SurfaceError::Lost) => self.resize(self.size),
           // The system is out of memory, we should probably quit
@@ -309,13 +1841,180 @@ impl Application {
           // All other errors (Outdated, Timeout) should be resolved by the next frame
           Err(e) => eprintln!("{:?}", e),
         }
+        self.load_dropped_file();
+        self.emit_due_sensor_readings();
+        self.frame_timer.tick();
+        if self.title_updater.tick() {
+          self.update_title();
+          if self.log_stats {
+            let stats = self.total_stats();
+            println!(
+              "draw calls: {}, triangles: {}, instances: {}",
+              stats.draw_calls, stats.triangles, stats.instances
+            );
+          }
+        }
       }
+      Event::UserEvent(command) => command.dispatch_event(&mut self, control_flow),
       Event::MainEventsCleared => {
+        if let Some(frame_limiter) = &mut self.frame_limiter {
+          frame_limiter.wait();
+        }
         // RedrawRequested will only trigger once, unless we manually
         // request it.
-        self.window.request_redraw();
+        if let Some(display) = &self.display {
+          display.window.request_redraw();
+        }
       }
       _ => {}
     });
   }
 }
+
+impl CommandHandler for Application {
+  fn screenshot(&mut self, path: &Path) {
+    if let Err(err) = self.save_screenshot(path) {
+      eprintln!("failed to save screenshot '{}': '{}'", path.display(), err);
+    }
+  }
+
+  fn load_features(&mut self, path: &Path) {
+    let features = FeatureDB::open(path).and_then(|database| database.load_all(None));
+    if let Err(err) = features.and_then(|features| self.apply_feature_update(features)) {
+      eprintln!("failed to load features '{}': '{}'", path.display(), err);
+    }
+  }
+
+  fn shutdown(&mut self) {
+    if self.recorder.is_some() {
+      self.toggle_recording();
+    }
+    self.stop_gif_recording();
+    for save in self.gif_saves.drain(..) {
+      // The worker reports its own failures
+      let _ = save.join();
+    }
+    if self.profiler.is_enabled() {
+      print!("{}", self.profiler.summary());
+    }
+  }
+
+  fn set_fog_density(&mut self, density: f32) {
+    self.fog_density = density;
+    self.feature_renderer.set_fog(density, fog_color(), &self.queue);
+    if let Some(lod_renderer) = &self.lod_renderer {
+      lod_renderer.set_fog(density, fog_color(), &self.queue);
+    }
+    for layer in &self.feature_layers {
+      layer.renderer.set_fog(density, fog_color(), &self.queue);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn run_headless_for_test() {
+    if crate::gfx::renderer::test::headless_device().is_none() {
+      eprintln!("skipping run_headless_for_test: no adapter");
+      return;
+    }
+    let configuration = ApplicationConfiguration {
+      dataset: crate::featuredb::DEFAULT_DATASET.into(),
+      ssao: false,
+      debug_wireframe: false,
+      use_z_prepass: false,
+      position_index: true,
+      invert_y: false,
+      record: None,
+      gif: None,
+      snapshot_depth: None,
+      skybox: None,
+      replay: None,
+      config: None,
+      max_fps: None,
+      feature_display: FeatureDisplay::Meshes,
+      lod_thresholds: None,
+      layers: Vec::new(),
+      fog_density: 0.0,
+      metrics_port: None,
+      profile: false,
+      stats: false,
+      fly_path: None,
+      fly_duration: 10.0,
+      fly_loop: LoopMode::Once,
+    };
+    let database = FeatureDB::in_memory().unwrap();
+    database.insert(vec![Feature::mock()]).unwrap();
+    let size = winit::dpi::PhysicalSize::new(64, 48);
+    let mut application = async_std::task::block_on(Application::headless(configuration, database, size)).unwrap();
+    application.run_headless_for(10).unwrap();
+    assert_eq!(application.feature_renderer.instance_count(), 1);
+    assert_eq!(application.emit_sensor_readings().len(), 1);
+  }
+
+  #[test]
+  fn dropped_file_kind_test() {
+    assert_eq!(
+      DroppedFileKind::classify(Path::new("run.sqlite")),
+      Some(DroppedFileKind::Database)
+    );
+    assert_eq!(
+      DroppedFileKind::classify(Path::new("run.DB")),
+      Some(DroppedFileKind::Database)
+    );
+    assert_eq!(
+      DroppedFileKind::classify(Path::new("a/features.csv")),
+      Some(DroppedFileKind::Csv)
+    );
+    assert_eq!(
+      DroppedFileKind::classify(Path::new("features.json")),
+      Some(DroppedFileKind::Json)
+    );
+    assert_eq!(DroppedFileKind::classify(Path::new("features.pcd")), None);
+    assert_eq!(DroppedFileKind::classify(Path::new("features")), None);
+  }
+
+  #[test]
+  fn drag_drop_test() {
+    let database = FeatureDB::in_memory().unwrap();
+    database.insert(vec![Feature::mock()]).unwrap();
+
+    // A dropped database takes over, switching to its only dataset
+    let path = std::env::temp_dir().join("simulator_application_drag_drop_test.sqlite");
+    let _ = std::fs::remove_file(&path);
+    FeatureDB::open(&path)
+      .unwrap()
+      .insert(
+        (1..=3)
+          .map(|x| {
+            Feature::mock()
+              .with_position((x as f32, 0.0, 0.0))
+              .with_dataset("lidar")
+          })
+          .collect(),
+      )
+      .unwrap();
+    let kind = DroppedFileKind::classify(&path).unwrap();
+    let dropped = kind.load(&path, &database, crate::featuredb::DEFAULT_DATASET).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let loaded = dropped.database.unwrap();
+    assert_eq!(dropped.dataset, "lidar");
+    assert_eq!(dropped.features.len(), 3);
+    assert_eq!(database.count(None).unwrap(), 1);
+
+    // A dropped CSV file is upserted into the current database, replacing the feature sharing id 1
+    let path = std::env::temp_dir().join("simulator_application_drag_drop_test.csv");
+    loaded.export_csv(&path).unwrap();
+    let dropped = DroppedFileKind::Csv.load(&path, &database, "lidar").unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(dropped.database.is_none());
+    assert_eq!(dropped.dataset, "lidar");
+    assert_eq!(dropped.features.len(), 3);
+    assert_eq!(database.count(None).unwrap(), 3);
+
+    assert!(DroppedFileKind::Json.load(&path, &database, "lidar").is_err());
+  }
+}
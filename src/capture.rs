@@ -0,0 +1,361 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Size GIF frames are rendered at, small enough to quantize every frame while recording
+pub const GIF_WIDTH: u32 = 320;
+pub const GIF_HEIGHT: u32 = 240;
+
+/// Recordings stop by themselves after this many seconds.
+pub const MAX_GIF_DURATION: f32 = 10.0;
+
+/// One frame reduced to at most 256 colors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GifFrame {
+  /// Packed RGB triples
+  pub palette: Vec<u8>,
+  /// Palette index of every pixel, row by row
+  pub indices: Vec<u8>,
+}
+
+impl GifFrame {
+  /// Quantizes tightly packed RGBA8 rows to a median cut palette with Floyd–Steinberg dithering. Alpha is ignored.
+  pub fn quantize(rgba: &[u8], width: u32, height: u32) -> Self {
+    let pixels: Vec<[u8; 3]> = rgba
+      .chunks_exact(4)
+      .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+      .collect();
+    let palette = median_cut(&pixels, 256);
+    let indices = dither(&pixels, width as usize, height as usize, &palette);
+    Self {
+      palette: palette.iter().flatten().copied().collect(),
+      indices,
+    }
+  }
+}
+
+/// Frames captured at a fixed rate for an animated GIF. Frames are quantized and the file is written on a worker
+/// thread, so capturing only costs the render thread the readback.
+pub struct GifRecording {
+  fps: f32,
+  /// Frames handed to the worker so far
+  frame_count: usize,
+  next_capture: Instant,
+  sender: Sender<Vec<u8>>,
+  worker: JoinHandle<()>,
+}
+
+impl GifRecording {
+  pub fn new(path: PathBuf, fps: f32) -> Self {
+    let fps = fps.max(1.0);
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    let worker = std::thread::spawn(move || {
+      let frames: Vec<GifFrame> = receiver
+        .iter()
+        .map(|rgba| GifFrame::quantize(&rgba, GIF_WIDTH, GIF_HEIGHT))
+        .collect();
+      if let Err(err) = save(&path, &frames, fps) {
+        eprintln!("failed to save GIF '{}': '{}'", path.display(), err);
+      }
+    });
+    Self {
+      fps,
+      frame_count: 0,
+      next_capture: Instant::now(),
+      sender,
+      worker,
+    }
+  }
+
+  /// Whether a frame is due at `now`, scheduling the next one if so. Frames are skipped rather than caught up when
+  /// rendering falls behind.
+  pub fn frame_due(&mut self, now: Instant) -> bool {
+    if now < self.next_capture {
+      return false;
+    }
+    let interval = Duration::from_secs_f32(1.0 / self.fps);
+    self.next_capture += interval;
+    if self.next_capture <= now {
+      self.next_capture = now + interval;
+    }
+    true
+  }
+
+  /// Hands a `GIF_WIDTH`×`GIF_HEIGHT` frame of tightly packed RGBA8 rows to the worker.
+  pub fn push(&mut self, rgba: Vec<u8>) {
+    // A worker that went away has nothing left to save
+    let _ = self.sender.send(rgba);
+    self.frame_count += 1;
+  }
+
+  /// Whether the recording has reached `MAX_GIF_DURATION`.
+  pub fn is_full(&self) -> bool {
+    self.frame_count as f32 >= self.fps * MAX_GIF_DURATION
+  }
+
+  /// Stops the recording. The worker quantizes the frames still queued and saves the file, looping forever, in the
+  /// background. Join the returned handle to wait for the file, which is needed before exiting. Failures are
+  /// reported by the worker.
+  pub fn finish(self) -> JoinHandle<()> {
+    drop(self.sender);
+    self.worker
+  }
+}
+
+/// Encodes `frames` to a new GIF file at `path`.
+fn save(path: &Path, frames: &[GifFrame], fps: f32) -> Result<(), gif::EncodingError> {
+  let mut writer = BufWriter::new(File::create(path)?);
+  encode_gif(&mut writer, frames, GIF_WIDTH, GIF_HEIGHT, fps)?;
+  writer.flush()?;
+  Ok(())
+}
+
+/// Writes `frames` of `width`×`height` as a looping GIF, each shown for `1 / fps` seconds.
+pub fn encode_gif<W: Write>(
+  writer: W,
+  frames: &[GifFrame],
+  width: u32,
+  height: u32,
+  fps: f32,
+) -> Result<(), gif::EncodingError> {
+  let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &[])?;
+  encoder.set_repeat(gif::Repeat::Infinite)?;
+  // GIF delays are in hundredths of a second
+  let delay = (100.0 / fps).round() as u16;
+  for frame in frames {
+    encoder.write_frame(&gif::Frame {
+      delay,
+      width: width as u16,
+      height: height as u16,
+      palette: Some(frame.palette.clone()),
+      buffer: Cow::Borrowed(&frame.indices),
+      ..gif::Frame::default()
+    })?;
+  }
+  Ok(())
+}
+
+/// Pixels of one median cut box, with its widest channel found once when the box is made.
+struct ColorBox {
+  pixels: Vec<[u8; 3]>,
+  channel: usize,
+  range: u8,
+}
+
+impl ColorBox {
+  fn new(pixels: Vec<[u8; 3]>) -> Self {
+    let (mut min, mut max) = ([255u8; 3], [0u8; 3]);
+    for pixel in &pixels {
+      for channel in 0..3 {
+        min[channel] = min[channel].min(pixel[channel]);
+        max[channel] = max[channel].max(pixel[channel]);
+      }
+    }
+    let (channel, range) = (0..3)
+      .map(|channel| (channel, max[channel].saturating_sub(min[channel])))
+      .max_by_key(|&(_, range)| range)
+      .unwrap();
+    Self { pixels, channel, range }
+  }
+
+  /// Halves at the median of the widest channel.
+  fn split(mut self) -> (Self, Self) {
+    let channel = self.channel;
+    let middle = self.pixels.len() / 2;
+    self.pixels.select_nth_unstable_by_key(middle, |pixel| pixel[channel]);
+    let upper = self.pixels.split_off(middle);
+    (Self::new(self.pixels), Self::new(upper))
+  }
+}
+
+/// Palette of at most `colors` averages of boxes found by repeatedly splitting the box with the widest channel at
+/// its median.
+fn median_cut(pixels: &[[u8; 3]], colors: usize) -> Vec<[u8; 3]> {
+  if pixels.is_empty() {
+    return vec![[0, 0, 0]];
+  }
+  let mut boxes = vec![ColorBox::new(pixels.to_vec())];
+  while boxes.len() < colors {
+    let (idx, widest) = boxes
+      .iter()
+      .enumerate()
+      .max_by_key(|(_, color_box)| color_box.range)
+      .unwrap();
+    if widest.range == 0 {
+      break;
+    }
+    let (lower, upper) = boxes.swap_remove(idx).split();
+    boxes.push(lower);
+    boxes.push(upper);
+  }
+  boxes
+    .iter()
+    .map(|ColorBox { pixels, .. }| {
+      let mut sum = [0u64; 3];
+      for pixel in pixels {
+        for channel in 0..3 {
+          sum[channel] += pixel[channel] as u64;
+        }
+      }
+      let count = pixels.len() as u64;
+      [(sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8]
+    })
+    .collect()
+}
+
+/// Maps every pixel to its nearest palette color, spreading the error onto the neighbours not yet mapped.
+fn dither(pixels: &[[u8; 3]], width: usize, height: usize, palette: &[[u8; 3]]) -> Vec<u8> {
+  let mut error = vec![[0.0f32; 3]; pixels.len()];
+  let mut nearest_cache: HashMap<[u8; 3], u8> = HashMap::new();
+  let mut indices = Vec::with_capacity(pixels.len());
+  for y in 0..height {
+    for x in 0..width {
+      let i = y * width + x;
+      let mut color = [0u8; 3];
+      for channel in 0..3 {
+        color[channel] = (pixels[i][channel] as f32 + error[i][channel])
+          .round()
+          .clamp(0.0, 255.0) as u8;
+      }
+      let index = *nearest_cache.entry(color).or_insert_with(|| nearest(palette, color));
+      indices.push(index);
+
+      let chosen = palette[index as usize];
+      let mut spread = |dx: isize, dy: usize, weight: f32| {
+        let nx = x as isize + dx;
+        if nx < 0 || nx >= width as isize || y + dy >= height {
+          return;
+        }
+        let j = (y + dy) * width + nx as usize;
+        for channel in 0..3 {
+          error[j][channel] += (color[channel] as f32 - chosen[channel] as f32) * weight;
+        }
+      };
+      spread(1, 0, 7.0 / 16.0);
+      spread(-1, 1, 3.0 / 16.0);
+      spread(0, 1, 5.0 / 16.0);
+      spread(1, 1, 1.0 / 16.0);
+    }
+  }
+  indices
+}
+
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+  let distance = |entry: &[u8; 3]| -> i32 {
+    (0..3)
+      .map(|channel| {
+        let d = entry[channel] as i32 - color[channel] as i32;
+        d * d
+      })
+      .sum()
+  };
+  palette
+    .iter()
+    .enumerate()
+    .min_by_key(|(_, entry)| distance(entry))
+    .map(|(idx, _)| idx as u8)
+    .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn median_cut_test() {
+    let pixels = [[0, 0, 0], [10, 0, 0], [200, 0, 0], [210, 0, 0]];
+    let mut palette = median_cut(&pixels, 2);
+    palette.sort_unstable();
+    assert_eq!(palette, vec![[5, 0, 0], [205, 0, 0]]);
+    // Never more colors than distinct pixels
+    assert_eq!(median_cut(&[[7, 8, 9]; 5], 256), vec![[7, 8, 9]]);
+    assert_eq!(median_cut(&pixels, 256).len(), 4);
+  }
+
+  #[test]
+  fn quantize_test() {
+    // A gradient with more colors than fit in a palette
+    let (width, height) = (64, 64);
+    let rgba: Vec<u8> = (0..width * height)
+      .flat_map(|i| vec![(i % width * 4) as u8, (i / width * 4) as u8, 128, 255])
+      .collect();
+    let frame = GifFrame::quantize(&rgba, width, height);
+    assert_eq!(frame.palette.len(), 256 * 3);
+    assert_eq!(frame.indices.len(), (width * height) as usize);
+    // Dithering keeps the average color
+    let mean_red = frame
+      .indices
+      .iter()
+      .map(|&idx| frame.palette[idx as usize * 3] as f32)
+      .sum::<f32>()
+      / frame.indices.len() as f32;
+    assert!((mean_red - 126.0).abs() < 2.0, "{}", mean_red);
+  }
+
+  #[test]
+  fn encode_gif_test() {
+    let (width, height) = (8, 6);
+    let frames: Vec<GifFrame> = (0..3)
+      .map(|i| {
+        let rgba: Vec<u8> = (0..width * height)
+          .flat_map(|p| vec![(p * 5) as u8, i * 80, 0, 255])
+          .collect();
+        GifFrame::quantize(&rgba, width, height)
+      })
+      .collect();
+    let mut gif = Vec::new();
+    encode_gif(&mut gif, &frames, width, height, 10.0).unwrap();
+    assert!(gif.starts_with(b"GIF89a"));
+
+    let mut decoder = gif::DecodeOptions::new();
+    decoder.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = decoder.read_info(gif.as_slice()).unwrap();
+    let mut decoded = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+      assert_eq!(frame.delay, 10);
+      assert_eq!(frame.buffer.as_ref(), frames[decoded].indices.as_slice());
+      decoded += 1;
+    }
+    assert_eq!(decoded, 3);
+  }
+
+  #[test]
+  fn frame_due_test() {
+    let mut recording = GifRecording::new("unused.gif".into(), 10.0);
+    let start = Instant::now();
+    assert!(recording.frame_due(start));
+    assert!(!recording.frame_due(start + Duration::from_millis(50)));
+    assert!(recording.frame_due(start + Duration::from_millis(100)));
+    // Falling behind skips frames instead of bursting
+    assert!(recording.frame_due(start + Duration::from_secs(1)));
+    assert!(!recording.frame_due(start + Duration::from_millis(1050)));
+    for _ in 0..(10.0 * MAX_GIF_DURATION) as usize {
+      assert!(!recording.is_full());
+      recording.push(Vec::new());
+    }
+    assert!(recording.is_full());
+  }
+
+  #[test]
+  fn recording_save_test() {
+    let path = std::env::temp_dir().join("simulator_capture_recording_save_test.gif");
+    let mut recording = GifRecording::new(path.clone(), 10.0);
+    for i in 0..2u8 {
+      recording.push(vec![i * 100; (GIF_WIDTH * GIF_HEIGHT * 4) as usize]);
+    }
+    recording.finish().join().unwrap();
+    let gif = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut decoder = gif::DecodeOptions::new().read_info(gif.as_slice()).unwrap();
+    let mut decoded = 0;
+    while decoder.read_next_frame().unwrap().is_some() {
+      decoded += 1;
+    }
+    assert_eq!(decoded, 2);
+  }
+}
@@ -1,9 +1,16 @@
-use super::featuredb::{Feature, FeatureDB};
+use super::application::ApplicationConfiguration;
+use super::featuredb::{Feature, FeatureDB, FeatureStats, DEFAULT_DATASET};
+use super::gfx::camera::LoopMode;
+use super::gfx::renderer::{FeatureDisplay, LodFeatureRenderer};
+use super::pointcloud::PointCloudWriter;
 
 use cgmath::Vector3;
 use clap::{App, Arg};
 use rand_distr::{Distribution, Normal, Uniform};
 
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
 fn rand_f32_tuple3(dist: &impl Distribution<f32>) -> (f32, f32, f32) {
   (
     dist.sample(&mut rand::thread_rng()),
@@ -12,20 +19,244 @@ fn rand_f32_tuple3(dist: &impl Distribution<f32>) -> (f32, f32, f32) {
   )
 }
 
+/// Features with normally distributed positions around the origin and random colors.
+pub fn generate_random(n: u32) -> Vec<Feature> {
+  let mean = Normal::new(0.0, 3.0).unwrap();
+  let variance = Normal::new(1.0, 0.1).unwrap();
+  let uniform = Uniform::from(0..255);
+  (0..n)
+    .map(|_| Feature {
+      id: 0,
+      n: 1,
+      age: 0,
+      color: (
+        uniform.sample(&mut rand::thread_rng()),
+        uniform.sample(&mut rand::thread_rng()),
+        uniform.sample(&mut rand::thread_rng()),
+      )
+        .into(),
+      position_mean: rand_f32_tuple3(&mean).into(),
+      position_deviation: Vector3::from(rand_f32_tuple3(&variance)).map(|x| x.abs()),
+      orientation_mean: (0.0, 0.0, 1.0).into(),
+      orientation_deviation: 0.0,
+      radius_mean: 1.0,
+      radius_deviation: 0.1,
+      material: 255,
+      dataset: DEFAULT_DATASET.into(),
+    })
+    .collect()
+}
+
+/// Number of features in an `n`×`n`×`n` grid, or `None` if there are more than feature ids can count.
+fn grid_count(n: u32) -> Option<u32> {
+  n.checked_mul(n)?.checked_mul(n)
+}
+
+/// `n`×`n`×`n` features with unit spacing centered on the origin, colored by grid coordinate. Panics if `grid_count`
+/// is `None`.
+pub fn generate_grid(n: u32) -> Vec<Feature> {
+  let count = grid_count(n).expect("grid to have at most u32::MAX features");
+  let offset = (n as f32 - 1.0) / 2.0;
+  let shade = |i: u32| (u64::from(i) * 255 / u64::from(n.saturating_sub(1).max(1))) as u8;
+  let mut features = Vec::with_capacity(count as usize);
+  for x in 0..n {
+    for y in 0..n {
+      for z in 0..n {
+        features.push(Feature {
+          id: 0,
+          n: 1,
+          age: 0,
+          color: (shade(x), shade(y), shade(z)).into(),
+          position_mean: (x as f32 - offset, y as f32 - offset, z as f32 - offset).into(),
+          position_deviation: (0.0, 0.0, 0.0).into(),
+          orientation_mean: (0.0, 0.0, 0.0).into(),
+          orientation_deviation: 0.0,
+          radius_mean: 0.25,
+          radius_deviation: 0.0,
+          material: 255,
+          dataset: DEFAULT_DATASET.into(),
+        });
+      }
+    }
+  }
+  features
+}
+
+/// Value below which `p` percent of the ascending `samples` fall, using the nearest rank.
+fn percentile(samples: &[Duration], p: f64) -> Duration {
+  let rank = (p / 100.0 * samples.len() as f64).ceil() as usize;
+  samples[rank.clamp(1, samples.len()) - 1]
+}
+
+fn throughput(count: usize, elapsed: Duration) -> f64 {
+  count as f64 / elapsed.as_secs_f64()
+}
+
+/// Times inserting and loading back `n` random features and 100 `find_nearest` queries in a scratch database,
+/// returning the report.
+pub fn benchmark_db(n: u32) -> Result<String, String> {
+  const QUERIES: usize = 100;
+  let path = std::env::temp_dir().join("simulator_benchmark.sqlite");
+  let _ = std::fs::remove_file(&path);
+  let database = FeatureDB::open(&path).map_err(|err| format!("failed to create benchmark database: '{}'", err))?;
+  let features = generate_random(n);
+
+  let start = Instant::now();
+  database
+    .insert(features)
+    .map_err(|err| format!("failed to insert features: '{}'", err))?;
+  let insert = start.elapsed();
+
+  let start = Instant::now();
+  let loaded = database
+    .load_all(None)
+    .map_err(|err| format!("failed to load features: '{}'", err))?;
+  let load = start.elapsed();
+
+  let mut queries = Vec::with_capacity(QUERIES);
+  for query in generate_random(QUERIES as u32) {
+    let start = Instant::now();
+    database
+      .find_nearest(query.position_mean, None)
+      .map_err(|err| format!("failed to find nearest feature: '{}'", err))?;
+    queries.push(start.elapsed());
+  }
+  queries.sort();
+  drop(database);
+  let _ = std::fs::remove_file(&path);
+
+  let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+  Ok(format!(
+    "insert:       {} features in {:.3}s ({:.0} features/s)\n\
+     load_all:     {} features in {:.3}s ({:.0} features/s)\n\
+     find_nearest: {} queries, p50 {:.3}ms, p95 {:.3}ms, p99 {:.3}ms",
+    n,
+    insert.as_secs_f64(),
+    throughput(n as usize, insert),
+    loaded.len(),
+    load.as_secs_f64(),
+    throughput(loaded.len(), load),
+    QUERIES,
+    millis(percentile(&queries, 50.0)),
+    millis(percentile(&queries, 95.0)),
+    millis(percentile(&queries, 99.0)),
+  ))
+}
+
+fn format_vector(vector: Vector3<f32>) -> String {
+  format!("({:.3}, {:.3}, {:.3})", vector.x, vector.y, vector.z)
+}
+
+/// Lays out `stats` as a two column ASCII table, one row per statistic and material.
+pub fn stats_table(stats: &FeatureStats) -> String {
+  let mut rows = vec![
+    ("features".to_owned(), stats.count.to_string()),
+    ("centroid".into(), format_vector(stats.position_centroid)),
+    ("position stddev".into(), format_vector(stats.position_stddev)),
+    ("bounds min".into(), format_vector(stats.position_min)),
+    ("bounds max".into(), format_vector(stats.position_max)),
+    (
+      "radius".into(),
+      format!("{:.3} ± {:.3}", stats.radius_mean, stats.radius_stddev),
+    ),
+    ("newest age".into(), stats.age_min.to_string()),
+    ("oldest age".into(), stats.age_max.to_string()),
+  ];
+  rows.extend(
+    stats
+      .materials
+      .iter()
+      .map(|(material, count)| (format!("material {}", material), count.to_string())),
+  );
+  let width =
+    |column: fn(&(String, String)) -> &String| rows.iter().map(|row| column(row).chars().count()).max().unwrap_or(0);
+  let (key_width, value_width) = (width(|row| &row.0), width(|row| &row.1));
+  let border = format!("+-{}-+-{}-+", "-".repeat(key_width), "-".repeat(value_width));
+  let mut table = border.clone();
+  for (key, value) in &rows {
+    // Padded by hand since `format!` widths count bytes of the non-ASCII ±
+    table.push_str(&format!(
+      "\n| {}{} | {}{} |",
+      key,
+      " ".repeat(key_width - key.chars().count()),
+      value,
+      " ".repeat(value_width - value.chars().count()),
+    ));
+  }
+  table.push('\n');
+  table.push_str(&border);
+  table
+}
+
+/// `stats` as a JSON object for scripts, with vectors as `[x, y, z]` arrays.
+pub fn stats_json(stats: &FeatureStats) -> String {
+  let vector = |vector: Vector3<f32>| [vector.x, vector.y, vector.z];
+  serde_json::json!({
+    "count": stats.count,
+    "centroid": vector(stats.position_centroid),
+    "position_stddev": vector(stats.position_stddev),
+    "bounds_min": vector(stats.position_min),
+    "bounds_max": vector(stats.position_max),
+    "radius_mean": stats.radius_mean,
+    "radius_stddev": stats.radius_stddev,
+    "age_min": stats.age_min,
+    "age_max": stats.age_max,
+    "materials": stats
+      .materials
+      .iter()
+      .map(|&(material, count)| serde_json::json!({ "material": material, "count": count }))
+      .collect::<Vec<_>>(),
+  })
+  .to_string()
+}
+
 pub struct Cli {
   generate: Option<String>,
   clear: bool,
+  dataset: String,
+  ssao: bool,
+  debug_wireframe: bool,
+  z_prepass: bool,
+  position_index: bool,
+  invert_y: bool,
+  record: Option<PathBuf>,
+  gif: Option<PathBuf>,
+  snapshot_depth: Option<PathBuf>,
+  skybox: Option<PathBuf>,
+  replay: Option<PathBuf>,
+  export_pcd: Option<PathBuf>,
+  export_ply: Option<PathBuf>,
+  config: Option<PathBuf>,
+  max_fps: Option<f32>,
+  lod_near: Option<f32>,
+  lod_far: Option<f32>,
+  render_mode: FeatureDisplay,
+  layers: Vec<(String, PathBuf)>,
+  fog_density: f32,
+  benchmark_db: Option<u32>,
+  deduplicate: Option<f32>,
+  db_stats: bool,
+  json_stats: bool,
+  metrics_port: Option<u16>,
+  profile: bool,
+  stats: bool,
+  fly_path: Option<PathBuf>,
+  fly_duration: f32,
+  fly_loop: LoopMode,
+  #[cfg(feature = "headless")]
+  headless: Option<u32>,
 }
 
 impl Cli {
   pub fn new() -> Self {
-    let matches = App::new("Lawny Simulator")
+    let app = App::new("Lawny Simulator")
       .arg(
         Arg::with_name("generate")
           .short("g")
           .long("generate")
           .takes_value(true)
-          .help("Generate database"),
+          .value_name("random[:N]|grid:N")
+          .help("Generate N random features (default 100) or an N×N×N grid of features"),
       )
       .arg(
         Arg::with_name("clear")
@@ -34,13 +265,353 @@ impl Cli {
           .takes_value(false)
           .help("Clears database"),
       )
-      .get_matches();
+      .arg(
+        Arg::with_name("dataset")
+          .short("d")
+          .long("dataset")
+          .takes_value(true)
+          .default_value(DEFAULT_DATASET)
+          .help("Dataset to generate into and display"),
+      )
+      .arg(
+        Arg::with_name("ssao")
+          .long("ssao")
+          .takes_value(false)
+          .help("Enables screen-space ambient occlusion, in builds with the ssao feature"),
+      )
+      .arg(
+        Arg::with_name("debug-wireframe")
+          .long("debug-wireframe")
+          .takes_value(false)
+          .help("Draws a wireframe of the UV sphere mesh at the origin"),
+      )
+      .arg(
+        Arg::with_name("z-prepass")
+          .long("z-prepass")
+          .takes_value(false)
+          .help("Renders feature depth in a separate pass first to reduce overdraw"),
+      )
+      .arg(
+        Arg::with_name("no-position-index")
+          .long("no-position-index")
+          .help("Drops the feature position index, which speeds up inserts at the cost of region queries"),
+      )
+      .arg(
+        Arg::with_name("invert-y")
+          .long("invert-y")
+          .help("Tilts the camera down when the mouse moves up; toggled with I"),
+      )
+      .arg(
+        Arg::with_name("record")
+          .long("record")
+          .takes_value(true)
+          .value_name("FILE")
+          .help("Records feature snapshots to FILE while toggled on with R"),
+      )
+      .arg(
+        Arg::with_name("gif")
+          .long("gif")
+          .takes_value(true)
+          .value_name("FILE")
+          .help("Records the view to an animated GIF FILE while toggled on with G, for at most 10 seconds"),
+      )
+      .arg(
+        Arg::with_name("snapshot-depth")
+          .long("snapshot-depth")
+          .takes_value(true)
+          .value_name("FILE")
+          .help("Writes the distance to the first rendered frame's surfaces to FILE as little-endian f32 rows"),
+      )
+      .arg(
+        Arg::with_name("skybox")
+          .long("skybox")
+          .takes_value(true)
+          .value_name("DIR")
+          .help("Draws the cubemap in px.png, nx.png, py.png, ny.png, pz.png and nz.png in DIR as the background"),
+      )
+      .arg(
+        Arg::with_name("replay")
+          .long("replay")
+          .takes_value(true)
+          .value_name("FILE")
+          .help("Plays back a recording, paused and resumed with Space"),
+      )
+      .arg(
+        Arg::with_name("export-pcd")
+          .long("export-pcd")
+          .takes_value(true)
+          .value_name("FILE")
+          .help("Exports features as an ASCII PCD point cloud"),
+      )
+      .arg(
+        Arg::with_name("export-ply")
+          .long("export-ply")
+          .takes_value(true)
+          .value_name("FILE")
+          .help("Exports features as a binary PLY point cloud"),
+      )
+      .arg(
+        Arg::with_name("config")
+          .long("config")
+          .takes_value(true)
+          .value_name("FILE")
+          .help("Reads [camera], [simulation] and [rendering] settings from a TOML file"),
+      )
+      .arg(
+        Arg::with_name("max-fps")
+          .long("max-fps")
+          .takes_value(true)
+          .value_name("FPS")
+          .validator(|fps| match fps.parse::<f32>() {
+            Ok(fps) if fps > 0.0 => Ok(()),
+            _ => Err(format!("invalid frame rate '{}'", fps)),
+          })
+          .help("Sleeps between frames to stay at or below FPS frames per second"),
+      )
+      .arg(
+        Arg::with_name("lod-near")
+          .long("lod-near")
+          .takes_value(true)
+          .value_name("METERS")
+          .validator(|distance| match distance.parse::<f32>() {
+            Ok(distance) if distance >= 0.0 => Ok(()),
+            _ => Err(format!("invalid distance '{}'", distance)),
+          })
+          .help("Draws features closer than METERS with full detail spheres and coarser ones beyond, 5 by default"),
+      )
+      .arg(
+        Arg::with_name("lod-far")
+          .long("lod-far")
+          .takes_value(true)
+          .value_name("METERS")
+          .validator(|distance| match distance.parse::<f32>() {
+            Ok(distance) if distance >= 0.0 => Ok(()),
+            _ => Err(format!("invalid distance '{}'", distance)),
+          })
+          .help("Draws features farther than METERS with the coarsest spheres, 20 by default"),
+      )
+      .arg(
+        Arg::with_name("render-mode")
+          .long("render-mode")
+          .takes_value(true)
+          .value_name("MODE")
+          .default_value("meshes")
+          .validator(|mode| mode.parse::<FeatureDisplay>().map(|_| ()))
+          .help("Draws features as meshes or as a point cloud of one dot each: meshes or points"),
+      )
+      .arg(
+        Arg::with_name("layer")
+          .long("layer")
+          .takes_value(true)
+          .multiple(true)
+          .number_of_values(1)
+          .value_name("NAME:FILE")
+          .validator(|layer| match layer.split_once(':') {
+            Some((name, path)) if !name.is_empty() && !path.is_empty() => Ok(()),
+            _ => Err(format!("invalid layer '{}', expected NAME:FILE", layer)),
+          })
+          .help("Also draws the features in the database FILE, toggled with 1-4 in the order given"),
+      )
+      .arg(
+        Arg::with_name("fog-density")
+          .long("fog-density")
+          .takes_value(true)
+          .value_name("DENSITY")
+          .default_value("0")
+          .validator(|density| match density.parse::<f32>() {
+            Ok(density) if density >= 0.0 => Ok(()),
+            _ => Err(format!("invalid fog density '{}'", density)),
+          })
+          .help("Fades features into the background with distance, 0 for no fog"),
+      )
+      .arg(
+        Arg::with_name("benchmark-db")
+          .long("benchmark-db")
+          .takes_value(true)
+          .value_name("N")
+          .validator(|count| {
+            count
+              .parse::<u32>()
+              .map(|_| ())
+              .map_err(|_| format!("invalid feature count '{}'", count))
+          })
+          .help("Times inserting, loading and querying N features in a scratch database"),
+      )
+      .arg(
+        Arg::with_name("db-stats")
+          .long("db-stats")
+          .help("Prints a table of feature count, centroid, bounds, radius, age and materials in the database"),
+      )
+      .arg(
+        Arg::with_name("json-stats")
+          .long("json-stats")
+          .help("Prints the --db-stats statistics as JSON instead"),
+      )
+      .arg(
+        Arg::with_name("deduplicate")
+          .long("deduplicate")
+          .takes_value(true)
+          .value_name("EPSILON")
+          .validator(|epsilon| match epsilon.parse::<f32>() {
+            Ok(epsilon) if epsilon > 0.0 => Ok(()),
+            _ => Err(format!("invalid distance '{}'", epsilon)),
+          })
+          .help("Merges features closer than EPSILON meters to each other into the better observed one"),
+      )
+      .arg(
+        Arg::with_name("metrics-port")
+          .long("metrics-port")
+          .takes_value(true)
+          .value_name("PORT")
+          .validator(|port| {
+            port
+              .parse::<u16>()
+              .map(|_| ())
+              .map_err(|_| format!("invalid port '{}'", port))
+          })
+          .help("Serves Prometheus metrics at http://0.0.0.0:PORT/metrics"),
+      )
+      .arg(
+        Arg::with_name("profile")
+          .long("profile")
+          .help("Times each render pass on the GPU and prints a summary on exit"),
+      )
+      .arg(
+        Arg::with_name("stats")
+          .long("stats")
+          .help("Prints renderer draw calls, triangles and instances every 30 frames"),
+      )
+      .arg(
+        Arg::with_name("fly-path")
+          .long("fly-path")
+          .takes_value(true)
+          .value_name("FILE")
+          .help(
+            "Flies the camera through the JSON array of {\"eye\": [x, y, z], \"target\": [x, y, z]} waypoints in FILE",
+          ),
+      )
+      .arg(
+        Arg::with_name("fly-duration")
+          .long("fly-duration")
+          .takes_value(true)
+          .value_name("SECONDS")
+          .default_value("10")
+          .validator(|duration| match duration.parse::<f32>() {
+            Ok(duration) if duration >= 0.0 => Ok(()),
+            _ => Err(format!("invalid duration '{}'", duration)),
+          })
+          .help("Time the --fly-path takes from the first waypoint to the last"),
+      )
+      .arg(
+        Arg::with_name("fly-loop")
+          .long("fly-loop")
+          .takes_value(true)
+          .value_name("MODE")
+          .default_value("once")
+          .validator(|mode| mode.parse::<LoopMode>().map(|_| ()))
+          .help("What the --fly-path does at its last waypoint: once, loop or ping-pong"),
+      );
+    #[cfg(feature = "headless")]
+    let app = app.arg(
+      Arg::with_name("headless")
+        .long("headless")
+        .takes_value(true)
+        .value_name("FRAMES")
+        .validator(|frames| {
+          frames
+            .parse::<u32>()
+            .map(|_| ())
+            .map_err(|_| format!("invalid frame count '{}'", frames))
+        })
+        .help("Runs FRAMES frames offscreen without opening a window, then exits"),
+    );
+    let matches = app.get_matches();
     Cli {
       generate: matches.value_of("generate").map(|x| x.into()),
       clear: matches.is_present("clear"),
+      dataset: matches.value_of("dataset").unwrap().into(),
+      ssao: matches.is_present("ssao"),
+      debug_wireframe: matches.is_present("debug-wireframe"),
+      z_prepass: matches.is_present("z-prepass"),
+      position_index: !matches.is_present("no-position-index"),
+      invert_y: matches.is_present("invert-y"),
+      record: matches.value_of("record").map(PathBuf::from),
+      gif: matches.value_of("gif").map(PathBuf::from),
+      snapshot_depth: matches.value_of("snapshot-depth").map(PathBuf::from),
+      skybox: matches.value_of("skybox").map(PathBuf::from),
+      replay: matches.value_of("replay").map(PathBuf::from),
+      export_pcd: matches.value_of("export-pcd").map(PathBuf::from),
+      export_ply: matches.value_of("export-ply").map(PathBuf::from),
+      config: matches.value_of("config").map(PathBuf::from),
+      max_fps: matches.value_of("max-fps").map(|fps| fps.parse().unwrap()),
+      lod_near: matches.value_of("lod-near").map(|distance| distance.parse().unwrap()),
+      lod_far: matches.value_of("lod-far").map(|distance| distance.parse().unwrap()),
+      render_mode: matches.value_of("render-mode").unwrap().parse().unwrap(),
+      fog_density: matches.value_of("fog-density").unwrap().parse().unwrap(),
+      benchmark_db: matches.value_of("benchmark-db").map(|count| count.parse().unwrap()),
+      deduplicate: matches.value_of("deduplicate").map(|epsilon| epsilon.parse().unwrap()),
+      db_stats: matches.is_present("db-stats"),
+      json_stats: matches.is_present("json-stats"),
+      metrics_port: matches.value_of("metrics-port").map(|port| port.parse().unwrap()),
+      profile: matches.is_present("profile"),
+      stats: matches.is_present("stats"),
+      fly_path: matches.value_of("fly-path").map(PathBuf::from),
+      fly_duration: matches.value_of("fly-duration").unwrap().parse().unwrap(),
+      fly_loop: matches.value_of("fly-loop").unwrap().parse().unwrap(),
+      #[cfg(feature = "headless")]
+      headless: matches.value_of("headless").map(|frames| frames.parse().unwrap()),
+      layers: matches
+        .values_of("layer")
+        .into_iter()
+        .flatten()
+        .map(|layer| {
+          let (name, path) = layer.split_once(':').unwrap();
+          (name.into(), PathBuf::from(path))
+        })
+        .collect(),
     }
   }
 
+  pub fn configuration(&self) -> ApplicationConfiguration {
+    ApplicationConfiguration {
+      dataset: self.dataset.clone(),
+      ssao: self.ssao,
+      debug_wireframe: self.debug_wireframe,
+      use_z_prepass: self.z_prepass,
+      position_index: self.position_index,
+      invert_y: self.invert_y,
+      record: self.record.clone(),
+      gif: self.gif.clone(),
+      snapshot_depth: self.snapshot_depth.clone(),
+      skybox: self.skybox.clone(),
+      replay: self.replay.clone(),
+      config: self.config.clone(),
+      max_fps: self.max_fps,
+      feature_display: self.render_mode,
+      lod_thresholds: match (self.lod_near, self.lod_far) {
+        (None, None) => None,
+        (near, far) => Some((
+          near.unwrap_or(LodFeatureRenderer::DEFAULT_NEAR),
+          far.unwrap_or(LodFeatureRenderer::DEFAULT_FAR),
+        )),
+      },
+      layers: self.layers.clone(),
+      fog_density: self.fog_density,
+      metrics_port: self.metrics_port,
+      profile: self.profile,
+      stats: self.stats,
+      fly_path: self.fly_path.clone(),
+      fly_duration: self.fly_duration,
+      fly_loop: self.fly_loop,
+    }
+  }
+
+  /// Frames to run offscreen with `--headless` in place of opening the window.
+  #[cfg(feature = "headless")]
+  pub fn headless_frames(&self) -> Option<u32> {
+    self.headless
+  }
+
   pub fn run(&self) -> Result<bool, String> {
     let mut cli_mode = false;
     let database = FeatureDB::new().map_err(|_| "failed to load feature database".to_owned())?;
@@ -51,37 +622,158 @@ impl Cli {
       cli_mode = true;
     }
     if let Some(generate) = &self.generate {
-      if generate == "random" {
-        let mean = Normal::new(0.0, 3.0).unwrap();
-        let variance = Normal::new(1.0, 0.1).unwrap();
-        let uniform = Uniform::from(0..255);
-        let features = (0..100)
-          .map(|_| Feature {
-            id: 0,
-            n: 1,
-            age: 0,
-            color: (
-              uniform.sample(&mut rand::thread_rng()),
-              uniform.sample(&mut rand::thread_rng()),
-              uniform.sample(&mut rand::thread_rng()),
-            )
-              .into(),
-            position_mean: rand_f32_tuple3(&mean).into(),
-            position_deviation: Vector3::from(rand_f32_tuple3(&variance)).map(|x| x.abs()),
-            orientation_mean: (0.0, 0.0, 1.0).into(),
-            orientation_deviation: 0.0,
-            radius_mean: 1.0,
-            radius_deviation: 0.1,
-            material: 255
-          })
-          .collect();
-        database.insert(features).unwrap();
-        cli_mode = true;
+      let (kind, count) = match generate.split_once(':') {
+        Some((kind, count)) => (
+          kind,
+          Some(
+            count
+              .parse::<u32>()
+              .map_err(|_| format!("invalid feature count '{}'", count))?,
+          ),
+        ),
+        None => (generate.as_str(), None),
+      };
+      let mut features = match (kind, count) {
+        ("random", count) => generate_random(count.unwrap_or(100)),
+        ("grid", Some(count)) if grid_count(count).is_none() => {
+          return Err(format!(
+            "grid size {} too large, {}³ features do not fit in a u32",
+            count, count
+          ))
+        }
+        ("grid", Some(count)) => generate_grid(count),
+        _ => {
+          return Err(format!(
+            "invalid arg value '{}', expected 'random[:N]' or 'grid:N'",
+            generate
+          ))
+        }
+      };
+      for feature in features.iter_mut() {
+        feature.dataset = self.dataset.clone();
+      }
+      database
+        .insert(features)
+        .map_err(|err| format!("failed to insert features: '{}'", err))?;
+      cli_mode = true;
+    }
+    if let Some(path) = &self.export_pcd {
+      let features = database
+        .load_all(None)
+        .map_err(|err| format!("failed to load features: '{}'", err))?;
+      PointCloudWriter::create(path)
+        .and_then(|mut writer| writer.write_pcd(&features))
+        .map_err(|err| err.to_string())?;
+      cli_mode = true;
+    }
+    if let Some(path) = &self.export_ply {
+      let features = database
+        .load_all(None)
+        .map_err(|err| format!("failed to load features: '{}'", err))?;
+      PointCloudWriter::create(path)
+        .and_then(|mut writer| writer.write_ply(&features))
+        .map_err(|err| err.to_string())?;
+      cli_mode = true;
+    }
+    if let Some(epsilon) = self.deduplicate {
+      let deleted = database
+        .deduplicate_near_features(epsilon)
+        .map_err(|err| format!("failed to deduplicate features: '{}'", err))?;
+      println!("merged {} duplicate features", deleted);
+      cli_mode = true;
+    }
+    if self.db_stats || self.json_stats {
+      let stats = database
+        .aggregate_statistics(None)
+        .map_err(|err| format!("failed to compute feature statistics: '{}'", err))?;
+      if self.json_stats {
+        println!("{}", stats_json(&stats));
       } else {
-        return Err(format!("invalid arg value '{}', expected 'random'", generate));
+        println!("{}", stats_table(&stats));
       }
+      cli_mode = true;
+    }
+
+    if let Some(count) = self.benchmark_db {
+      println!("{}", benchmark_db(count)?);
+      cli_mode = true;
     }
 
     Ok(cli_mode)
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use cgmath::InnerSpace;
+
+  #[test]
+  fn generate_random_test() {
+    assert_eq!(generate_random(0).len(), 0);
+    assert_eq!(generate_random(500).len(), 500);
+  }
+
+  #[test]
+  fn generate_grid_test() {
+    let features = generate_grid(3);
+    assert_eq!(features.len(), 27);
+    for a in features.iter() {
+      let nearest = features
+        .iter()
+        .filter(|b| *b != a)
+        .map(|b| (b.position_mean - a.position_mean).magnitude())
+        .fold(f32::INFINITY, f32::min);
+      assert!((nearest - 1.0).abs() < 0.00001);
+    }
+    assert_eq!(features[0].position_mean, (-1.0, -1.0, -1.0).into());
+    assert_eq!(features[26].position_mean, (1.0, 1.0, 1.0).into());
+  }
+
+  #[test]
+  fn grid_count_test() {
+    assert_eq!(grid_count(3), Some(27));
+    assert_eq!(grid_count(1625), Some(1625 * 1625 * 1625));
+    assert_eq!(grid_count(1626), None);
+    assert_eq!(grid_count(u32::MAX), None);
+  }
+
+  #[test]
+  fn percentile_test() {
+    let samples: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+    assert_eq!(percentile(&samples, 50.0), Duration::from_millis(50));
+    assert_eq!(percentile(&samples, 95.0), Duration::from_millis(95));
+    assert_eq!(percentile(&samples, 99.0), Duration::from_millis(99));
+    assert_eq!(percentile(&samples, 0.0), Duration::from_millis(1));
+    assert_eq!(percentile(&samples[..1], 99.0), Duration::from_millis(1));
+  }
+
+  #[test]
+  fn stats_output_test() {
+    let database = FeatureDB::in_memory().unwrap();
+    let mut features = generate_grid(3);
+    for feature in features.iter_mut() {
+      feature.position_mean += Vector3::new(1.0, 2.0, -3.0);
+    }
+    database.insert(features).unwrap();
+    let stats = database.aggregate_statistics(None).unwrap();
+
+    let table = stats_table(&stats);
+    assert!(table.contains("| centroid        | (1.000, 2.000, -3.000) |"));
+    assert!(table.contains("| features        | 27                     |"));
+    let lengths: Vec<usize> = table.lines().map(|line| line.chars().count()).collect();
+    assert!(lengths.iter().all(|&length| length == lengths[0]));
+
+    let json: serde_json::Value = serde_json::from_str(&stats_json(&stats)).unwrap();
+    assert_eq!(json["centroid"], serde_json::json!([1.0, 2.0, -3.0]));
+    assert_eq!(json["materials"][0]["count"], 27);
+  }
+
+  #[test]
+  fn benchmark_db_test() {
+    let report = benchmark_db(50).unwrap();
+    assert!(report.starts_with("insert:       50 features"));
+    assert!(report.contains("load_all:     50 features"));
+    assert!(report.contains("find_nearest: 100 queries"));
+  }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use winit::event_loop::ControlFlow;
+
+use std::path::{Path, PathBuf};
+
+/// Requests other threads can make of the running application, delivered as event loop user events through an
+/// `EventLoopProxy`. The WebSocket client and the metrics server pass on the ones they receive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AppCommand {
+  /// Renders a frame right away and saves it to the image file at the path
+  Screenshot(PathBuf),
+  /// Merges every feature of the feature database at the path into the current one
+  LoadFeatures(PathBuf),
+  Shutdown,
+  SetFog(f32),
+}
+
+/// Carries out `AppCommand`s. Failures are reported by the handler since there is no one to return them to.
+pub trait CommandHandler {
+  fn screenshot(&mut self, path: &Path);
+  fn load_features(&mut self, path: &Path);
+  /// Cleans up before the event loop exits.
+  fn shutdown(&mut self);
+  fn set_fog_density(&mut self, density: f32);
+}
+
+impl AppCommand {
+  pub fn dispatch<H: CommandHandler>(self, handler: &mut H) {
+    match self {
+      AppCommand::Screenshot(path) => handler.screenshot(&path),
+      AppCommand::LoadFeatures(path) => handler.load_features(&path),
+      AppCommand::Shutdown => handler.shutdown(),
+      AppCommand::SetFog(density) => handler.set_fog_density(density),
+    }
+  }
+
+  /// Dispatches a command delivered to the event loop, asking the loop to exit after `Shutdown`.
+  pub fn dispatch_event<H: CommandHandler>(self, handler: &mut H, control_flow: &mut ControlFlow) {
+    let exit = self == AppCommand::Shutdown;
+    self.dispatch(handler);
+    if exit {
+      *control_flow = ControlFlow::Exit;
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[derive(Default)]
+  struct RecordingHandler {
+    calls: Vec<String>,
+  }
+
+  impl CommandHandler for RecordingHandler {
+    fn screenshot(&mut self, path: &Path) {
+      self.calls.push(format!("screenshot {}", path.display()));
+    }
+
+    fn load_features(&mut self, path: &Path) {
+      self.calls.push(format!("load {}", path.display()));
+    }
+
+    fn shutdown(&mut self) {
+      self.calls.push("shutdown".into());
+    }
+
+    fn set_fog_density(&mut self, density: f32) {
+      self.calls.push(format!("fog {}", density));
+    }
+  }
+
+  #[test]
+  fn dispatch_test() {
+    let mut handler = RecordingHandler::default();
+    for command in [
+      AppCommand::Screenshot("frame.png".into()),
+      AppCommand::SetFog(0.5),
+      AppCommand::LoadFeatures("lawn.db".into()),
+      AppCommand::Shutdown,
+    ] {
+      command.dispatch(&mut handler);
+    }
+    assert_eq!(
+      handler.calls,
+      vec!["screenshot frame.png", "fog 0.5", "load lawn.db", "shutdown"]
+    );
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn event_loop_proxy_test() {
+    use winit::event::Event;
+    use winit::event_loop::EventLoop;
+    use winit::platform::run_return::EventLoopExtRunReturn;
+
+    let display = std::env::var_os("DISPLAY").or_else(|| std::env::var_os("WAYLAND_DISPLAY"));
+    if display.is_none() {
+      eprintln!("skipping event_loop_proxy_test: no display");
+      return;
+    }
+    // Tests run off the main thread
+    let mut event_loop: EventLoop<AppCommand> = winit::platform::unix::EventLoopExtUnix::new_any_thread();
+    let proxy = event_loop.create_proxy();
+    std::thread::spawn(move || {
+      proxy.send_event(AppCommand::Screenshot("frame.png".into())).unwrap();
+      proxy.send_event(AppCommand::Shutdown).unwrap();
+    })
+    .join()
+    .unwrap();
+
+    let mut handler = RecordingHandler::default();
+    event_loop.run_return(|event, _, control_flow| {
+      if let Event::UserEvent(command) = event {
+        command.dispatch_event(&mut handler, control_flow);
+      }
+    });
+    assert_eq!(handler.calls, vec!["screenshot frame.png", "shutdown"]);
+  }
+
+  #[test]
+  fn json_test() {
+    let command: AppCommand = serde_json::from_str(r#"{"Screenshot": "frame.png"}"#).unwrap();
+    assert_eq!(command, AppCommand::Screenshot("frame.png".into()));
+    assert_eq!(
+      serde_json::from_str::<AppCommand>(r#""Shutdown""#).unwrap(),
+      AppCommand::Shutdown
+    );
+    assert!(serde_json::from_str::<AppCommand>(r#""Explode""#).is_err());
+  }
+}
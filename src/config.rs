@@ -0,0 +1,127 @@
+use serde::Deserialize;
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Io(std::io::Error),
+  Toml(toml::de::Error),
+  /// A key that is present but holds the wrong kind of value
+  Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ConfigError::Io(err) => write!(f, "failed to read config: '{}'", err),
+      ConfigError::Toml(err) => write!(f, "invalid config: '{}'", err),
+      ConfigError::Invalid(key) => write!(f, "invalid value for '{}'", key),
+    }
+  }
+}
+
+impl From<std::io::Error> for ConfigError {
+  fn from(other: std::io::Error) -> Self {
+    ConfigError::Io(other)
+  }
+}
+
+impl From<toml::de::Error> for ConfigError {
+  fn from(other: toml::de::Error) -> Self {
+    ConfigError::Toml(other)
+  }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+  /// Overrides how many updates a feature survives without being seen again
+  pub max_feature_age: Option<u32>,
+}
+
+/// Render options enabled here are OR-ed with the matching command line flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderingConfig {
+  pub ssao: bool,
+  pub debug_wireframe: bool,
+  pub z_prepass: bool,
+}
+
+/// Contents of a `--config` file. Every section and key is optional.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  /// Raw `[camera]` table, read by `Camera::from_toml`
+  pub camera: toml::Value,
+  pub simulation: SimulationConfig,
+  pub rendering: RenderingConfig,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      camera: toml::Value::Table(toml::value::Table::new()),
+      simulation: SimulationConfig::default(),
+      rendering: RenderingConfig::default(),
+    }
+  }
+}
+
+impl Config {
+  pub fn load(path: &Path) -> Result<Self, ConfigError> {
+    std::fs::read_to_string(path)?.parse()
+  }
+}
+
+impl FromStr for Config {
+  type Err = ConfigError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Ok(toml::from_str(s)?)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parse_sections_test() {
+    let config: Config = r#"
+      [camera]
+      fovy = 45.0
+
+      [simulation]
+      max_feature_age = 50
+
+      [rendering]
+      ssao = true
+    "#
+    .parse()
+    .unwrap();
+    assert_eq!(config.camera["fovy"].as_float(), Some(45.0));
+    assert_eq!(config.simulation.max_feature_age, Some(50));
+    assert!(config.rendering.ssao);
+    assert!(!config.rendering.z_prepass);
+
+    let empty: Config = "".parse().unwrap();
+    assert!(empty.camera.as_table().unwrap().is_empty());
+    assert!(matches!(
+      "[rendering]\nfog = true".parse::<Config>(),
+      Err(ConfigError::Toml(_))
+    ));
+  }
+
+  #[test]
+  fn load_test() {
+    let path = std::env::temp_dir().join("simulator_config_test.toml");
+    std::fs::write(&path, "[simulation]\nmax_feature_age = 10\n").unwrap();
+    let config = Config::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(config.simulation.max_feature_age, Some(10));
+    assert!(matches!(Config::load(&path), Err(ConfigError::Io(_))));
+  }
+}
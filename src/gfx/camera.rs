@@ -1,6 +1,17 @@
-use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use crate::config::ConfigError;
+use crate::raycast::{Frustum, Ray};
+
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, Rad, SquareMatrix, Vector2, Vector3};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use serde::Deserialize;
 use wgpu::util::DeviceExt;
 use wgpu::{BindGroup, BindGroupLayout, Buffer, Device};
+use winit::dpi::PhysicalSize;
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
@@ -14,12 +25,15 @@ pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct CameraUniform {
   view_proj: [[f32; 4]; 4],
+  /// World-space eye position, with w unused
+  eye: [f32; 4],
 }
 
 impl Default for CameraUniform {
   fn default() -> Self {
     Self {
       view_proj: Matrix4::identity().into(),
+      eye: [0.0, 0.0, 0.0, 1.0],
     }
   }
 }
@@ -80,6 +94,18 @@ impl Camera {
     }
   }
 
+  /// Camera at `eye` looking at `target`, with the default projection.
+  #[allow(dead_code)]
+  pub fn look_at(device: &Device, eye: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Self {
+    CameraBuilder::new(eye, target, up).build(device)
+  }
+
+  /// Camera from a `[camera]` config table; missing keys keep the `CameraBuilder::default()` values.
+  #[allow(dead_code)]
+  pub fn from_toml(config: &toml::Value, device: &Device) -> Result<Self, ConfigError> {
+    Ok(CameraBuilder::default().with_toml(config)?.build(device))
+  }
+
   #[cfg(test)]
   pub fn mock() -> Self {
     Self {
@@ -94,11 +120,24 @@ impl Camera {
     }
   }
 
-  pub fn update(&mut self, device: &Device) {
+  /// World to wgpu clip space.
+  fn view_projection(&self) -> Matrix4<f32> {
     let view = Matrix4::look_at_rh(self.eye, self.target, self.up);
     let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+    OPENGL_TO_WGPU_MATRIX * proj * view
+  }
+
+  /// Volume visible through the camera.
+  #[allow(dead_code)]
+  pub fn frustum(&self) -> Frustum {
+    Frustum::from_view_proj(self.view_projection())
+  }
+
+  pub fn update(&mut self, device: &Device) {
+    let view_proj = self.view_projection();
     let private = self.private.as_mut().unwrap();
-    private.uniform.view_proj = (OPENGL_TO_WGPU_MATRIX * proj * view).into();
+    private.uniform.view_proj = view_proj.into();
+    private.uniform.eye = self.eye.to_homogeneous().into();
     private.buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
       label: Some("Camera Buffer"),
       contents: bytemuck::cast_slice(&[private.uniform]),
@@ -115,6 +154,68 @@ impl Camera {
     });
   }
 
+  /// Ray from the eye through `ndc` on the near plane, with x right and y up in [-1, 1]. `target` is on the near
+  /// plane, so `t` counts in multiples of the near plane distance rather than world units.
+  #[allow(dead_code)]
+  pub fn ray_from_ndc(&self, ndc: Vector2<f32>) -> Ray {
+    ray_through(self.eye, &self.inverse_view_projection(), ndc)
+  }
+
+  /// Pixel position of `world_pos` in a viewport of `size`, from the top left with y down. `None` if the point is
+  /// behind the eye or outside the frustum.
+  #[allow(dead_code)]
+  pub fn project_point(&self, world_pos: Point3<f32>, size: PhysicalSize<u32>) -> Option<Vector2<f32>> {
+    let clip = self.view_projection() * world_pos.to_homogeneous();
+    if clip.w <= 0.0 {
+      return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || !(0.0..=1.0).contains(&ndc.z) {
+      return None;
+    }
+    Some(Vector2::new(
+      (ndc.x + 1.0) * 0.5 * size.width as f32,
+      (1.0 - ndc.y) * 0.5 * size.height as f32,
+    ))
+  }
+
+  /// Distance along the view direction of a [0, 1] depth buffer `depth`, from `znear` at 0 to `zfar` at 1.
+  pub fn linear_depth(&self, depth: f32) -> f32 {
+    // Inverts the perspective divide of `view_projection`, whose depth is `zfar (z - znear) / (z (zfar - znear))`
+    self.znear * self.zfar / (self.zfar * (1.0 - depth) + self.znear * depth)
+  }
+
+  fn inverse_view_projection(&self) -> Matrix4<f32> {
+    self
+      .view_projection()
+      .invert()
+      .expect("camera projection to be invertible")
+  }
+
+  /// Rays through the center of every pixel of a `width`×`height` image, row by row from the top left.
+  #[allow(dead_code)]
+  pub fn to_ray_grid(&self, width: u32, height: u32) -> Vec<Ray> {
+    #[cfg(feature = "rayon")]
+    {
+      let inverse = self.inverse_view_projection();
+      (0..width * height)
+        .into_par_iter()
+        .map(|idx| ray_through(self.eye, &inverse, pixel_ndc(idx % width, idx / width, width, height)))
+        .collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    self.ray_stream(width, height).map(|(_, _, ray)| ray).collect()
+  }
+
+  /// Lazy `to_ray_grid`, with the pixel coordinates of each ray.
+  #[allow(dead_code)]
+  pub fn ray_stream(&self, width: u32, height: u32) -> impl Iterator<Item = (u32, u32, Ray)> + '_ {
+    let inverse = self.inverse_view_projection();
+    (0..height).flat_map(move |y| {
+      (0..width).map(move |x| (x, y, ray_through(self.eye, &inverse, pixel_ndc(x, y, width, height))))
+    })
+  }
+
   pub fn forward(&self) -> Vector3<f32> {
     (self.target - self.eye).normalize()
   }
@@ -124,6 +225,38 @@ impl Camera {
     delta.cross(self.up).normalize()
   }
 
+  /// Orbits the eye about `target`, first by `yaw` about `up` and then by `pitch` towards `up`. The elevation above
+  /// the plane perpendicular to `up` is clamped to ±89° so the view never flips over the pole.
+  #[allow(dead_code)]
+  pub fn orbit(&mut self, target: Point3<f32>, yaw: Deg<f32>, pitch: Deg<f32>) {
+    const MAX_ELEVATION: f32 = 89.0;
+    let up = self.up.normalize();
+    let offset = Matrix3::from_axis_angle(up, yaw) * (self.eye - target);
+    let axis = offset.cross(up);
+    let offset = if axis.magnitude2() > 0.0 {
+      let elevation: Deg<f32> = Rad(offset.normalize().dot(up).clamp(-1.0, 1.0).asin()).into();
+      let clamped = Deg((elevation + pitch).0.clamp(-MAX_ELEVATION, MAX_ELEVATION));
+      Matrix3::from_axis_angle(axis.normalize(), clamped - elevation) * offset
+    } else {
+      offset
+    };
+    self.eye = target + offset;
+    self.target = target;
+    self.up = up;
+  }
+
+  /// Animation through `waypoints`, each an `(eye, target)` pair, taking `duration` seconds end to end. Plays once
+  /// unless given another `LoopMode`. Panics if `waypoints` is empty.
+  pub fn fly_path(waypoints: Vec<(Point3<f32>, Point3<f32>)>, duration: f32) -> CameraPath {
+    assert!(!waypoints.is_empty(), "a camera path needs at least one waypoint");
+    CameraPath {
+      waypoints,
+      total_duration: duration,
+      elapsed: 0.0,
+      loop_mode: LoopMode::Once,
+    }
+  }
+
   pub fn layout(device: &Device) -> BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
       entries: &[wgpu::BindGroupLayoutEntry {
@@ -144,3 +277,482 @@ impl Camera {
     &self.private.as_ref().unwrap().bind_group
   }
 }
+
+#[derive(Clone)]
+pub struct CameraBuilder {
+  eye: Point3<f32>,
+  target: Point3<f32>,
+  up: Vector3<f32>,
+  fovy: f32,
+  znear: f32,
+  zfar: f32,
+  aspect: f32,
+}
+
+/// Five units back along +Z, looking at the origin
+impl Default for CameraBuilder {
+  fn default() -> Self {
+    Self::new((0.0, 0.0, 5.0).into(), (0.0, 0.0, 0.0).into(), Vector3::unit_y())
+  }
+}
+
+impl CameraBuilder {
+  pub fn new(eye: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Self {
+    Self {
+      eye,
+      target,
+      up,
+      fovy: 60.0,
+      znear: 0.001,
+      zfar: 1000.0,
+      aspect: 1.0,
+    }
+  }
+
+  /// Vertical field of view in degrees
+  #[allow(dead_code)]
+  pub fn fovy(mut self, fovy: f32) -> Self {
+    self.fovy = fovy;
+    self
+  }
+
+  #[allow(dead_code)]
+  pub fn znear(mut self, znear: f32) -> Self {
+    self.znear = znear;
+    self
+  }
+
+  #[allow(dead_code)]
+  pub fn zfar(mut self, zfar: f32) -> Self {
+    self.zfar = zfar;
+    self
+  }
+
+  pub fn aspect(mut self, aspect: f32) -> Self {
+    self.aspect = aspect;
+    self
+  }
+
+  fn apply(self, camera: &mut Camera) {
+    camera.eye = self.eye;
+    camera.target = self.target;
+    camera.up = self.up;
+    camera.fovy = self.fovy;
+    camera.znear = self.znear;
+    camera.zfar = self.zfar;
+    camera.aspect = self.aspect;
+  }
+
+  /// Overrides `eye`, `target` and `up` (`[x, y, z]` arrays) and `fovy`, `znear`, `zfar` and `aspect` with any
+  /// present in the `[camera]` table `config`.
+  pub fn with_toml(mut self, config: &toml::Value) -> Result<Self, ConfigError> {
+    let float = |value: &toml::Value| value.as_float().or_else(|| value.as_integer().map(|x| x as f64));
+    let read_f32 = |key: &str| -> Result<Option<f32>, ConfigError> {
+      config.get(key).map_or(Ok(None), |value| {
+        float(value)
+          .map(|x| Some(x as f32))
+          .ok_or_else(|| ConfigError::Invalid(format!("camera.{}", key)))
+      })
+    };
+    let read_vector = |key: &str| -> Result<Option<Vector3<f32>>, ConfigError> {
+      config.get(key).map_or(Ok(None), |value| {
+        let invalid = || ConfigError::Invalid(format!("camera.{}", key));
+        let array = value.as_array().filter(|array| array.len() == 3).ok_or_else(invalid)?;
+        let mut vector = Vector3::new(0.0, 0.0, 0.0);
+        for (i, component) in array.iter().enumerate() {
+          vector[i] = float(component).ok_or_else(invalid)? as f32;
+        }
+        Ok(Some(vector))
+      })
+    };
+    if !config.is_table() {
+      return Err(ConfigError::Invalid("camera".into()));
+    }
+    if let Some(eye) = read_vector("eye")? {
+      self.eye = Point3::from_vec(eye);
+    }
+    if let Some(target) = read_vector("target")? {
+      self.target = Point3::from_vec(target);
+    }
+    self.up = read_vector("up")?.unwrap_or(self.up);
+    self.fovy = read_f32("fovy")?.unwrap_or(self.fovy);
+    self.znear = read_f32("znear")?.unwrap_or(self.znear);
+    self.zfar = read_f32("zfar")?.unwrap_or(self.zfar);
+    self.aspect = read_f32("aspect")?.unwrap_or(self.aspect);
+    Ok(self)
+  }
+
+  /// Creates the camera and uploads its view projection.
+  pub fn build(self, device: &Device) -> Camera {
+    let mut camera = Camera::new(device);
+    self.apply(&mut camera);
+    camera.update(device);
+    camera
+  }
+}
+
+#[derive(Debug)]
+pub enum CameraPathError {
+  Io(std::io::Error),
+  Json(serde_json::Error),
+  Empty,
+}
+
+impl fmt::Display for CameraPathError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CameraPathError::Io(err) => write!(f, "failed to read camera path: '{}'", err),
+      CameraPathError::Json(err) => write!(f, "invalid camera path: '{}'", err),
+      CameraPathError::Empty => write!(f, "camera path has no waypoints"),
+    }
+  }
+}
+
+impl From<std::io::Error> for CameraPathError {
+  fn from(other: std::io::Error) -> Self {
+    CameraPathError::Io(other)
+  }
+}
+
+impl From<serde_json::Error> for CameraPathError {
+  fn from(other: serde_json::Error) -> Self {
+    CameraPathError::Json(other)
+  }
+}
+
+/// What a `CameraPath` does once it reaches its last waypoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopMode {
+  /// Stays at the last waypoint
+  Once,
+  /// Jumps back to the first waypoint
+  Loop,
+  /// Flies back through the waypoints in reverse
+  PingPong,
+}
+
+impl FromStr for LoopMode {
+  type Err = String;
+
+  fn from_str(mode: &str) -> Result<Self, Self::Err> {
+    match mode {
+      "once" => Ok(LoopMode::Once),
+      "loop" => Ok(LoopMode::Loop),
+      "ping-pong" => Ok(LoopMode::PingPong),
+      _ => Err(format!(
+        "invalid loop mode '{}', expected 'once', 'loop' or 'ping-pong'",
+        mode
+      )),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct Waypoint {
+  eye: [f32; 3],
+  target: [f32; 3],
+}
+
+/// Camera fly-through along Catmull-Rom splines through its eye and target waypoints. Each pair of consecutive
+/// waypoints gets an equal share of `total_duration`.
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+  pub waypoints: Vec<(Point3<f32>, Point3<f32>)>,
+  /// Seconds from the first waypoint to the last
+  pub total_duration: f32,
+  /// Seconds played so far
+  pub elapsed: f32,
+  pub loop_mode: LoopMode,
+}
+
+impl CameraPath {
+  /// Path through the waypoints in a JSON array of `{ "eye": [x, y, z], "target": [x, y, z] }` objects.
+  pub fn from_json(json: &str, duration: f32) -> Result<Self, CameraPathError> {
+    let waypoints: Vec<Waypoint> = serde_json::from_str(json)?;
+    if waypoints.is_empty() {
+      return Err(CameraPathError::Empty);
+    }
+    let waypoints = waypoints
+      .into_iter()
+      .map(|waypoint| (waypoint.eye.into(), waypoint.target.into()))
+      .collect();
+    Ok(Camera::fly_path(waypoints, duration))
+  }
+
+  pub fn load(path: &Path, duration: f32) -> Result<Self, CameraPathError> {
+    Self::from_json(&std::fs::read_to_string(path)?, duration)
+  }
+
+  pub fn with_loop_mode(mut self, loop_mode: LoopMode) -> Self {
+    self.loop_mode = loop_mode;
+    self
+  }
+
+  /// Advances the path by `dt` seconds, returning the new eye and target.
+  pub fn tick(&mut self, dt: f32) -> (Point3<f32>, Point3<f32>) {
+    self.elapsed += dt;
+    self.sample(self.playhead())
+  }
+
+  /// Whether a path played once has reached its last waypoint. Looping paths never finish.
+  pub fn is_finished(&self) -> bool {
+    self.loop_mode == LoopMode::Once && self.elapsed >= self.total_duration
+  }
+
+  /// Seconds along the path from the first waypoint that `elapsed` corresponds to.
+  fn playhead(&self) -> f32 {
+    let total = self.total_duration;
+    if total <= 0.0 {
+      return total.max(0.0);
+    }
+    match self.loop_mode {
+      LoopMode::Once => self.elapsed.min(total),
+      LoopMode::Loop => self.elapsed.rem_euclid(total),
+      LoopMode::PingPong => {
+        let time = self.elapsed.rem_euclid(2.0 * total);
+        if time > total {
+          2.0 * total - time
+        } else {
+          time
+        }
+      }
+    }
+  }
+
+  /// Eye and target `time` seconds along the path.
+  fn sample(&self, time: f32) -> (Point3<f32>, Point3<f32>) {
+    let last = self.waypoints.len() - 1;
+    if last == 0 {
+      return self.waypoints[0];
+    }
+    let progress = if self.total_duration > 0.0 {
+      (time / self.total_duration).clamp(0.0, 1.0) * last as f32
+    } else {
+      last as f32
+    };
+    let segment = (progress.floor() as usize).min(last - 1);
+    let t = progress - segment as f32;
+    // The end waypoints are repeated so the spline still passes through them
+    let at = |i: isize| self.waypoints[i.clamp(0, last as isize) as usize];
+    let i = segment as isize;
+    let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+    (
+      catmull_rom(p0.0, p1.0, p2.0, p3.0, t),
+      catmull_rom(p0.1, p1.1, p2.1, p3.1, t),
+    )
+  }
+}
+
+/// Ray from `eye` to the point on the near plane at `ndc`, given the inverse view projection.
+fn ray_through(eye: Point3<f32>, inverse_view_projection: &Matrix4<f32>, ndc: Vector2<f32>) -> Ray {
+  let near = inverse_view_projection * ndc.extend(0.0).extend(1.0);
+  Ray {
+    eye,
+    target: Point3::from_homogeneous(near),
+  }
+}
+
+/// Normalized device coordinates of the center of pixel (`x`, `y`), counted from the top left.
+fn pixel_ndc(x: u32, y: u32, width: u32, height: u32) -> Vector2<f32> {
+  Vector2::new(
+    (x as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+    1.0 - (y as f32 + 0.5) / height as f32 * 2.0,
+  )
+}
+
+/// Point `t` of the way from `p1` to `p2` on the uniform Catmull-Rom spline through the four points.
+fn catmull_rom(p0: Point3<f32>, p1: Point3<f32>, p2: Point3<f32>, p3: Point3<f32>, t: f32) -> Point3<f32> {
+  let (p0, p1, p2, p3) = (p0.to_vec(), p1.to_vec(), p2.to_vec(), p3.to_vec());
+  let (t2, t3) = (t * t, t * t * t);
+  Point3::from_vec(
+    (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+      * 0.5,
+  )
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use cgmath::MetricSpace;
+
+  #[test]
+  fn linear_depth_test() {
+    let mut camera = Camera::mock();
+    camera.znear = 0.1;
+    camera.zfar = 100.0;
+    assert!((camera.linear_depth(0.0) - 0.1).abs() < 1e-6);
+    assert!((camera.linear_depth(1.0) - 100.0).abs() < 1e-3);
+    // Round trip a point 10 units in front of the eye through the projection
+    let forward = camera.forward();
+    let clip = camera.view_projection() * (camera.eye + forward * 10.0).to_homogeneous();
+    assert!((camera.linear_depth(clip.z / clip.w) - 10.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn project_point_test() {
+    let size = PhysicalSize {
+      width: 1200,
+      height: 800,
+    };
+    let mut camera = Camera::mock();
+    camera.aspect = size.width as f32 / size.height as f32;
+    let center = camera.project_point(camera.eye + camera.forward() * 5.0, size).unwrap();
+    assert!((center - Vector2::new(600.0, 400.0)).magnitude() < 0.01, "{:?}", center);
+    // Round trip through the ray of a pixel in the top left quadrant
+    let ray = camera.ray_from_ndc(Vector2::new(-0.5, 0.5));
+    let point = camera
+      .project_point(ray.eye + ray.delta().normalize() * 5.0, size)
+      .unwrap();
+    assert!((point - Vector2::new(300.0, 200.0)).magnitude() < 1.0, "{:?}", point);
+    assert_eq!(camera.project_point(camera.eye - camera.forward() * 5.0, size), None);
+    assert_eq!(camera.project_point(camera.eye + camera.right() * 5.0, size), None);
+  }
+
+  #[test]
+  fn ray_grid_test() {
+    let mut camera = Camera::mock();
+    camera.eye = (0.0, 0.0, 5.0).into();
+    camera.fovy = 90.0;
+    camera.aspect = 2.0;
+    // The center of the screen looks straight at the target
+    let center = camera.ray_from_ndc(Vector2::new(0.0, 0.0));
+    assert_eq!(center.eye, camera.eye);
+    assert!((center.delta().normalize() + Vector3::unit_z()).magnitude() < 0.00001);
+    // A 90° vertical field of view puts the top edge 45° up, and the right edge is twice as far out
+    let corner = camera.ray_from_ndc(Vector2::new(1.0, 1.0)).delta();
+    assert!((corner.y / -corner.z - 1.0).abs() < 0.001, "{:?}", corner);
+    assert!((corner.x / -corner.z - 2.0).abs() < 0.001, "{:?}", corner);
+
+    let grid = camera.to_ray_grid(4, 3);
+    let stream: Vec<_> = camera.ray_stream(4, 3).collect();
+    assert_eq!(grid.len(), 12);
+    assert_eq!(stream.len(), 12);
+    for (idx, (ray, &(x, y, streamed))) in grid.iter().zip(&stream).enumerate() {
+      assert_eq!((x, y), (idx as u32 % 4, idx as u32 / 4));
+      assert_eq!(*ray, streamed);
+    }
+    // Rows run top to bottom and pixels left to right
+    assert!(grid[0].delta().x < 0.0 && grid[0].delta().y > 0.0);
+    assert!(grid[11].delta().x > 0.0 && grid[11].delta().y < 0.0);
+    // The middle row of an odd height passes through the center
+    assert!(grid[4].delta().y.abs() < 0.00001);
+  }
+
+  #[test]
+  fn orbit_yaw_test() {
+    let mut camera = Camera::mock();
+    camera.orbit(Point3::new(0.0, 0.0, 0.0), Deg(90.0), Deg(0.0));
+    assert!(camera.eye.distance((-1.0, 0.0, 0.0).into()) < 0.00001);
+    assert_eq!(camera.target, (0.0, 0.0, 0.0).into());
+    assert_eq!(camera.up, Vector3::unit_y());
+  }
+
+  #[test]
+  fn orbit_pitch_clamp_test() {
+    let mut camera = Camera::mock();
+    camera.orbit(Point3::new(0.0, 0.0, 0.0), Deg(0.0), Deg(120.0));
+    let Rad(max): Rad<f32> = Deg(89.0).into();
+    assert!(camera.eye.distance((0.0, max.sin(), -max.cos()).into()) < 0.00001);
+  }
+
+  #[test]
+  fn builder_defaults_test() {
+    let mut manual = Camera::mock();
+    manual.eye = (0.0, 0.0, 5.0).into();
+    manual.target = (0.0, 0.0, 0.0).into();
+    manual.up = Vector3::unit_y();
+    manual.fovy = 60.0;
+    manual.znear = 0.001;
+    manual.zfar = 1000.0;
+    manual.aspect = 1.0;
+    let mut built = Camera::mock();
+    built.fovy = 90.0;
+    CameraBuilder::new((0.0, 0.0, 5.0).into(), (0.0, 0.0, 0.0).into(), Vector3::unit_y()).apply(&mut built);
+    assert_eq!(
+      (built.eye, built.target, built.up),
+      (manual.eye, manual.target, manual.up)
+    );
+    assert_eq!(
+      (built.fovy, built.znear, built.zfar, built.aspect),
+      (manual.fovy, manual.znear, manual.zfar, manual.aspect)
+    );
+  }
+
+  #[test]
+  fn from_toml_test() {
+    let config: toml::Value = "fovy = 45.0".parse().unwrap();
+    let mut camera = Camera::mock();
+    CameraBuilder::default().with_toml(&config).unwrap().apply(&mut camera);
+    assert_eq!(camera.fovy, 45.0);
+    assert_eq!(camera.eye, (0.0, 0.0, 5.0).into());
+    assert_eq!(camera.zfar, 1000.0);
+
+    let config: toml::Value = "eye = [1, 2.5, 3]".parse().unwrap();
+    CameraBuilder::default().with_toml(&config).unwrap().apply(&mut camera);
+    assert_eq!(camera.eye, (1.0, 2.5, 3.0).into());
+
+    for invalid in ["fovy = \"wide\"", "eye = [1.0, 2.0]"] {
+      let config: toml::Value = invalid.parse().unwrap();
+      assert!(matches!(
+        CameraBuilder::default().with_toml(&config),
+        Err(ConfigError::Invalid(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn fly_path_waypoints_test() {
+    let waypoints = vec![
+      ((0.0, 0.0, 0.0).into(), (0.0, 0.0, 1.0).into()),
+      ((1.0, 2.0, 0.0).into(), (0.0, 0.0, 1.0).into()),
+      ((4.0, 0.0, 0.0).into(), (1.0, 0.0, 1.0).into()),
+    ];
+    let mut path = Camera::fly_path(waypoints.clone(), 2.0);
+    // The spline passes through every waypoint at the start of its segment
+    assert_eq!(path.tick(0.0), waypoints[0]);
+    let (eye, target) = path.tick(1.0);
+    assert!(eye.distance(waypoints[1].0) < 0.00001);
+    assert!(target.distance(waypoints[1].1) < 0.00001);
+    assert!(!path.is_finished());
+    let (eye, _) = path.tick(0.5);
+    assert!(eye.x > 1.0 && eye.x < 4.0);
+    let (eye, _) = path.tick(5.0);
+    assert!(eye.distance(waypoints[2].0) < 0.00001);
+    assert!(path.is_finished());
+  }
+
+  #[test]
+  fn fly_path_loop_mode_test() {
+    let waypoints = vec![
+      ((0.0, 0.0, 0.0).into(), (0.0, 0.0, 1.0).into()),
+      ((2.0, 0.0, 0.0).into(), (2.0, 0.0, 1.0).into()),
+    ];
+    let mut looped = Camera::fly_path(waypoints.clone(), 1.0).with_loop_mode(LoopMode::Loop);
+    let (eye, _) = looped.tick(1.25);
+    assert!(eye.distance(looped.clone().with_loop_mode(LoopMode::Once).sample(0.25).0) < 0.00001);
+    assert!(!looped.is_finished());
+
+    let mut ping_pong = Camera::fly_path(waypoints, 1.0).with_loop_mode(LoopMode::PingPong);
+    let (eye, _) = ping_pong.tick(1.25);
+    assert!(eye.distance(ping_pong.sample(0.75).0) < 0.00001);
+    let (eye, _) = ping_pong.tick(0.75);
+    assert!(eye.distance((0.0, 0.0, 0.0).into()) < 0.00001);
+
+    assert_eq!("ping-pong".parse(), Ok(LoopMode::PingPong));
+    assert!("bounce".parse::<LoopMode>().is_err());
+  }
+
+  #[test]
+  fn fly_path_json_test() {
+    let json = r#"[{ "eye": [0, 1, 2], "target": [0, 0, 0] }, { "eye": [3, 4, 5], "target": [1, 1, 1] }]"#;
+    let path = CameraPath::from_json(json, 4.0).unwrap();
+    assert_eq!(path.waypoints[1], ((3.0, 4.0, 5.0).into(), (1.0, 1.0, 1.0).into()));
+    assert_eq!(path.total_duration, 4.0);
+    assert_eq!(path.loop_mode, LoopMode::Once);
+    assert!(matches!(CameraPath::from_json("[]", 1.0), Err(CameraPathError::Empty)));
+    assert!(matches!(
+      CameraPath::from_json(r#"[{ "eye": [0, 1] }]"#, 1.0),
+      Err(CameraPathError::Json(_))
+    ));
+  }
+}
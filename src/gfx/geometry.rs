@@ -1,10 +1,484 @@
-use cgmath::{Point3, Vector3};
+use cgmath::{
+  EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, MetricSpace, Point3, SquareMatrix, Transform, Vector2, Vector3,
+  Vector4, Zero,
+};
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::iter::FromIterator;
+
+#[derive(Debug)]
+pub enum GeometryError {
+  TooFewPoints(usize),
+}
+
+impl fmt::Display for GeometryError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      GeometryError::TooFewPoints(n) => write!(f, "polygon needs at least 3 points, got {}", n),
+    }
+  }
+}
+
+/// Vertex indices, kept as `u16` until one no longer fits.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexBuffer {
+  U16(Vec<u16>),
+  U32(Vec<u32>),
+}
+
+impl Default for IndexBuffer {
+  fn default() -> Self {
+    IndexBuffer::U16(Vec::new())
+  }
+}
+
+impl From<Vec<u16>> for IndexBuffer {
+  fn from(indices: Vec<u16>) -> Self {
+    IndexBuffer::U16(indices)
+  }
+}
+
+impl From<Vec<u32>> for IndexBuffer {
+  fn from(indices: Vec<u32>) -> Self {
+    IndexBuffer::U32(indices)
+  }
+}
+
+impl FromIterator<u32> for IndexBuffer {
+  fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+    let mut indices = IndexBuffer::default();
+    indices.extend_from_slice(&iter.into_iter().collect::<Vec<_>>());
+    indices
+  }
+}
+
+impl IndexBuffer {
+  pub fn len(&self) -> usize {
+    match self {
+      IndexBuffer::U16(indices) => indices.len(),
+      IndexBuffer::U32(indices) => indices.len(),
+    }
+  }
+
+  #[allow(dead_code)]
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get(&self, i: usize) -> Option<u32> {
+    match self {
+      IndexBuffer::U16(indices) => indices.get(i).map(|&idx| idx as u32),
+      IndexBuffer::U32(indices) => indices.get(i).copied(),
+    }
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+    (0..self.len()).filter_map(move |i| self.get(i))
+  }
+
+  /// Index triples of each triangle of a triangle list.
+  pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+    (0..self.len() / 3).map(move |t| [0, 1, 2].map(|i| self.get(3 * t + i).unwrap()))
+  }
+
+  #[allow(dead_code)]
+  pub fn to_vec(&self) -> Vec<u32> {
+    self.iter().collect()
+  }
+
+  /// Appends `indices`, widening to `u32` first if any of them needs it.
+  pub fn extend_from_slice(&mut self, indices: &[u32]) {
+    if let IndexBuffer::U16(narrow) = self {
+      if indices.iter().all(|&idx| idx <= u16::MAX as u32) {
+        narrow.extend(indices.iter().map(|&idx| idx as u16));
+        return;
+      }
+      *self = IndexBuffer::U32(narrow.iter().map(|&idx| idx as u32).collect());
+    }
+    if let IndexBuffer::U32(wide) = self {
+      wide.extend_from_slice(indices);
+    }
+  }
+
+  pub fn format(&self) -> wgpu::IndexFormat {
+    match self {
+      IndexBuffer::U16(_) => wgpu::IndexFormat::Uint16,
+      IndexBuffer::U32(_) => wgpu::IndexFormat::Uint32,
+    }
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    match self {
+      IndexBuffer::U16(indices) => bytemuck::cast_slice(indices),
+      IndexBuffer::U32(indices) => bytemuck::cast_slice(indices),
+    }
+  }
+}
 
 #[derive(Default)]
 pub struct Geometry {
   pub vertices: Vec<Point3<f32>>,
   pub normals: Vec<Vector3<f32>>,
-  pub indices: Vec<u16>,
+  pub indices: IndexBuffer,
+}
+
+impl Geometry {
+  /// Merges vertices that lie within `epsilon` of each other, keeping the first occurrence (and its normal) and
+  /// remapping indices onto it. Triangles that collapse to a line or point are removed. `epsilon` must be positive.
+  #[allow(dead_code)]
+  pub fn weld_vertices(&mut self, epsilon: f32) {
+    debug_assert!(epsilon > 0.0, "weld epsilon must be positive");
+    let cell = |p: &Point3<f32>| {
+      (
+        (p.x / epsilon).floor() as i32,
+        (p.y / epsilon).floor() as i32,
+        (p.z / epsilon).floor() as i32,
+      )
+    };
+
+    let mut grid: HashMap<(i32, i32, i32), Vec<u32>> = HashMap::new();
+    let mut remap = Vec::with_capacity(self.vertices.len());
+    let mut vertices: Vec<Point3<f32>> = Vec::new();
+    let mut normals = Vec::new();
+    for (i, vertex) in self.vertices.iter().enumerate() {
+      let (x, y, z) = cell(vertex);
+      // A vertex within epsilon is at most one cell away in every direction
+      let existing = (-1..=1)
+        .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (x + dx, y + dy, z + dz))))
+        .filter_map(|key| grid.get(&key))
+        .flatten()
+        .copied()
+        .find(|&idx| vertices[idx as usize].distance(*vertex) <= epsilon);
+      let idx = existing.unwrap_or_else(|| {
+        let idx = vertices.len() as u32;
+        vertices.push(*vertex);
+        if let Some(normal) = self.normals.get(i) {
+          normals.push(*normal);
+        }
+        grid.entry((x, y, z)).or_default().push(idx);
+        idx
+      });
+      remap.push(idx);
+    }
+
+    for (original, &idx) in self.vertices.iter().zip(remap.iter()) {
+      debug_assert!(vertices[idx as usize].distance(*original) <= epsilon);
+    }
+
+    self.indices = self
+      .indices
+      .triangles()
+      .map(|triangle| triangle.map(|idx| remap[idx as usize]))
+      .filter(|[a, b, c]| a != b && b != c && a != c)
+      .flatten()
+      .collect();
+    self.vertices = vertices;
+    self.normals = normals;
+  }
+
+  #[allow(dead_code)]
+  pub fn compute_surface_area(&self) -> f32 {
+    self
+      .indices
+      .triangles()
+      .map(|t| {
+        let [a, b, c] = t.map(|i| self.vertices[i as usize]);
+        0.5 * (b - a).cross(c - a).magnitude()
+      })
+      .sum()
+  }
+
+  /// Bakes `matrix` into the vertices, transforming normals by the inverse transpose of its upper 3×3 so they stay
+  /// perpendicular to the surface. Normals are left alone if that part isn't invertible, and triangles are rewound
+  /// if it mirrors the geometry so they keep facing outward.
+  #[allow(dead_code)]
+  pub fn apply_transform(&mut self, matrix: Matrix4<f32>) {
+    for vertex in self.vertices.iter_mut() {
+      *vertex = matrix.transform_point(*vertex);
+    }
+    let linear = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+    if let Some(inverse) = linear.invert() {
+      let normal_matrix = inverse.transpose();
+      for normal in self.normals.iter_mut() {
+        *normal = (normal_matrix * *normal).normalize();
+      }
+    }
+    if linear.determinant() < 0.0 {
+      self.indices = self.indices.triangles().flat_map(|[a, b, c]| [a, c, b]).collect();
+    }
+  }
+
+  /// Converts the triangle list into a line list with one line per unique edge, keeping the same vertices.
+  pub fn to_wireframe_lines(&self) -> Geometry {
+    let mut edges = HashSet::new();
+    let mut indices = Vec::new();
+    for triangle in self.indices.triangles() {
+      for (a, b) in [
+        (triangle[0], triangle[1]),
+        (triangle[1], triangle[2]),
+        (triangle[2], triangle[0]),
+      ] {
+        if a != b && edges.insert((a.min(b), a.max(b))) {
+          indices.extend_from_slice(&[a, b]);
+        }
+      }
+    }
+    Geometry {
+      vertices: self.vertices.clone(),
+      normals: self.normals.clone(),
+      indices: indices.into_iter().collect(),
+    }
+  }
+
+  /// Simplifies the mesh to about `target_triangle_count` triangles with quadric error metrics (Garland and Heckbert,
+  /// 1997): every vertex sums the squared distances to the planes of its faces, and the edge whose merged vertex adds
+  /// the least error is collapsed first. Boundary edges get steep planes of their own so open borders stay put.
+  /// Collapses that would fold a face over or pinch the surface are skipped, which can leave more triangles than
+  /// asked for. Merged vertices take the average of the two normals.
+  #[allow(dead_code)]
+  pub fn decimate(&self, target_triangle_count: usize) -> Geometry {
+    // Boundary planes outweigh face planes so the border moves last
+    const BOUNDARY_WEIGHT: f64 = 1000.0;
+    let vertex_count = self.vertices.len();
+    let mut positions: Vec<Vector3<f64>> = self
+      .vertices
+      .iter()
+      .map(|vertex| vertex.to_vec().cast().unwrap())
+      .collect();
+    let mut normals = self.normals.clone();
+    let mut triangles: Vec<Option<[usize; 3]>> = self
+      .indices
+      .triangles()
+      .map(|triangle| Some(triangle.map(|idx| idx as usize)))
+      .collect();
+    let mut incident: Vec<HashSet<usize>> = vec![HashSet::new(); vertex_count];
+    let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+      let [a, b, c] = triangle.unwrap();
+      for (v, w) in [(a, b), (b, c), (c, a)] {
+        incident[v].insert(t);
+        edge_faces.entry((v.min(w), v.max(w))).or_default().push(t);
+      }
+    }
+
+    let mut quadrics = vec![Quadric(Matrix4::zero()); vertex_count];
+    let mut unit_normals = vec![None; triangles.len()];
+    for (t, triangle) in triangles.iter().enumerate() {
+      let triangle = triangle.unwrap();
+      let normal = face_normal(triangle.map(|v| positions[v]));
+      // Degenerate faces have no plane
+      if normal.magnitude2() > 0.0 {
+        let normal = normal.normalize();
+        let plane = Quadric::plane(normal, -normal.dot(positions[triangle[0]]), 1.0);
+        for v in triangle {
+          quadrics[v] = quadrics[v] + plane;
+        }
+        unit_normals[t] = Some(normal);
+      }
+    }
+    for (&(a, b), faces) in &edge_faces {
+      if let [face] = faces[..] {
+        if let Some(normal) = unit_normals[face] {
+          let perpendicular = (positions[b] - positions[a]).cross(normal);
+          if perpendicular.magnitude2() > 0.0 {
+            let perpendicular = perpendicular.normalize();
+            let plane = Quadric::plane(perpendicular, -perpendicular.dot(positions[a]), BOUNDARY_WEIGHT);
+            quadrics[a] = quadrics[a] + plane;
+            quadrics[b] = quadrics[b] + plane;
+          }
+        }
+      }
+    }
+
+    let mut versions = vec![0u32; vertex_count];
+    let mut heap: BinaryHeap<Collapse> = edge_faces
+      .keys()
+      .map(|&(a, b)| Collapse::new(a, b, &positions, &quadrics, &versions))
+      .collect();
+    let neighbors = |incident: &[HashSet<usize>], triangles: &[Option<[usize; 3]>], v: usize| -> HashSet<usize> {
+      incident[v]
+        .iter()
+        .flat_map(|&t| triangles[t].unwrap())
+        .filter(|&w| w != v)
+        .collect()
+    };
+    let mut remaining = triangles.len();
+    while remaining > target_triangle_count {
+      let collapse = match heap.pop() {
+        Some(collapse) => collapse,
+        None => break,
+      };
+      let (a, b, position) = (collapse.a, collapse.b, collapse.position);
+      if (versions[a], versions[b]) != collapse.versions {
+        continue;
+      }
+      let shared: Vec<usize> = incident[a].intersection(&incident[b]).copied().collect();
+      if shared.is_empty() || remaining - shared.len() < target_triangle_count {
+        continue;
+      }
+      // Ends with more common neighbours than shared faces would pinch the surface into a non-manifold edge
+      let common = neighbors(&incident, &triangles, a)
+        .intersection(&neighbors(&incident, &triangles, b))
+        .count();
+      if common != shared.len() {
+        continue;
+      }
+      let folds = incident[a]
+        .union(&incident[b])
+        .filter(|t| !shared.contains(t))
+        .any(|&t| {
+          let triangle = triangles[t].unwrap();
+          let before = face_normal(triangle.map(|v| positions[v]));
+          let after = face_normal(triangle.map(|v| if v == a || v == b { position } else { positions[v] }));
+          before.dot(after) <= 0.0
+        });
+      if folds {
+        continue;
+      }
+
+      for &t in &shared {
+        for v in triangles[t].take().unwrap() {
+          incident[v].remove(&t);
+        }
+      }
+      remaining -= shared.len();
+      for t in std::mem::take(&mut incident[b]) {
+        for v in triangles[t].as_mut().unwrap().iter_mut().filter(|v| **v == b) {
+          *v = a;
+        }
+        incident[a].insert(t);
+      }
+      positions[a] = position;
+      quadrics[a] = quadrics[a] + quadrics[b];
+      if let (Some(&na), Some(&nb)) = (normals.get(a), normals.get(b)) {
+        if (na + nb).magnitude2() > 0.0 {
+          normals[a] = (na + nb).normalize();
+        }
+      }
+      versions[a] += 1;
+      versions[b] += 1;
+      for n in neighbors(&incident, &triangles, a) {
+        heap.push(Collapse::new(a, n, &positions, &quadrics, &versions));
+      }
+    }
+
+    // Keep only the vertices still in use, in their original order
+    let used: HashSet<usize> = triangles.iter().flatten().flatten().copied().collect();
+    let mut remap = vec![0; vertex_count];
+    let mut geometry = Geometry::default();
+    for v in (0..vertex_count).filter(|v| used.contains(v)) {
+      remap[v] = geometry.vertices.len() as u32;
+      geometry.vertices.push(Point3::from_vec(positions[v].cast().unwrap()));
+      if let Some(&normal) = normals.get(v) {
+        geometry.normals.push(normal);
+      }
+    }
+    geometry.indices = triangles
+      .iter()
+      .flatten()
+      .flat_map(|triangle| triangle.map(|v| remap[v]))
+      .collect();
+    geometry
+  }
+}
+
+/// Error quadric of Garland and Heckbert: the summed squared distance of a point to a set of planes is `vᵀ Q v` for
+/// the point in homogeneous coordinates `v`.
+#[derive(Debug, Clone, Copy)]
+struct Quadric(Matrix4<f64>);
+
+impl Quadric {
+  /// Plane `normal · p + d = 0` scaled by `weight`, with `normal` of unit length.
+  fn plane(normal: Vector3<f64>, d: f64, weight: f64) -> Self {
+    let p = normal.extend(d);
+    Quadric(Matrix4::from_cols(p * p.x, p * p.y, p * p.z, p * p.w) * weight)
+  }
+
+  fn error(&self, position: Vector3<f64>) -> f64 {
+    let v = position.extend(1.0);
+    v.dot(self.0 * v)
+  }
+
+  /// Position of least error, where the gradient of the error vanishes, or `None` if it isn't unique.
+  fn optimum(&self) -> Option<Vector3<f64>> {
+    let mut gradient = self.0;
+    gradient.x.w = 0.0;
+    gradient.y.w = 0.0;
+    gradient.z.w = 0.0;
+    gradient.w.w = 1.0;
+    if gradient.determinant().abs() < 1e-12 {
+      return None;
+    }
+    Some((gradient.invert()? * Vector4::unit_w()).truncate())
+  }
+}
+
+impl std::ops::Add for Quadric {
+  type Output = Self;
+
+  fn add(self, other: Self) -> Self {
+    Quadric(self.0 + other.0)
+  }
+}
+
+/// Candidate merge of vertex `b` into `a` at `position`, valid while neither vertex has changed since.
+#[derive(Debug)]
+struct Collapse {
+  error: f64,
+  a: usize,
+  b: usize,
+  position: Vector3<f64>,
+  versions: (u32, u32),
+}
+
+impl Collapse {
+  fn new(a: usize, b: usize, positions: &[Vector3<f64>], quadrics: &[Quadric], versions: &[u32]) -> Self {
+    let quadric = quadrics[a] + quadrics[b];
+    let midpoint = (positions[a] + positions[b]) / 2.0;
+    // Without a unique optimum the best of the ends and the midpoint will do
+    let position = quadric.optimum().unwrap_or_else(|| {
+      [positions[a], positions[b], midpoint]
+        .iter()
+        .copied()
+        .min_by(|p, q| quadric.error(*p).total_cmp(&quadric.error(*q)))
+        .unwrap()
+    });
+    Collapse {
+      error: quadric.error(position),
+      a,
+      b,
+      position,
+      versions: (versions[a], versions[b]),
+    }
+  }
+}
+
+// Ordered by least error first, making `BinaryHeap<Collapse>` a min-heap
+impl Ord for Collapse {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other.error.total_cmp(&self.error)
+  }
+}
+
+impl PartialOrd for Collapse {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for Collapse {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Collapse {}
+
+/// Unnormalized normal of the triangle through the three points.
+fn face_normal([a, b, c]: [Vector3<f64>; 3]) -> Vector3<f64> {
+  (b - a).cross(c - a)
 }
 
 pub fn uv_sphere(n: u32) -> Geometry {
@@ -26,28 +500,679 @@ pub fn uv_sphere(n: u32) -> Geometry {
       // Push indices
       if i > 0 && j > 0 {
         let idx = [i * n + j, i * n + (j - 1), (i - 1) * n + j, (i - 1) * n + (j - 1)];
-        geometry
-          .indices
-          .extend_from_slice(&[idx[0] as u16, idx[1] as u16, idx[2] as u16]);
+        geometry.indices.extend_from_slice(&[idx[0], idx[1], idx[2]]);
         if i > 1 {
-          geometry
-            .indices
-            .extend_from_slice(&[idx[3] as u16, idx[2] as u16, idx[1] as u16]);
+          geometry.indices.extend_from_slice(&[idx[3], idx[2], idx[1]]);
         }
       }
     }
     // Push indices for square that connects end of current segment to start
     if i > 0 {
       let idx = [i * n, (i + 1) * n - 1, (i - 1) * n, i * n - 1];
-      geometry
-        .indices
-        .extend_from_slice(&[idx[0] as u16, idx[1] as u16, idx[2] as u16]);
+      geometry.indices.extend_from_slice(&[idx[0], idx[1], idx[2]]);
       if i > 1 {
-        geometry
-          .indices
-          .extend_from_slice(&[idx[3] as u16, idx[2] as u16, idx[1] as u16]);
+        geometry.indices.extend_from_slice(&[idx[3], idx[2], idx[1]]);
       }
     }
   }
   geometry
 }
+
+/// Unit sphere made by repeatedly splitting the faces of an icosahedron into four, giving evenly sized triangles.
+pub fn icosphere(subdivisions: u32) -> Geometry {
+  let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+  #[rustfmt::skip]
+  let mut vertices: Vec<Point3<f32>> = vec![
+    (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+    (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+    (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+  ]
+  .into_iter()
+  .map(|vertex| Point3::from_vec(Vector3::from(vertex).normalize()))
+  .collect();
+  #[rustfmt::skip]
+  let mut indices: Vec<u16> = vec![
+    0, 11, 5, 0, 5, 1, 0, 1, 7, 0, 7, 10, 0, 10, 11,
+    1, 5, 9, 5, 11, 4, 11, 10, 2, 10, 7, 6, 7, 1, 8,
+    3, 9, 4, 3, 4, 2, 3, 2, 6, 3, 6, 8, 3, 8, 9,
+    4, 9, 5, 2, 4, 11, 6, 2, 10, 8, 6, 7, 9, 8, 1,
+  ];
+
+  for _ in 0..subdivisions {
+    let mut midpoints: HashMap<(u16, u16), u16> = HashMap::new();
+    let mut midpoint = |a: u16, b: u16| {
+      *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+        let mid = vertices[a as usize].midpoint(vertices[b as usize]);
+        vertices.push(Point3::from_vec(mid.to_vec().normalize()));
+        (vertices.len() - 1) as u16
+      })
+    };
+    indices = indices
+      .chunks_exact(3)
+      .flat_map(|triangle| {
+        let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+        let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+        [a, ab, ca, b, bc, ab, c, ca, bc, ab, bc, ca]
+      })
+      .collect();
+  }
+
+  Geometry {
+    normals: vertices.iter().map(|vertex| vertex.to_vec()).collect(),
+    vertices,
+    indices: indices.into(),
+  }
+}
+
+/// Closed cylinder about the Y axis, centred on the origin. The caps have their own vertices so they shade flat.
+pub fn cylinder(segments: u32, radius: f32, height: f32) -> Geometry {
+  let mut geometry = Geometry::default();
+  let half = height / 2.0;
+  let ring = |j: u32| {
+    let theta = (j as f32 / segments as f32) * 2.0 * std::f32::consts::PI;
+    (theta.cos(), theta.sin())
+  };
+
+  // Side wall, as pairs of bottom and top vertices
+  for j in 0..segments {
+    let (x, z) = ring(j);
+    geometry.vertices.push((radius * x, -half, radius * z).into());
+    geometry.vertices.push((radius * x, half, radius * z).into());
+    geometry.normals.push((x, 0.0, z).into());
+    geometry.normals.push((x, 0.0, z).into());
+    let next = (j + 1) % segments;
+    let idx = [2 * j, 2 * j + 1, 2 * next, 2 * next + 1];
+    geometry.indices.extend_from_slice(&[idx[0], idx[1], idx[2]]);
+    geometry.indices.extend_from_slice(&[idx[2], idx[1], idx[3]]);
+  }
+
+  // Caps, as a centre vertex followed by a ring
+  for (y, normal) in [(half, Vector3::unit_y()), (-half, -Vector3::unit_y())] {
+    let center = geometry.vertices.len() as u32;
+    geometry.vertices.push((0.0, y, 0.0).into());
+    geometry.normals.push(normal);
+    for j in 0..segments {
+      let (x, z) = ring(j);
+      geometry.vertices.push((radius * x, y, radius * z).into());
+      geometry.normals.push(normal);
+      let (a, b) = (center + 1 + j, center + 1 + (j + 1) % segments);
+      if y > 0.0 {
+        geometry.indices.extend_from_slice(&[center, b, a]);
+      } else {
+        geometry.indices.extend_from_slice(&[center, a, b]);
+      }
+    }
+  }
+  geometry
+}
+
+/// Hollow cylinder between `inner_radius` and `outer_radius`, centred on the origin with its axis along Y. The outer
+/// wall, inner wall and both annulus caps each get their own vertices per segment so the edges stay sharp.
+#[allow(dead_code)]
+pub fn ring(inner_radius: f32, outer_radius: f32, height: f32, segments: u32) -> Geometry {
+  let mut geometry = Geometry::default();
+  let half = height / 2.0;
+  let direction = |j: u32| {
+    let theta = (j as f32 / segments as f32) * 2.0 * std::f32::consts::PI;
+    Vector3::new(theta.cos(), 0.0, theta.sin())
+  };
+  // Corners are counter-clockwise seen from the side the normals face
+  let mut quad = |corners: [Point3<f32>; 4], normals: [Vector3<f32>; 4]| {
+    let base = geometry.vertices.len() as u32;
+    geometry.vertices.extend_from_slice(&corners);
+    geometry.normals.extend_from_slice(&normals);
+    geometry
+      .indices
+      .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+  };
+
+  for j in 0..segments {
+    let (d0, d1) = (direction(j), direction(j + 1));
+    let point =
+      |radius: f32, direction: Vector3<f32>, y: f32| Point3::new(radius * direction.x, y, radius * direction.z);
+    let [outer0, outer1, inner0, inner1] = [
+      (outer_radius, d0),
+      (outer_radius, d1),
+      (inner_radius, d0),
+      (inner_radius, d1),
+    ]
+    .map(|(radius, direction)| (point(radius, direction, -half), point(radius, direction, half)));
+
+    quad([outer0.0, outer0.1, outer1.1, outer1.0], [d0, d0, d1, d1]);
+    quad([inner1.0, inner1.1, inner0.1, inner0.0], [-d1, -d1, -d0, -d0]);
+    quad([inner0.1, inner1.1, outer1.1, outer0.1], [Vector3::unit_y(); 4]);
+    quad([outer0.0, outer1.0, inner1.0, inner0.0], [-Vector3::unit_y(); 4]);
+  }
+  geometry
+}
+
+/// Extrudes a convex polygon from Y = 0 up to Y = `height`. Each point `(x, y)` of the outline maps to `(x, 0, y)`
+/// and the outline must be counter-clockwise in those coordinates. Every side gets its own vertices so it shades flat.
+#[allow(dead_code)]
+pub fn extrude_polygon(points: &[Vector2<f32>], height: f32) -> Result<Geometry, GeometryError> {
+  if points.len() < 3 {
+    return Err(GeometryError::TooFewPoints(points.len()));
+  }
+  let mut geometry = Geometry::default();
+  let n = points.len();
+
+  // Side walls, as bottom and top of the edge start followed by the edge end
+  for i in 0..n {
+    let (p, q) = (points[i], points[(i + 1) % n]);
+    let edge = q - p;
+    let normal = Vector3::new(edge.y, 0.0, -edge.x).normalize();
+    let base = geometry.vertices.len() as u32;
+    for &(point, y) in &[(p, 0.0), (q, 0.0), (q, height), (p, height)] {
+      geometry.vertices.push((point.x, y, point.y).into());
+      geometry.normals.push(normal);
+    }
+    geometry
+      .indices
+      .extend_from_slice(&[base, base + 2, base + 1, base, base + 3, base + 2]);
+  }
+
+  // Caps, as a fan around the centroid
+  let centroid = points.iter().fold(Vector2::new(0.0, 0.0), |sum, &point| sum + point) / n as f32;
+  for (y, normal) in [(height, Vector3::unit_y()), (0.0, -Vector3::unit_y())] {
+    let center = geometry.vertices.len() as u32;
+    geometry.vertices.push((centroid.x, y, centroid.y).into());
+    geometry.normals.push(normal);
+    for point in points {
+      geometry.vertices.push((point.x, y, point.y).into());
+      geometry.normals.push(normal);
+    }
+    for i in 0..n as u32 {
+      let (a, b) = (center + 1 + i, center + 1 + (i + 1) % n as u32);
+      if y > 0.0 {
+        geometry.indices.extend_from_slice(&[center, b, a]);
+      } else {
+        geometry.indices.extend_from_slice(&[center, a, b]);
+      }
+    }
+  }
+  Ok(geometry)
+}
+
+/// Orthonormal frame along a curve, with `binormal = tangent × normal`. The normal and binormal span the
+/// cross-section.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+  pub tangent: Vector3<f32>,
+  pub normal: Vector3<f32>,
+  pub binormal: Vector3<f32>,
+}
+
+impl Frame {
+  /// Frame along `tangent`, which need not be normalized. The Frenet-Serret normal is undefined on straight runs and
+  /// flips at inflections, so after `previous` the normal is instead the previous one with its component along the
+  /// new tangent removed, which keeps the cross-section from twisting. A zero tangent, such as from a repeated
+  /// point, keeps the previous frame.
+  pub fn from_tangent(tangent: Vector3<f32>, previous: Option<&Frame>) -> Frame {
+    let tangent = match (tangent.magnitude2() > f32::EPSILON, previous) {
+      (true, _) => tangent.normalize(),
+      (false, Some(previous)) => return *previous,
+      (false, None) => Vector3::unit_y(),
+    };
+    let without_tangent = |v: Vector3<f32>| v - tangent * v.dot(tangent);
+    let normal = match previous {
+      Some(previous) => {
+        let normal = without_tangent(previous.normal);
+        if normal.magnitude2() > f32::EPSILON {
+          normal.normalize()
+        } else {
+          // The curve turned a right angle towards the old normal, leaving the old binormal in the cross-section
+          without_tangent(previous.binormal).normalize().cross(tangent)
+        }
+      }
+      None => {
+        let axis = if tangent.x.abs() < 0.9 {
+          Vector3::unit_x()
+        } else {
+          Vector3::unit_y()
+        };
+        without_tangent(axis).normalize()
+      }
+    };
+    Frame {
+      tangent,
+      normal,
+      binormal: tangent.cross(normal),
+    }
+  }
+}
+
+/// Tube of `radius` through `points`, with a ring of `segments` vertices around each point so consecutive sections
+/// share their joint. Repeated points are dropped. Rings face the average direction of their two sections, and the
+/// sides shade smoothly with normals pointing away from the axis. Flat disks cap both ends. Fewer than two distinct
+/// points give no geometry.
+#[allow(dead_code)]
+pub fn tube_along_spline(points: &[Point3<f32>], radius: f32, segments: u32) -> Geometry {
+  let mut geometry = Geometry::default();
+  let mut points = points.to_vec();
+  points.dedup_by(|a, b| a.distance2(*b) <= f32::EPSILON);
+  if points.len() < 2 {
+    return geometry;
+  }
+
+  let direction = |i: usize| (points[i + 1] - points[i]).normalize();
+  let mut frames = vec![Frame::from_tangent(direction(0), None)];
+  for i in 1..points.len() {
+    // A point the curve doubles back through averages to no direction, keeping the previous frame
+    let outgoing = if i + 1 < points.len() {
+      direction(i)
+    } else {
+      Vector3::zero()
+    };
+    let frame = Frame::from_tangent(direction(i - 1) + outgoing, frames.last());
+    frames.push(frame);
+  }
+
+  let offset = |frame: &Frame, j: u32| {
+    let theta = (j as f32 / segments as f32) * 2.0 * std::f32::consts::PI;
+    frame.normal * theta.cos() + frame.binormal * theta.sin()
+  };
+
+  // Side wall, as one ring per point
+  for (i, (&point, frame)) in points.iter().zip(&frames).enumerate() {
+    for j in 0..segments {
+      let normal = offset(frame, j);
+      geometry.vertices.push(point + normal * radius);
+      geometry.normals.push(normal);
+    }
+    if i > 0 {
+      let (a, b) = ((i as u32 - 1) * segments, i as u32 * segments);
+      for j in 0..segments {
+        let next = (j + 1) % segments;
+        geometry.indices.extend_from_slice(&[a + j, a + next, b + j]);
+        geometry.indices.extend_from_slice(&[a + next, b + next, b + j]);
+      }
+    }
+  }
+
+  // Caps, as a centre vertex followed by a ring
+  let last = points.len() - 1;
+  for (point, frame, end) in [(points[0], frames[0], false), (points[last], frames[last], true)] {
+    let normal = if end { frame.tangent } else { -frame.tangent };
+    let center = geometry.vertices.len() as u32;
+    geometry.vertices.push(point);
+    geometry.normals.push(normal);
+    for j in 0..segments {
+      geometry.vertices.push(point + offset(&frame, j) * radius);
+      geometry.normals.push(normal);
+      let (a, b) = (center + 1 + j, center + 1 + (j + 1) % segments);
+      if end {
+        geometry.indices.extend_from_slice(&[center, a, b]);
+      } else {
+        geometry.indices.extend_from_slice(&[center, b, a]);
+      }
+    }
+  }
+  geometry
+}
+
+/// Square from -1 to 1 in the XY plane facing +Z, which covers the screen when used as clip coordinates.
+pub fn fullscreen_quad() -> Geometry {
+  Geometry {
+    vertices: vec![
+      (-1.0, -1.0, 0.0).into(),
+      (1.0, -1.0, 0.0).into(),
+      (1.0, 1.0, 0.0).into(),
+      (-1.0, 1.0, 0.0).into(),
+    ],
+    normals: vec![Vector3::unit_z(); 4],
+    indices: vec![0u16, 1, 2, 0, 2, 3].into(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// Asserts every triangle winds counter-clockwise when seen from the side its vertex normals face.
+  fn assert_outward(geometry: &Geometry) {
+    for triangle in geometry.indices.triangles() {
+      let [a, b, c] = triangle.map(|i| i as usize);
+      let face = (geometry.vertices[b] - geometry.vertices[a]).cross(geometry.vertices[c] - geometry.vertices[a]);
+      let normal = geometry.normals[a] + geometry.normals[b] + geometry.normals[c];
+      assert!(face.dot(normal) > 0.0, "triangle {:?} winds inward", triangle);
+    }
+  }
+
+  /// Closed unit sphere of `segments` around and `rings` of latitude between single pole vertices, with
+  /// `2 * segments * rings` triangles.
+  fn closed_sphere(segments: u32, rings: u32) -> Geometry {
+    let mut geometry = Geometry::default();
+    let mut push = |vertex: Vector3<f32>| {
+      geometry.vertices.push(Point3::from_vec(vertex));
+      geometry.normals.push(vertex);
+    };
+    push(Vector3::unit_y());
+    for i in 1..=rings {
+      let phi = i as f32 / (rings + 1) as f32 * std::f32::consts::PI;
+      for j in 0..segments {
+        let theta = j as f32 / segments as f32 * 2.0 * std::f32::consts::PI;
+        push(Vector3::new(
+          phi.sin() * theta.cos(),
+          phi.cos(),
+          phi.sin() * theta.sin(),
+        ));
+      }
+    }
+    push(-Vector3::unit_y());
+    let bottom = segments * rings + 1;
+    let ring = |i: u32, j: u32| 1 + i * segments + j % segments;
+    let mut indices = Vec::new();
+    for j in 0..segments {
+      indices.extend_from_slice(&[0, ring(0, j + 1), ring(0, j)]);
+      for i in 0..rings - 1 {
+        indices.extend_from_slice(&[ring(i, j), ring(i, j + 1), ring(i + 1, j)]);
+        indices.extend_from_slice(&[ring(i + 1, j), ring(i, j + 1), ring(i + 1, j + 1)]);
+      }
+      indices.extend_from_slice(&[ring(rings - 1, j), ring(rings - 1, j + 1), bottom]);
+    }
+    geometry.indices = indices.into_iter().collect();
+    geometry
+  }
+
+  #[test]
+  fn decimate_test() {
+    let sphere = closed_sphere(10, 5);
+    assert_eq!(sphere.indices.len() / 3, 100);
+    assert_outward(&sphere);
+
+    let decimated = sphere.decimate(20);
+    assert_eq!(decimated.indices.len() / 3, 20);
+    // Still a closed surface, with every edge shared by two triangles
+    let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+    for [a, b, c] in decimated.indices.triangles() {
+      for (v, w) in [(a, b), (b, c), (c, a)] {
+        *edges.entry((v.min(w), v.max(w))).or_default() += 1;
+      }
+    }
+    assert!(edges.values().all(|&count| count == 2), "{:?}", edges);
+    // Euler characteristic of a sphere
+    assert_eq!(decimated.vertices.len() as i64 - edges.len() as i64 + 20, 2);
+    assert_eq!(decimated.normals.len(), decimated.vertices.len());
+    assert_outward(&decimated);
+    for vertex in &decimated.vertices {
+      let radius = vertex.to_vec().magnitude();
+      assert!(radius > 0.5 && radius < 1.5, "{:?}", vertex);
+    }
+
+    // Asking for more triangles than there are changes nothing
+    assert_eq!(sphere.decimate(200).indices, sphere.indices);
+  }
+
+  #[test]
+  fn decimate_plane_test() {
+    // A flat grid loses its inner vertices without moving off the plane or shrinking its border
+    let n = 5;
+    let mut grid = Geometry::default();
+    for i in 0..=n {
+      for j in 0..=n {
+        grid.vertices.push(Point3::new(i as f32, j as f32, 0.0));
+        grid.normals.push(Vector3::unit_z());
+      }
+    }
+    let idx = |i: u32, j: u32| i * (n + 1) + j;
+    let mut indices = Vec::new();
+    for i in 0..n {
+      for j in 0..n {
+        indices.extend_from_slice(&[idx(i, j), idx(i + 1, j), idx(i + 1, j + 1)]);
+        indices.extend_from_slice(&[idx(i, j), idx(i + 1, j + 1), idx(i, j + 1)]);
+      }
+    }
+    grid.indices = indices.into_iter().collect();
+    let area = grid.compute_surface_area();
+
+    let decimated = grid.decimate(10);
+    assert!(decimated.indices.len() / 3 <= 12, "{}", decimated.indices.len() / 3);
+    assert!(decimated.vertices.iter().all(|vertex| vertex.z.abs() < 0.0001));
+    assert!((decimated.compute_surface_area() - area).abs() < 0.01);
+    assert_outward(&decimated);
+  }
+
+  #[test]
+  fn uv_sphere_apply_transform_test() {
+    let mut geometry = uv_sphere(10);
+    let original = uv_sphere(10);
+    geometry.apply_transform(Matrix4::from_scale(2.0));
+    for (vertex, original) in geometry.vertices.iter().zip(original.vertices.iter()) {
+      assert!((vertex.to_vec().magnitude() - 2.0 * original.to_vec().magnitude()).abs() < 0.00001);
+    }
+    for (normal, original) in geometry.normals.iter().zip(original.normals.iter()) {
+      assert!((normal - original.normalize()).magnitude() < 0.00001);
+    }
+    assert_eq!(geometry.indices, original.indices);
+
+    // Squashing keeps normals perpendicular to the surface and mirroring keeps triangles facing outward
+    let mut geometry = cylinder(8, 1.0, 1.0);
+    geometry.apply_transform(
+      Matrix4::from_translation((0.0, 3.0, 0.0).into()) * Matrix4::from_nonuniform_scale(-1.0, 0.5, 2.0),
+    );
+    assert!(geometry
+      .normals
+      .iter()
+      .all(|normal| (normal.magnitude() - 1.0).abs() < 0.00001));
+    assert!(geometry
+      .vertices
+      .iter()
+      .all(|vertex| (vertex.y - 3.0).abs() <= 0.25 + 0.00001));
+    assert_outward(&geometry);
+
+    let mut flat = fullscreen_quad();
+    flat.apply_transform(Matrix4::from_nonuniform_scale(1.0, 1.0, 0.0));
+    assert_eq!(flat.normals, fullscreen_quad().normals);
+  }
+
+  #[test]
+  fn icosphere_test() {
+    let geometry = icosphere(2);
+    assert_eq!(geometry.indices.len(), 20 * 16 * 3);
+    assert_eq!(geometry.vertices.len(), 162);
+    assert!(geometry
+      .vertices
+      .iter()
+      .all(|vertex| (vertex.to_vec().magnitude() - 1.0).abs() < 0.00001));
+    assert_outward(&geometry);
+  }
+
+  #[test]
+  fn cylinder_test() {
+    let geometry = cylinder(16, 1.0, 0.1);
+    assert_eq!(geometry.vertices.len(), 16 * 2 + 2 * 17);
+    assert_eq!(geometry.indices.len(), 16 * 4 * 3);
+    assert_outward(&geometry);
+  }
+
+  #[test]
+  fn ring_test() {
+    let segments = 12;
+    let geometry = ring(0.5, 1.0, 0.2, segments);
+    assert_eq!(geometry.vertices.len() as u32, 4 * segments * 4);
+    assert_eq!(geometry.indices.len() as u32, 4 * segments * 6);
+    assert_outward(&geometry);
+    // The outer wall is the first quad of each segment
+    let outer_wall = (0..geometry.vertices.len()).filter(|i| i % 16 < 4);
+    for (vertex, normal) in outer_wall.map(|i| (geometry.vertices[i], geometry.normals[i])) {
+      assert!((vertex.x.powi(2) + vertex.z.powi(2) - 1.0).abs() < 0.00001);
+      assert!(normal.x.hypot(normal.z) > 0.0);
+      assert!(vertex.to_vec().dot(normal) > 0.0);
+    }
+  }
+
+  #[test]
+  fn tube_along_spline_test() {
+    let (points, segments) = (64, 8);
+    // Two turns of a unit helix climbing one unit per turn
+    let helix: Vec<Point3<f32>> = (0..points)
+      .map(|i| {
+        let t = i as f32 / (points - 1) as f32 * 4.0 * std::f32::consts::PI;
+        Point3::new(t.cos(), t / (2.0 * std::f32::consts::PI), t.sin())
+      })
+      .collect();
+    let geometry = tube_along_spline(&helix, 0.1, segments);
+    assert_eq!(geometry.vertices.len() as u32, points * segments + 2 * (segments + 1));
+    assert_eq!(
+      geometry.indices.len() as u32,
+      ((points - 1) * segments * 2 + 2 * segments) * 3
+    );
+    assert_outward(&geometry);
+
+    // Each ring sits on the tube surface around its point, with normals pointing away from it
+    for (i, center) in helix.iter().enumerate() {
+      for j in 0..segments as usize {
+        let idx = i * segments as usize + j;
+        let (vertex, normal) = (geometry.vertices[idx], geometry.normals[idx]);
+        assert!(((vertex - center).magnitude() - 0.1).abs() < 0.00001);
+        assert!((vertex - center).normalize().dot(normal) > 0.9999);
+      }
+    }
+    // End caps face along the curve, away from the tube
+    let caps = (points * segments) as usize;
+    let start = (helix[1] - helix[0]).normalize();
+    let end = (helix[points as usize - 1] - helix[points as usize - 2]).normalize();
+    for normal in &geometry.normals[caps..caps + segments as usize + 1] {
+      assert!((normal + start).magnitude() < 0.00001);
+    }
+    for normal in &geometry.normals[caps + segments as usize + 1..] {
+      assert!((normal - end).magnitude() < 0.00001);
+    }
+    // Consecutive rings barely rotate about the axis
+    for ring in 1..points as usize {
+      let (a, b) = (
+        geometry.normals[(ring - 1) * segments as usize],
+        geometry.normals[ring * segments as usize],
+      );
+      assert!(a.dot(b) > 0.95);
+    }
+  }
+
+  #[test]
+  fn tube_degenerate_test() {
+    assert!(tube_along_spline(&[], 1.0, 8).vertices.is_empty());
+    assert!(tube_along_spline(&[Point3::new(1.0, 2.0, 3.0); 3], 1.0, 8)
+      .vertices
+      .is_empty());
+    // Colinear points keep the frame of the point before, and repeated ones are dropped
+    let line = [
+      Point3::new(0.0, 0.0, 0.0),
+      Point3::new(0.0, 0.0, 0.0),
+      Point3::new(1.0, 0.0, 0.0),
+      Point3::new(2.0, 0.0, 0.0),
+      Point3::new(2.0, 0.0, 0.0),
+    ];
+    let geometry = tube_along_spline(&line, 0.5, 4);
+    assert_eq!(geometry.vertices.len(), 3 * 4 + 2 * 5);
+    assert_outward(&geometry);
+    for ring in 1..3 {
+      assert_eq!(geometry.normals[ring * 4], geometry.normals[0]);
+    }
+    assert!(geometry.vertices.iter().all(|vertex| vertex.x.is_finite()));
+
+    // A right angle turn towards the old normal still gives an orthonormal frame
+    let frame = Frame::from_tangent(Vector3::unit_x(), None);
+    let turned = Frame::from_tangent(frame.normal, Some(&frame));
+    assert!((turned.tangent.cross(turned.normal) - turned.binormal).magnitude() < 0.00001);
+    assert!(turned.normal.dot(turned.tangent).abs() < 0.00001);
+    assert!((turned.normal.magnitude() - 1.0).abs() < 0.00001);
+  }
+
+  #[test]
+  fn extrude_polygon_test() {
+    let square = [
+      Vector2::new(0.0, 0.0),
+      Vector2::new(1.0, 0.0),
+      Vector2::new(1.0, 1.0),
+      Vector2::new(0.0, 1.0),
+    ];
+    let geometry = extrude_polygon(&square, 2.0).unwrap();
+    // 8 side triangles, 4 in each cap fan
+    assert_eq!(geometry.indices.len(), (8 + 4 + 4) * 3);
+    assert_outward(&geometry);
+    let center = Point3::new(0.5, 1.0, 0.5);
+    for (vertex, normal) in geometry.vertices.iter().zip(geometry.normals.iter()) {
+      assert!((vertex - center).dot(*normal) > 0.0);
+    }
+    assert!(extrude_polygon(&square[..2], 1.0).is_err());
+  }
+
+  #[test]
+  fn fullscreen_quad_test() {
+    let quad = fullscreen_quad();
+    assert_eq!(quad.indices.len(), 6);
+    assert!((quad.compute_surface_area() - 4.0).abs() < 0.00001);
+    assert_outward(&quad);
+  }
+
+  #[test]
+  fn wireframe_lines_test() {
+    let quad = Geometry {
+      vertices: vec![
+        (0.0, 0.0, 0.0).into(),
+        (1.0, 0.0, 0.0).into(),
+        (1.0, 1.0, 0.0).into(),
+        (0.0, 1.0, 0.0).into(),
+      ],
+      normals: vec![Vector3::unit_z(); 4],
+      indices: vec![0u16, 1, 2, 0, 2, 3].into(),
+    };
+    let lines = quad.to_wireframe_lines();
+    assert_eq!(lines.vertices.len(), 4);
+    // 4 sides plus the shared diagonal
+    assert_eq!(lines.indices.to_vec(), vec![0, 1, 1, 2, 2, 0, 2, 3, 3, 0]);
+  }
+
+  #[test]
+  fn weld_vertices_test() {
+    let mut geometry = Geometry {
+      vertices: vec![
+        (0.0, 0.0, 0.0).into(),
+        (1.0, 0.0, 0.0).into(),
+        (0.0, 1.0, 0.0).into(),
+        (1.0 + 1e-6, 0.0, 0.0).into(),
+        (1.0, 1.0, 0.0).into(),
+      ],
+      normals: vec![Vector3::unit_z(); 5],
+      indices: vec![0u16, 1, 2, 3, 4, 2].into(),
+    };
+    geometry.weld_vertices(1e-4);
+    assert_eq!(geometry.vertices.len(), 4);
+    assert_eq!(geometry.normals.len(), 4);
+    assert_eq!(geometry.indices.to_vec(), vec![0, 1, 2, 1, 3, 2]);
+  }
+
+  #[test]
+  fn weld_uv_sphere_poles_test() {
+    let n = 10;
+    let mut geometry = uv_sphere(n);
+    geometry.weld_vertices(1e-4);
+    // Each pole ring collapses to a single vertex
+    assert_eq!(geometry.vertices.len() as u32, (n + 1) * n - 2 * (n - 1));
+    assert!(geometry
+      .indices
+      .iter()
+      .all(|idx| (idx as usize) < geometry.vertices.len()));
+  }
+
+  #[test]
+  fn index_buffer_widen_test() {
+    let mut indices = IndexBuffer::default();
+    indices.extend_from_slice(&[0, 1, 2]);
+    assert_eq!(indices.format(), wgpu::IndexFormat::Uint16);
+    indices.extend_from_slice(&[2, 1, 70_000]);
+    assert_eq!(indices, IndexBuffer::U32(vec![0, 1, 2, 2, 1, 70_000]));
+    assert_eq!(indices.as_bytes().len(), 6 * 4);
+    assert_eq!(indices.triangles().collect::<Vec<_>>(), vec![[0, 1, 2], [2, 1, 70_000]]);
+    // Collecting narrows again when everything fits
+    assert_eq!(
+      vec![3u32, 4, 5].into_iter().collect::<IndexBuffer>(),
+      IndexBuffer::U16(vec![3, 4, 5])
+    );
+
+    let sphere = uv_sphere(300);
+    assert_eq!(sphere.indices.format(), wgpu::IndexFormat::Uint32);
+    assert!(sphere.indices.iter().any(|idx| idx > u16::MAX as u32));
+    assert!(sphere.indices.iter().all(|idx| (idx as usize) < sphere.vertices.len()));
+  }
+}
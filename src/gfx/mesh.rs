@@ -1,7 +1,8 @@
-use super::geometry::Geometry;
+use super::geometry::{Geometry, IndexBuffer};
 
-use cgmath::{Point3, Vector2, Vector3};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2, Vector3};
 
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 
 #[allow(dead_code)]
@@ -10,12 +11,15 @@ pub enum MeshFile<'a> {
   File(&'a Path),
 }
 
+#[allow(dead_code)]
 pub struct Mesh {
   pub id: String,
   pub vertices: Vec<Point3<f32>>,
-  pub indices: Option<Vec<u16>>,
+  pub indices: Option<IndexBuffer>,
   pub normals: Option<Vec<Vector3<f32>>>,
   pub uv_coords: Option<Vec<Vector2<f32>>>,
+  /// Material of each triangle; every triangle is material 0 when absent
+  pub material_ids: Option<Vec<u8>>,
 }
 
 impl From<Geometry> for Mesh {
@@ -26,6 +30,195 @@ impl From<Geometry> for Mesh {
       normals: Some(geometry.normals),
       indices: Some(geometry.indices),
       uv_coords: None,
+      material_ids: None,
+    }
+  }
+}
+
+impl Mesh {
+  /// Vertex index triples of each triangle, taken from `indices` or consecutive vertices when unindexed.
+  fn triangle_indices(&self) -> Vec<[u32; 3]> {
+    match &self.indices {
+      Some(indices) => indices.triangles().collect(),
+      None => (0..self.vertices.len() as u32 / 3)
+        .map(|t| [3 * t, 3 * t + 1, 3 * t + 2])
+        .collect(),
     }
   }
+
+  /// Vertex triples of each triangle.
+  fn triangles(&self) -> Vec<[Point3<f32>; 3]> {
+    self
+      .triangle_indices()
+      .into_iter()
+      .map(|t| t.map(|i| self.vertices[i as usize]))
+      .collect()
+  }
+
+  /// Splits the triangles into one indexed mesh per material, in ascending material order. Each sub-mesh only keeps
+  /// the vertices its triangles use.
+  #[allow(dead_code)]
+  pub fn split_by_material(&self) -> Vec<(u8, Mesh)> {
+    let mut groups: BTreeMap<u8, Vec<[u32; 3]>> = BTreeMap::new();
+    for (t, triangle) in self.triangle_indices().into_iter().enumerate() {
+      let material = self
+        .material_ids
+        .as_ref()
+        .and_then(|ids| ids.get(t).copied())
+        .unwrap_or(0);
+      groups.entry(material).or_default().push(triangle);
+    }
+    groups
+      .into_iter()
+      .map(|(material, triangles)| {
+        let mut remap = HashMap::new();
+        let mut used = Vec::new();
+        let mut indices = IndexBuffer::default();
+        for triangle in &triangles {
+          let triangle = triangle.map(|old| {
+            *remap.entry(old).or_insert_with(|| {
+              used.push(old as usize);
+              used.len() as u32 - 1
+            })
+          });
+          indices.extend_from_slice(&triangle);
+        }
+        let mesh = Mesh {
+          id: self.id.clone(),
+          vertices: used.iter().map(|&i| self.vertices[i]).collect(),
+          indices: Some(indices),
+          normals: self
+            .normals
+            .as_ref()
+            .map(|normals| used.iter().map(|&i| normals[i]).collect()),
+          uv_coords: self
+            .uv_coords
+            .as_ref()
+            .map(|uvs| used.iter().map(|&i| uvs[i]).collect()),
+          material_ids: Some(vec![material; triangles.len()]),
+        };
+        (material, mesh)
+      })
+      .collect()
+  }
+
+  /// Concatenates `meshes` into one indexed mesh whose triangles keep the material they are paired with, the inverse
+  /// of `split_by_material`. Normals and UVs are only kept if every mesh has them.
+  #[allow(dead_code)]
+  pub fn merge_by_material(meshes: Vec<(u8, Mesh)>) -> Mesh {
+    let keep_normals = meshes.iter().all(|(_, mesh)| mesh.normals.is_some());
+    let keep_uvs = meshes.iter().all(|(_, mesh)| mesh.uv_coords.is_some());
+    let mut merged = Mesh {
+      id: meshes.first().map(|(_, mesh)| mesh.id.clone()).unwrap_or_default(),
+      vertices: Vec::new(),
+      indices: Some(IndexBuffer::default()),
+      normals: if keep_normals { Some(Vec::new()) } else { None },
+      uv_coords: if keep_uvs { Some(Vec::new()) } else { None },
+      material_ids: Some(Vec::new()),
+    };
+    for (material, mesh) in meshes {
+      let base = merged.vertices.len() as u32;
+      let triangles = mesh.triangle_indices();
+      if let (Some(indices), Some(ids)) = (&mut merged.indices, &mut merged.material_ids) {
+        for triangle in &triangles {
+          indices.extend_from_slice(&triangle.map(|i| base + i));
+        }
+        ids.resize(ids.len() + triangles.len(), material);
+      }
+      if let (Some(normals), Some(mesh_normals)) = (&mut merged.normals, mesh.normals) {
+        normals.extend(mesh_normals);
+      }
+      if let (Some(uvs), Some(mesh_uvs)) = (&mut merged.uv_coords, mesh.uv_coords) {
+        uvs.extend(mesh_uvs);
+      }
+      merged.vertices.extend(mesh.vertices);
+    }
+    merged
+  }
+
+  #[allow(dead_code)]
+  pub fn compute_surface_area(&self) -> f32 {
+    self
+      .triangles()
+      .iter()
+      .map(|[a, b, c]| 0.5 * (b - a).cross(c - a).magnitude())
+      .sum()
+  }
+
+  /// Sum of the signed volumes of the tetrahedra between the origin and each triangle. For a closed mesh wound
+  /// counter-clockwise from outside this is its volume; otherwise the result depends on the origin.
+  #[allow(dead_code)]
+  pub fn compute_volume(&self) -> f32 {
+    self
+      .triangles()
+      .iter()
+      .map(|[a, b, c]| a.to_vec().dot(b.to_vec().cross(c.to_vec())) / 6.0)
+      .sum()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::gfx::geometry;
+
+  #[test]
+  fn surface_area_test() {
+    let four_pi = 4.0 * std::f32::consts::PI;
+    let coarse = (Mesh::from(geometry::uv_sphere(8)).compute_surface_area() - four_pi).abs();
+    let fine = (Mesh::from(geometry::uv_sphere(64)).compute_surface_area() - four_pi).abs();
+    assert!(fine < coarse);
+    assert!(fine < 0.02);
+    let empty = Mesh::from(geometry::Geometry::default());
+    assert_eq!(empty.compute_surface_area(), 0.0);
+  }
+
+  #[test]
+  fn unindexed_area_test() {
+    let mut mesh = Mesh::from(geometry::fullscreen_quad());
+    mesh.vertices = mesh
+      .indices
+      .take()
+      .unwrap()
+      .iter()
+      .map(|i| mesh.vertices[i as usize])
+      .collect();
+    assert!((mesh.compute_surface_area() - 4.0).abs() < 0.00001);
+  }
+
+  #[test]
+  fn volume_test() {
+    let sphere = Mesh::from(geometry::icosphere(4));
+    let expected = 4.0 / 3.0 * std::f32::consts::PI;
+    assert!((sphere.compute_volume() - expected).abs() < 0.02);
+    let square = [
+      Vector2::new(0.0, 0.0),
+      Vector2::new(2.0, 0.0),
+      Vector2::new(2.0, 1.0),
+      Vector2::new(0.0, 1.0),
+    ];
+    let prism = Mesh::from(geometry::extrude_polygon(&square, 3.0).unwrap());
+    assert!((prism.compute_volume() - 6.0).abs() < 0.0001);
+  }
+
+  #[test]
+  fn split_by_material_test() {
+    let mut mesh = Mesh::from(geometry::fullscreen_quad());
+    mesh.material_ids = Some(vec![2, 5]);
+    let split = mesh.split_by_material();
+    assert_eq!(split.len(), 2);
+    let (first, second) = (&split[0], &split[1]);
+    assert_eq!((first.0, second.0), (2, 5));
+    for (_, part) in &split {
+      assert_eq!(part.vertices.len(), 3);
+      assert_eq!(part.indices.as_ref().unwrap().to_vec(), vec![0, 1, 2]);
+      assert_eq!(part.normals.as_ref().unwrap().len(), 3);
+      assert!((part.compute_surface_area() - 2.0).abs() < 0.00001);
+    }
+    assert_eq!(first.1.vertices, mesh.vertices[..3].to_vec());
+
+    let merged = Mesh::merge_by_material(split);
+    assert_eq!(merged.material_ids, Some(vec![2, 5]));
+    assert_eq!(merged.triangles(), mesh.triangles());
+  }
 }
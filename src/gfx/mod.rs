@@ -1,6 +1,8 @@
 pub mod camera;
 pub mod geometry;
 pub mod mesh;
+pub mod profiler;
 pub mod renderer;
 pub mod shader;
+pub mod text;
 pub mod texture;
@@ -0,0 +1,246 @@
+use futures::FutureExt;
+use wgpu::{Buffer, BufferAsyncError, CommandEncoder, Device, QuerySet, Queue};
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Each scope takes a begin and an end timestamp.
+const MAX_SCOPES: u32 = 16;
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+/// Frames whose timestamps can be waiting to be read back at once
+const READBACK_FRAMES: usize = 3;
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), BufferAsyncError>> + Send>>;
+
+/// Buffer one frame's timestamps are resolved into and read back from.
+struct Readback {
+  buffer: Buffer,
+  /// Scopes of the frame resolved into `buffer` and the mapping that reads them, until they are read
+  pending: Option<(Vec<&'static str>, MapFuture)>,
+}
+
+struct TimestampQueries {
+  query_set: QuerySet,
+  readbacks: Vec<Readback>,
+  /// Readback this frame resolves into
+  next: usize,
+  /// Whether this frame was resolved into `next`
+  resolved: bool,
+  /// Nanoseconds per timestamp tick
+  period: f32,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Accumulated {
+  total: Duration,
+  frames: u32,
+}
+
+/// Times labelled scopes of a frame on the GPU with timestamp queries. Timings are read back without waiting on the
+/// GPU, so they lag the frame being recorded by a frame or two. Does nothing on devices without
+/// `Features::TIMESTAMP_QUERY`.
+pub struct GpuProfiler {
+  queries: Option<TimestampQueries>,
+  /// Labels of the scopes written this frame, in query order
+  scopes: Vec<&'static str>,
+  open: Vec<usize>,
+  timings: HashMap<&'static str, Duration>,
+  accumulated: HashMap<&'static str, Accumulated>,
+}
+
+impl GpuProfiler {
+  pub fn new(device: &Device, queue: &Queue) -> Self {
+    let queries = if device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+      Some(TimestampQueries {
+        query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+          label: Some("Profiler Queries"),
+          ty: wgpu::QueryType::Timestamp,
+          count: MAX_SCOPES * 2,
+        }),
+        readbacks: (0..READBACK_FRAMES)
+          .map(|_| Readback {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+              label: Some("Profiler Buffer"),
+              size: MAX_SCOPES as u64 * 2 * TIMESTAMP_SIZE,
+              usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+              mapped_at_creation: false,
+            }),
+            pending: None,
+          })
+          .collect(),
+        next: 0,
+        resolved: false,
+        period: queue.get_timestamp_period(),
+      })
+    } else {
+      None
+    };
+    Self::with_queries(queries)
+  }
+
+  /// A profiler that never records anything.
+  pub fn disabled() -> Self {
+    Self::with_queries(None)
+  }
+
+  fn with_queries(queries: Option<TimestampQueries>) -> Self {
+    Self {
+      queries,
+      scopes: Vec::new(),
+      open: Vec::new(),
+      timings: HashMap::new(),
+      accumulated: HashMap::new(),
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.queries.is_some()
+  }
+
+  /// Starts timing the commands recorded into `encoder` as `label`. Scopes past `MAX_SCOPES` in a frame are ignored.
+  pub fn begin_scope(&mut self, label: &'static str, encoder: &mut CommandEncoder) {
+    if let Some(queries) = &self.queries {
+      if self.scopes.len() < MAX_SCOPES as usize {
+        encoder.write_timestamp(&queries.query_set, self.scopes.len() as u32 * 2);
+        self.open.push(self.scopes.len());
+        self.scopes.push(label);
+      }
+    }
+  }
+
+  /// Ends the innermost open scope.
+  pub fn end_scope(&mut self, encoder: &mut CommandEncoder) {
+    if let (Some(queries), Some(scope)) = (&self.queries, self.open.pop()) {
+      encoder.write_timestamp(&queries.query_set, scope as u32 * 2 + 1);
+    }
+  }
+
+  /// Copies this frame's timestamps out of the query set. Call once all scopes have ended, before finishing
+  /// `encoder`. The frame goes untimed if every readback buffer is still waiting to be read.
+  pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+    if let Some(queries) = &mut self.queries {
+      let readback = &queries.readbacks[queries.next];
+      queries.resolved = !self.scopes.is_empty() && readback.pending.is_none();
+      if queries.resolved {
+        encoder.resolve_query_set(&queries.query_set, 0..self.scopes.len() as u32 * 2, &readback.buffer, 0);
+      }
+    }
+  }
+
+  /// Starts reading back the frame submitted after `resolve` and records the times of any earlier frames that have
+  /// finished, without waiting for the GPU.
+  pub fn collect(&mut self, device: &Device) {
+    let queries = match &mut self.queries {
+      Some(queries) => queries,
+      None => return,
+    };
+    device.poll(wgpu::Maintain::Poll);
+    for readback in &mut queries.readbacks {
+      let mapped = match &mut readback.pending {
+        Some((_, mapping)) => mapping.now_or_never(),
+        None => None,
+      };
+      if let Some(result) = mapped {
+        let (scopes, _) = readback.pending.take().unwrap();
+        if result.is_ok() {
+          let size = scopes.len() as u64 * 2 * TIMESTAMP_SIZE;
+          let ticks: Vec<u64> = bytemuck::cast_slice(&readback.buffer.slice(..size).get_mapped_range()).to_vec();
+          readback.buffer.unmap();
+          self.timings = scope_durations(&scopes, &ticks, queries.period);
+          for (&label, &duration) in &self.timings {
+            let accumulated = self.accumulated.entry(label).or_default();
+            accumulated.total += duration;
+            accumulated.frames += 1;
+          }
+        }
+      }
+    }
+    if queries.resolved {
+      let readback = &mut queries.readbacks[queries.next];
+      let size = self.scopes.len() as u64 * 2 * TIMESTAMP_SIZE;
+      let mapping = readback.buffer.slice(..size).map_async(wgpu::MapMode::Read);
+      readback.pending = Some((std::mem::take(&mut self.scopes), Box::pin(mapping)));
+      queries.next = (queries.next + 1) % READBACK_FRAMES;
+      queries.resolved = false;
+    }
+    self.scopes.clear();
+    self.open.clear();
+  }
+
+  /// Time each scope took in the last collected frame, by label.
+  pub fn timings(&self) -> &HashMap<&'static str, Duration> {
+    &self.timings
+  }
+
+  /// Table of the last and mean time of every scope seen so far, slowest first.
+  pub fn summary(&self) -> String {
+    let mut labels: Vec<_> = self.accumulated.iter().collect();
+    labels.sort_by_key(|(_, accumulated)| Reverse(accumulated.total));
+    let mut summary = format!("{:<16} {:>10} {:>10} {:>8}\n", "pass", "last ms", "mean ms", "frames");
+    for (label, accumulated) in labels {
+      let last = self.timings.get(label).copied().unwrap_or_default();
+      let mean = accumulated.total / accumulated.frames;
+      let _ = writeln!(
+        summary,
+        "{:<16} {:>10.3} {:>10.3} {:>8}",
+        label,
+        last.as_secs_f64() * 1000.0,
+        mean.as_secs_f64() * 1000.0,
+        accumulated.frames
+      );
+    }
+    summary
+  }
+}
+
+/// Converts begin and end `ticks` of each scope into durations, summing scopes that share a label.
+fn scope_durations(scopes: &[&'static str], ticks: &[u64], period: f32) -> HashMap<&'static str, Duration> {
+  let mut durations = HashMap::new();
+  for (&label, pair) in scopes.iter().zip(ticks.chunks_exact(2)) {
+    let nanos = pair[1].saturating_sub(pair[0]) as f64 * period as f64;
+    *durations.entry(label).or_insert(Duration::ZERO) += Duration::from_nanos(nanos as u64);
+  }
+  durations
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn scope_durations_test() {
+    let durations = scope_durations(
+      &["Main Pass", "SSAO", "Main Pass"],
+      &[100, 300, 300, 1300, 2000, 2100],
+      2.0,
+    );
+    assert_eq!(durations.len(), 2);
+    assert_eq!(durations["Main Pass"], Duration::from_nanos(600));
+    assert_eq!(durations["SSAO"], Duration::from_nanos(2000));
+    // Timestamps can go backwards across a counter reset
+    assert_eq!(
+      scope_durations(&["Main Pass"], &[10, 5], 1.0)["Main Pass"],
+      Duration::ZERO
+    );
+  }
+
+  #[test]
+  fn disabled_test() {
+    let mut profiler = GpuProfiler::disabled();
+    assert!(!profiler.is_enabled());
+    assert!(profiler.timings().is_empty());
+    assert_eq!(profiler.summary().lines().count(), 1);
+    profiler.accumulated.insert(
+      "Main Pass",
+      Accumulated {
+        total: Duration::from_millis(4),
+        frames: 2,
+      },
+    );
+    assert!(profiler.summary().contains("2.000"));
+  }
+}
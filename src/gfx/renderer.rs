@@ -1,23 +1,450 @@
 use super::camera::Camera;
+#[cfg(feature = "ssao")]
+use super::camera::OPENGL_TO_WGPU_MATRIX;
 use super::geometry::Geometry;
-use super::shader::feature::{FeatureInstance, FeatureVertex};
+use super::shader::feature::{FeatureInstance, FeatureVertex, FogUniform, MaterialUniform, NormalVertex};
 use super::texture::Texture;
+use crate::featuredb::Feature;
+use crate::raycast::{Ball, Intersect, Ray, Transform};
 
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Vector3};
+#[cfg(feature = "ssao")]
+use rand_distr::{Distribution, Uniform};
 use wgpu::util::DeviceExt;
-use wgpu::{Buffer, Device, RenderPass, RenderPipeline, SurfaceConfiguration};
+use wgpu::{
+  BindGroup, BindGroupLayout, Buffer, CommandEncoder, Device, Queue, RenderPass, RenderPipeline, ShaderModule,
+  SurfaceConfiguration, TextureView,
+};
+
+use std::fmt;
+use std::num::NonZeroU32;
+
+#[derive(Debug)]
+pub enum RenderError {
+  Map(wgpu::BufferAsyncError),
+  Image(image::ImageError),
+  Io(std::io::Error),
+}
+
+impl fmt::Display for RenderError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RenderError::Map(err) => write!(f, "failed to read back frame: '{}'", err),
+      RenderError::Image(err) => write!(f, "failed to save frame: '{}'", err),
+      RenderError::Io(err) => write!(f, "failed to write snapshot: '{}'", err),
+    }
+  }
+}
+
+impl From<wgpu::BufferAsyncError> for RenderError {
+  fn from(other: wgpu::BufferAsyncError) -> Self {
+    RenderError::Map(other)
+  }
+}
+
+impl From<image::ImageError> for RenderError {
+  fn from(other: image::ImageError) -> Self {
+    RenderError::Image(other)
+  }
+}
+
+impl From<std::io::Error> for RenderError {
+  fn from(other: std::io::Error) -> Self {
+    RenderError::Io(other)
+  }
+}
+
+/// Starts a labelled render pass with at most one color and one depth attachment, both of which are stored at the
+/// end of the pass. Attachments keep their existing contents unless given a clear value. The stencil aspect of the
+/// depth attachment is read-only unless cleared with `clear_stencil`.
+pub struct RenderPassBuilder<'a> {
+  encoder: &'a mut CommandEncoder,
+  label: &'a str,
+  color: Option<(&'a TextureView, wgpu::LoadOp<wgpu::Color>)>,
+  depth: Option<(&'a TextureView, wgpu::LoadOp<f32>)>,
+  stencil: Option<wgpu::LoadOp<u32>>,
+}
+
+impl<'a> RenderPassBuilder<'a> {
+  /// `label` names the pass in GPU debuggers and profiler traces.
+  pub fn new(encoder: &'a mut CommandEncoder, label: &'a str) -> Self {
+    Self {
+      encoder,
+      label,
+      color: None,
+      depth: None,
+      stencil: None,
+    }
+  }
+
+  pub fn color(mut self, view: &'a TextureView) -> Self {
+    self.color = Some((view, wgpu::LoadOp::Load));
+    self
+  }
+
+  /// Clears the color attachment set with `color` before drawing.
+  pub fn clear_color(mut self, clear: wgpu::Color) -> Self {
+    if let Some((_, load)) = &mut self.color {
+      *load = wgpu::LoadOp::Clear(clear);
+    }
+    self
+  }
+
+  pub fn depth(mut self, view: &'a TextureView) -> Self {
+    self.depth = Some((view, wgpu::LoadOp::Load));
+    self
+  }
+
+  /// Clears the depth attachment set with `depth` before drawing.
+  pub fn clear_depth(mut self, clear: f32) -> Self {
+    if let Some((_, load)) = &mut self.depth {
+      *load = wgpu::LoadOp::Clear(clear);
+    }
+    self
+  }
+
+  /// Clears the stencil of the depth attachment to `clear` before drawing and lets pipelines write to it.
+  pub fn clear_stencil(mut self, clear: u32) -> Self {
+    self.stencil = Some(wgpu::LoadOp::Clear(clear));
+    self
+  }
+
+  pub fn build(self) -> RenderPass<'a> {
+    let color_attachments: Vec<_> = self
+      .color
+      .into_iter()
+      .map(|(view, load)| wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations { load, store: true },
+      })
+      .collect();
+    let stencil = self.stencil;
+    self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some(self.label),
+      color_attachments: &color_attachments,
+      depth_stencil_attachment: self.depth.map(|(view, load)| wgpu::RenderPassDepthStencilAttachment {
+        view,
+        depth_ops: Some(wgpu::Operations { load, store: true }),
+        stencil_ops: stencil.map(|load| wgpu::Operations { load, store: true }),
+      }),
+    })
+  }
+}
+
+/// Copies a 4-byte-per-pixel `texture` back from the GPU, waiting for the copy, as tightly packed rows.
+pub fn read_texture(
+  device: &Device,
+  queue: &Queue,
+  texture: &Texture,
+  width: u32,
+  height: u32,
+) -> Result<Vec<u8>, RenderError> {
+  let row_bytes = 4 * width;
+  let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+  let padded_row_bytes = row_bytes.div_ceil(alignment) * alignment;
+  let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some("Readback Buffer"),
+    size: (padded_row_bytes * height) as wgpu::BufferAddress,
+    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+    mapped_at_creation: false,
+  });
+
+  let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+    label: Some("Readback Encoder"),
+  });
+  encoder.copy_texture_to_buffer(
+    texture.texture.as_image_copy(),
+    wgpu::ImageCopyBuffer {
+      buffer: &buffer,
+      layout: wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: NonZeroU32::new(padded_row_bytes),
+        rows_per_image: NonZeroU32::new(height),
+      },
+    },
+    wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 1,
+    },
+  );
+  queue.submit(std::iter::once(encoder.finish()));
+
+  let slice = buffer.slice(..);
+  let mapping = slice.map_async(wgpu::MapMode::Read);
+  device.poll(wgpu::Maintain::Wait);
+  async_std::task::block_on(mapping)?;
+  let pixels = slice
+    .get_mapped_range()
+    .chunks_exact(padded_row_bytes as usize)
+    .flat_map(|row| row[..row_bytes as usize].to_vec())
+    .collect();
+  buffer.unmap();
+  Ok(pixels)
+}
+
+/// Copies the depth aspect of a `Texture::DEPTH_FORMAT` `depth_texture` back from the GPU as tightly packed rows of
+/// [0, 1] depths. Combined depth-stencil formats can't be copied to buffers, so the depths are first drawn into a float
+/// render target.
+pub fn read_depth(
+  device: &Device,
+  queue: &Queue,
+  depth_texture: &Texture,
+  width: u32,
+  height: u32,
+) -> Result<Vec<f32>, RenderError> {
+  const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+  let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    entries: &[wgpu::BindGroupLayoutEntry {
+      binding: 0,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Depth,
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+      },
+      count: None,
+    }],
+    label: Some("depth_copy_bind_group_layout"),
+  });
+  let depth_view = depth_texture.texture.create_view(&wgpu::TextureViewDescriptor {
+    aspect: wgpu::TextureAspect::DepthOnly,
+    ..Default::default()
+  });
+  let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+    layout: &layout,
+    entries: &[wgpu::BindGroupEntry {
+      binding: 0,
+      resource: wgpu::BindingResource::TextureView(&depth_view),
+    }],
+    label: Some("depth_copy_bind_group"),
+  });
+  let shader = super::shader::depth_copy(device);
+  let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    label: Some("Depth Copy Pipeline Layout"),
+    bind_group_layouts: &[&layout],
+    push_constant_ranges: &[],
+  });
+  let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Depth Copy Pipeline"),
+    layout: Some(&pipeline_layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vertex",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fragment",
+      targets: &[wgpu::ColorTargetState {
+        format: FORMAT,
+        blend: None,
+        write_mask: wgpu::ColorWrites::ALL,
+      }],
+    }),
+    primitive: wgpu::PrimitiveState::default(),
+    depth_stencil: None,
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  });
+
+  let target = Texture::create_render_target(device, width, height, FORMAT, "depth_copy_texture");
+  let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+    label: Some("Depth Copy Encoder"),
+  });
+  {
+    let mut render_pass = RenderPassBuilder::new(&mut encoder, "Depth Copy Pass")
+      .color(&target.view)
+      .build();
+    render_pass.set_pipeline(&pipeline);
+    render_pass.set_bind_group(0, &bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+  queue.submit(std::iter::once(encoder.finish()));
+  let bytes = read_texture(device, queue, &target, width, height)?;
+  // The bytes aren't necessarily aligned for a cast
+  Ok(
+    bytes
+      .chunks_exact(4)
+      .map(|depth| f32::from_ne_bytes([depth[0], depth[1], depth[2], depth[3]]))
+      .collect(),
+  )
+}
+
+/// Work a renderer asks of the GPU each frame, computed from what it has uploaded rather than measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RendererStats {
+  pub draw_calls: u32,
+  pub triangles: u32,
+  pub instances: u32,
+}
+
+impl std::ops::Add for RendererStats {
+  type Output = Self;
+
+  fn add(self, other: Self) -> Self {
+    Self {
+      draw_calls: self.draw_calls + other.draw_calls,
+      triangles: self.triangles + other.triangles,
+      instances: self.instances + other.instances,
+    }
+  }
+}
+
+impl std::iter::Sum for RendererStats {
+  fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+    iter.fold(Self::default(), |sum, stats| sum + stats)
+  }
+}
+
+/// Shader a `BasicRenderer` draws with.
+#[allow(dead_code)]
+pub enum BasicShaderSource {
+  Wgsl(&'static str),
+  Precompiled(ShaderModule),
+}
+
+impl Default for BasicShaderSource {
+  fn default() -> Self {
+    BasicShaderSource::Wgsl(super::shader::BASIC_SOURCE)
+  }
+}
 
 pub struct BasicRendererConfiguration<'a> {
   pub device: &'a Device,
   pub surface_config: &'a SurfaceConfiguration,
+  pub shader: BasicShaderSource,
+  pub vertex_entry: &'static str,
+  pub fragment_entry: &'static str,
+}
+
+impl<'a> BasicRendererConfiguration<'a> {
+  /// Draws with `basic.wgsl` through its `vertex` and `fragment` entry points.
+  pub fn new(device: &'a Device, surface_config: &'a SurfaceConfiguration) -> Self {
+    Self {
+      device,
+      surface_config,
+      shader: BasicShaderSource::default(),
+      vertex_entry: "vertex",
+      fragment_entry: "fragment",
+    }
+  }
 }
 
 pub struct BasicRenderer {
   pipeline: RenderPipeline,
+  geometry: Option<BasicGeometry>,
+  topology: wgpu::PrimitiveTopology,
+}
+
+struct BasicGeometry {
+  vertex_buffer: Buffer,
+  index_buffer: Buffer,
+  index_count: u32,
+  index_format: wgpu::IndexFormat,
+}
+
+impl BasicGeometry {
+  fn new(geometry: &Geometry, device: &Device) -> Self {
+    let positions: Vec<[f32; 3]> = geometry.vertices.iter().map(|&vertex| vertex.into()).collect();
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Basic Vertex Buffer"),
+      contents: bytemuck::cast_slice(&positions[..]),
+      usage: wgpu::BufferUsages::VERTEX,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Basic Index Buffer"),
+      contents: geometry.indices.as_bytes(),
+      usage: wgpu::BufferUsages::INDEX,
+    });
+    Self {
+      vertex_buffer,
+      index_buffer,
+      index_count: geometry.indices.len() as u32,
+      index_format: geometry.indices.format(),
+    }
+  }
 }
 
 impl BasicRenderer {
   pub fn new(config: BasicRendererConfiguration) -> Self {
-    let shader = super::shader::basic(config.device);
+    Self {
+      pipeline: Self::create_pipeline(&config, &[], wgpu::PrimitiveTopology::TriangleList, false),
+      geometry: None,
+      topology: wgpu::PrimitiveTopology::TriangleList,
+    }
+  }
+
+  /// Draws `geometry` alpha blended over the whole frame, ignoring depth. With `basic.wgsl`, use the
+  /// `vertex_overlay` and `fragment_overlay` entry points to draw it in clip coordinates with a translucent red tint.
+  pub fn overlay(config: BasicRendererConfiguration, geometry: &Geometry) -> Self {
+    let pipeline = Self::create_pipeline(
+      &config,
+      &[Self::position_layout()],
+      wgpu::PrimitiveTopology::TriangleList,
+      true,
+    );
+    Self {
+      pipeline,
+      geometry: Some(BasicGeometry::new(geometry, config.device)),
+      topology: wgpu::PrimitiveTopology::TriangleList,
+    }
+  }
+
+  /// Draws the vertices of `geometry` as `topology` rather than the built-in triangle, for example the edge list
+  /// from `Geometry::to_wireframe_lines` as a `LineList`. With `basic.wgsl`, use the `vertex_geometry` entry point.
+  pub fn with_geometry(
+    config: BasicRendererConfiguration,
+    geometry: &Geometry,
+    topology: wgpu::PrimitiveTopology,
+  ) -> Self {
+    let pipeline = Self::create_pipeline(&config, &[Self::position_layout()], topology, false);
+    Self {
+      pipeline,
+      geometry: Some(BasicGeometry::new(geometry, config.device)),
+      topology,
+    }
+  }
+
+  /// One draw of the built-in triangle or the uploaded geometry; line and point lists count no triangles.
+  pub fn stats(&self) -> RendererStats {
+    let vertices = self.geometry.as_ref().map_or(3, |geometry| geometry.index_count);
+    let triangles = match self.topology {
+      wgpu::PrimitiveTopology::TriangleList => vertices / 3,
+      wgpu::PrimitiveTopology::TriangleStrip => vertices.saturating_sub(2),
+      _ => 0,
+    };
+    RendererStats {
+      draw_calls: 1,
+      triangles,
+      instances: 1,
+    }
+  }
+
+  fn position_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &ATTRIBUTES,
+    }
+  }
+
+  fn create_pipeline(
+    config: &BasicRendererConfiguration,
+    buffers: &[wgpu::VertexBufferLayout],
+    topology: wgpu::PrimitiveTopology,
+    overlay: bool,
+  ) -> RenderPipeline {
+    let compiled;
+    let shader = match &config.shader {
+      BasicShaderSource::Wgsl(source) => {
+        compiled = super::shader::basic(config.device, source);
+        &compiled
+      }
+      BasicShaderSource::Precompiled(module) => module,
+    };
 
     let camera_layout = Camera::layout(config.device);
 
@@ -27,25 +454,29 @@ impl BasicRenderer {
       push_constant_ranges: &[],
     });
 
-    let pipeline = config.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-      label: Some("Render Pipeline"),
+    config.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Basic Pipeline"),
       layout: Some(&render_pipeline_layout),
       vertex: wgpu::VertexState {
-        module: &shader,
-        entry_point: "vertex",
-        buffers: &[],
+        module: shader,
+        entry_point: config.vertex_entry,
+        buffers,
       },
       fragment: Some(wgpu::FragmentState {
-        module: &shader,
-        entry_point: "fragment",
+        module: shader,
+        entry_point: config.fragment_entry,
         targets: &[wgpu::ColorTargetState {
           format: config.surface_config.format,
-          blend: Some(wgpu::BlendState::REPLACE),
+          blend: Some(if overlay {
+            wgpu::BlendState::ALPHA_BLENDING
+          } else {
+            wgpu::BlendState::REPLACE
+          }),
           write_mask: wgpu::ColorWrites::ALL,
         }],
       }),
       primitive: wgpu::PrimitiveState {
-        topology: wgpu::PrimitiveTopology::TriangleList,
+        topology,
         strip_index_format: None,
         front_face: wgpu::FrontFace::Ccw,
         cull_mode: None,
@@ -55,8 +486,12 @@ impl BasicRenderer {
       },
       depth_stencil: Some(wgpu::DepthStencilState {
         format: Texture::DEPTH_FORMAT,
-        depth_write_enabled: true,
-        depth_compare: wgpu::CompareFunction::Less,
+        depth_write_enabled: !overlay,
+        depth_compare: if overlay {
+          wgpu::CompareFunction::Always
+        } else {
+          wgpu::CompareFunction::Less
+        },
         stencil: wgpu::StencilState::default(),
         bias: wgpu::DepthBiasState::default(),
       }),
@@ -66,15 +501,19 @@ impl BasicRenderer {
         alpha_to_coverage_enabled: false,
       },
       multiview: None,
-    });
-
-    Self { pipeline }
+    })
   }
 
   pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
     render_pass.set_pipeline(&self.pipeline);
     render_pass.set_bind_group(0, camera.bind_group(), &[]);
-    render_pass.draw(0..3, 0..1);
+    if let Some(geometry) = &self.geometry {
+      render_pass.set_vertex_buffer(0, geometry.vertex_buffer.slice(..));
+      render_pass.set_index_buffer(geometry.index_buffer.slice(..), geometry.index_format);
+      render_pass.draw_indexed(0..geometry.index_count, 0, 0..1);
+    } else {
+      render_pass.draw(0..3, 0..1);
+    }
   }
 }
 
@@ -82,47 +521,154 @@ pub struct FeatureRendererConfiguration<'a> {
   pub geometry: Geometry,
   pub instances: Vec<FeatureInstance>,
   pub device: &'a Device,
+  pub queue: &'a Queue,
   pub surface_config: &'a SurfaceConfiguration,
+  /// Shade only fragments matching the depth laid down by a `ZPrepass`.
+  pub use_z_prepass: bool,
 }
 
-pub struct FeatureRenderer {
-  pipeline: RenderPipeline,
-  vertex_buffer: Buffer,
-  #[allow(dead_code)]
-  vertices: Vec<FeatureVertex>,
-  index_buffer: Buffer,
-  indices: Vec<u16>,
-  instance_buffer: Buffer,
-  instances: Vec<FeatureInstance>,
+/// Matches `OutlineUniform` in `outline.wgsl`.
+#[cfg(feature = "outlines")]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineUniform {
+  color: [f32; 4],
+  scale: f32,
+  _padding: [f32; 3],
 }
 
-impl FeatureRenderer {
-  pub fn new(config: FeatureRendererConfiguration) -> Self {
-    let shader = super::shader::feature::compile(config.device);
+#[cfg(feature = "outlines")]
+impl OutlineUniform {
+  /// Growth of the outlined mesh about its center, so the outline is 5% of the feature radius wide
+  const SCALE: f32 = 1.05;
 
-    let camera_layout = Camera::layout(config.device);
+  fn new(color: [f32; 4]) -> Self {
+    Self {
+      color,
+      scale: Self::SCALE,
+      _padding: [0.0; 3],
+    }
+  }
+}
 
-    let render_pipeline_layout = config.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-      label: Some("Basic Shading Layout"),
-      bind_group_layouts: &[&camera_layout],
+/// Pipelines and instances of `FeatureRenderer::render_outline`.
+#[cfg(feature = "outlines")]
+struct FeatureOutline {
+  mask_pipeline: RenderPipeline,
+  pipeline: RenderPipeline,
+  /// Instances outlined by `render_outline`
+  buffer: Buffer,
+  capacity: usize,
+  count: usize,
+  uniform_buffer: Buffer,
+  bind_group: BindGroup,
+}
+
+#[cfg(feature = "outlines")]
+impl FeatureOutline {
+  fn new(device: &Device, camera_layout: &BindGroupLayout, format: wgpu::TextureFormat) -> Self {
+    let shader = super::shader::outline(device);
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+      label: Some("outline_bind_group_layout"),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Outline Layout"),
+      bind_group_layouts: &[camera_layout, &layout],
       push_constant_ranges: &[],
     });
+    // Marks every pixel the selected features cover with 1 in the stencil
+    let mask_pipeline = Self::create_pipeline(
+      device,
+      &shader,
+      &pipeline_layout,
+      format,
+      "vertex_mask",
+      wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Always,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Replace,
+      },
+    );
+    // Colors the rim of the grown features that falls outside the mask
+    let pipeline = Self::create_pipeline(
+      device,
+      &shader,
+      &pipeline_layout,
+      format,
+      "vertex_outline",
+      wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::NotEqual,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Keep,
+      },
+    );
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Outline Buffer"),
+      contents: bytemuck::cast_slice(&[OutlineUniform::new([0.0; 4])]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: uniform_buffer.as_entire_binding(),
+      }],
+      label: Some("outline_bind_group"),
+    });
 
-    let pipeline = config.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-      label: Some("Render Pipeline"),
-      layout: Some(&render_pipeline_layout),
+    Self {
+      mask_pipeline,
+      pipeline,
+      buffer: FeatureRenderer::instance_buffer(&[], device),
+      capacity: 0,
+      count: 0,
+      uniform_buffer,
+      bind_group,
+    }
+  }
+
+  /// Outline passes ignore depth so the outline shows through anything in front of the selection. Only the mask
+  /// pipeline writes to the stencil, and only the outline pipeline writes color.
+  fn create_pipeline(
+    device: &Device,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    vertex_entry: &str,
+    stencil: wgpu::StencilFaceState,
+  ) -> RenderPipeline {
+    let mask = stencil.pass_op == wgpu::StencilOperation::Replace;
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Outline Pipeline"),
+      layout: Some(layout),
       vertex: wgpu::VertexState {
-        module: &shader,
-        entry_point: "vertex",
+        module: shader,
+        entry_point: vertex_entry,
         buffers: &[FeatureVertex::description(), FeatureInstance::description()],
       },
       fragment: Some(wgpu::FragmentState {
-        module: &shader,
+        module: shader,
         entry_point: "fragment",
         targets: &[wgpu::ColorTargetState {
-          format: config.surface_config.format,
-          blend: Some(wgpu::BlendState::REPLACE),
-          write_mask: wgpu::ColorWrites::ALL,
+          format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: if mask {
+            wgpu::ColorWrites::empty()
+          } else {
+            wgpu::ColorWrites::ALL
+          },
         }],
       }),
       primitive: wgpu::PrimitiveState {
@@ -136,9 +682,14 @@ impl FeatureRenderer {
       },
       depth_stencil: Some(wgpu::DepthStencilState {
         format: Texture::DEPTH_FORMAT,
-        depth_write_enabled: true,
-        depth_compare: wgpu::CompareFunction::Less,
-        stencil: wgpu::StencilState::default(),
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState {
+          front: stencil,
+          back: stencil,
+          read_mask: 0xff,
+          write_mask: if mask { 0xff } else { 0 },
+        },
         bias: wgpu::DepthBiasState::default(),
       }),
       multisample: wgpu::MultisampleState {
@@ -147,51 +698,3317 @@ impl FeatureRenderer {
         alpha_to_coverage_enabled: false,
       },
       multiview: None,
-    });
+    })
+  }
+}
 
-    let vertices: Vec<FeatureVertex> = config
-      .geometry
-      .vertices
-      .iter()
-      .zip(config.geometry.normals.iter())
-      .map(FeatureVertex::from)
-      .collect();
+/// Feature instances on the GPU, written in place while they fit and reallocated with room to spare when they don't.
+struct InstanceBuffer {
+  buffer: Buffer,
+  /// Instances `buffer` can hold
+  capacity: usize,
+}
 
-    let vertex_buffer = config.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-      label: Some("Vertex Buffer"),
-      contents: bytemuck::cast_slice(&vertices[..]),
-      usage: wgpu::BufferUsages::VERTEX,
-    });
+impl InstanceBuffer {
+  const INSTANCE_SIZE: u64 = std::mem::size_of::<FeatureInstance>() as u64;
 
-    let index_buffer = config.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-      label: Some("Index Buffer"),
-      contents: bytemuck::cast_slice(&config.geometry.indices[..]),
-      usage: wgpu::BufferUsages::INDEX,
-    });
+  fn new(instances: &[FeatureInstance], device: &Device) -> Self {
+    Self {
+      buffer: FeatureRenderer::instance_buffer(instances, device),
+      capacity: instances.len(),
+    }
+  }
 
-    let instance_buffer = config.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+  fn allocate(capacity: usize, device: &Device) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
       label: Some("Instance Buffer"),
-      contents: bytemuck::cast_slice(&config.instances[..]),
+      size: capacity as u64 * Self::INSTANCE_SIZE,
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+    })
+  }
+
+  /// Reallocates to hold exactly `capacity` instances, keeping as many of the current ones as fit.
+  fn resize(&mut self, capacity: usize, device: &Device, queue: &Queue) {
+    if capacity == self.capacity {
+      return;
+    }
+    let buffer = Self::allocate(capacity, device);
+    let kept = self.capacity.min(capacity) as u64 * Self::INSTANCE_SIZE;
+    if kept > 0 {
+      let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Instance Resize Encoder"),
+      });
+      encoder.copy_buffer_to_buffer(&self.buffer, 0, &buffer, 0, kept);
+      queue.submit(std::iter::once(encoder.finish()));
+    }
+    self.buffer = buffer;
+    self.capacity = capacity;
+  }
+
+  /// Replaces the contents with `instances`, at least doubling the capacity if they don't fit.
+  fn write(&mut self, instances: &[FeatureInstance], device: &Device, queue: &Queue) {
+    if instances.len() > self.capacity {
+      // The old contents are overwritten anyway, so there is nothing to copy over
+      self.capacity = grown_capacity(self.capacity, instances.len());
+      self.buffer = Self::allocate(self.capacity, device);
+    }
+    if !instances.is_empty() {
+      queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+    }
+  }
+}
+
+/// Capacity to grow to from `capacity` to fit `needed` instances, at least doubling to amortize reallocations.
+fn grown_capacity(capacity: usize, needed: usize) -> usize {
+  needed.max(capacity * 2)
+}
+
+/// How `FeatureRenderer` draws its instances, for telling apart problems with the data from ones with shading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+  /// Lit and fogged, with partly visible instances blended
+  Solid,
+  /// Every mesh edge as a line
+  Wireframe,
+  /// Every mesh vertex as a point
+  Points,
+  /// Solid, with a short line along the normal of every vertex
+  Normals,
+  /// Distance from the eye in grey
+  Depth,
+  /// Instance color and texture without lighting or fog
+  MaterialColor,
+}
+
+impl RenderMode {
+  pub const ALL: [RenderMode; 6] = [
+    RenderMode::Solid,
+    RenderMode::Wireframe,
+    RenderMode::Points,
+    RenderMode::Normals,
+    RenderMode::Depth,
+    RenderMode::MaterialColor,
+  ];
+
+  /// The mode after this one, wrapping around to `Solid`.
+  pub fn next(self) -> Self {
+    let idx = Self::ALL.iter().position(|&mode| mode == self).unwrap();
+    Self::ALL[(idx + 1) % Self::ALL.len()]
+  }
+}
+
+impl fmt::Display for RenderMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      RenderMode::Solid => "solid",
+      RenderMode::Wireframe => "wireframe",
+      RenderMode::Points => "points",
+      RenderMode::Normals => "normals",
+      RenderMode::Depth => "depth",
+      RenderMode::MaterialColor => "material color",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// What features are drawn as, chosen with `--render-mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureDisplay {
+  /// Instanced meshes from a `FeatureRenderer`
+  Meshes,
+  /// One dot per feature from a `PointCloudRenderer`
+  Points,
+}
+
+impl std::str::FromStr for FeatureDisplay {
+  type Err = String;
+
+  fn from_str(mode: &str) -> Result<Self, Self::Err> {
+    match mode {
+      "meshes" => Ok(FeatureDisplay::Meshes),
+      "points" => Ok(FeatureDisplay::Points),
+      _ => Err(format!("invalid render mode '{}', expected 'meshes' or 'points'", mode)),
+    }
+  }
+}
+
+/// Pipelines of the `RenderMode`s other than `Solid`. None of them blend, so they draw every instance at once.
+struct ModePipelines {
+  wireframe: RenderPipeline,
+  points: RenderPipeline,
+  normals: RenderPipeline,
+  depth: RenderPipeline,
+  material_color: RenderPipeline,
+}
+
+impl ModePipelines {
+  fn new(device: &Device, shader: &ShaderModule, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat) -> Self {
+    let normals_shader = super::shader::normals(device);
+    let feature_buffers = [FeatureVertex::description(), FeatureInstance::description()];
+    let normal_buffers = [NormalVertex::description(), FeatureInstance::description()];
+    let pipeline = |label: &str,
+                    module: &ShaderModule,
+                    fragment_entry: &str,
+                    topology: wgpu::PrimitiveTopology,
+                    buffers: &[wgpu::VertexBufferLayout]| {
+      device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+          module,
+          entry_point: "vertex",
+          buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+          module,
+          entry_point: fragment_entry,
+          targets: &[wgpu::ColorTargetState {
+            format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+          }],
+        }),
+        primitive: wgpu::PrimitiveState {
+          topology,
+          strip_index_format: None,
+          front_face: wgpu::FrontFace::Ccw,
+          cull_mode: Some(wgpu::Face::Back),
+          polygon_mode: wgpu::PolygonMode::Fill,
+          unclipped_depth: false,
+          conservative: false,
+        },
+        // Less or equal so that the modes also draw over depth from a `ZPrepass`
+        depth_stencil: Some(wgpu::DepthStencilState {
+          format: Texture::DEPTH_FORMAT,
+          depth_write_enabled: true,
+          depth_compare: wgpu::CompareFunction::LessEqual,
+          stencil: wgpu::StencilState::default(),
+          bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+          count: 1,
+          mask: !0,
+          alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+      })
+    };
+    use wgpu::PrimitiveTopology::{LineList, PointList, TriangleList};
+    Self {
+      wireframe: pipeline(
+        "Feature Wireframe Pipeline",
+        shader,
+        "fragment",
+        LineList,
+        &feature_buffers,
+      ),
+      points: pipeline(
+        "Feature Points Pipeline",
+        shader,
+        "fragment",
+        PointList,
+        &feature_buffers,
+      ),
+      normals: pipeline(
+        "Feature Normals Pipeline",
+        &normals_shader,
+        "fragment",
+        LineList,
+        &normal_buffers,
+      ),
+      depth: pipeline(
+        "Feature Depth Pipeline",
+        shader,
+        "fragment_depth",
+        TriangleList,
+        &feature_buffers,
+      ),
+      material_color: pipeline(
+        "Feature Material Pipeline",
+        shader,
+        "fragment_material",
+        TriangleList,
+        &feature_buffers,
+      ),
+    }
+  }
+}
+
+/// Matches `PickUniform` in `pick.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PickUniform {
+  base: u32,
+  _padding: [u32; 3],
+}
+
+impl PickUniform {
+  fn new(base: usize) -> Self {
+    Self {
+      base: base as u32,
+      _padding: [0; 3],
+    }
+  }
+}
+
+/// Instance index stored in a picking texture pixel, which holds one more than the index so that 0 means none.
+fn decode_pick(pixel: u32) -> Option<u32> {
+  pixel.checked_sub(1)
+}
+
+/// Edges and normal lines of the feature mesh, drawn by the `Wireframe` and `Normals` modes.
+struct DebugMesh {
+  edge_buffer: Buffer,
+  edge_count: u32,
+  edge_format: wgpu::IndexFormat,
+  normal_buffer: Buffer,
+  normal_count: u32,
+}
+
+impl DebugMesh {
+  fn new(geometry: &Geometry, vertices: &[FeatureVertex], device: &Device) -> Self {
+    let edges = geometry.to_wireframe_lines().indices;
+    let edge_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Edge Index Buffer"),
+      contents: edges.as_bytes(),
+      usage: wgpu::BufferUsages::INDEX,
+    });
+    let normals = NormalVertex::lines(vertices);
+    let normal_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Normal Line Buffer"),
+      contents: bytemuck::cast_slice(&normals),
       usage: wgpu::BufferUsages::VERTEX,
     });
-
     Self {
-      pipeline,
-      vertex_buffer,
-      vertices,
-      index_buffer,
-      indices: config.geometry.indices,
-      instance_buffer,
-      instances: config.instances,
+      edge_buffer,
+      edge_count: edges.len() as u32,
+      edge_format: edges.format(),
+      normal_buffer,
+      normal_count: normals.len() as u32,
     }
   }
+}
 
-  pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
-    render_pass.set_pipeline(&self.pipeline);
-    render_pass.set_bind_group(0, camera.bind_group(), &[]);
-    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-    render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-    render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-    render_pass.draw_indexed(0..self.indices.len() as u32, 0, 0..self.instances.len() as u32);
+/// Draws every feature instance with the same mesh. Instances with `visibility` below 1 are drawn in a second,
+/// alpha-blended pass sorted back to front.
+pub struct FeatureRenderer {
+  opaque_pipeline: RenderPipeline,
+  transparent_pipeline: RenderPipeline,
+  mode_pipelines: ModePipelines,
+  render_mode: RenderMode,
+  pick_pipeline: RenderPipeline,
+  /// Index of the first opaque and first transparent instance, in the order of `get_instance_position`
+  pick_base_buffers: [Buffer; 2],
+  pick_bind_groups: [BindGroup; 2],
+  debug_mesh: DebugMesh,
+  vertex_buffer: Buffer,
+  vertices: Vec<FeatureVertex>,
+  index_buffer: Buffer,
+  index_count: u32,
+  index_format: wgpu::IndexFormat,
+  opaque_buffer: InstanceBuffer,
+  opaque: Vec<FeatureInstance>,
+  transparent_buffer: InstanceBuffer,
+  transparent: Vec<FeatureInstance>,
+  /// Bumped whenever the instances change, so copies of them can tell when they are stale
+  instance_generation: u64,
+  /// Set when the transparent instances change and must be re-sorted before drawing
+  depth_sort_needed: bool,
+  /// Camera direction the transparent instances were last sorted along
+  last_camera_forward: Option<Vector3<f32>>,
+  depth_sort: bool,
+  atlas_layout: BindGroupLayout,
+  atlas_texture: Texture,
+  normal_texture: Texture,
+  material_buffer: Buffer,
+  /// Atlas, normal map and material, bound together
+  atlas_bind_group: BindGroup,
+  fog_buffer: Buffer,
+  fog_bind_group: BindGroup,
+  #[cfg(feature = "outlines")]
+  outline: FeatureOutline,
+  /// Result of the last `pick_instance`
+  last_picked: Option<usize>,
+}
+
+impl FeatureRenderer {
+  pub fn new(config: FeatureRendererConfiguration) -> Self {
+    let shader = super::shader::feature::compile(config.device);
+
+    let camera_layout = Camera::layout(config.device);
+    let atlas_layout = config
+      .device
+      .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+          wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+              sample_type: wgpu::TextureSampleType::Float { filterable: true },
+              view_dimension: wgpu::TextureViewDimension::D2,
+              multisampled: false,
+            },
+            count: None,
+          },
+          wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+          },
+          wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+              sample_type: wgpu::TextureSampleType::Float { filterable: true },
+              view_dimension: wgpu::TextureViewDimension::D2,
+              multisampled: false,
+            },
+            count: None,
+          },
+          wgpu::BindGroupLayoutEntry {
+            binding: 3,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: None,
+            },
+            count: None,
+          },
+        ],
+        label: Some("atlas_bind_group_layout"),
+      });
+
+    let fog_layout = config
+      .device
+      .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }],
+        label: Some("fog_bind_group_layout"),
+      });
+
+    let render_pipeline_layout = config.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Basic Shading Layout"),
+      bind_group_layouts: &[&camera_layout, &atlas_layout, &fog_layout],
+      push_constant_ranges: &[],
+    });
+
+    let opaque_pipeline = Self::create_pipeline(
+      config.device,
+      &shader,
+      &render_pipeline_layout,
+      wgpu::ColorTargetState {
+        format: config.surface_config.format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+      },
+      !config.use_z_prepass,
+      if config.use_z_prepass {
+        wgpu::CompareFunction::Equal
+      } else {
+        wgpu::CompareFunction::Less
+      },
+    );
+    let transparent_pipeline = Self::create_pipeline(
+      config.device,
+      &shader,
+      &render_pipeline_layout,
+      wgpu::ColorTargetState {
+        format: config.surface_config.format,
+        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+        write_mask: wgpu::ColorWrites::ALL,
+      },
+      false,
+      wgpu::CompareFunction::Less,
+    );
+
+    let mode_pipelines = ModePipelines::new(
+      config.device,
+      &shader,
+      &render_pipeline_layout,
+      config.surface_config.format,
+    );
+
+    let pick_layout = config
+      .device
+      .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::VERTEX,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }],
+        label: Some("pick_bind_group_layout"),
+      });
+    let pick_pipeline = Self::create_pick_pipeline(config.device, &camera_layout, &pick_layout);
+
+    let (vertices, vertex_buffer, index_buffer) = Self::geometry_buffers(&config.geometry, config.device);
+    let debug_mesh = DebugMesh::new(&config.geometry, &vertices, config.device);
+
+    let (opaque, transparent): (Vec<_>, Vec<_>) = config
+      .instances
+      .into_iter()
+      .partition(|instance| instance.visibility >= 1.0);
+
+    let device = config.device;
+    let pick_base_buffers = [0, opaque.len()].map(|base| {
+      device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Pick Base Buffer"),
+        contents: bytemuck::cast_slice(&[PickUniform::new(base)]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      })
+    });
+    let pick_bind_groups = [&pick_base_buffers[0], &pick_base_buffers[1]].map(|buffer| {
+      device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &pick_layout,
+        entries: &[wgpu::BindGroupEntry {
+          binding: 0,
+          resource: buffer.as_entire_binding(),
+        }],
+        label: Some("pick_bind_group"),
+      })
+    });
+
+    // Untextured until an atlas is set, so every tile samples white
+    let atlas_texture = Texture::white(config.device, config.queue);
+    // Lit by the vertex normals until a normal map is set
+    let normal_texture = Texture::flat_normal(config.device, config.queue);
+    let material_buffer = config.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Material Buffer"),
+      contents: bytemuck::cast_slice(&[MaterialUniform::new(false)]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let atlas_bind_group = Self::atlas_bind_group(
+      &atlas_layout,
+      &atlas_texture,
+      &normal_texture,
+      &material_buffer,
+      config.device,
+    );
+
+    let fog_buffer = config.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Fog Buffer"),
+      contents: bytemuck::cast_slice(&[FogUniform::new(0.0, [0.0; 4])]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let fog_bind_group = config.device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &fog_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: fog_buffer.as_entire_binding(),
+      }],
+      label: Some("fog_bind_group"),
+    });
+
+    Self {
+      opaque_pipeline,
+      transparent_pipeline,
+      mode_pipelines,
+      render_mode: RenderMode::Solid,
+      pick_pipeline,
+      pick_base_buffers,
+      pick_bind_groups,
+      debug_mesh,
+      vertex_buffer,
+      vertices,
+      index_buffer,
+      index_count: config.geometry.indices.len() as u32,
+      index_format: config.geometry.indices.format(),
+      opaque_buffer: InstanceBuffer::new(&opaque, config.device),
+      opaque,
+      transparent_buffer: InstanceBuffer::new(&transparent, config.device),
+      instance_generation: 0,
+      depth_sort_needed: !transparent.is_empty(),
+      transparent,
+      last_camera_forward: None,
+      depth_sort: true,
+      atlas_layout,
+      atlas_texture,
+      normal_texture,
+      material_buffer,
+      atlas_bind_group,
+      fog_buffer,
+      fog_bind_group,
+      #[cfg(feature = "outlines")]
+      outline: FeatureOutline::new(config.device, &camera_layout, config.surface_config.format),
+      last_picked: None,
+    }
+  }
+
+  fn create_pipeline(
+    device: &Device,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    target: wgpu::ColorTargetState,
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+  ) -> RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Feature Pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vertex",
+        buffers: &[FeatureVertex::description(), FeatureInstance::description()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: "fragment",
+        targets: &[target],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: Some(wgpu::Face::Back),
+        polygon_mode: wgpu::PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled,
+        depth_compare,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    })
+  }
+
+  fn create_pick_pipeline(
+    device: &Device,
+    camera_layout: &BindGroupLayout,
+    pick_layout: &BindGroupLayout,
+  ) -> RenderPipeline {
+    let shader = super::shader::pick(device);
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Pick Layout"),
+      bind_group_layouts: &[camera_layout, pick_layout],
+      push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Pick Pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vertex",
+        buffers: &[FeatureVertex::description(), FeatureInstance::description()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fragment",
+        // Integer targets can't blend
+        targets: &[wgpu::ColorTargetState {
+          format: Self::PICK_FORMAT,
+          blend: None,
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: Some(wgpu::Face::Back),
+        polygon_mode: wgpu::PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Less,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    })
+  }
+
+  fn instance_buffer(instances: &[FeatureInstance], device: &Device) -> Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Instance Buffer"),
+      contents: bytemuck::cast_slice(instances),
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    })
+  }
+
+  fn atlas_bind_group(
+    layout: &BindGroupLayout,
+    texture: &Texture,
+    normal_texture: &Texture,
+    material_buffer: &Buffer,
+    device: &Device,
+  ) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&texture.view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&texture.sampler),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::TextureView(&normal_texture.view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: material_buffer.as_entire_binding(),
+        },
+      ],
+      label: Some("atlas_bind_group"),
+    })
+  }
+
+  /// Samples each instance's `uv_offset`/`uv_scale` tile of `texture` to modulate its color.
+  #[allow(dead_code)]
+  pub fn set_atlas_texture(&mut self, texture: Texture, device: &Device) {
+    self.atlas_texture = texture;
+    self.rebind_atlas(device);
+  }
+
+  /// Perturbs the lighting normals by the tangent-space `texture`, sampled with the same atlas tile as the color, or
+  /// goes back to the vertex normals with `None`. The texture should be in a linear format such as the ones from
+  /// `Texture::normal_map_from_image`.
+  #[allow(dead_code)]
+  pub fn set_normal_map(&mut self, texture: Option<Texture>, device: &Device, queue: &Queue) {
+    let use_normal_map = texture.is_some();
+    self.normal_texture = texture.unwrap_or_else(|| Texture::flat_normal(device, queue));
+    queue.write_buffer(
+      &self.material_buffer,
+      0,
+      bytemuck::cast_slice(&[MaterialUniform::new(use_normal_map)]),
+    );
+    self.rebind_atlas(device);
+  }
+
+  fn rebind_atlas(&mut self, device: &Device) {
+    self.atlas_bind_group = Self::atlas_bind_group(
+      &self.atlas_layout,
+      &self.atlas_texture,
+      &self.normal_texture,
+      &self.material_buffer,
+      device,
+    );
+  }
+
+  /// Blends features towards `color` by `1 - exp(-density * distance)`; a `density` of zero turns fog off.
+  pub fn set_fog(&self, density: f32, color: [f32; 4], queue: &Queue) {
+    queue.write_buffer(
+      &self.fog_buffer,
+      0,
+      bytemuck::cast_slice(&[FogUniform::new(density, color)]),
+    );
+  }
+
+  fn geometry_buffers(geometry: &Geometry, device: &Device) -> (Vec<FeatureVertex>, Buffer, Buffer) {
+    let vertices: Vec<FeatureVertex> = geometry
+      .vertices
+      .iter()
+      .zip(geometry.normals.iter())
+      .map(FeatureVertex::from)
+      .collect();
+
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Vertex Buffer"),
+      contents: bytemuck::cast_slice(&vertices[..]),
+      usage: wgpu::BufferUsages::VERTEX,
+    });
+
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Index Buffer"),
+      contents: geometry.indices.as_bytes(),
+      usage: wgpu::BufferUsages::INDEX,
+    });
+
+    (vertices, vertex_buffer, index_buffer)
+  }
+
+  /// Replaces the mesh drawn for every instance, leaving the instances untouched.
+  pub fn set_geometry(&mut self, geometry: Geometry, device: &Device) {
+    let (vertices, vertex_buffer, index_buffer) = Self::geometry_buffers(&geometry, device);
+    self.debug_mesh = DebugMesh::new(&geometry, &vertices, device);
+    self.vertices = vertices;
+    self.vertex_buffer = vertex_buffer;
+    self.index_buffer = index_buffer;
+    self.index_count = geometry.indices.len() as u32;
+    self.index_format = geometry.indices.format();
+  }
+
+  /// Replaces the rendered instances, writing them over the old ones and only reallocating the instance buffers when
+  /// they run out of room.
+  pub fn update_instances(&mut self, instances: Vec<FeatureInstance>, device: &Device, queue: &Queue) {
+    let (opaque, transparent): (Vec<_>, Vec<_>) =
+      instances.into_iter().partition(|instance| instance.visibility >= 1.0);
+    self.opaque_buffer.write(&opaque, device, queue);
+    self.opaque = opaque;
+    self.transparent_buffer.write(&transparent, device, queue);
+    queue.write_buffer(
+      &self.pick_base_buffers[1],
+      0,
+      bytemuck::cast_slice(&[PickUniform::new(self.opaque.len())]),
+    );
+    self.depth_sort_needed = !transparent.is_empty();
+    self.transparent = transparent;
+    self.instance_generation += 1;
+    self.last_picked = None;
+  }
+
+  /// Recolors the instances of feature `id` in place, without uploading the other instances again.
+  pub fn update_instance_color(&mut self, id: u32, color: [f32; 3], queue: &Queue) {
+    for (instances, buffer) in [
+      (&mut self.opaque, &self.opaque_buffer),
+      (&mut self.transparent, &self.transparent_buffer),
+    ] {
+      for (idx, instance) in instances.iter_mut().enumerate() {
+        if instance.id == id {
+          instance.color = color;
+          self.instance_generation += 1;
+          queue.write_buffer(
+            &buffer.buffer,
+            idx as u64 * InstanceBuffer::INSTANCE_SIZE,
+            bytemuck::cast_slice(&[*instance]),
+          );
+        }
+      }
+    }
+  }
+
+  /// Reallocates both instance buffers to hold `new_capacity` instances each, copying the current instances over. This
+  /// avoids reallocating in `update_instances` when the number of features to come is known up front.
+  #[allow(dead_code)]
+  pub fn resize_instance_buffer(&mut self, new_capacity: usize, device: &Device, queue: &Queue) {
+    let new_capacity = new_capacity.max(self.opaque.len()).max(self.transparent.len());
+    self.opaque_buffer.resize(new_capacity, device, queue);
+    self.transparent_buffer.resize(new_capacity, device, queue);
+  }
+
+  /// Instances `update_instances` can take without reallocating, however they split into opaque and transparent.
+  #[allow(dead_code)]
+  pub fn instance_capacity(&self) -> usize {
+    self.opaque_buffer.capacity.min(self.transparent_buffer.capacity)
+  }
+
+  /// Changes whenever `update_instances` or `update_instance_color` change the instances. Sorting them does not count.
+  pub fn instance_generation(&self) -> u64 {
+    self.instance_generation
+  }
+
+  /// Number of instances drawn by `render_opaque` and `render_transparent` together.
+  pub fn instance_count(&self) -> usize {
+    self.opaque.len() + self.transparent.len()
+  }
+
+  /// World-space center of instance `idx`, counting the opaque instances first and then the transparent ones in
+  /// their current sort order.
+  #[allow(dead_code)]
+  pub fn get_instance_position(&self, idx: usize) -> Option<Point3<f32>> {
+    self
+      .opaque
+      .get(idx)
+      .or_else(|| self.transparent.get(idx.checked_sub(self.opaque.len())?))
+      .map(FeatureInstance::position)
+  }
+
+  /// Id of the feature drawn by instance `idx`, in the order of `get_instance_position`.
+  pub fn instance_id(&self, idx: usize) -> Option<u32> {
+    self
+      .opaque
+      .get(idx)
+      .or_else(|| self.transparent.get(idx.checked_sub(self.opaque.len())?))
+      .map(|instance| instance.id)
+  }
+
+  /// Index, in the order of `get_instance_position`, of the nearest instance in front of the ray's eye that the ray
+  /// hits. Instances are picked as balls of their scale around their center.
+  #[allow(dead_code)]
+  pub fn pick_instance(&mut self, ray: &Ray) -> Option<usize> {
+    self.last_picked = pick_instance(self.opaque.iter().chain(&self.transparent), ray);
+    self.last_picked
+  }
+
+  #[allow(dead_code)]
+  pub fn last_picked(&self) -> Option<usize> {
+    self.last_picked
+  }
+
+  /// Every instance, in the order of `get_instance_position`.
+  pub fn instances(&self) -> impl Iterator<Item = &FeatureInstance> + '_ {
+    self.opaque.iter().chain(&self.transparent)
+  }
+
+  /// World-space centers of every instance, in the order of `get_instance_position`.
+  #[allow(dead_code)]
+  pub fn positions_iter(&self) -> impl Iterator<Item = Point3<f32>> + '_ {
+    self
+      .opaque
+      .iter()
+      .chain(&self.transparent)
+      .map(FeatureInstance::position)
+  }
+
+  /// One draw for each of the opaque and transparent passes that has instances, with every instance drawing the
+  /// whole mesh.
+  pub fn stats(&self) -> RendererStats {
+    let instances = self.instance_count() as u32;
+    RendererStats {
+      draw_calls: (!self.opaque.is_empty()) as u32 + (!self.transparent.is_empty()) as u32,
+      triangles: self.index_count / 3 * instances,
+      instances,
+    }
+  }
+
+  /// Whether `sort_transparent` orders the transparent instances; when off they are drawn in the order given.
+  #[allow(dead_code)]
+  pub fn set_depth_sort(&mut self, enabled: bool) {
+    self.depth_sort = enabled;
+    self.depth_sort_needed |= enabled && !self.transparent.is_empty();
+  }
+
+  /// Re-sorts the transparent instances back to front if they changed or the camera turned since the last sort.
+  /// Depth order only depends on the view direction, so moving the eye alone never needs a sort.
+  pub fn sort_transparent(&mut self, camera: &Camera, queue: &Queue) {
+    let forward = camera.forward();
+    let turned = !self
+      .last_camera_forward
+      .is_some_and(|last| last.dot(forward) > SORT_DIRECTION_TOLERANCE);
+    if !self.depth_sort || !(self.depth_sort_needed || turned) {
+      return;
+    }
+    sort_back_to_front(&mut self.transparent, camera);
+    queue.write_buffer(
+      &self.transparent_buffer.buffer,
+      0,
+      bytemuck::cast_slice(&self.transparent),
+    );
+    self.depth_sort_needed = false;
+    self.last_camera_forward = Some(forward);
+  }
+
+  pub fn render_mode(&self) -> RenderMode {
+    self.render_mode
+  }
+
+  /// Draws the instances as `mode` from the next frame on.
+  pub fn set_render_mode(&mut self, mode: RenderMode) {
+    self.render_mode = mode;
+  }
+
+  /// Draws the fully visible instances, writing depth. Modes other than `Solid` and `Normals` draw every instance
+  /// here, opaque or not.
+  pub fn render_opaque<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+    render_pass.set_bind_group(2, &self.fog_bind_group, &[]);
+    let pipelines = &self.mode_pipelines;
+    let buffers = [
+      (&self.opaque_buffer.buffer, self.opaque.len()),
+      (&self.transparent_buffer.buffer, self.transparent.len()),
+    ];
+    let mesh = &self.debug_mesh;
+    match self.render_mode {
+      RenderMode::Solid | RenderMode::Normals => {
+        if !self.opaque.is_empty() {
+          render_pass.set_pipeline(&self.opaque_pipeline);
+          self.draw(render_pass, &self.opaque_buffer.buffer, self.opaque.len());
+        }
+        if self.render_mode == RenderMode::Normals {
+          render_pass.set_pipeline(&pipelines.normals);
+          render_pass.set_vertex_buffer(0, mesh.normal_buffer.slice(..));
+          for &(buffer, count) in buffers.iter().filter(|(_, count)| *count > 0) {
+            render_pass.set_vertex_buffer(1, buffer.slice(..));
+            render_pass.draw(0..mesh.normal_count, 0..count as u32);
+          }
+        }
+      }
+      RenderMode::Wireframe => {
+        render_pass.set_pipeline(&pipelines.wireframe);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(mesh.edge_buffer.slice(..), mesh.edge_format);
+        for &(buffer, count) in buffers.iter().filter(|(_, count)| *count > 0) {
+          render_pass.set_vertex_buffer(1, buffer.slice(..));
+          render_pass.draw_indexed(0..mesh.edge_count, 0, 0..count as u32);
+        }
+      }
+      RenderMode::Points => {
+        render_pass.set_pipeline(&pipelines.points);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for &(buffer, count) in buffers.iter().filter(|(_, count)| *count > 0) {
+          render_pass.set_vertex_buffer(1, buffer.slice(..));
+          render_pass.draw(0..self.vertices.len() as u32, 0..count as u32);
+        }
+      }
+      RenderMode::Depth | RenderMode::MaterialColor => {
+        let pipeline = if self.render_mode == RenderMode::Depth {
+          &pipelines.depth
+        } else {
+          &pipelines.material_color
+        };
+        render_pass.set_pipeline(pipeline);
+        for &(buffer, count) in buffers.iter().filter(|(_, count)| *count > 0) {
+          self.draw(render_pass, buffer, count);
+        }
+      }
+    }
+  }
+
+  /// Blends the partly visible instances over everything opaque, in the order of the last `sort_transparent`.
+  pub fn render_transparent<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    let blended = matches!(self.render_mode, RenderMode::Solid | RenderMode::Normals);
+    if self.transparent.is_empty() || !blended {
+      return;
+    }
+    render_pass.set_pipeline(&self.transparent_pipeline);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+    render_pass.set_bind_group(2, &self.fog_bind_group, &[]);
+    self.draw(render_pass, &self.transparent_buffer.buffer, self.transparent.len());
+  }
+
+  /// Format of the textures `render_picking` draws into.
+  pub const PICK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+  /// Clears `picking_texture` and `depth_texture` and writes the index of the nearest instance covering every pixel,
+  /// in the order of `get_instance_position`, to be read back by `read_pick_result`. `picking_texture` must be a
+  /// `PICK_FORMAT` render target such as one from `Texture::create_render_target`.
+  pub fn render_picking(
+    &self,
+    encoder: &mut CommandEncoder,
+    picking_texture: &Texture,
+    depth_texture: &Texture,
+    camera: &Camera,
+  ) {
+    let mut render_pass = RenderPassBuilder::new(encoder, "Pick Pass")
+      .color(&picking_texture.view)
+      .clear_color(wgpu::Color::TRANSPARENT)
+      .depth(&depth_texture.view)
+      .clear_depth(1.0)
+      .build();
+    render_pass.set_pipeline(&self.pick_pipeline);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    let buffers = [
+      (&self.opaque_buffer.buffer, self.opaque.len()),
+      (&self.transparent_buffer.buffer, self.transparent.len()),
+    ];
+    for (&(buffer, count), bind_group) in buffers.iter().zip(&self.pick_bind_groups) {
+      if count > 0 {
+        render_pass.set_bind_group(1, bind_group, &[]);
+        self.draw(&mut render_pass, buffer, count);
+      }
+    }
+  }
+
+  /// Instance index at pixel (`x`, `y`) of a `picking_texture` drawn by `render_picking`, or `None` where no instance
+  /// was drawn or outside the texture. Waits for the GPU to finish the pick pass.
+  pub fn read_pick_result(
+    &self,
+    device: &Device,
+    queue: &Queue,
+    picking_texture: &Texture,
+    x: u32,
+    y: u32,
+  ) -> Option<u32> {
+    if x >= picking_texture.width() || y >= picking_texture.height() {
+      return None;
+    }
+    let size = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Pick Readback Buffer"),
+      size,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+      mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Pick Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+      wgpu::ImageCopyTexture {
+        texture: &picking_texture.texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d { x, y, z: 0 },
+        aspect: wgpu::TextureAspect::All,
+      },
+      wgpu::ImageCopyBuffer {
+        buffer: &buffer,
+        layout: wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: NonZeroU32::new(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+          rows_per_image: None,
+        },
+      },
+      wgpu::Extent3d {
+        width: 1,
+        height: 1,
+        depth_or_array_layers: 1,
+      },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    if let Err(err) = async_std::task::block_on(mapping) {
+      eprintln!("failed to read back pick: '{}'", err);
+      return None;
+    }
+    let pixel = bytemuck::cast_slice::<u8, u32>(&slice.get_mapped_range())[0];
+    buffer.unmap();
+    decode_pick(pixel)
+  }
+
+  /// Outlines the instances of the features in `selected_ids` with `color` in the following `render_outline` calls.
+  #[cfg(feature = "outlines")]
+  pub fn set_outline(&mut self, selected_ids: &[u32], color: [f32; 4], device: &Device, queue: &Queue) {
+    let selected = outline_instances(self.opaque.iter().chain(&self.transparent), selected_ids);
+    let outline = &mut self.outline;
+    if selected.len() > outline.capacity {
+      outline.buffer = Self::instance_buffer(&selected, device);
+      outline.capacity = selected.len();
+    } else if !selected.is_empty() {
+      queue.write_buffer(&outline.buffer, 0, bytemuck::cast_slice(&selected));
+    }
+    outline.count = selected.len();
+    queue.write_buffer(
+      &outline.uniform_buffer,
+      0,
+      bytemuck::cast_slice(&[OutlineUniform::new(color)]),
+    );
+  }
+
+  /// Draws a silhouette around the features picked by `set_outline`, in a pass whose stencil was cleared to 0. The
+  /// selection is first marked in the stencil, then grown slightly and drawn wherever it isn't marked.
+  #[cfg(feature = "outlines")]
+  pub fn render_outline<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    let outline = &self.outline;
+    if outline.count == 0 {
+      return;
+    }
+    render_pass.set_stencil_reference(1);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    render_pass.set_bind_group(1, &outline.bind_group, &[]);
+    render_pass.set_pipeline(&outline.mask_pipeline);
+    self.draw(render_pass, &outline.buffer, outline.count);
+    render_pass.set_pipeline(&outline.pipeline);
+    self.draw(render_pass, &outline.buffer, outline.count);
+  }
+
+  fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, instances: &'a Buffer, count: usize) {
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.set_vertex_buffer(1, instances.slice(..));
+    render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+    render_pass.draw_indexed(0..self.index_count, 0, 0..count as u32);
+  }
+}
+
+/// The instances drawing any of the features in `ids`.
+#[cfg(feature = "outlines")]
+fn outline_instances<'a>(instances: impl Iterator<Item = &'a FeatureInstance>, ids: &[u32]) -> Vec<FeatureInstance> {
+  instances
+    .filter(|instance| ids.contains(&instance.id))
+    .copied()
+    .collect()
+}
+
+/// Index of the instance whose ball is hit nearest in front of the ray's eye.
+fn pick_instance<'a>(instances: impl Iterator<Item = &'a FeatureInstance>, ray: &Ray) -> Option<usize> {
+  instances
+    .enumerate()
+    .filter_map(|(idx, instance)| {
+      let model = Matrix4::from(instance.model);
+      let center = model.w.truncate();
+      let scale = model.x.truncate().magnitude();
+      // Moving the ray to the instance's center keeps distances along it, so `t` is comparable between instances
+      let local = Transform::new(Matrix4::from_translation(center))?.apply_forward(ray);
+      let t = Ball::new(scale)
+        .intersect(&local)
+        .hits()
+        .map(|hit| local.parameter(hit.position))
+        .find(|&t| t >= 0.0)?;
+      Some((idx, t))
+    })
+    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+    .map(|(idx, _)| idx)
+}
+
+/// Sphere segments of each `LodFeatureRenderer` level, nearest first.
+pub const LOD_SPHERE_SEGMENTS: [u32; 3] = [64, 16, 4];
+
+/// Level of detail for an instance `distance` from the eye: 0 closer than `near`, 2 at `far` or beyond and 1 between.
+fn lod_level(distance: f32, near: f32, far: f32) -> usize {
+  if distance < near {
+    0
+  } else if distance < far {
+    1
+  } else {
+    2
+  }
+}
+
+/// Splits `instances` into the levels of detail they are drawn at from `eye`.
+fn partition_lod<'a, I: IntoIterator<Item = &'a FeatureInstance>>(
+  instances: I,
+  eye: Point3<f32>,
+  near: f32,
+  far: f32,
+) -> [Vec<FeatureInstance>; 3] {
+  let mut levels = [Vec::new(), Vec::new(), Vec::new()];
+  for instance in instances {
+    let distance = (instance.position() - eye).magnitude();
+    levels[lod_level(distance, near, far)].push(*instance);
+  }
+  levels
+}
+
+/// Draws feature instances as spheres of fewer segments the farther they are from the eye, with one
+/// `FeatureRenderer` per level of `LOD_SPHERE_SEGMENTS`. The instances are re-binned by `update` every frame.
+pub struct LodFeatureRenderer {
+  levels: [FeatureRenderer; 3],
+  near: f32,
+  far: f32,
+}
+
+impl LodFeatureRenderer {
+  pub const DEFAULT_NEAR: f32 = 5.0;
+  pub const DEFAULT_FAR: f32 = 20.0;
+
+  /// A renderer without any instances. Every level is drawn the same way as a `FeatureRenderer` without a z prepass.
+  pub fn new(device: &Device, queue: &Queue, surface_config: &SurfaceConfiguration) -> Self {
+    let level = |segments: u32| {
+      FeatureRenderer::new(FeatureRendererConfiguration {
+        geometry: super::geometry::uv_sphere(segments),
+        instances: Vec::new(),
+        device,
+        queue,
+        surface_config,
+        use_z_prepass: false,
+      })
+    };
+    Self {
+      levels: LOD_SPHERE_SEGMENTS.map(level),
+      near: Self::DEFAULT_NEAR,
+      far: Self::DEFAULT_FAR,
+    }
+  }
+
+  /// Distances from the eye at which instances drop to the middle and then the lowest level of detail.
+  pub fn set_thresholds(&mut self, near: f32, far: f32) {
+    self.near = near;
+    self.far = far.max(near);
+  }
+
+  /// Replaces the drawn instances with `instances`, each in the level for its distance from the camera's eye, and
+  /// sorts the transparent ones of each level.
+  pub fn update<'a, I: IntoIterator<Item = &'a FeatureInstance>>(
+    &mut self,
+    instances: I,
+    camera: &Camera,
+    device: &Device,
+    queue: &Queue,
+  ) {
+    let bins = partition_lod(instances, camera.eye, self.near, self.far);
+    for (level, instances) in self.levels.iter_mut().zip(bins) {
+      level.update_instances(instances, device, queue);
+      level.sort_transparent(camera, queue);
+    }
+  }
+
+  pub fn set_fog(&self, density: f32, color: [f32; 4], queue: &Queue) {
+    for level in &self.levels {
+      level.set_fog(density, color, queue);
+    }
+  }
+
+  pub fn set_render_mode(&mut self, mode: RenderMode) {
+    for level in &mut self.levels {
+      level.set_render_mode(mode);
+    }
+  }
+
+  pub fn stats(&self) -> RendererStats {
+    self.levels.iter().map(FeatureRenderer::stats).sum()
+  }
+
+  pub fn render_opaque<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    for level in &self.levels {
+      level.render_opaque(render_pass, camera);
+    }
+  }
+
+  /// Draws the partly visible instances, farthest level first.
+  pub fn render_transparent<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    for level in self.levels.iter().rev() {
+      level.render_transparent(render_pass, camera);
+    }
+  }
+}
+
+/// Cosine of the largest camera turn that keeps the previous transparent sort order.
+const SORT_DIRECTION_TOLERANCE: f32 = 0.999;
+
+/// Orders instances from farthest to nearest along the camera's view direction.
+fn sort_back_to_front(instances: &mut [FeatureInstance], camera: &Camera) {
+  let forward = camera.forward();
+  let depth = |instance: &FeatureInstance| (instance.position() - camera.eye).dot(forward);
+  instances.sort_by(|a, b| depth(b).partial_cmp(&depth(a)).unwrap_or(std::cmp::Ordering::Equal));
+}
+
+/// Depth-only pass over the opaque feature instances so the main pass shades each pixel once.
+pub struct ZPrepass {
+  pipeline: RenderPipeline,
+}
+
+impl ZPrepass {
+  pub fn new(device: &Device, _config: &SurfaceConfiguration) -> Self {
+    let shader = super::shader::feature::compile(device);
+
+    let camera_layout = Camera::layout(device);
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Z Prepass Layout"),
+      bind_group_layouts: &[&camera_layout],
+      push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Z Prepass Pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vertex",
+        buffers: &[FeatureVertex::description(), FeatureInstance::description()],
+      },
+      fragment: None,
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: Some(wgpu::Face::Back),
+        polygon_mode: wgpu::PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Less,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    });
+
+    Self { pipeline }
+  }
+
+  /// Clears `depth_texture` and writes the depth of every instance in `features`.
+  pub fn render(
+    &self,
+    encoder: &mut CommandEncoder,
+    depth_texture: &Texture,
+    features: &FeatureRenderer,
+    camera: &Camera,
+  ) {
+    let mut render_pass = RenderPassBuilder::new(encoder, "Z Prepass")
+      .depth(&depth_texture.view)
+      .clear_depth(1.0)
+      .build();
+    if features.opaque.is_empty() {
+      return;
+    }
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    features.draw(&mut render_pass, &features.opaque_buffer.buffer, features.opaque.len());
+  }
+}
+
+pub struct InstancedLineRendererConfiguration<'a> {
+  pub path: &'a [[f32; 3]],
+  pub color: [f32; 4],
+  pub device: &'a Device,
+  pub surface_config: &'a SurfaceConfiguration,
+}
+
+/// Draws a path of world-space points as connected line segments.
+pub struct InstancedLineRenderer {
+  pipeline: RenderPipeline,
+  point_buffer: Buffer,
+  capacity: usize,
+  point_count: usize,
+  color_buffer: Buffer,
+  color_bind_group: BindGroup,
+}
+
+impl InstancedLineRenderer {
+  pub fn new(config: InstancedLineRendererConfiguration) -> Self {
+    let shader = super::shader::line(config.device);
+
+    let camera_layout = Camera::layout(config.device);
+    let color_layout = config
+      .device
+      .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }],
+        label: Some("line_bind_group_layout"),
+      });
+
+    let layout = config.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Line Layout"),
+      bind_group_layouts: &[&camera_layout, &color_layout],
+      push_constant_ranges: &[],
+    });
+
+    const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+    let pipeline = config.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Line Pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vertex",
+        buffers: &[wgpu::VertexBufferLayout {
+          array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+          step_mode: wgpu::VertexStepMode::Vertex,
+          attributes: &ATTRIBUTES,
+        }],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fragment",
+        targets: &[wgpu::ColorTargetState {
+          format: config.surface_config.format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::LineStrip,
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: None,
+        polygon_mode: wgpu::PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Less,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    });
+
+    let color_buffer = config.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Line Color Buffer"),
+      contents: bytemuck::cast_slice(&config.color),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let color_bind_group = config.device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &color_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: color_buffer.as_entire_binding(),
+      }],
+      label: Some("line_bind_group"),
+    });
+
+    let (point_buffer, capacity) = Self::point_buffer(config.path, config.device);
+
+    Self {
+      pipeline,
+      point_buffer,
+      capacity,
+      point_count: config.path.len(),
+      color_buffer,
+      color_bind_group,
+    }
+  }
+
+  fn point_buffer(path: &[[f32; 3]], device: &Device) -> (Buffer, usize) {
+    // Keep at least one point so the buffer is never empty
+    let padding = [[0.0; 3]];
+    let points = if path.is_empty() { &padding[..] } else { path };
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Line Point Buffer"),
+      contents: bytemuck::cast_slice(points),
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    });
+    (buffer, points.len())
+  }
+
+  /// Replaces the drawn path, writing into the existing buffer when it is large enough.
+  pub fn update_path(&mut self, path: &[[f32; 3]], device: &Device, queue: &Queue) {
+    if path.len() > self.capacity {
+      let (point_buffer, capacity) = Self::point_buffer(path, device);
+      self.point_buffer = point_buffer;
+      self.capacity = capacity;
+    } else if !path.is_empty() {
+      queue.write_buffer(&self.point_buffer, 0, bytemuck::cast_slice(path));
+    }
+    self.point_count = path.len();
+  }
+
+  #[allow(dead_code)]
+  pub fn set_color(&self, color: [f32; 4], queue: &Queue) {
+    queue.write_buffer(&self.color_buffer, 0, bytemuck::cast_slice(&color));
+  }
+
+  pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    if self.point_count < 2 {
+      return;
+    }
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    render_pass.set_bind_group(1, &self.color_bind_group, &[]);
+    render_pass.set_vertex_buffer(0, self.point_buffer.slice(..));
+    render_pass.draw(0..self.point_count as u32, 0..1);
+  }
+}
+
+/// One point of a `PointCloudRenderer`, stepped per instance.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointVertex {
+  pub position: [f32; 3],
+  pub color: [f32; 4],
+  /// Width and height in pixels
+  pub point_size: f32,
+}
+
+impl PointVertex {
+  const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4, 2 => Float32];
+
+  pub fn description<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<PointVertex>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Instance,
+      attributes: &Self::ATTRIBUTES,
+    }
+  }
+}
+
+/// Interleaves `points` with their `colors` and `sizes`. Points without a size are `DEFAULT_POINT_SIZE` pixels and
+/// points without a color are white.
+fn point_vertices(points: &[[f32; 3]], colors: &[[f32; 4]], sizes: &[f32]) -> Vec<PointVertex> {
+  points
+    .iter()
+    .enumerate()
+    .map(|(idx, &position)| PointVertex {
+      position,
+      color: colors.get(idx).copied().unwrap_or([1.0; 4]),
+      point_size: sizes
+        .get(idx)
+        .copied()
+        .unwrap_or(PointCloudRenderer::DEFAULT_POINT_SIZE),
+    })
+    .collect()
+}
+
+/// Draws every point as a colored square of a fixed size in pixels, however far away it is.
+pub struct PointCloudRenderer {
+  pipeline: RenderPipeline,
+  point_buffer: Buffer,
+  capacity: usize,
+  point_count: usize,
+  viewport_buffer: Buffer,
+  viewport_bind_group: BindGroup,
+}
+
+impl PointCloudRenderer {
+  pub const DEFAULT_POINT_SIZE: f32 = 1.0;
+
+  pub fn new(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    points: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+    sizes: Vec<f32>,
+  ) -> Self {
+    let shader = super::shader::point_cloud(device);
+
+    let camera_layout = Camera::layout(device);
+    let viewport_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+      label: Some("point_cloud_bind_group_layout"),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Point Cloud Layout"),
+      bind_group_layouts: &[&camera_layout, &viewport_layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Point Cloud Pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vertex",
+        buffers: &[PointVertex::description()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fragment",
+        targets: &[wgpu::ColorTargetState {
+          format: config.format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleStrip,
+        ..Default::default()
+      },
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Less,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    let viewport_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Point Cloud Viewport Buffer"),
+      contents: bytemuck::cast_slice(&[config.width as f32, config.height as f32]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let viewport_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &viewport_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: viewport_buffer.as_entire_binding(),
+      }],
+      label: Some("point_cloud_bind_group"),
+    });
+
+    let vertices = point_vertices(&points, &colors, &sizes);
+    let (point_buffer, capacity) = Self::point_buffer(&vertices, device);
+    Self {
+      pipeline,
+      point_buffer,
+      capacity,
+      point_count: vertices.len(),
+      viewport_buffer,
+      viewport_bind_group,
+    }
+  }
+
+  fn point_buffer(vertices: &[PointVertex], device: &Device) -> (Buffer, usize) {
+    // Keep at least one point so the buffer is never empty
+    let padding = [bytemuck::Zeroable::zeroed()];
+    let vertices = if vertices.is_empty() { &padding[..] } else { vertices };
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Point Cloud Buffer"),
+      contents: bytemuck::cast_slice(vertices),
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    });
+    (buffer, vertices.len())
+  }
+
+  /// Replaces the drawn points, writing into the existing buffer when it is large enough.
+  pub fn update_points(
+    &mut self,
+    points: &[[f32; 3]],
+    colors: &[[f32; 4]],
+    sizes: &[f32],
+    device: &Device,
+    queue: &Queue,
+  ) {
+    let vertices = point_vertices(points, colors, sizes);
+    if vertices.len() > self.capacity {
+      let (point_buffer, capacity) = Self::point_buffer(&vertices, device);
+      self.point_buffer = point_buffer;
+      self.capacity = capacity;
+    } else if !vertices.is_empty() {
+      queue.write_buffer(&self.point_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+    self.point_count = vertices.len();
+  }
+
+  /// Keeps point sizes in pixels after the render target changes size.
+  pub fn resize(&self, width: u32, height: u32, queue: &Queue) {
+    queue.write_buffer(
+      &self.viewport_buffer,
+      0,
+      bytemuck::cast_slice(&[width as f32, height as f32]),
+    );
+  }
+
+  /// One draw of four vertices for each point.
+  pub fn stats(&self) -> RendererStats {
+    RendererStats {
+      draw_calls: (self.point_count > 0) as u32,
+      triangles: 2 * self.point_count as u32,
+      instances: self.point_count as u32,
+    }
+  }
+
+  pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    if self.point_count == 0 {
+      return;
+    }
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    render_pass.set_bind_group(1, &self.viewport_bind_group, &[]);
+    render_pass.set_vertex_buffer(0, self.point_buffer.slice(..));
+    render_pass.draw(0..4, 0..self.point_count as u32);
+  }
+}
+
+/// One feature's position uncertainty in an `EllipsoidRenderer`, stepped per instance.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct EllipsoidInstance {
+  /// Maps the unit sphere onto the ellipsoid, scaling each axis differently
+  pub model: [[f32; 4]; 4],
+  /// Inverse transpose of the upper 3x3 of `model`, which transforms normals under the non-uniform scale
+  pub normal: [[f32; 3]; 3],
+  pub color: [f32; 4],
+}
+
+impl From<&Feature> for EllipsoidInstance {
+  fn from(feature: &Feature) -> Self {
+    let model = Matrix4::from_translation(feature.position_mean)
+      * Matrix4::from(feature.orientation_quaternion())
+      * feature.uncertainty_ellipsoid_transform();
+    let linear = Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+    // An ellipsoid flattened by a zero deviation has no inverse, but then its normals hardly matter
+    let normal = linear.invert().map_or(linear, |inverse| inverse.transpose());
+    EllipsoidInstance {
+      model: model.into(),
+      normal: normal.into(),
+      color: feature
+        .color
+        .map(|x| x as f32 / 255.0)
+        .extend(EllipsoidRenderer::ALPHA)
+        .into(),
+    }
+  }
+}
+
+impl EllipsoidInstance {
+  const ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+    2 => Float32x4,
+    3 => Float32x4,
+    4 => Float32x4,
+    5 => Float32x4,
+    6 => Float32x3,
+    7 => Float32x3,
+    8 => Float32x3,
+    9 => Float32x4,
+  ];
+
+  pub fn description<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<EllipsoidInstance>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Instance,
+      attributes: &Self::ATTRIBUTES,
+    }
+  }
+}
+
+/// Draws the one standard deviation position ellipsoid of every feature, translucent and over the solid features, so
+/// anisotropic uncertainty shows up as stretched shells.
+pub struct EllipsoidRenderer {
+  pipeline: RenderPipeline,
+  vertex_buffer: Buffer,
+  index_buffer: Buffer,
+  index_count: u32,
+  index_format: wgpu::IndexFormat,
+  instance_buffer: Buffer,
+  capacity: usize,
+  instance_count: usize,
+}
+
+impl EllipsoidRenderer {
+  pub const ALPHA: f32 = 0.3;
+  const SPHERE_SEGMENTS: u32 = 16;
+
+  pub fn new(device: &Device, config: &SurfaceConfiguration) -> Self {
+    let shader = super::shader::ellipsoid(device);
+
+    const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+    let vertex_layout = wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<[[f32; 3]; 2]>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &VERTEX_ATTRIBUTES,
+    };
+    let camera_layout = Camera::layout(device);
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Ellipsoid Layout"),
+      bind_group_layouts: &[&camera_layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Ellipsoid Pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vertex",
+        buffers: &[vertex_layout, EllipsoidInstance::description()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fragment",
+        targets: &[wgpu::ColorTargetState {
+          format: config.format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: Some(wgpu::Face::Back),
+        ..Default::default()
+      },
+      // Overlaid on the solid spheres, which usually enclose the ellipsoids
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    let sphere = super::geometry::uv_sphere(Self::SPHERE_SEGMENTS);
+    let vertices: Vec<[[f32; 3]; 2]> = sphere
+      .vertices
+      .iter()
+      .zip(sphere.normals.iter())
+      .map(|(&position, &normal)| [position.into(), normal.into()])
+      .collect();
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Ellipsoid Vertex Buffer"),
+      contents: bytemuck::cast_slice(&vertices),
+      usage: wgpu::BufferUsages::VERTEX,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Ellipsoid Index Buffer"),
+      contents: sphere.indices.as_bytes(),
+      usage: wgpu::BufferUsages::INDEX,
+    });
+    let (instance_buffer, capacity) = Self::instance_buffer(&[], device);
+    Self {
+      pipeline,
+      vertex_buffer,
+      index_buffer,
+      index_count: sphere.indices.len() as u32,
+      index_format: sphere.indices.format(),
+      instance_buffer,
+      capacity,
+      instance_count: 0,
+    }
+  }
+
+  fn instance_buffer(instances: &[EllipsoidInstance], device: &Device) -> (Buffer, usize) {
+    // Keep at least one instance so the buffer is never empty
+    let padding = [bytemuck::Zeroable::zeroed()];
+    let instances = if instances.is_empty() { &padding[..] } else { instances };
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Ellipsoid Instance Buffer"),
+      contents: bytemuck::cast_slice(instances),
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    });
+    (buffer, instances.len())
+  }
+
+  /// Replaces the drawn ellipsoids with those of `features`, writing into the existing buffer when it is large enough.
+  pub fn update(&mut self, features: &[Feature], device: &Device, queue: &Queue) {
+    let instances: Vec<EllipsoidInstance> = features.iter().map(EllipsoidInstance::from).collect();
+    if instances.len() > self.capacity {
+      let (instance_buffer, capacity) = Self::instance_buffer(&instances, device);
+      self.instance_buffer = instance_buffer;
+      self.capacity = capacity;
+    } else if !instances.is_empty() {
+      queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+    }
+    self.instance_count = instances.len();
+  }
+
+  /// One instanced draw of the sphere mesh.
+  pub fn stats(&self) -> RendererStats {
+    RendererStats {
+      draw_calls: (self.instance_count > 0) as u32,
+      triangles: self.index_count / 3 * self.instance_count as u32,
+      instances: self.instance_count as u32,
+    }
+  }
+
+  pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    if self.instance_count == 0 {
+      return;
+    }
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+    render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+    render_pass.draw_indexed(0..self.index_count, 0, 0..self.instance_count as u32);
+  }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GridVertex {
+  pub position: [f32; 3],
+  pub color: [f32; 4],
+}
+
+impl GridVertex {
+  pub fn description<'a>() -> wgpu::VertexBufferLayout<'a> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+      0 => Float32x3,
+      1 => Float32x4,
+    ];
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<GridVertex>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &ATTRIBUTES,
+    }
+  }
+}
+
+const GRID_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const GRID_X_COLOR: [f32; 4] = [0.6, 0.2, 0.2, 1.0];
+const GRID_Z_COLOR: [f32; 4] = [0.2, 0.2, 0.6, 1.0];
+
+/// Draws a square grid of lines on the XZ plane around the origin, with the lines along the X and Z axes colored red
+/// and blue, and optionally the three world axes over it.
+pub struct GridRenderer {
+  pipeline: RenderPipeline,
+  /// Grid lines followed by the axis lines
+  vertex_buffer: Buffer,
+  grid_vertex_count: u32,
+  visible: bool,
+  show_axes: bool,
+}
+
+impl GridRenderer {
+  /// A grid spanning `-half_extent..=half_extent` along X and Z, split into `divisions` cells each side of the axes.
+  pub fn new(device: &Device, config: &SurfaceConfiguration, half_extent: f32, divisions: u32) -> GridRenderer {
+    let shader = super::shader::grid(device);
+    let camera_layout = Camera::layout(device);
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Grid Layout"),
+      bind_group_layouts: &[&camera_layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Grid Pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vertex",
+        buffers: &[GridVertex::description()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fragment",
+        targets: &[wgpu::ColorTargetState {
+          format: config.format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::LineList,
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: None,
+        polygon_mode: wgpu::PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      // Equal depth lets the axes draw over the grid lines they lie on
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::LessEqual,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    });
+
+    let mut vertices = grid_vertices(half_extent, divisions);
+    let grid_vertex_count = vertices.len() as u32;
+    vertices.extend(axis_vertices(half_extent));
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Grid Vertex Buffer"),
+      contents: bytemuck::cast_slice(&vertices),
+      usage: wgpu::BufferUsages::VERTEX,
+    });
+
+    GridRenderer {
+      pipeline,
+      vertex_buffer,
+      grid_vertex_count,
+      visible: true,
+      show_axes: false,
+    }
+  }
+
+  pub fn set_visible(&mut self, visible: bool) {
+    self.visible = visible;
+  }
+
+  pub fn is_visible(&self) -> bool {
+    self.visible
+  }
+
+  /// Whether the X, Y and Z axes are drawn in red, green and blue over the grid.
+  #[allow(dead_code)]
+  pub fn show_axes(&mut self, show: bool) {
+    self.show_axes = show;
+  }
+
+  pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    if !self.visible {
+      return;
+    }
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.draw(0..self.grid_vertex_count, 0..1);
+    if self.show_axes {
+      render_pass.draw(self.grid_vertex_count..self.grid_vertex_count + 6, 0..1);
+    }
+  }
+}
+
+/// Line list of `2 * divisions + 1` lines along X and as many along Z, evenly spaced over `-half_extent..=half_extent`.
+fn grid_vertices(half_extent: f32, divisions: u32) -> Vec<GridVertex> {
+  let divisions = divisions as i32;
+  let spacing = if divisions > 0 {
+    half_extent / divisions as f32
+  } else {
+    0.0
+  };
+  let vertex = |position: [f32; 3], color: [f32; 4]| GridVertex { position, color };
+  (-divisions..=divisions)
+    .flat_map(|i| {
+      let offset = i as f32 * spacing;
+      let (x_color, z_color) = if i == 0 {
+        (GRID_X_COLOR, GRID_Z_COLOR)
+      } else {
+        (GRID_COLOR, GRID_COLOR)
+      };
+      vec![
+        vertex([-half_extent, 0.0, offset], x_color),
+        vertex([half_extent, 0.0, offset], x_color),
+        vertex([offset, 0.0, -half_extent], z_color),
+        vertex([offset, 0.0, half_extent], z_color),
+      ]
+    })
+    .collect()
+}
+
+/// Line list of the X, Y and Z axes through the origin in red, green and blue.
+fn axis_vertices(half_extent: f32) -> Vec<GridVertex> {
+  let colors = [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]];
+  colors
+    .iter()
+    .enumerate()
+    .flat_map(|(axis, &color)| {
+      let mut end = [0.0; 3];
+      end[axis] = half_extent;
+      let start = [-end[0], -end[1], -end[2]];
+      vec![
+        GridVertex { position: start, color },
+        GridVertex { position: end, color },
+      ]
+    })
+    .collect()
+}
+
+/// Corners of the skybox cube, indexed by `x + 2y + 4z` with each coordinate 0 for -1 and 1 for 1.
+fn skybox_corners() -> [[f32; 3]; 8] {
+  [0, 1, 2, 3, 4, 5, 6, 7].map(|i| [i & 1, (i >> 1) & 1, (i >> 2) & 1].map(|bit| bit as f32 * 2.0 - 1.0))
+}
+
+/// Triangles of the skybox cube, wound counter-clockwise seen from inside.
+const SKYBOX_INDICES: [u16; 36] = [
+  0, 2, 6, 0, 6, 4, 1, 7, 3, 1, 5, 7, 0, 5, 1, 0, 4, 5, 2, 3, 7, 2, 7, 6, 0, 1, 3, 0, 3, 2, 4, 7, 5, 4, 6, 7,
+];
+
+/// Draws a cubemap behind everything else, looked up by the view direction. Draw it first in the main pass, since it
+/// neither tests nor writes depth.
+pub struct SkyboxRenderer {
+  pipeline: RenderPipeline,
+  vertex_buffer: Buffer,
+  index_buffer: Buffer,
+  cubemap_bind_group: BindGroup,
+  _cubemap: Texture,
+}
+
+impl SkyboxRenderer {
+  pub fn new(device: &Device, config: &SurfaceConfiguration, cubemap: Texture) -> Self {
+    let shader = super::shader::skybox(device);
+
+    let camera_layout = Camera::layout(device);
+    let cubemap_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+      label: Some("skybox_bind_group_layout"),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Skybox Layout"),
+      bind_group_layouts: &[&camera_layout, &cubemap_layout],
+      push_constant_ranges: &[],
+    });
+    const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Skybox Pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vertex",
+        buffers: &[wgpu::VertexBufferLayout {
+          array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+          step_mode: wgpu::VertexStepMode::Vertex,
+          attributes: &ATTRIBUTES,
+        }],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fragment",
+        targets: &[wgpu::ColorTargetState {
+          format: config.format,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: Some(wgpu::Face::Back),
+        ..Default::default()
+      },
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Skybox Vertex Buffer"),
+      contents: bytemuck::cast_slice(&skybox_corners()),
+      usage: wgpu::BufferUsages::VERTEX,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Skybox Index Buffer"),
+      contents: bytemuck::cast_slice(&SKYBOX_INDICES),
+      usage: wgpu::BufferUsages::INDEX,
+    });
+    let cubemap_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &cubemap_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&cubemap.view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+        },
+      ],
+      label: Some("skybox_bind_group"),
+    });
+    Self {
+      pipeline,
+      vertex_buffer,
+      index_buffer,
+      cubemap_bind_group,
+      _cubemap: cubemap,
+    }
+  }
+
+  /// One draw of the twelve cube triangles.
+  pub fn stats(&self) -> RendererStats {
+    RendererStats {
+      draw_calls: 1,
+      triangles: SKYBOX_INDICES.len() as u32 / 3,
+      instances: 1,
+    }
+  }
+
+  pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a Camera) {
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera.bind_group(), &[]);
+    render_pass.set_bind_group(1, &self.cubemap_bind_group, &[]);
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+    render_pass.draw_indexed(0..SKYBOX_INDICES.len() as u32, 0, 0..1);
+  }
+}
+
+#[cfg(feature = "ssao")]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+  projection: [[f32; 4]; 4],
+  inverse_projection: [[f32; 4]; 4],
+  kernel: [f32; 48],
+  sample_count: u32,
+  radius: f32,
+  bias: f32,
+  _padding: u32,
+}
+
+/// Screen-space ambient occlusion. `render` estimates per-pixel occlusion from the depth buffer by sampling a
+/// hemisphere around each reconstructed position; `resolve` then darkens the rendered frame by that factor.
+#[cfg(feature = "ssao")]
+pub struct SsaoPass {
+  uniform: SsaoUniform,
+  buffer: Buffer,
+  layout: BindGroupLayout,
+  pipeline: RenderPipeline,
+  resolve_layout: BindGroupLayout,
+  resolve_pipeline: RenderPipeline,
+  occlusion_view: TextureView,
+  resolve_bind_group: BindGroup,
+}
+
+#[cfg(feature = "ssao")]
+impl SsaoPass {
+  pub const MAX_SAMPLES: usize = 16;
+  const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+  pub fn new(device: &Device, config: &SurfaceConfiguration, sample_count: usize) -> Self {
+    assert!(
+      sample_count <= Self::MAX_SAMPLES,
+      "at most 16 SSAO samples are supported"
+    );
+
+    let uniform = SsaoUniform {
+      projection: Matrix4::identity().into(),
+      inverse_projection: Matrix4::identity().into(),
+      kernel: Self::kernel(sample_count),
+      sample_count: sample_count as u32,
+      radius: 0.5,
+      bias: 0.025,
+      _padding: 0,
+    };
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("SSAO Buffer"),
+      contents: bytemuck::cast_slice(&[uniform]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Depth,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+      ],
+      label: Some("ssao_bind_group_layout"),
+    });
+    let pipeline = Self::fullscreen_pipeline(
+      device,
+      "SSAO Pipeline",
+      &super::shader::ssao(device),
+      &layout,
+      wgpu::ColorTargetState {
+        format: Self::OCCLUSION_FORMAT,
+        blend: None,
+        write_mask: wgpu::ColorWrites::ALL,
+      },
+    );
+
+    let resolve_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+          sample_type: wgpu::TextureSampleType::Float { filterable: false },
+          view_dimension: wgpu::TextureViewDimension::D2,
+          multisampled: false,
+        },
+        count: None,
+      }],
+      label: Some("ssao_resolve_bind_group_layout"),
+    });
+    // Multiply the frame's color by the occlusion factor and keep its alpha
+    let resolve_pipeline = Self::fullscreen_pipeline(
+      device,
+      "SSAO Resolve Pipeline",
+      &super::shader::ssao_resolve(device),
+      &resolve_layout,
+      wgpu::ColorTargetState {
+        format: config.format,
+        blend: Some(wgpu::BlendState {
+          color: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Dst,
+            dst_factor: wgpu::BlendFactor::Zero,
+            operation: wgpu::BlendOperation::Add,
+          },
+          alpha: wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+          },
+        }),
+        write_mask: wgpu::ColorWrites::ALL,
+      },
+    );
+
+    let occlusion_view = Self::create_occlusion_view(device, config);
+    let resolve_bind_group = Self::create_resolve_bind_group(device, &resolve_layout, &occlusion_view);
+
+    Self {
+      uniform,
+      buffer,
+      layout,
+      pipeline,
+      resolve_layout,
+      resolve_pipeline,
+      occlusion_view,
+      resolve_bind_group,
+    }
+  }
+
+  /// Random sample offsets in the +Z unit hemisphere, packed as vec3s and scaled to cluster near the origin.
+  fn kernel(sample_count: usize) -> [f32; 48] {
+    let mut rng = rand::thread_rng();
+    let unit = Uniform::from(-1.0..1.0);
+    let positive = Uniform::from(0.0..1.0);
+    let mut kernel = [0.0; 48];
+    for i in 0..sample_count {
+      let direction = Vector3::new(unit.sample(&mut rng), unit.sample(&mut rng), positive.sample(&mut rng));
+      let direction = if direction.magnitude2() > 0.0 {
+        direction.normalize()
+      } else {
+        Vector3::unit_z()
+      };
+      let scale = i as f32 / sample_count as f32;
+      let sample = direction * positive.sample(&mut rng) * (0.1 + 0.9 * scale * scale);
+      kernel[i * 3..i * 3 + 3].copy_from_slice(&[sample.x, sample.y, sample.z]);
+    }
+    kernel
+  }
+
+  fn fullscreen_pipeline(
+    device: &Device,
+    label: &str,
+    shader: &wgpu::ShaderModule,
+    layout: &BindGroupLayout,
+    target: wgpu::ColorTargetState,
+  ) -> RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some(label),
+      bind_group_layouts: &[layout],
+      push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some(label),
+      layout: Some(&pipeline_layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vertex",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: "fragment",
+        targets: &[target],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    })
+  }
+
+  fn create_occlusion_view(device: &Device, config: &SurfaceConfiguration) -> TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("ssao_occlusion_texture"),
+      size: wgpu::Extent3d {
+        width: config.width,
+        height: config.height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: Self::OCCLUSION_FORMAT,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+  }
+
+  fn create_resolve_bind_group(device: &Device, layout: &BindGroupLayout, occlusion_view: &TextureView) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(occlusion_view),
+      }],
+      label: Some("ssao_resolve_bind_group"),
+    })
+  }
+
+  /// Recreates the occlusion texture to match the surface size.
+  pub fn resize(&mut self, device: &Device, config: &SurfaceConfiguration) {
+    self.occlusion_view = Self::create_occlusion_view(device, config);
+    self.resolve_bind_group = Self::create_resolve_bind_group(device, &self.resolve_layout, &self.occlusion_view);
+  }
+
+  /// Computes the occlusion factor for every pixel of `depth_texture` into the occlusion texture.
+  pub fn render(
+    &mut self,
+    device: &Device,
+    queue: &Queue,
+    encoder: &mut CommandEncoder,
+    depth_texture: &Texture,
+    camera: &Camera,
+  ) {
+    let projection =
+      OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(camera.fovy), camera.aspect, camera.znear, camera.zfar);
+    self.uniform.projection = projection.into();
+    self.uniform.inverse_projection = projection.invert().unwrap_or_else(Matrix4::identity).into();
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+
+    // Only the depth aspect of the depth-stencil texture can be sampled
+    let depth_view = depth_texture.texture.create_view(&wgpu::TextureViewDescriptor {
+      aspect: wgpu::TextureAspect::DepthOnly,
+      ..Default::default()
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &self.layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: self.buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(&depth_view),
+        },
+      ],
+      label: Some("ssao_bind_group"),
+    });
+
+    let mut render_pass = RenderPassBuilder::new(encoder, "SSAO Pass")
+      .color(&self.occlusion_view)
+      .clear_color(wgpu::Color::WHITE)
+      .build();
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, &bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+
+  /// Multiplies the occlusion factor into the frame already rendered to `view`.
+  pub fn resolve(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+    let mut render_pass = RenderPassBuilder::new(encoder, "SSAO Resolve Pass").color(view).build();
+    render_pass.set_pipeline(&self.resolve_pipeline);
+    render_pass.set_bind_group(0, &self.resolve_bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+  use super::*;
+  use crate::gfx::camera::CameraBuilder;
+  use crate::gfx::geometry::{self, IndexBuffer};
+
+  /// Headless device, or `None` on machines without a GPU or software rasterizer.
+  pub(crate) fn headless_device() -> Option<(Device, Queue)> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    async_std::task::block_on(async {
+      let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await?;
+      adapter
+        .request_device(&wgpu::DeviceDescriptor::default(), None)
+        .await
+        .ok()
+    })
+  }
+
+  #[test]
+  fn render_mode_test() {
+    let mut mode = RenderMode::Solid;
+    let mut visited = Vec::new();
+    for _ in 0..RenderMode::ALL.len() {
+      visited.push(mode);
+      mode = mode.next();
+    }
+    assert_eq!(mode, RenderMode::Solid);
+    assert_eq!(visited, RenderMode::ALL);
+    assert_eq!(RenderMode::MaterialColor.to_string(), "material color");
+  }
+
+  #[test]
+  fn offscreen_feature_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping offscreen_feature_test: no adapter");
+        return;
+      }
+    };
+    const SIZE: u32 = 64;
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: SIZE,
+      height: SIZE,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let camera = CameraBuilder::new((0.0, 0.0, 5.0).into(), (0.0, 0.0, 0.0).into(), Vector3::unit_y()).build(&device);
+    let features = FeatureRenderer::new(FeatureRendererConfiguration {
+      geometry: geometry::uv_sphere(20),
+      instances: vec![FeatureInstance::mock().with_color([1.0, 0.0, 0.0])],
+      device: &device,
+      queue: &queue,
+      surface_config: &config,
+      use_z_prepass: false,
+    });
+    let target = Texture::create_render_target(&device, SIZE, SIZE, config.format, "Test Target");
+    let depth = Texture::create_depth_texture(&device, &config, "test_depth_texture");
+
+    let render = || {
+      let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+      {
+        let mut render_pass = RenderPassBuilder::new(&mut encoder, "Test Pass")
+          .color(&target.view)
+          .clear_color(wgpu::Color::BLUE)
+          .depth(&depth.view)
+          .clear_depth(1.0)
+          .build();
+        features.render_opaque(&mut render_pass, &camera);
+      }
+      queue.submit(std::iter::once(encoder.finish()));
+      read_texture(&device, &queue, &target, SIZE, SIZE).unwrap()
+    };
+
+    let pixels = render();
+    let pixel = |x: u32, y: u32| &pixels[(4 * (y * SIZE + x)) as usize..][..4];
+    assert_eq!(pixel(0, 0), [0, 0, 255, 255]);
+    assert_ne!(pixel(SIZE / 2, SIZE / 2), [0, 0, 255, 255]);
+    // The depth clear lets the same frame draw again rather than failing the depth test
+    assert_eq!(render(), pixels);
+
+    // Zero density leaves the colors alone, even with a fog color set
+    features.set_fog(0.0, [0.0, 1.0, 0.0, 1.0], &queue);
+    assert_eq!(render(), pixels);
+    // Dense fog swallows the sphere entirely
+    features.set_fog(100.0, [0.0, 1.0, 0.0, 1.0], &queue);
+    let fogged = render();
+    assert_eq!(
+      &fogged[(4 * (SIZE / 2 * SIZE + SIZE / 2)) as usize..][..4],
+      [0, 255, 0, 255]
+    );
+  }
+
+  #[test]
+  fn decode_pick_test() {
+    assert_eq!(decode_pick(0), None);
+    assert_eq!(decode_pick(1), Some(0));
+    assert_eq!(decode_pick(42), Some(41));
+    let module = naga::front::wgsl::parse_str(include_str!("shader/pick.wgsl")).unwrap();
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+      .validate(&module)
+      .unwrap();
+  }
+
+  #[test]
+  fn picking_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping picking_test: no adapter");
+        return;
+      }
+    };
+    const SIZE: u32 = 64;
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: SIZE,
+      height: SIZE,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let camera = CameraBuilder::new((0.0, 0.0, 5.0).into(), (0.0, 0.0, 0.0).into(), Vector3::unit_y()).build(&device);
+    let instance = |x: f32, visibility: f32, id: u32| {
+      FeatureInstance::mock()
+        .with_position((x, 0.0, 0.0))
+        .with_visibility(visibility)
+        .with_id(id)
+    };
+    let mut features = FeatureRenderer::new(FeatureRendererConfiguration {
+      geometry: geometry::uv_sphere(20),
+      instances: vec![instance(-1.5, 0.5, 7), instance(1.5, 1.0, 9)],
+      device: &device,
+      queue: &queue,
+      surface_config: &config,
+      use_z_prepass: false,
+    });
+    let picking = Texture::create_render_target(&device, SIZE, SIZE, FeatureRenderer::PICK_FORMAT, "Test Picking");
+    let depth = Texture::create_depth_texture(&device, &config, "test_depth_texture");
+    let pick = |features: &FeatureRenderer, x: u32, y: u32| {
+      let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+      features.render_picking(&mut encoder, &picking, &depth, &camera);
+      queue.submit(std::iter::once(encoder.finish()));
+      features
+        .read_pick_result(&device, &queue, &picking, x, y)
+        .and_then(|idx| features.instance_id(idx as usize))
+    };
+    assert_eq!(pick(&features, 0, 0), None);
+    assert_eq!(pick(&features, SIZE, 0), None);
+    // Opaque instances come first, so the transparent one on the left is numbered after them
+    assert_eq!(pick(&features, SIZE / 4, SIZE / 2), Some(7));
+    assert_eq!(pick(&features, 3 * SIZE / 4, SIZE / 2), Some(9));
+    // Moving an instance between passes renumbers it
+    features.update_instances(vec![instance(-1.5, 1.0, 7), instance(1.5, 0.5, 9)], &device, &queue);
+    assert_eq!(pick(&features, SIZE / 4, SIZE / 2), Some(7));
+    assert_eq!(pick(&features, 3 * SIZE / 4, SIZE / 2), Some(9));
+  }
+
+  #[test]
+  fn read_depth_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping read_depth_test: no adapter");
+        return;
+      }
+    };
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: 5,
+      height: 3,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let depth = Texture::create_depth_texture(&device, &config, "test_depth_texture");
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    RenderPassBuilder::new(&mut encoder, "Test Clear Pass")
+      .depth(&depth.view)
+      .clear_depth(0.25)
+      .build();
+    queue.submit(std::iter::once(encoder.finish()));
+    let depths = read_depth(&device, &queue, &depth, 5, 3).unwrap();
+    assert_eq!(depths.len(), 15);
+    assert!(depths.iter().all(|&d| (d - 0.25).abs() < 1e-6), "{:?}", depths);
+  }
+
+  #[test]
+  fn sort_back_to_front_test() {
+    let instance = |z: f32| {
+      FeatureInstance::mock()
+        .with_position((0.0, 0.0, z))
+        .with_visibility(0.5)
+    };
+    // Mock camera at z = -1 looking towards +z
+    let camera = Camera::mock();
+    let mut instances = vec![instance(0.0), instance(5.0), instance(-3.0), instance(2.0)];
+    sort_back_to_front(&mut instances, &camera);
+    let zs: Vec<f32> = instances.iter().map(|instance| instance.model[3][2]).collect();
+    assert_eq!(zs, vec![5.0, 2.0, 0.0, -3.0]);
+    let distance = |instance: &FeatureInstance| (Point3::new(0.0, 0.0, instance.model[3][2]) - camera.eye).magnitude();
+    assert!(instances
+      .iter()
+      .all(|instance| distance(instance) <= distance(&instances[0])));
+  }
+
+  #[test]
+  fn instance_position_test() {
+    let instance = FeatureInstance::mock()
+      .with_model(Matrix4::from_scale(0.5))
+      .with_position((1.0, 2.0, 3.0));
+    assert_eq!(instance.position(), Point3::new(1.0, 2.0, 3.0));
+  }
+
+  #[test]
+  fn u32_index_format_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping u32_index_format_test: no adapter");
+        return;
+      }
+    };
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: 64,
+      height: 64,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let mut geometry = geometry::fullscreen_quad();
+    geometry.indices = IndexBuffer::U32(vec![0, 1, 2, 0, 2, 3]);
+    let mut renderer = FeatureRenderer::new(FeatureRendererConfiguration {
+      geometry,
+      instances: Vec::new(),
+      device: &device,
+      queue: &queue,
+      surface_config: &config,
+      use_z_prepass: false,
+    });
+    assert_eq!(renderer.index_format, wgpu::IndexFormat::Uint32);
+    assert_eq!(renderer.index_count, 6);
+    renderer.set_geometry(geometry::fullscreen_quad(), &device);
+    assert_eq!(renderer.index_format, wgpu::IndexFormat::Uint16);
+  }
+
+  #[test]
+  fn feature_renderer_stats_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping feature_renderer_stats_test: no adapter");
+        return;
+      }
+    };
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: 64,
+      height: 64,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let instance = |visibility: f32| FeatureInstance::mock().with_visibility(visibility);
+    let renderer = FeatureRenderer::new(FeatureRendererConfiguration {
+      geometry: geometry::fullscreen_quad(),
+      instances: vec![instance(1.0), instance(1.0), instance(0.5)],
+      device: &device,
+      queue: &queue,
+      surface_config: &config,
+      use_z_prepass: false,
+    });
+    assert_eq!(
+      renderer.stats(),
+      RendererStats {
+        draw_calls: 2,
+        triangles: 6,
+        instances: 3,
+      }
+    );
+  }
+
+  #[test]
+  fn instance_buffer_capacity_test() {
+    assert_eq!(grown_capacity(0, 3), 3);
+    assert_eq!(grown_capacity(3, 4), 6);
+    assert_eq!(grown_capacity(6, 20), 20);
+
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping instance_buffer_capacity_test: no adapter");
+        return;
+      }
+    };
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: 64,
+      height: 64,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let instances = |count: usize| -> Vec<FeatureInstance> {
+      (0..count)
+        .map(|id| {
+          FeatureInstance::mock()
+            .with_position((id as f32, 0.0, 0.0))
+            .with_id(id as u32)
+        })
+        .collect()
+    };
+    let mut renderer = FeatureRenderer::new(FeatureRendererConfiguration {
+      geometry: geometry::fullscreen_quad(),
+      instances: instances(3),
+      device: &device,
+      queue: &queue,
+      surface_config: &config,
+      use_z_prepass: false,
+    });
+    assert_eq!(renderer.opaque_buffer.capacity, 3);
+    renderer.update_instances(instances(4), &device, &queue);
+    assert_eq!(renderer.opaque_buffer.capacity, 6);
+    // Fewer instances reuse the buffer
+    renderer.update_instances(instances(5), &device, &queue);
+    assert_eq!(renderer.opaque_buffer.capacity, 6);
+    renderer.update_instances(instances(20), &device, &queue);
+    assert_eq!(renderer.opaque_buffer.capacity, 20);
+
+    let generation = renderer.instance_generation();
+    renderer.resize_instance_buffer(32, &device, &queue);
+    assert_eq!(renderer.instance_capacity(), 32);
+    // Never shrinks below the current instances
+    renderer.resize_instance_buffer(1, &device, &queue);
+    assert_eq!(renderer.instance_capacity(), 20);
+    // Moving the instances to a new buffer leaves them as they were
+    assert_eq!(renderer.instance_generation(), generation);
+
+    // The instances survive both copies
+    let size = 20 * InstanceBuffer::INSTANCE_SIZE;
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+      label: None,
+      size,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+      mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    encoder.copy_buffer_to_buffer(&renderer.opaque_buffer.buffer, 0, &readback, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+    let slice = readback.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    async_std::task::block_on(mapping).unwrap();
+    assert_eq!(
+      slice.get_mapped_range().to_vec(),
+      bytemuck::cast_slice::<_, u8>(&instances(20)).to_vec()
+    );
+
+    renderer.update_instance_color(7, [0.0, 1.0, 0.0], &queue);
+    assert_ne!(renderer.instance_generation(), generation);
+  }
+
+  #[test]
+  fn grid_vertices_test() {
+    let vertices = grid_vertices(2.0, 2);
+    // (2 * divisions + 1) * 2 lines of two vertices each
+    assert_eq!(vertices.len(), 5 * 2 * 2);
+    let lines: Vec<&[GridVertex]> = vertices.chunks_exact(2).collect();
+    let x_axis = lines
+      .iter()
+      .find(|line| line[0].position == [-2.0, 0.0, 0.0] && line[1].position == [2.0, 0.0, 0.0])
+      .unwrap();
+    assert_eq!(x_axis[0].color, GRID_X_COLOR);
+    let z_axis = lines
+      .iter()
+      .find(|line| line[0].position == [0.0, 0.0, -2.0] && line[1].position == [0.0, 0.0, 2.0])
+      .unwrap();
+    assert_eq!(z_axis[0].color, GRID_Z_COLOR);
+    assert!(lines.iter().any(|line| line[0].position == [-2.0, 0.0, 1.0]));
+    assert_eq!(lines.iter().filter(|line| line[0].color == GRID_COLOR).count(), 8);
+    assert!(vertices.iter().all(|vertex| vertex.position[1] == 0.0));
+    // No divisions leaves just the lines through the origin
+    assert_eq!(grid_vertices(2.0, 0).len(), 4);
+
+    let axes = axis_vertices(3.0);
+    assert_eq!(axes.len(), 6);
+    assert_eq!(axes[3].position, [0.0, 3.0, 0.0]);
+    assert_eq!(axes[3].color, [0.0, 1.0, 0.0, 1.0]);
+  }
+
+  #[test]
+  fn lod_level_test() {
+    assert_eq!(lod_level(0.0, 5.0, 20.0), 0);
+    assert_eq!(lod_level(5.0, 5.0, 20.0), 1);
+    assert_eq!(lod_level(19.9, 5.0, 20.0), 1);
+    assert_eq!(lod_level(20.0, 5.0, 20.0), 2);
+    let instance = |x: f32, id: u32| FeatureInstance::mock().with_position((x, 0.0, 0.0)).with_id(id);
+    let instances = [
+      instance(1.0, 0),
+      instance(-10.0, 1),
+      instance(30.0, 2),
+      instance(3.0, 3),
+    ];
+    let levels = partition_lod(&instances, Point3::new(0.0, 0.0, 0.0), 5.0, 20.0);
+    let ids = |level: &[FeatureInstance]| level.iter().map(|instance| instance.id).collect::<Vec<_>>();
+    assert_eq!(ids(&levels[0]), vec![0, 3]);
+    assert_eq!(ids(&levels[1]), vec![1]);
+    assert_eq!(ids(&levels[2]), vec![2]);
+  }
+
+  #[test]
+  fn lod_triangle_savings_test() {
+    // 1000 features spread evenly over 50 m in front of the eye
+    let (near, far) = (LodFeatureRenderer::DEFAULT_NEAR, LodFeatureRenderer::DEFAULT_FAR);
+    let distances: Vec<f32> = (0..1000).map(|i| i as f32 * 0.05).collect();
+    let triangles = |segments: u32| (geometry::uv_sphere(segments).indices.len() / 3) as u64;
+    let full = triangles(LOD_SPHERE_SEGMENTS[0]) * distances.len() as u64;
+    let lod: u64 = distances
+      .iter()
+      .map(|&distance| triangles(LOD_SPHERE_SEGMENTS[lod_level(distance, near, far)]))
+      .sum();
+    // Only the nearest tenth keeps the full mesh
+    assert!(lod * 5 < full, "{} of {} triangles", lod, full);
+  }
+
+  #[test]
+  fn feature_display_test() {
+    assert_eq!("meshes".parse(), Ok(FeatureDisplay::Meshes));
+    assert_eq!("points".parse(), Ok(FeatureDisplay::Points));
+    assert!("wireframe".parse::<FeatureDisplay>().is_err());
+  }
+
+  #[test]
+  fn point_vertices_test() {
+    let vertices = point_vertices(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]], &[[1.0, 0.0, 0.0, 0.5]], &[]);
+    assert_eq!(
+      vertices,
+      vec![
+        PointVertex {
+          position: [1.0, 2.0, 3.0],
+          color: [1.0, 0.0, 0.0, 0.5],
+          point_size: PointCloudRenderer::DEFAULT_POINT_SIZE,
+        },
+        PointVertex {
+          position: [4.0, 5.0, 6.0],
+          color: [1.0; 4],
+          point_size: PointCloudRenderer::DEFAULT_POINT_SIZE,
+        },
+      ]
+    );
+    assert_eq!(std::mem::size_of::<PointVertex>(), 32);
+    let module = naga::front::wgsl::parse_str(include_str!("shader/point_cloud.wgsl")).unwrap();
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+      .validate(&module)
+      .unwrap();
+  }
+
+  #[test]
+  fn skybox_cube_test() {
+    let corners = skybox_corners().map(Vector3::from);
+    assert_eq!(corners[0], Vector3::new(-1.0, -1.0, -1.0));
+    assert_eq!(corners[6], Vector3::new(-1.0, 1.0, 1.0));
+    // Every face is seen from inside the cube
+    for triangle in SKYBOX_INDICES.chunks(3) {
+      let [a, b, c] = [0, 1, 2].map(|i| corners[triangle[i] as usize]);
+      assert!(
+        (b - a).cross(c - a).dot(a + b + c) < 0.0,
+        "triangle {:?} faces out",
+        triangle
+      );
+    }
+    let mut used = SKYBOX_INDICES.to_vec();
+    used.sort_unstable();
+    used.dedup();
+    assert_eq!(used.len(), 8);
+    let module = naga::front::wgsl::parse_str(include_str!("shader/skybox.wgsl")).unwrap();
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+      .validate(&module)
+      .unwrap();
+  }
+
+  #[test]
+  fn skybox_renderer_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping skybox_renderer_test: no adapter");
+        return;
+      }
+    };
+    const SIZE: u32 = 16;
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8Unorm,
+      width: SIZE,
+      height: SIZE,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    // Only -Z, the face looked at, is green
+    let faces: Vec<Vec<u8>> = (0..6)
+      .map(|face| {
+        let color = if face == 5 { [0, 255, 0, 255] } else { [255, 0, 0, 255] };
+        let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        let mut bytes = Vec::new();
+        image.write_to(&mut bytes, image::ImageOutputFormat::Png).unwrap();
+        bytes
+      })
+      .collect();
+    let cubemap = Texture::create_cubemap(&device, &queue, [0, 1, 2, 3, 4, 5].map(|face| &faces[face][..])).unwrap();
+    let skybox = SkyboxRenderer::new(&device, &config, cubemap);
+    let camera = CameraBuilder::new((0.0, 0.0, 5.0).into(), (0.0, 0.0, 0.0).into(), Vector3::unit_y()).build(&device);
+    let target = Texture::create_render_target(&device, SIZE, SIZE, config.format, "Test Target");
+    let depth = Texture::create_depth_texture(&device, &config, "test_depth_texture");
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+      let mut render_pass = RenderPassBuilder::new(&mut encoder, "Test Pass")
+        .color(&target.view)
+        .clear_color(wgpu::Color::BLACK)
+        .depth(&depth.view)
+        .clear_depth(1.0)
+        .build();
+      skybox.render(&mut render_pass, &camera);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+    let pixels = read_texture(&device, &queue, &target, SIZE, SIZE).unwrap();
+    let center = ((SIZE / 2 * SIZE + SIZE / 2) * 4) as usize;
+    assert_eq!(&pixels[center..center + 3], &[0, 255, 0]);
+  }
+
+  fn ellipsoid_feature() -> Feature {
+    Feature {
+      position_deviation: (0.5, 2.0, 4.0).into(),
+      // A quarter turn about z
+      orientation_mean: (0.0, 0.0, std::f32::consts::FRAC_PI_2).into(),
+      ..Feature::mock().with_position((1.0, 2.0, 3.0)).with_color((255, 0, 0))
+    }
+  }
+
+  #[test]
+  fn ellipsoid_instance_test() {
+    let feature = ellipsoid_feature();
+    let instance = EllipsoidInstance::from(&feature);
+    assert_eq!(instance.color, [1.0, 0.0, 0.0, EllipsoidRenderer::ALPHA]);
+    let model = Matrix4::from(instance.model);
+    // The x deviation ends up along y after the rotation, and the z deviation is untouched
+    let x = model * cgmath::Vector4::unit_x();
+    assert!((x - cgmath::Vector4::new(0.0, 0.5, 0.0, 0.0)).magnitude() < 1e-5);
+    let z = model * cgmath::Vector4::unit_z();
+    assert!((z - cgmath::Vector4::new(0.0, 0.0, 4.0, 0.0)).magnitude() < 1e-5);
+    assert_eq!(model.w, cgmath::Vector4::new(1.0, 2.0, 3.0, 1.0));
+    // Normals stay perpendicular to the surface under the non-uniform scale
+    let normal = Matrix3::from(instance.normal);
+    let linear = Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+    let (point, tangent) = (Vector3::new(1.0, 1.0, 0.0).normalize(), Vector3::new(-1.0, 1.0, 0.0));
+    assert!((normal * point).dot(linear * tangent).abs() < 1e-5);
+    assert_eq!(std::mem::size_of::<EllipsoidInstance>(), 116);
+    let module = naga::front::wgsl::parse_str(include_str!("shader/ellipsoid.wgsl")).unwrap();
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+      .validate(&module)
+      .unwrap();
+  }
+
+  #[test]
+  fn ellipsoid_renderer_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping ellipsoid_renderer_test: no adapter");
+        return;
+      }
+    };
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8Unorm,
+      width: 32,
+      height: 32,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let mut renderer = EllipsoidRenderer::new(&device, &config);
+    assert_eq!(renderer.stats().draw_calls, 0);
+    let feature = ellipsoid_feature();
+    renderer.update(&[feature.clone(), feature], &device, &queue);
+    let stats = renderer.stats();
+    assert_eq!((stats.draw_calls, stats.instances), (1, 2));
+  }
+
+  #[test]
+  fn point_cloud_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping point_cloud_test: no adapter");
+        return;
+      }
+    };
+    const SIZE: u32 = 32;
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8Unorm,
+      width: SIZE,
+      height: SIZE,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let camera = CameraBuilder::new((0.0, 0.0, 5.0).into(), (0.0, 0.0, 0.0).into(), Vector3::unit_y()).build(&device);
+    let points = PointCloudRenderer::new(
+      &device,
+      &config,
+      vec![[0.0, 0.0, 0.0]],
+      vec![[1.0, 0.0, 0.0, 1.0]],
+      vec![8.0],
+    );
+    let target = Texture::create_render_target(&device, SIZE, SIZE, config.format, "Test Target");
+    let depth = Texture::create_depth_texture(&device, &config, "test_depth_texture");
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+      let mut render_pass = RenderPassBuilder::new(&mut encoder, "Test Pass")
+        .color(&target.view)
+        .clear_color(wgpu::Color::BLACK)
+        .depth(&depth.view)
+        .clear_depth(1.0)
+        .build();
+      points.render(&mut render_pass, &camera);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+    let pixels = read_texture(&device, &queue, &target, SIZE, SIZE).unwrap();
+    let red = pixels.chunks_exact(4).filter(|pixel| pixel[0] == 255).count();
+    // An 8 pixel square in the middle
+    assert_eq!(red, 64);
+    assert_eq!(points.stats().triangles, 2);
+  }
+
+  #[test]
+  fn renderer_stats_sum_test() {
+    let stats = |n: u32| RendererStats {
+      draw_calls: 1,
+      triangles: n,
+      instances: n,
+    };
+    let total: RendererStats = vec![stats(2), stats(3)].into_iter().sum();
+    assert_eq!(
+      total,
+      RendererStats {
+        draw_calls: 2,
+        triangles: 5,
+        instances: 5,
+      }
+    );
+  }
+
+  #[test]
+  fn basic_shader_source_test() {
+    let (device, _queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping basic_shader_source_test: no adapter");
+        return;
+      }
+    };
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: 64,
+      height: 64,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    const SOURCE: &str = "
+      [[stage(vertex)]]
+      fn main_vs([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+        return vec4<f32>(f32(index), 0.0, 0.0, 1.0);
+      }
+
+      [[stage(fragment)]]
+      fn main_fs() -> [[location(0)]] vec4<f32> {
+        return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+      }
+    ";
+    let module = crate::gfx::shader::basic(&device, SOURCE);
+    for shader in [BasicShaderSource::Wgsl(SOURCE), BasicShaderSource::Precompiled(module)] {
+      let renderer = BasicRenderer::new(BasicRendererConfiguration {
+        shader,
+        vertex_entry: "main_vs",
+        fragment_entry: "main_fs",
+        ..BasicRendererConfiguration::new(&device, &config)
+      });
+      assert_eq!(renderer.stats().triangles, 1);
+    }
+  }
+
+  #[cfg(feature = "outlines")]
+  #[test]
+  fn outline_instances_test() {
+    let instance = |id: u32| FeatureInstance::mock().with_position((id as f32, 0.0, 0.0)).with_id(id);
+    let instances = [instance(1), instance(2), instance(3), instance(4)];
+    let selected = outline_instances(instances.iter(), &[4, 2, 9]);
+    assert_eq!(
+      selected.iter().map(|instance| instance.id).collect::<Vec<_>>(),
+      vec![2, 4]
+    );
+    assert!(outline_instances(instances.iter(), &[]).is_empty());
+  }
+
+  #[test]
+  fn pick_instance_test() {
+    let instance = |position: (f32, f32, f32), scale: f32| {
+      FeatureInstance::mock()
+        .with_model(Matrix4::from_scale(scale))
+        .with_position(position)
+    };
+    let instances = [instance((0.0, 0.0, -10.0), 1.0), instance((0.5, 0.0, -5.0), 1.0)];
+    let ray = |eye: (f32, f32, f32), target: (f32, f32, f32)| Ray {
+      eye: eye.into(),
+      target: target.into(),
+    };
+    // Both lie on the ray; the nearer one wins
+    assert_eq!(
+      pick_instance(instances.iter(), &ray((0.0, 0.0, 0.0), (0.0, 0.0, -1.0))),
+      Some(1)
+    );
+    // Only the farther one is within reach of the ray
+    assert_eq!(
+      pick_instance(instances.iter(), &ray((-0.8, 0.0, 0.0), (-0.8, 0.0, -1.0))),
+      Some(0)
+    );
+    // A larger scale widens the ball
+    let wide = [instance((3.0, 0.0, -5.0), 4.0)];
+    assert_eq!(
+      pick_instance(wide.iter(), &ray((0.0, 0.0, 0.0), (0.0, 0.0, -1.0))),
+      Some(0)
+    );
+    // Balls behind the eye and beside the ray are missed
+    assert_eq!(
+      pick_instance(instances.iter(), &ray((0.0, 0.0, 0.0), (0.0, 0.0, 1.0))),
+      None
+    );
+    assert_eq!(
+      pick_instance(instances.iter(), &ray((5.0, 0.0, 0.0), (5.0, 0.0, -1.0))),
+      None
+    );
+  }
+
+  #[cfg(feature = "outlines")]
+  #[test]
+  fn render_outline_test() {
+    let (device, queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping render_outline_test: no adapter");
+        return;
+      }
+    };
+    const SIZE: u32 = 64;
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: SIZE,
+      height: SIZE,
+      present_mode: wgpu::PresentMode::Fifo,
+    };
+    let camera = CameraBuilder::new((0.0, 0.0, 5.0).into(), (0.0, 0.0, 0.0).into(), Vector3::unit_y()).build(&device);
+    let mut features = FeatureRenderer::new(FeatureRendererConfiguration {
+      geometry: geometry::uv_sphere(20),
+      instances: vec![FeatureInstance::mock()
+        .with_model(Matrix4::from_scale(1.5))
+        .with_color([1.0, 0.0, 0.0])
+        .with_id(7)],
+      device: &device,
+      queue: &queue,
+      surface_config: &config,
+      use_z_prepass: false,
+    });
+    let target = Texture::create_render_target(&device, SIZE, SIZE, config.format, "Test Target");
+    let depth = Texture::create_depth_texture(&device, &config, "test_depth_texture");
+
+    let render = |features: &FeatureRenderer| {
+      let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+      {
+        let mut render_pass = RenderPassBuilder::new(&mut encoder, "Test Pass")
+          .color(&target.view)
+          .clear_color(wgpu::Color::BLUE)
+          .depth(&depth.view)
+          .clear_depth(1.0)
+          .clear_stencil(0)
+          .build();
+        features.render_opaque(&mut render_pass, &camera);
+        features.render_outline(&mut render_pass, &camera);
+      }
+      queue.submit(std::iter::once(encoder.finish()));
+      read_texture(&device, &queue, &target, SIZE, SIZE).unwrap()
+    };
+
+    let unselected = render(&features);
+    features.set_outline(&[7], [0.0, 1.0, 0.0, 1.0], &device, &queue);
+    let outlined = render(&features);
+    let pixel = |pixels: &[u8], x: u32, y: u32| pixels[(4 * (y * SIZE + x)) as usize..][..4].to_vec();
+    // The feature itself is untouched and only some of the background around it turns green
+    let center = SIZE / 2;
+    assert_eq!(pixel(&outlined, center, center), pixel(&unselected, center, center));
+    assert_eq!(pixel(&outlined, 0, 0), vec![0, 0, 255, 255]);
+    let outline = (0..SIZE)
+      .filter(|&x| pixel(&outlined, x, center) == vec![0, 255, 0, 255])
+      .count();
+    assert!(outline > 0 && outline < 8);
   }
 }
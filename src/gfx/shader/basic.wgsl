@@ -22,9 +22,33 @@ fn vertex(
   return out;
 }
 
+[[stage(vertex)]]
+fn vertex_geometry(
+  [[location(0)]] position: vec3<f32>,
+) -> VertexOutput {
+  var out: VertexOutput;
+  out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+  return out;
+}
+
+// Draws positions directly as clip coordinates, ignoring the camera
+[[stage(vertex)]]
+fn vertex_overlay(
+  [[location(0)]] position: vec3<f32>,
+) -> VertexOutput {
+  var out: VertexOutput;
+  out.clip_position = vec4<f32>(position.xy, 0.0, 1.0);
+  return out;
+}
+
 // Fragment shader
 
 [[stage(fragment)]]
 fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
   return vec4<f32>(0.3, 0.2, 0.1, 1.0);
 }
+
+[[stage(fragment)]]
+fn fragment_overlay(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+  return vec4<f32>(0.8, 0.0, 0.0, 0.25);
+}
@@ -0,0 +1,19 @@
+// Copies the depth aspect of a depth-stencil texture into a single channel float target, since combined
+// depth-stencil formats can't be copied to buffers directly.
+
+[[group(0), binding(0)]]
+var depth_texture: texture_depth_2d;
+
+// Fullscreen triangle
+[[stage(vertex)]]
+fn vertex(
+  [[builtin(vertex_index)]] in_vertex_index: u32,
+) -> [[builtin(position)]] vec4<f32> {
+  let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+  return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fragment([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] f32 {
+  return textureLoad(depth_texture, vec2<i32>(position.xy), 0);
+}
@@ -0,0 +1,60 @@
+// Uncertainty ellipsoid shader
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>;
+  eye: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+  [[location(0)]] position: vec3<f32>;
+  [[location(1)]] normal: vec3<f32>;
+};
+
+struct InstanceInput {
+  [[location(2)]] model_0: vec4<f32>;
+  [[location(3)]] model_1: vec4<f32>;
+  [[location(4)]] model_2: vec4<f32>;
+  [[location(5)]] model_3: vec4<f32>;
+  // Inverse transpose of the upper 3x3 of the model matrix
+  [[location(6)]] normal_0: vec3<f32>;
+  [[location(7)]] normal_1: vec3<f32>;
+  [[location(8)]] normal_2: vec3<f32>;
+  [[location(9)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  [[location(0)]] normal: vec3<f32>;
+  [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vertex(
+  vertex: VertexInput,
+  instance: InstanceInput
+) -> VertexOutput {
+  var out: VertexOutput;
+  let model = mat4x4<f32>(
+    instance.model_0,
+    instance.model_1,
+    instance.model_2,
+    instance.model_3,
+  );
+  out.clip_position = camera.view_proj * model * vec4<f32>(vertex.position, 1.0);
+  // The scale is not uniform, so normals need their own matrix
+  let normal_matrix = mat3x3<f32>(instance.normal_0, instance.normal_1, instance.normal_2);
+  out.normal = normalize(normal_matrix * vertex.normal);
+  out.color = instance.color;
+  return out;
+}
+
+let LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.3, 1.0, 0.5);
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+  let diffuse = max(dot(normalize(in.normal), normalize(LIGHT_DIRECTION)), 0.0);
+  return vec4<f32>(in.color.rgb * (0.4 + 0.6 * diffuse), in.color.a);
+}
@@ -1,4 +1,6 @@
-use cgmath::{Point3, Vector3};
+use crate::featuredb::Feature;
+
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
 use wgpu::{Device, ShaderModule, VertexBufferLayout};
 
 pub fn compile(device: &Device) -> ShaderModule {
@@ -13,6 +15,8 @@ pub fn compile(device: &Device) -> ShaderModule {
 pub struct FeatureVertex {
   pub position: [f32; 3],
   pub normal: [f32; 3],
+  /// Direction of increasing u in the spherical mapping of `feature.wgsl`, with the bitangent's handedness in w
+  pub tangent: [f32; 4],
 }
 
 impl From<(&Point3<f32>, &Vector3<f32>)> for FeatureVertex {
@@ -20,15 +24,28 @@ impl From<(&Point3<f32>, &Vector3<f32>)> for FeatureVertex {
     FeatureVertex {
       position: (*from.0).into(),
       normal: (*from.1).into(),
+      tangent: spherical_tangent(*from.1).extend(1.0).into(),
     }
   }
 }
 
+/// Tangent of the spherical mapping `u = atan2(z, x) / 2π + 0.5` at unit `normal`, along the circle of latitude. At
+/// the poles, where u is undefined, any direction perpendicular to the normal will do.
+fn spherical_tangent(normal: Vector3<f32>) -> Vector3<f32> {
+  let tangent = Vector3::new(-normal.z, 0.0, normal.x);
+  if tangent.magnitude2() > f32::EPSILON {
+    tangent.normalize()
+  } else {
+    Vector3::unit_x()
+  }
+}
+
 impl FeatureVertex {
   pub fn description<'a>() -> VertexBufferLayout<'a> {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
       0 => Float32x3,
       1 => Float32x3,
+      10 => Float32x4,
     ];
     wgpu::VertexBufferLayout {
       array_stride: std::mem::size_of::<FeatureVertex>() as wgpu::BufferAddress,
@@ -38,21 +55,130 @@ impl FeatureVertex {
   }
 }
 
+/// One end of a vertex normal line in `normals.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct NormalVertex {
+  pub position: [f32; 3],
+  pub normal: [f32; 3],
+  /// 0 at the vertex and 1 at the far end of the line
+  pub extent: f32,
+}
+
+impl NormalVertex {
+  /// A `LineList` with a line along the normal of every vertex.
+  pub fn lines(vertices: &[FeatureVertex]) -> Vec<NormalVertex> {
+    vertices
+      .iter()
+      .flat_map(|vertex| {
+        [0.0, 1.0].map(|extent| NormalVertex {
+          position: vertex.position,
+          normal: vertex.normal,
+          extent,
+        })
+      })
+      .collect()
+  }
+
+  pub fn description<'a>() -> VertexBufferLayout<'a> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+      0 => Float32x3,
+      1 => Float32x3,
+      10 => Float32,
+    ];
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<NormalVertex>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &ATTRIBUTES,
+    }
+  }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct FeatureInstance {
   pub model: [[f32; 4]; 4],
   pub color: [f32; 3],
+  /// Corner of the instance's tile in the texture atlas
+  pub uv_offset: [f32; 2],
+  /// Size of the instance's tile in the texture atlas
+  pub uv_scale: [f32; 2],
+  /// Opacity; instances below 1 are drawn in the transparent pass and values above 1 scale the brightness
+  pub visibility: f32,
+  /// Id of the feature drawn, not passed to the shader
+  pub id: u32,
+}
+
+/// Exponential distance fog, disabled at zero `density`. Matches `FogUniform` in `feature.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FogUniform {
+  pub color: [f32; 4],
+  pub density: f32,
+  _padding: [f32; 3],
+}
+
+/// Matches `MaterialUniform` in `feature.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+  pub use_normal_map: u32,
+  _padding: [u32; 3],
+}
+
+impl MaterialUniform {
+  pub fn new(use_normal_map: bool) -> Self {
+    Self {
+      use_normal_map: use_normal_map as u32,
+      _padding: [0; 3],
+    }
+  }
+}
+
+impl FogUniform {
+  pub fn new(density: f32, color: [f32; 4]) -> Self {
+    Self {
+      color,
+      density,
+      _padding: [0.0; 3],
+    }
+  }
+}
+
+impl From<&Feature> for FeatureInstance {
+  fn from(feature: &Feature) -> Self {
+    FeatureInstance {
+      model: feature.transform().into(),
+      color: feature.color.map(|x| x as f32 / 255.0).into(),
+      uv_offset: [0.0, 0.0],
+      uv_scale: [1.0, 1.0],
+      visibility: 1.0,
+      id: feature.id,
+    }
+  }
 }
 
 impl FeatureInstance {
+  /// World-space center of the instance, the translation column of its model matrix.
+  pub fn position(&self) -> Point3<f32> {
+    Point3::from_homogeneous(Matrix4::from(self.model).w)
+  }
+
+  /// World-space radius of the unit mesh, the length of the model's x axis.
+  pub fn radius(&self) -> f32 {
+    Matrix4::from(self.model).x.truncate().magnitude()
+  }
+
   pub fn description<'a>() -> VertexBufferLayout<'a> {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+    const ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
       2 => Float32x4,
       3 => Float32x4,
       4 => Float32x4,
       5 => Float32x4,
       6 => Float32x3,
+      7 => Float32x2,
+      8 => Float32x2,
+      9 => Float32,
     ];
     wgpu::VertexBufferLayout {
       array_stride: std::mem::size_of::<FeatureInstance>() as wgpu::BufferAddress,
@@ -61,3 +187,111 @@ impl FeatureInstance {
     }
   }
 }
+
+#[cfg(test)]
+impl FeatureInstance {
+  /// An opaque white unit instance at the origin with id 0, for tests to adjust with the `with_` methods.
+  pub(crate) fn mock() -> Self {
+    Self {
+      model: Matrix4::from_scale(1.0).into(),
+      color: [1.0, 1.0, 1.0],
+      uv_offset: [0.0, 0.0],
+      uv_scale: [1.0, 1.0],
+      visibility: 1.0,
+      id: 0,
+    }
+  }
+
+  pub(crate) fn with_id(self, id: u32) -> Self {
+    Self { id, ..self }
+  }
+
+  pub(crate) fn with_model(self, model: Matrix4<f32>) -> Self {
+    Self {
+      model: model.into(),
+      ..self
+    }
+  }
+
+  /// Moves the instance to `position`, keeping its scale.
+  pub(crate) fn with_position(self, position: (f32, f32, f32)) -> Self {
+    let mut model = self.model;
+    model[3] = [position.0, position.1, position.2, 1.0];
+    Self { model, ..self }
+  }
+
+  pub(crate) fn with_color(self, color: [f32; 3]) -> Self {
+    Self { color, ..self }
+  }
+
+  pub(crate) fn with_visibility(self, visibility: f32) -> Self {
+    Self { visibility, ..self }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn spherical_tangent_test() {
+    // Along the equator the tangent points towards increasing longitude
+    assert_eq!(spherical_tangent(Vector3::unit_x()), Vector3::unit_z());
+    assert_eq!(spherical_tangent(Vector3::unit_z()), -Vector3::unit_x());
+    let normal = Vector3::new(1.0, 1.0, 1.0).normalize();
+    assert!(spherical_tangent(normal).dot(normal).abs() < 0.00001);
+    assert_eq!(spherical_tangent(Vector3::unit_y()), Vector3::unit_x());
+  }
+
+  #[test]
+  fn normal_lines_test() {
+    let vertex = FeatureVertex::from((&Point3::new(1.0, 2.0, 3.0), &Vector3::unit_y()));
+    let lines = NormalVertex::lines(&[vertex, vertex]);
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0].extent, 0.0);
+    assert_eq!(lines[1].extent, 1.0);
+    assert!(lines
+      .iter()
+      .all(|end| end.position == [1.0, 2.0, 3.0] && end.normal == [0.0, 1.0, 0.0]));
+    let module = naga::front::wgsl::parse_str(include_str!("normals.wgsl")).unwrap();
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+      .validate(&module)
+      .unwrap();
+  }
+
+  #[test]
+  fn shader_validates_test() {
+    let module = naga::front::wgsl::parse_str(include_str!("feature.wgsl")).unwrap();
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+      .validate(&module)
+      .unwrap();
+    // Every vertex attribute location is read by the vertex stage
+    let vertex = module.entry_points.iter().find(|entry| entry.name == "vertex").unwrap();
+    let mut locations: Vec<u32> = vertex
+      .function
+      .arguments
+      .iter()
+      .flat_map(|argument| -> Vec<naga::Binding> {
+        match &module.types[argument.ty].inner {
+          naga::TypeInner::Struct { members, .. } => {
+            members.iter().filter_map(|member| member.binding.clone()).collect()
+          }
+          _ => argument.binding.clone().into_iter().collect(),
+        }
+      })
+      .filter_map(|binding| match binding {
+        naga::Binding::Location { location, .. } => Some(location),
+        _ => None,
+      })
+      .collect();
+    locations.sort_unstable();
+    let mut expected: Vec<u32> = FeatureVertex::description()
+      .attributes
+      .iter()
+      .chain(FeatureInstance::description().attributes)
+      .map(|attribute| attribute.shader_location)
+      .collect();
+    expected.sort_unstable();
+    assert_eq!(locations, expected);
+  }
+}
@@ -2,6 +2,7 @@
 
 struct CameraUniform {
   view_proj: mat4x4<f32>;
+  eye: vec4<f32>;
 };
 
 [[group(0), binding(0)]]
@@ -10,6 +11,8 @@ var<uniform> camera: CameraUniform;
 struct VertexInput {
   [[location(0)]] position: vec3<f32>;
   [[location(1)]] normal: vec3<f32>;
+  // Direction of increasing u, with the handedness of the bitangent in w
+  [[location(10)]] tangent: vec4<f32>;
 };
 
 struct InstanceInput {
@@ -18,12 +21,22 @@ struct InstanceInput {
   [[location(4)]] model_2: vec4<f32>;
   [[location(5)]] model_3: vec4<f32>;
   [[location(6)]] color: vec3<f32>;
+  [[location(7)]] uv_offset: vec2<f32>;
+  [[location(8)]] uv_scale: vec2<f32>;
+  [[location(9)]] visibility: f32;
 };
 
 struct VertexOutput {
   [[builtin(position)]] clip_position: vec4<f32>;
+  // World-space TBN columns
   [[location(0), interpolate(perspective)]] normal: vec3<f32>;
   [[location(1)]] color: vec3<f32>;
+  [[location(2)]] uv: vec2<f32>;
+  [[location(3)]] visibility: f32;
+  [[location(4)]] view_depth: f32;
+  [[location(5)]] tangent: vec3<f32>;
+  [[location(6)]] bitangent: vec3<f32>;
+  [[location(7)]] world_position: vec3<f32>;
 };
 
 [[stage(vertex)]]
@@ -38,14 +51,51 @@ fn vertex(
     instance.model_2,
     instance.model_3,
   );
-  out.clip_position = camera.view_proj * model * vec4<f32>(vertex.position, 1.0);
-  out.normal = vertex.normal;
+  let world_position = model * vec4<f32>(vertex.position, 1.0);
+  out.clip_position = camera.view_proj * world_position;
+  out.world_position = world_position.xyz;
+  // With a perspective projection w is the distance in front of the eye
+  out.view_depth = out.clip_position.w;
+  // Instances are only ever scaled uniformly, so the model matrix also transforms normals
+  let rotation = mat3x3<f32>(instance.model_0.xyz, instance.model_1.xyz, instance.model_2.xyz);
+  out.normal = normalize(rotation * vertex.normal);
+  out.tangent = normalize(rotation * vertex.tangent.xyz);
+  out.bitangent = cross(out.normal, out.tangent) * vertex.tangent.w;
   out.color = instance.color;
+  out.visibility = instance.visibility;
+  // Spherical mapping of the unit normal onto the instance's atlas tile
+  let PI = 3.1415926538;
+  let n = normalize(vertex.normal);
+  let uv = vec2<f32>(atan2(n.z, n.x) / (2.0 * PI) + 0.5, acos(clamp(n.y, -1.0, 1.0)) / PI);
+  out.uv = uv * instance.uv_scale + instance.uv_offset;
   return out;
 }
 
 // Fragment shader
 
+[[group(1), binding(0)]]
+var atlas_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var atlas_sampler: sampler;
+[[group(1), binding(2)]]
+var normal_texture: texture_2d<f32>;
+
+struct MaterialUniform {
+  // 1 to perturb the normals by `normal_texture`, 0 to use the vertex normals
+  use_normal_map: u32;
+};
+
+[[group(1), binding(3)]]
+var<uniform> material: MaterialUniform;
+
+struct FogUniform {
+  color: vec4<f32>;
+  density: f32;
+};
+
+[[group(2), binding(0)]]
+var<uniform> fog: FogUniform;
+
 [[stage(fragment)]]
 fn fragment(vertex: VertexOutput) -> [[location(0)]] vec4<f32> {
   let PI = 3.1415926538;
@@ -54,7 +104,45 @@ fn fragment(vertex: VertexOutput) -> [[location(0)]] vec4<f32> {
   let LIGHT_DIRECTION = vec3<f32>(0.0, Y, Z);
   let LIGHT_INTENSITY = 1.0;
   let ALBEDO = 1.0;
-  let illumination = dot(vertex.normal, -LIGHT_DIRECTION);
-  let illumination = max(0.0, illumination);
-  return vec4<f32>(ALBEDO * LIGHT_INTENSITY * illumination * vertex.color, 1.0);
+  let SPECULAR = 0.25;
+  let SHININESS = 32.0;
+
+  // Tangent-space normal from the map, remapped from [0, 1] to [-1, 1]
+  let mapped = textureSample(normal_texture, atlas_sampler, vertex.uv).xyz * 2.0 - 1.0;
+  let tbn = mat3x3<f32>(normalize(vertex.tangent), normalize(vertex.bitangent), normalize(vertex.normal));
+  var normal = normalize(vertex.normal);
+  if (material.use_normal_map != 0u) {
+    normal = normalize(tbn * mapped);
+  }
+
+  // Blinn-Phong
+  let light = -LIGHT_DIRECTION;
+  let view = normalize(camera.eye.xyz - vertex.world_position);
+  let halfway = normalize(light + view);
+  let illumination = max(0.0, dot(normal, light));
+  let specular = SPECULAR * pow(max(0.0, dot(normal, halfway)), SHININESS) * f32(illumination > 0.0);
+
+  let texel = textureSample(atlas_texture, atlas_sampler, vertex.uv).rgb;
+  // Visibility above 1 brightens the feature instead
+  let brightness = max(vertex.visibility, 1.0);
+  let lit = LIGHT_INTENSITY * brightness * (ALBEDO * illumination * vertex.color * texel + specular);
+  let fog_factor = 1.0 - exp(-fog.density * vertex.view_depth);
+  return vec4<f32>(mix(lit, fog.color.rgb, fog_factor), min(vertex.visibility, 1.0));
+}
+
+// Distance mapped to black by `fragment_depth`
+let DEPTH_RANGE: f32 = 20.0;
+
+// Distance from the eye in grey, white up close and black from `DEPTH_RANGE` on
+[[stage(fragment)]]
+fn fragment_depth(vertex: VertexOutput) -> [[location(0)]] vec4<f32> {
+  let depth = clamp(vertex.view_depth / DEPTH_RANGE, 0.0, 1.0);
+  return vec4<f32>(vec3<f32>(1.0 - depth), 1.0);
+}
+
+// Instance color and atlas texel alone, without lighting or fog
+[[stage(fragment)]]
+fn fragment_material(vertex: VertexOutput) -> [[location(0)]] vec4<f32> {
+  let texel = textureSample(atlas_texture, atlas_sampler, vertex.uv).rgb;
+  return vec4<f32>(vertex.color * texel, 1.0);
 }
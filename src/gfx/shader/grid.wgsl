@@ -0,0 +1,31 @@
+// Grid shader
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  [[location(0)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vertex(
+  [[location(0)]] position: vec3<f32>,
+  [[location(1)]] color: vec4<f32>,
+) -> VertexOutput {
+  var out: VertexOutput;
+  out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+  out.color = color;
+  return out;
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+  return in.color;
+}
@@ -0,0 +1,29 @@
+// Line shader
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct LineUniform {
+  color: vec4<f32>;
+};
+
+[[group(1), binding(0)]]
+var<uniform> line: LineUniform;
+
+[[stage(vertex)]]
+fn vertex(
+  [[location(0)]] position: vec3<f32>,
+) -> [[builtin(position)]] vec4<f32> {
+  return camera.view_proj * vec4<f32>(position, 1.0);
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fragment() -> [[location(0)]] vec4<f32> {
+  return line.color;
+}
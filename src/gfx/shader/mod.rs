@@ -1,10 +1,101 @@
-pub mod feature;
-
-use wgpu::{Device, ShaderModule};
-
-pub fn basic(device: &Device) -> ShaderModule {
-  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-    label: Some("Basic Shader"),
-    source: wgpu::ShaderSource::Wgsl(include_str!("basic.wgsl").into()),
-  })
-}
+pub mod feature;
+
+use wgpu::{Device, ShaderModule};
+
+/// The default `BasicRenderer` shader.
+pub const BASIC_SOURCE: &str = include_str!("basic.wgsl");
+
+/// Compiles a WGSL shader for a `BasicRenderer`.
+pub fn basic(device: &Device, source: &str) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Basic Shader"),
+    source: wgpu::ShaderSource::Wgsl(source.into()),
+  })
+}
+
+pub fn line(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Line Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("line.wgsl").into()),
+  })
+}
+
+#[cfg(feature = "ssao")]
+pub fn ssao(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("SSAO Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("ssao.wgsl").into()),
+  })
+}
+
+#[cfg(feature = "ssao")]
+pub fn ssao_resolve(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("SSAO Resolve Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("ssao_resolve.wgsl").into()),
+  })
+}
+
+pub fn grid(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Grid Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("grid.wgsl").into()),
+  })
+}
+
+pub fn normals(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Normals Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("normals.wgsl").into()),
+  })
+}
+
+pub fn pick(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Pick Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("pick.wgsl").into()),
+  })
+}
+
+pub fn depth_copy(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Depth Copy Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("depth_copy.wgsl").into()),
+  })
+}
+
+pub fn point_cloud(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Point Cloud Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("point_cloud.wgsl").into()),
+  })
+}
+
+pub fn ellipsoid(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Ellipsoid Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("ellipsoid.wgsl").into()),
+  })
+}
+
+pub fn skybox(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Skybox Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("skybox.wgsl").into()),
+  })
+}
+
+pub fn text(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Text Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("text.wgsl").into()),
+  })
+}
+
+#[cfg(feature = "outlines")]
+pub fn outline(device: &Device) -> ShaderModule {
+  device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+    label: Some("Outline Shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("outline.wgsl").into()),
+  })
+}
@@ -0,0 +1,55 @@
+// Feature normals, drawn as a short line from every vertex of every instance
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>;
+  eye: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+  [[location(0)]] position: vec3<f32>;
+  [[location(1)]] normal: vec3<f32>;
+  // 0 at the vertex and 1 at the far end of its line
+  [[location(10)]] extent: f32;
+};
+
+struct InstanceInput {
+  [[location(2)]] model_0: vec4<f32>;
+  [[location(3)]] model_1: vec4<f32>;
+  [[location(4)]] model_2: vec4<f32>;
+  [[location(5)]] model_3: vec4<f32>;
+};
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  [[location(0)]] color: vec3<f32>;
+};
+
+// Line length relative to the unscaled mesh, so lines grow with the feature
+let NORMAL_LENGTH: f32 = 0.2;
+
+[[stage(vertex)]]
+fn vertex(
+  vertex: VertexInput,
+  instance: InstanceInput
+) -> VertexOutput {
+  var out: VertexOutput;
+  let model = mat4x4<f32>(
+    instance.model_0,
+    instance.model_1,
+    instance.model_2,
+    instance.model_3,
+  );
+  let position = vertex.position + vertex.normal * NORMAL_LENGTH * vertex.extent;
+  out.clip_position = camera.view_proj * model * vec4<f32>(position, 1.0);
+  // Object-space direction as a color, so that opposite normals are easy to tell apart
+  out.color = vertex.normal * 0.5 + 0.5;
+  return out;
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+  return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,55 @@
+// Outline shader
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct OutlineUniform {
+  color: vec4<f32>;
+  scale: f32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> outline: OutlineUniform;
+
+struct VertexInput {
+  [[location(0)]] position: vec3<f32>;
+  [[location(1)]] normal: vec3<f32>;
+};
+
+struct InstanceInput {
+  [[location(2)]] model_0: vec4<f32>;
+  [[location(3)]] model_1: vec4<f32>;
+  [[location(4)]] model_2: vec4<f32>;
+  [[location(5)]] model_3: vec4<f32>;
+};
+
+fn project(vertex: VertexInput, instance: InstanceInput, scale: f32) -> vec4<f32> {
+  let model = mat4x4<f32>(
+    instance.model_0,
+    instance.model_1,
+    instance.model_2,
+    instance.model_3,
+  );
+  return camera.view_proj * model * vec4<f32>(vertex.position * scale, 1.0);
+}
+
+// Marks the pixels covered by the feature itself
+[[stage(vertex)]]
+fn vertex_mask(vertex: VertexInput, instance: InstanceInput) -> [[builtin(position)]] vec4<f32> {
+  return project(vertex, instance, 1.0);
+}
+
+// Grows the feature about its center so only a rim is left outside the mask
+[[stage(vertex)]]
+fn vertex_outline(vertex: VertexInput, instance: InstanceInput) -> [[builtin(position)]] vec4<f32> {
+  return project(vertex, instance, outline.scale);
+}
+
+[[stage(fragment)]]
+fn fragment() -> [[location(0)]] vec4<f32> {
+  return outline.color;
+}
@@ -0,0 +1,56 @@
+// Feature picking: writes one more than the index of the instance covering each pixel, leaving 0 for none
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>;
+  eye: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct PickUniform {
+  // Index of the first instance in the bound instance buffer
+  base: u32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> pick: PickUniform;
+
+struct VertexInput {
+  [[location(0)]] position: vec3<f32>;
+};
+
+struct InstanceInput {
+  [[location(2)]] model_0: vec4<f32>;
+  [[location(3)]] model_1: vec4<f32>;
+  [[location(4)]] model_2: vec4<f32>;
+  [[location(5)]] model_3: vec4<f32>;
+};
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  [[location(0), interpolate(flat)]] index: u32;
+};
+
+[[stage(vertex)]]
+fn vertex(
+  vertex: VertexInput,
+  instance: InstanceInput,
+  [[builtin(instance_index)]] instance_index: u32,
+) -> VertexOutput {
+  var out: VertexOutput;
+  let model = mat4x4<f32>(
+    instance.model_0,
+    instance.model_1,
+    instance.model_2,
+    instance.model_3,
+  );
+  out.clip_position = camera.view_proj * model * vec4<f32>(vertex.position, 1.0);
+  out.index = pick.base + instance_index + 1u;
+  return out;
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] u32 {
+  return in.index;
+}
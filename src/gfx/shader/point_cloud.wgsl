@@ -0,0 +1,49 @@
+// Point cloud shader. WGSL has no point size, so every point is drawn as a square of `point_size` pixels from a
+// four vertex triangle strip instead of a `PointList` point.
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct ViewportUniform {
+  // Render target size in pixels
+  size: vec2<f32>;
+};
+
+[[group(1), binding(0)]]
+var<uniform> viewport: ViewportUniform;
+
+struct PointInput {
+  [[location(0)]] position: vec3<f32>;
+  [[location(1)]] color: vec4<f32>;
+  [[location(2)]] point_size: f32;
+};
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  [[location(0)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vertex(
+  [[builtin(vertex_index)]] in_vertex_index: u32,
+  point: PointInput,
+) -> VertexOutput {
+  // Corners (-1, -1), (1, -1), (-1, 1), (1, 1)
+  let corner = vec2<f32>(f32(in_vertex_index & 1u), f32(in_vertex_index >> 1u)) * 2.0 - 1.0;
+  var out: VertexOutput;
+  out.clip_position = camera.view_proj * vec4<f32>(point.position, 1.0);
+  // Offset in NDC, scaled by w to undo the perspective divide
+  let offset = corner * point.point_size / viewport.size * out.clip_position.w;
+  out.clip_position = out.clip_position + vec4<f32>(offset, 0.0, 0.0);
+  out.color = point.color;
+  return out;
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+  return in.color;
+}
@@ -0,0 +1,36 @@
+// Skybox shader. The cube is centred on the eye and pushed to the far plane, so it never gets closer however the
+// camera moves.
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>;
+  eye: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(0)]]
+var skybox_texture: texture_cube<f32>;
+[[group(1), binding(1)]]
+var skybox_sampler: sampler;
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  // Direction from the eye, which the cubemap is looked up with
+  [[location(0)]] direction: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vertex([[location(0)]] position: vec3<f32>) -> VertexOutput {
+  var out: VertexOutput;
+  let clip_position = camera.view_proj * vec4<f32>(camera.eye.xyz + position, 1.0);
+  // Depth of one after the perspective divide
+  out.clip_position = clip_position.xyww;
+  out.direction = position;
+  return out;
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+  return textureSample(skybox_texture, skybox_sampler, in.direction);
+}
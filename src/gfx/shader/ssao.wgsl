@@ -0,0 +1,86 @@
+// Screen-space ambient occlusion
+
+struct SsaoUniform {
+  projection: mat4x4<f32>;
+  inverse_projection: mat4x4<f32>;
+  // 16 tightly packed vec3 hemisphere samples
+  kernel: array<vec4<f32>, 12>;
+  sample_count: u32;
+  radius: f32;
+  bias: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> ssao: SsaoUniform;
+[[group(0), binding(1)]]
+var depth_texture: texture_depth_2d;
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  [[location(0)]] uv: vec2<f32>;
+};
+
+// Fullscreen triangle covering uv [0, 1] with y pointing down
+[[stage(vertex)]]
+fn vertex(
+  [[builtin(vertex_index)]] in_vertex_index: u32,
+) -> VertexOutput {
+  var out: VertexOutput;
+  let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+  out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+  out.uv = uv;
+  return out;
+}
+
+fn kernel_component(k: u32) -> f32 {
+  return ssao.kernel[k / 4u][k % 4u];
+}
+
+fn kernel_sample(i: u32) -> vec3<f32> {
+  let k = i * 3u;
+  return vec3<f32>(kernel_component(k), kernel_component(k + 1u), kernel_component(k + 2u));
+}
+
+fn view_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+  let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+  let position = ssao.inverse_projection * ndc;
+  return position.xyz / position.w;
+}
+
+fn load_depth(uv: vec2<f32>) -> f32 {
+  let size = textureDimensions(depth_texture);
+  let coords = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+  return textureLoad(depth_texture, coords, 0);
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] f32 {
+  let depth = textureLoad(depth_texture, vec2<i32>(in.clip_position.xy), 0);
+  let position = view_position(in.uv, depth);
+  // Derivatives must be taken in uniform control flow, before discarding the background
+  let normal = normalize(cross(dpdy(position), dpdx(position)));
+  if (depth >= 1.0) {
+    return 1.0;
+  }
+  var helper = vec3<f32>(1.0, 0.0, 0.0);
+  if (abs(normal.x) > 0.9) {
+    helper = vec3<f32>(0.0, 1.0, 0.0);
+  }
+  let tangent = normalize(cross(helper, normal));
+  let bitangent = cross(normal, tangent);
+
+  var occlusion = 0.0;
+  for (var i = 0u; i < ssao.sample_count; i = i + 1u) {
+    let offset = kernel_sample(i);
+    let sample = position + (tangent * offset.x + bitangent * offset.y + normal * offset.z) * ssao.radius;
+    let clip = ssao.projection * vec4<f32>(sample, 1.0);
+    let ndc = clip.xy / clip.w;
+    let sample_uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    let scene = view_position(sample_uv, load_depth(sample_uv));
+    let range = clamp(ssao.radius / max(abs(position.z - scene.z), 0.0001), 0.0, 1.0);
+    occlusion = occlusion + select(0.0, 1.0, scene.z >= sample.z + ssao.bias) * range;
+  }
+  return 1.0 - occlusion / f32(max(ssao.sample_count, 1u));
+}
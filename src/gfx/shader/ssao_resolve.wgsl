@@ -0,0 +1,20 @@
+// Multiplies the ambient occlusion factor into the rendered frame
+
+[[group(0), binding(0)]]
+var occlusion_texture: texture_2d<f32>;
+
+[[stage(vertex)]]
+fn vertex(
+  [[builtin(vertex_index)]] in_vertex_index: u32,
+) -> [[builtin(position)]] vec4<f32> {
+  let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+  return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn fragment([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+  let occlusion = textureLoad(occlusion_texture, vec2<i32>(position.xy), 0).r;
+  return vec4<f32>(occlusion, occlusion, occlusion, 1.0);
+}
@@ -0,0 +1,36 @@
+// Vertex shader
+
+struct VertexInput {
+  [[location(0)]] position: vec2<f32>;
+  [[location(1)]] uv: vec2<f32>;
+  [[location(2)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+  [[builtin(position)]] clip_position: vec4<f32>;
+  [[location(0)]] uv: vec2<f32>;
+  [[location(1)]] color: vec4<f32>;
+};
+
+// Positions are already normalized screen coordinates
+[[stage(vertex)]]
+fn vertex(in: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+  out.uv = in.uv;
+  out.color = in.color;
+  return out;
+}
+
+// Fragment shader
+
+[[group(0), binding(0)]]
+var t_atlas: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_atlas: sampler;
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+  let coverage = textureSample(t_atlas, s_atlas, in.uv).r;
+  return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,323 @@
+use super::texture::Texture;
+
+use wgpu::{Buffer, Device, Queue, RenderPass, RenderPipeline};
+
+use std::num::NonZeroU32;
+
+/// 8×8 monospace glyphs for every byte value, one byte per row with bit 0 as the leftmost pixel. Only printable
+/// ASCII is drawn; the rest are blank.
+const FONT: &[u8; 256 * 8] = include_bytes!("font8x8.bin");
+const GLYPH_SIZE: u32 = 8;
+/// Glyphs per row and column of the atlas
+const ATLAS_GLYPHS: u32 = 16;
+const ATLAS_SIZE: u32 = GLYPH_SIZE * ATLAS_GLYPHS;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextVertex {
+  /// Pixels from the top left corner of the screen until flushed, then normalized screen coordinates
+  pub position: [f32; 2],
+  pub uv: [f32; 2],
+  pub color: [f32; 4],
+}
+
+impl TextVertex {
+  pub fn description<'a>() -> wgpu::VertexBufferLayout<'a> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+      0 => Float32x2,
+      1 => Float32x2,
+      2 => Float32x4,
+    ];
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<TextVertex>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &ATTRIBUTES,
+    }
+  }
+}
+
+/// Draws queued screen-space debug text with the built-in bitmap font. The pipeline has no depth state, so
+/// `flush` must be called inside a pass without a depth attachment.
+pub struct TextRenderer {
+  pipeline: RenderPipeline,
+  atlas_bind_group: wgpu::BindGroup,
+  vertex_buffer: Buffer,
+  /// Vertices the vertex buffer can hold
+  capacity: usize,
+  vertex_count: u32,
+  vertices: Vec<TextVertex>,
+  /// Screen pixels per font pixel
+  pub scale: f32,
+}
+
+impl TextRenderer {
+  pub fn new(device: &Device, queue: &Queue, format: wgpu::TextureFormat) -> Self {
+    let atlas = Self::atlas_texture(device, queue);
+    let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+      label: Some("text_atlas_bind_group_layout"),
+    });
+    let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &atlas_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&atlas.view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+        },
+      ],
+      label: Some("text_atlas_bind_group"),
+    });
+
+    let shader = super::shader::text(device);
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Text Layout"),
+      bind_group_layouts: &[&atlas_layout],
+      push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Text Pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vertex",
+        buffers: &[TextVertex::description()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fragment",
+        targets: &[wgpu::ColorTargetState {
+          format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::Ccw,
+        cull_mode: None,
+        polygon_mode: wgpu::PolygonMode::Fill,
+        unclipped_depth: false,
+        conservative: false,
+      },
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState {
+        count: 1,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    });
+
+    let capacity = 0;
+    Self {
+      pipeline,
+      atlas_bind_group,
+      vertex_buffer: Self::vertex_buffer(device, capacity),
+      capacity,
+      vertex_count: 0,
+      vertices: Vec::new(),
+      scale: 2.0,
+    }
+  }
+
+  fn atlas_texture(device: &Device, queue: &Queue) -> Texture {
+    // Nearest filtering keeps the glyph edges sharp at any scale
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      address_mode_u: wgpu::AddressMode::ClampToEdge,
+      address_mode_v: wgpu::AddressMode::ClampToEdge,
+      address_mode_w: wgpu::AddressMode::ClampToEdge,
+      mag_filter: wgpu::FilterMode::Nearest,
+      min_filter: wgpu::FilterMode::Nearest,
+      mipmap_filter: wgpu::FilterMode::Nearest,
+      ..Default::default()
+    });
+    let atlas = Texture::new(
+      device,
+      Some("Text Atlas"),
+      ATLAS_SIZE,
+      ATLAS_SIZE,
+      wgpu::TextureFormat::R8Unorm,
+      wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      sampler,
+    );
+    queue.write_texture(
+      wgpu::ImageCopyTexture {
+        aspect: wgpu::TextureAspect::All,
+        texture: &atlas.texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+      },
+      &atlas_pixels(FONT),
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: NonZeroU32::new(ATLAS_SIZE),
+        rows_per_image: NonZeroU32::new(ATLAS_SIZE),
+      },
+      wgpu::Extent3d {
+        width: ATLAS_SIZE,
+        height: ATLAS_SIZE,
+        depth_or_array_layers: 1,
+      },
+    );
+    atlas
+  }
+
+  fn vertex_buffer(device: &Device, capacity: usize) -> Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Text Vertex Buffer"),
+      size: (capacity.max(1) * std::mem::size_of::<TextVertex>()) as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    })
+  }
+
+  /// Queues `text` with its top left corner `x`, `y` pixels from the top left of the screen. Newlines start a new
+  /// line and characters outside ASCII are drawn as '?'.
+  pub fn queue_text(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+    glyph_quads(&mut self.vertices, x, y, self.scale, text, color);
+  }
+
+  /// Uploads the text queued since the last flush and draws it into `render_pass`.
+  pub fn flush<'a>(
+    &'a mut self,
+    device: &Device,
+    queue: &Queue,
+    render_pass: &mut RenderPass<'a>,
+    screen_size: [f32; 2],
+  ) {
+    for vertex in &mut self.vertices {
+      vertex.position = [
+        vertex.position[0] / screen_size[0] * 2.0 - 1.0,
+        1.0 - vertex.position[1] / screen_size[1] * 2.0,
+      ];
+    }
+    if self.vertices.len() > self.capacity {
+      self.capacity = self.vertices.len().next_power_of_two();
+      self.vertex_buffer = Self::vertex_buffer(device, self.capacity);
+    }
+    if !self.vertices.is_empty() {
+      queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+    }
+    self.vertex_count = self.vertices.len() as u32;
+    self.vertices.clear();
+    if self.vertex_count == 0 {
+      return;
+    }
+
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, &self.atlas_bind_group, &[]);
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.draw(0..self.vertex_count, 0..1);
+  }
+}
+
+/// Unpacks the font into a single channel atlas of `ATLAS_GLYPHS`×`ATLAS_GLYPHS` glyphs, with glyph `c` in column
+/// `c % 16` and row `c / 16`.
+fn atlas_pixels(font: &[u8]) -> Vec<u8> {
+  let mut pixels = vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize];
+  for (glyph, rows) in font.chunks_exact(GLYPH_SIZE as usize).enumerate() {
+    let origin_x = glyph as u32 % ATLAS_GLYPHS * GLYPH_SIZE;
+    let origin_y = glyph as u32 / ATLAS_GLYPHS * GLYPH_SIZE;
+    for (y, row) in (0..).zip(rows) {
+      for x in 0..GLYPH_SIZE {
+        if row & (1 << x) != 0 {
+          pixels[((origin_y + y) * ATLAS_SIZE + origin_x + x) as usize] = 255;
+        }
+      }
+    }
+  }
+  pixels
+}
+
+/// Appends two triangles per drawn character of `text` to `vertices`, in pixel coordinates.
+fn glyph_quads(vertices: &mut Vec<TextVertex>, x: f32, y: f32, scale: f32, text: &str, color: [f32; 4]) {
+  let size = GLYPH_SIZE as f32 * scale;
+  let uv_size = 1.0 / ATLAS_GLYPHS as f32;
+  let (mut cursor_x, mut cursor_y) = (x, y);
+  for c in text.chars() {
+    if c == '\n' {
+      cursor_x = x;
+      cursor_y += size;
+      continue;
+    }
+    let glyph = if c.is_ascii() { c as u32 } else { '?' as u32 };
+    if c != ' ' {
+      let u = (glyph % ATLAS_GLYPHS) as f32 * uv_size;
+      let v = (glyph / ATLAS_GLYPHS) as f32 * uv_size;
+      let corner = |dx: f32, dy: f32| TextVertex {
+        position: [cursor_x + dx * size, cursor_y + dy * size],
+        uv: [u + dx * uv_size, v + dy * uv_size],
+        color,
+      };
+      let (top_left, top_right) = (corner(0.0, 0.0), corner(1.0, 0.0));
+      let (bottom_left, bottom_right) = (corner(0.0, 1.0), corner(1.0, 1.0));
+      vertices.extend_from_slice(&[top_left, bottom_left, top_right, top_right, bottom_left, bottom_right]);
+    }
+    cursor_x += size;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn atlas_pixels_test() {
+    let pixels = atlas_pixels(FONT);
+    assert_eq!(pixels.len(), 128 * 128);
+    // 'A' (0x41) is in column 1 of row 4; its top row is 0x0C, lighting pixels 2 and 3
+    let row = |glyph_x: usize, glyph_y: usize, y: usize| {
+      let start = (glyph_y * 8 + y) * 128 + glyph_x * 8;
+      pixels[start..start + 8].to_vec()
+    };
+    assert_eq!(row(1, 4, 0), vec![0, 0, 255, 255, 0, 0, 0, 0]);
+    // Control characters are blank
+    assert!((0..8).all(|y| row(0, 0, y).iter().all(|&p| p == 0)));
+  }
+
+  #[test]
+  fn glyph_quads_test() {
+    let mut vertices = Vec::new();
+    let color = [1.0, 0.5, 0.0, 1.0];
+    glyph_quads(&mut vertices, 10.0, 20.0, 2.0, "A b\nç", color);
+    // The space takes up room but draws nothing
+    assert_eq!(vertices.len(), 3 * 6);
+
+    let a = &vertices[0..6];
+    assert_eq!(a[0].position, [10.0, 20.0]);
+    assert_eq!(a[5].position, [26.0, 36.0]);
+    assert_eq!(a[0].uv, [1.0 / 16.0, 4.0 / 16.0]);
+    assert_eq!(a[5].uv, [2.0 / 16.0, 5.0 / 16.0]);
+    assert!(vertices.iter().all(|vertex| vertex.color == color));
+
+    let b = &vertices[6..12];
+    assert_eq!(b[0].position, [42.0, 20.0]);
+
+    // The newline returns to the starting column and the non-ASCII character falls back to '?'
+    let fallback = &vertices[12..18];
+    assert_eq!(fallback[0].position, [10.0, 36.0]);
+    assert_eq!(fallback[0].uv, [15.0 / 16.0, 3.0 / 16.0]);
+  }
+}
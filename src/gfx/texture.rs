@@ -3,33 +3,90 @@ use image::GenericImageView;
 use std::error::Error;
 use std::num::NonZeroU32;
 
+#[allow(dead_code)]
 pub struct Texture {
   pub texture: wgpu::Texture,
   pub view: wgpu::TextureView,
   pub sampler: wgpu::Sampler,
+  size: wgpu::Extent3d,
+  format: wgpu::TextureFormat,
+  usage: wgpu::TextureUsages,
+  label: Option<String>,
 }
 
 impl Texture {
-  pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+  /// Depth with an 8-bit stencil, which the selection outline is masked with
+  pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
 
-  pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+  /// Single 2D texture of `width`×`height` with one mip level and sample, viewed whole.
+  pub fn new(
+    device: &wgpu::Device,
+    label: Option<&str>,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+    sampler: wgpu::Sampler,
+  ) -> Self {
     let size = wgpu::Extent3d {
-      width: config.width,
-      height: config.height,
+      width,
+      height,
       depth_or_array_layers: 1,
     };
-    let desc = wgpu::TextureDescriptor {
-      label: Some(label),
+    let (texture, view) = Self::create(device, label, size, format, usage);
+    Self {
+      texture,
+      view,
+      sampler,
+      size,
+      format,
+      usage,
+      label: label.map(String::from),
+    }
+  }
+
+  fn create(
+    device: &wgpu::Device,
+    label: Option<&str>,
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+  ) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label,
       size,
       mip_level_count: 1,
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
-      format: Self::DEPTH_FORMAT,
-      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-    };
-    let texture = device.create_texture(&desc);
-
+      format,
+      usage,
+    });
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+  }
+
+  /// Replaces the texture and view with blank ones of the new size, keeping the format, usage and sampler. Does
+  /// nothing if the size is unchanged.
+  pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+    if width == self.size.width && height == self.size.height {
+      return;
+    }
+    self.size.width = width;
+    self.size.height = height;
+    let (texture, view) = Self::create(device, self.label.as_deref(), self.size, self.format, self.usage);
+    self.texture = texture;
+    self.view = view;
+  }
+
+  pub fn width(&self) -> u32 {
+    self.size.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.size.height
+  }
+
+  pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
     let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
       // 4.
       address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -44,7 +101,35 @@ impl Texture {
       ..Default::default()
     });
 
-    Self { texture, view, sampler }
+    Self::new(
+      device,
+      Some(label),
+      config.width,
+      config.height,
+      Self::DEPTH_FORMAT,
+      wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+      sampler,
+    )
+  }
+
+  /// Color texture that can be rendered into and copied out of.
+  #[allow(dead_code)]
+  pub fn create_render_target(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    label: &str,
+  ) -> Self {
+    Self::new(
+      device,
+      Some(label),
+      width,
+      height,
+      format,
+      wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+      device.create_sampler(&wgpu::SamplerDescriptor::default()),
+    )
   }
 
   #[allow(dead_code)]
@@ -58,31 +143,93 @@ impl Texture {
     Self::from_image(device, queue, &img, Some(label))
   }
 
-  #[allow(dead_code)]
   pub fn from_image(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     img: &image::DynamicImage,
     label: Option<&str>,
+  ) -> Result<Self, Box<dyn Error>> {
+    Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+  }
+
+  /// Like `from_image`, but without decoding sRGB, since normal maps store directions rather than colors.
+  pub fn normal_map_from_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    img: &image::DynamicImage,
+    label: Option<&str>,
+  ) -> Result<Self, Box<dyn Error>> {
+    Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8Unorm)
+  }
+
+  fn from_image_with_format(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    img: &image::DynamicImage,
+    label: Option<&str>,
+    format: wgpu::TextureFormat,
   ) -> Result<Self, Box<dyn Error>> {
     let rgba = img.as_rgba8().unwrap();
     let dimensions = img.dimensions();
 
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      address_mode_u: wgpu::AddressMode::ClampToEdge,
+      address_mode_v: wgpu::AddressMode::ClampToEdge,
+      address_mode_w: wgpu::AddressMode::ClampToEdge,
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Nearest,
+      mipmap_filter: wgpu::FilterMode::Nearest,
+      ..Default::default()
+    });
+    let texture = Self::new(
+      device,
+      label,
+      dimensions.0,
+      dimensions.1,
+      format,
+      wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      sampler,
+    );
+
+    queue.write_texture(
+      wgpu::ImageCopyTexture {
+        aspect: wgpu::TextureAspect::All,
+        texture: &texture.texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+      },
+      rgba,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: NonZeroU32::new(4 * dimensions.0),
+        rows_per_image: NonZeroU32::new(dimensions.1),
+      },
+      texture.size,
+    );
+
+    Ok(texture)
+  }
+
+  /// Cube texture from six PNG encoded faces in the order +X, -X, +Y, -Y, +Z, -Z. The faces must be square and all the
+  /// same size.
+  pub fn create_cubemap(device: &wgpu::Device, queue: &wgpu::Queue, faces: [&[u8]; 6]) -> Result<Self, Box<dyn Error>> {
+    let (size, pixels) = decode_cubemap_faces(faces)?;
     let size = wgpu::Extent3d {
-      width: dimensions.0,
-      height: dimensions.1,
-      depth_or_array_layers: 1,
+      width: size,
+      height: size,
+      depth_or_array_layers: 6,
     };
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
     let texture = device.create_texture(&wgpu::TextureDescriptor {
-      label,
+      label: Some("Cubemap Texture"),
       size,
       mip_level_count: 1,
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba8UnormSrgb,
-      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      format,
+      usage,
     });
-
     queue.write_texture(
       wgpu::ImageCopyTexture {
         aspect: wgpu::TextureAspect::All,
@@ -90,26 +237,135 @@ impl Texture {
         mip_level: 0,
         origin: wgpu::Origin3d::ZERO,
       },
-      rgba,
+      &pixels,
       wgpu::ImageDataLayout {
         offset: 0,
-        bytes_per_row: NonZeroU32::new(4 * dimensions.0),
-        rows_per_image: NonZeroU32::new(dimensions.1),
+        bytes_per_row: NonZeroU32::new(4 * size.width),
+        rows_per_image: NonZeroU32::new(size.height),
       },
       size,
     );
-
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(wgpu::TextureViewDimension::Cube),
+      ..Default::default()
+    });
     let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
       address_mode_u: wgpu::AddressMode::ClampToEdge,
       address_mode_v: wgpu::AddressMode::ClampToEdge,
       address_mode_w: wgpu::AddressMode::ClampToEdge,
       mag_filter: wgpu::FilterMode::Linear,
-      min_filter: wgpu::FilterMode::Nearest,
+      min_filter: wgpu::FilterMode::Linear,
       mipmap_filter: wgpu::FilterMode::Nearest,
       ..Default::default()
     });
+    Ok(Self {
+      texture,
+      view,
+      sampler,
+      size,
+      format,
+      usage,
+      label: Some("Cubemap Texture".into()),
+    })
+  }
+
+  /// Creates a 1×1 texture filled with the given color, for binding in place of an absent texture.
+  pub fn from_solid_color(device: &wgpu::Device, queue: &wgpu::Queue, r: u8, g: u8, b: u8, a: u8, label: &str) -> Self {
+    let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([r, g, b, a])));
+    Self::from_image(device, queue, &img, Some(label)).expect("solid color image is RGBA8")
+  }
+
+  pub fn white(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+    Self::from_solid_color(device, queue, 255, 255, 255, 255, "White Texture")
+  }
+
+  #[allow(dead_code)]
+  pub fn black(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+    Self::from_solid_color(device, queue, 0, 0, 0, 255, "Black Texture")
+  }
+
+  /// Normal map pointing straight out of the surface.
+  pub fn flat_normal(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+    let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255])));
+    Self::normal_map_from_image(device, queue, &img, Some("Flat Normal Texture")).expect("solid color image is RGBA8")
+  }
+}
+
+/// Decodes the six faces of `Texture::create_cubemap` into their side length and their RGBA8 pixels, one face after
+/// another.
+fn decode_cubemap_faces(faces: [&[u8]; 6]) -> Result<(u32, Vec<u8>), Box<dyn Error>> {
+  let mut size = None;
+  let mut pixels = Vec::new();
+  for (face, bytes) in faces.iter().enumerate() {
+    let image = image::load_from_memory(bytes)?.to_rgba8();
+    let (width, height) = image.dimensions();
+    if width != height || matches!(size, Some(size) if size != width) {
+      return Err(
+        format!(
+          "cubemap face {} is {}×{}, expected equal square faces",
+          face, width, height
+        )
+        .into(),
+      );
+    }
+    size = Some(width);
+    pixels.extend_from_slice(&image);
+  }
+  Ok((size.unwrap_or(0), pixels))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::gfx::renderer::test::headless_device;
+  use crate::gfx::renderer::RenderPassBuilder;
+
+  fn png(width: u32, height: u32, color: [u8; 3]) -> Vec<u8> {
+    let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(width, height, image::Rgb(color)));
+    let mut bytes = Vec::new();
+    image.write_to(&mut bytes, image::ImageOutputFormat::Png).unwrap();
+    bytes
+  }
 
-    Ok(Self { texture, view, sampler })
+  fn refs(faces: &[Vec<u8>]) -> [&[u8]; 6] {
+    [0, 1, 2, 3, 4, 5].map(|face| &faces[face][..])
+  }
+
+  #[test]
+  fn decode_cubemap_faces_test() {
+    let faces: Vec<Vec<u8>> = (0..6).map(|face| png(2, 2, [face * 40, 0, 0])).collect();
+    let (size, pixels) = decode_cubemap_faces(refs(&faces)).unwrap();
+    assert_eq!(size, 2);
+    assert_eq!(pixels.len(), 6 * 2 * 2 * 4);
+    // Faces follow each other, with an opaque alpha added
+    assert_eq!(&pixels[16..20], &[40, 0, 0, 255]);
+
+    let mut uneven = faces.clone();
+    uneven[3] = png(4, 4, [0, 0, 0]);
+    assert!(decode_cubemap_faces(refs(&uneven)).is_err());
+    let mut oblong = faces;
+    oblong[0] = png(2, 1, [0, 0, 0]);
+    assert!(decode_cubemap_faces(refs(&oblong)).is_err());
+  }
+
+  #[test]
+  fn texture_resize_test() {
+    let (device, _queue) = match headless_device() {
+      Some(device) => device,
+      None => {
+        eprintln!("skipping texture_resize_test: no adapter");
+        return;
+      }
+    };
+    let mut target = Texture::create_render_target(&device, 32, 16, wgpu::TextureFormat::Rgba8Unorm, "Resized");
+    assert_eq!((target.width(), target.height()), (32, 16));
+    target.resize(&device, 64, 48);
+    assert_eq!((target.width(), target.height()), (64, 48));
+    // The new texture must be usable with the old format and usage
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    RenderPassBuilder::new(&mut encoder, "Resize Test Pass")
+      .color(&target.view)
+      .build();
+    device.poll(wgpu::Maintain::Wait);
   }
 }
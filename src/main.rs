@@ -1,9 +1,17 @@
 mod application;
+mod capture;
 mod cli;
+mod command;
+mod config;
 mod featuredb;
 mod gfx;
+mod metrics;
 mod net;
+mod pointcloud;
 mod raycast;
+mod replay;
+mod sensor;
+mod stats;
 #[allow(dead_code)]
 mod trackball;
 mod ui;
@@ -15,7 +23,35 @@ use cli::Cli;
 async fn main() {
   let cli = Cli::new();
   if !cli.run().unwrap() {
-    let app = Application::new().await;
+    #[cfg(feature = "headless")]
+    if let Some(frames) = cli.headless_frames() {
+      run_headless(&cli, frames).await;
+      return;
+    }
+    let app = Application::new(cli.configuration()).await;
     app.run().await;
   }
 }
+
+#[cfg(feature = "headless")]
+async fn run_headless(cli: &Cli, frames: u32) {
+  let database = match featuredb::FeatureDB::new() {
+    Ok(database) => database,
+    Err(err) => {
+      eprintln!("failed to load feature database: '{}'", err);
+      return;
+    }
+  };
+  let size = winit::dpi::PhysicalSize::new(800, 600);
+  let mut app = match Application::headless(cli.configuration(), database, size).await {
+    Some(app) => app,
+    None => {
+      eprintln!("failed to run headless: no adapter");
+      return;
+    }
+  };
+  if let Err(err) = app.run_headless_for(frames) {
+    eprintln!("{}", err);
+  }
+  command::CommandHandler::shutdown(&mut app);
+}
@@ -0,0 +1,302 @@
+use super::command::AppCommand;
+
+use async_std::io::{BufReader, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use winit::event_loop::EventLoopProxy;
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Largest `POST /command` body read, far more than any `AppCommand` takes
+const MAX_COMMAND_SIZE: usize = 64 * 1024;
+
+/// Counters shared between the frame loop and the metrics server. Clones share the same values.
+#[derive(Clone, Default)]
+pub struct Metrics {
+  pub frames_rendered: Arc<AtomicU64>,
+  /// Feature instances drawn in the last frame, across the main features and visible layers
+  pub features_visible: Arc<AtomicU64>,
+  pub ws_messages_received: Arc<AtomicU64>,
+  pub db_insert_count: Arc<AtomicU64>,
+  /// Cursor rays cast to hover features and to pick measurement points
+  pub raycast_count: Arc<AtomicU64>,
+  pub draw_calls_total: Arc<AtomicU64>,
+  pub triangles_total: Arc<AtomicU64>,
+  /// WebSocket payload bytes, copied from the client's statistics every frame
+  pub ws_bytes_received: Arc<AtomicU64>,
+  pub ws_bytes_sent: Arc<AtomicU64>,
+}
+
+impl Metrics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Every metric in the Prometheus text exposition format.
+  pub fn export_prometheus(&self) -> String {
+    let metrics = [
+      ("frames_rendered", "counter", "Frames rendered", &self.frames_rendered),
+      (
+        "features_visible",
+        "gauge",
+        "Feature instances drawn in the last frame",
+        &self.features_visible,
+      ),
+      (
+        "ws_messages_received",
+        "counter",
+        "WebSocket messages received",
+        &self.ws_messages_received,
+      ),
+      (
+        "db_insert_count",
+        "counter",
+        "Features upserted into the database",
+        &self.db_insert_count,
+      ),
+      (
+        "raycast_count",
+        "counter",
+        "Cursor rays cast against the features",
+        &self.raycast_count,
+      ),
+      (
+        "draw_calls_total",
+        "counter",
+        "Draw calls issued by the renderers",
+        &self.draw_calls_total,
+      ),
+      (
+        "triangles_total",
+        "counter",
+        "Triangles drawn by the renderers",
+        &self.triangles_total,
+      ),
+    ];
+    let mut export = String::new();
+    for (name, kind, help, value) in metrics {
+      let name = format!("simulator_{}", name);
+      writeln!(export, "# HELP {} {}", name, help).unwrap();
+      writeln!(export, "# TYPE {} {}", name, kind).unwrap();
+      writeln!(export, "{} {}", name, value.load(Ordering::Relaxed)).unwrap();
+    }
+    writeln!(
+      export,
+      "# HELP simulator_ws_bytes_total WebSocket payload bytes by direction"
+    )
+    .unwrap();
+    writeln!(export, "# TYPE simulator_ws_bytes_total counter").unwrap();
+    for (direction, value) in [("rx", &self.ws_bytes_received), ("tx", &self.ws_bytes_sent)] {
+      writeln!(
+        export,
+        "simulator_ws_bytes_total{{direction=\"{}\"}} {}",
+        direction,
+        value.load(Ordering::Relaxed)
+      )
+      .unwrap();
+    }
+    export
+  }
+
+  /// Serves `export_prometheus` at `GET /metrics` on `port` until the listener fails. `AppCommand`s posted as JSON to
+  /// `POST /command` are passed on to `commands`.
+  pub async fn serve(self, port: u16, commands: Option<EventLoopProxy<AppCommand>>) -> std::io::Result<()> {
+    self
+      .serve_listener(TcpListener::bind(("0.0.0.0", port)).await?, commands)
+      .await
+  }
+
+  async fn serve_listener(
+    self,
+    listener: TcpListener,
+    commands: Option<EventLoopProxy<AppCommand>>,
+  ) -> std::io::Result<()> {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+      let metrics = self.clone();
+      let commands = commands.clone();
+      async_std::task::spawn(async move {
+        if let Err(err) = metrics.respond(stream?, commands.as_ref()).await {
+          eprintln!("failed to serve metrics: '{}'", err);
+        }
+        std::io::Result::Ok(())
+      });
+    }
+    Ok(())
+  }
+
+  async fn respond(&self, mut stream: TcpStream, commands: Option<&EventLoopProxy<AppCommand>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let response = if request_line.starts_with("POST /command ") {
+      let body = read_body(&mut reader).await?;
+      command_response(&body, commands).to_owned()
+    } else if request_line.starts_with("GET /metrics ") {
+      let body = self.export_prometheus();
+      format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+      )
+    } else {
+      "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+    };
+    stream.write_all(response.as_bytes()).await
+  }
+}
+
+/// Reads the headers following the request line and then the body they give the length of, up to `MAX_COMMAND_SIZE`.
+async fn read_body(reader: &mut BufReader<&TcpStream>) -> std::io::Result<Vec<u8>> {
+  let mut length = 0;
+  loop {
+    let mut header = String::new();
+    reader.read_line(&mut header).await?;
+    let header = header.trim_end();
+    if header.is_empty() {
+      break;
+    }
+    if let Some((name, value)) = header.split_once(':') {
+      if name.eq_ignore_ascii_case("content-length") {
+        length = value.trim().parse().unwrap_or(0);
+      }
+    }
+  }
+  let mut body = vec![0; length.min(MAX_COMMAND_SIZE)];
+  reader.read_exact(&mut body).await?;
+  Ok(body)
+}
+
+/// Passes on the `AppCommand` in `body` and answers whether it was.
+fn command_response(body: &[u8], commands: Option<&EventLoopProxy<AppCommand>>) -> &'static str {
+  let command = match serde_json::from_slice::<AppCommand>(body) {
+    Ok(command) => command,
+    Err(_) => return "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+  };
+  match commands.map(|commands| commands.send_event(command)) {
+    Some(Ok(())) => "HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    // Headless, or the event loop has exited
+    _ => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn export_prometheus_test() {
+    let metrics = Metrics::new();
+    metrics.frames_rendered.fetch_add(3, Ordering::Relaxed);
+    metrics.features_visible.store(42, Ordering::Relaxed);
+    let export = metrics.export_prometheus();
+    assert!(export.contains(
+      "# HELP simulator_frames_rendered Frames rendered\n\
+       # TYPE simulator_frames_rendered counter\n\
+       simulator_frames_rendered 3\n"
+    ));
+    assert!(export.contains("# TYPE simulator_features_visible gauge\nsimulator_features_visible 42\n"));
+    assert!(export.contains("simulator_raycast_count 0\n"));
+    metrics.ws_bytes_sent.store(12, Ordering::Relaxed);
+    let export = metrics.export_prometheus();
+    assert!(export.contains(
+      "# TYPE simulator_ws_bytes_total counter\n\
+       simulator_ws_bytes_total{direction=\"rx\"} 0\n\
+       simulator_ws_bytes_total{direction=\"tx\"} 12\n"
+    ));
+    assert_eq!(export.lines().count(), 7 * 3 + 4);
+  }
+
+  #[test]
+  fn serve_test() {
+    async_std::task::block_on(async {
+      let metrics = Metrics::new();
+      metrics.ws_messages_received.store(7, Ordering::Relaxed);
+      let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      async_std::task::spawn(metrics.serve_listener(listener, None));
+
+      let get = |path: &'static str| async move {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+          .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+          .await
+          .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+      };
+      let response = get("/metrics").await;
+      assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+      assert!(response.ends_with(
+        &Metrics::new()
+          .export_prometheus()
+          .replace("simulator_ws_messages_received 0", "simulator_ws_messages_received 7")
+      ));
+      assert!(get("/").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    });
+  }
+
+  async fn post_command(address: std::net::SocketAddr, body: &str) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!(
+      "POST /command HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+      body.len(),
+      body
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+  }
+
+  #[test]
+  fn command_test() {
+    async_std::task::block_on(async {
+      let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      async_std::task::spawn(Metrics::new().serve_listener(listener, None));
+      assert!(post_command(address, "{")
+        .await
+        .starts_with("HTTP/1.1 400 Bad Request\r\n"));
+      // Without an event loop there is no one to take the command
+      assert!(post_command(address, r#""Shutdown""#)
+        .await
+        .starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    });
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn command_event_loop_test() {
+    use winit::event::Event;
+    use winit::event_loop::{ControlFlow, EventLoop};
+    use winit::platform::run_return::EventLoopExtRunReturn;
+
+    let display = std::env::var_os("DISPLAY").or_else(|| std::env::var_os("WAYLAND_DISPLAY"));
+    if display.is_none() {
+      eprintln!("skipping command_event_loop_test: no display");
+      return;
+    }
+    // Tests run off the main thread
+    let mut event_loop: EventLoop<AppCommand> = winit::platform::unix::EventLoopExtUnix::new_any_thread();
+    let proxy = event_loop.create_proxy();
+    async_std::task::block_on(async {
+      let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      async_std::task::spawn(Metrics::new().serve_listener(listener, Some(proxy)));
+      let response = post_command(address, r#"{"Screenshot": "frame.png"}"#).await;
+      assert!(response.starts_with("HTTP/1.1 202 Accepted\r\n"));
+    });
+    let mut received = None;
+    event_loop.run_return(|event, _, control_flow| {
+      if let Event::UserEvent(command) = event {
+        received = Some(command);
+        *control_flow = ControlFlow::Exit;
+      }
+    });
+    assert_eq!(received, Some(AppCommand::Screenshot("frame.png".into())));
+  }
+}
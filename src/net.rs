@@ -1,48 +1,902 @@
-use std::sync::{Mutex, MutexGuard};
+use super::command::AppCommand;
+use super::featuredb::Feature;
+use super::sensor::SensorReading;
 
-use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
-use futures::StreamExt;
+use std::convert::TryInto;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::channel::mpsc::{TrySendError, UnboundedReceiver, UnboundedSender};
+use futures::{Sink, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
 use async_tungstenite::async_std::connect_async;
-use tungstenite::Error;
+use tungstenite::{Error, Message};
+use winit::event_loop::EventLoopProxy;
+
+/// How often the client pings the robot to measure latency
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+/// Stored round trip before the first pong arrives
+const NO_ROUND_TRIP: u64 = u64::MAX;
+
+/// Messages exchanged with the robot; with the JSON codec these are text frames of the form `{"type": ..., "data": ...}`.
+// The variant names are the message types on the wire
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum SimulatorMessage {
+  FeatureUpdate(Vec<Feature>),
+  /// Paths followed by the robot, each a list of world-space points
+  PathUpdate(Vec<Vec<[f32; 3]>>),
+  /// New display color of a feature already sent, after its classification was refined
+  FeatureColorUpdate {
+    id: u32,
+    r: u8,
+    g: u8,
+    b: u8,
+  },
+  /// Mock sensor returns of the rendered features, only ever sent by the simulator
+  SensorData(Vec<SensorReading>),
+  /// Request for the application, such as a screenshot, only ever sent by the robot
+  Command(AppCommand),
+}
+
+/// Messages that may carry an `AppCommand` for the event loop rather than data for the frame loop.
+pub trait CommandMessage: Sized {
+  /// The command carried, or the message back if it carries none.
+  fn into_command(self) -> Result<AppCommand, Self>;
+}
+
+impl CommandMessage for SimulatorMessage {
+  fn into_command(self) -> Result<AppCommand, Self> {
+    match self {
+      SimulatorMessage::Command(command) => Ok(command),
+      other => Err(other),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+  Connected,
+  Disconnected,
+}
+
+#[derive(Debug)]
+pub enum EncodeError {
+  Json(serde_json::Error),
+  Binary(rmp_serde::encode::Error),
+}
+
+impl fmt::Display for EncodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      EncodeError::Json(err) => write!(f, "json: {}", err),
+      EncodeError::Binary(err) => write!(f, "binary: {}", err),
+    }
+  }
+}
+
+impl From<serde_json::Error> for EncodeError {
+  fn from(err: serde_json::Error) -> Self {
+    EncodeError::Json(err)
+  }
+}
+
+impl From<rmp_serde::encode::Error> for EncodeError {
+  fn from(err: rmp_serde::encode::Error) -> Self {
+    EncodeError::Binary(err)
+  }
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+  /// Fewer bytes than the 4-byte length header
+  MissingHeader(usize),
+  /// Payload length disagreeing with the header
+  Length {
+    header: usize,
+    payload: usize,
+  },
+  Payload(rmp_serde::decode::Error),
+}
 
-pub struct Message;
+impl fmt::Display for FrameError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FrameError::MissingHeader(len) => write!(f, "{} byte frame is too short for its header", len),
+      FrameError::Length { header, payload } => {
+        write!(f, "header gives {} bytes but the payload has {}", header, payload)
+      }
+      FrameError::Payload(err) => write!(f, "invalid payload: {}", err),
+    }
+  }
+}
 
-pub struct Client {
-  _send_queue: UnboundedSender<Message>,
-  receive_queue: Mutex<UnboundedReceiver<Message>>,
+impl From<rmp_serde::decode::Error> for FrameError {
+  fn from(err: rmp_serde::decode::Error) -> Self {
+    FrameError::Payload(err)
+  }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+  Json(serde_json::Error),
+  Binary(rmp_serde::decode::Error),
+  Frame(FrameError),
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DecodeError::Json(err) => write!(f, "json: {}", err),
+      DecodeError::Binary(err) => write!(f, "binary: {}", err),
+      DecodeError::Frame(err) => write!(f, "frame: {}", err),
+    }
+  }
 }
 
-impl Client {
-  pub async fn new() -> Result<Self, Error> {
+impl From<FrameError> for DecodeError {
+  fn from(err: FrameError) -> Self {
+    DecodeError::Frame(err)
+  }
+}
+
+impl From<serde_json::Error> for DecodeError {
+  fn from(err: serde_json::Error) -> Self {
+    DecodeError::Json(err)
+  }
+}
+
+impl From<rmp_serde::decode::Error> for DecodeError {
+  fn from(err: rmp_serde::decode::Error) -> Self {
+    DecodeError::Binary(err)
+  }
+}
+
+/// Turns outgoing messages into websocket frame payloads.
+pub trait MessageEncoder<M>: Send + Sync {
+  fn encode(&self, msg: &M) -> Result<Vec<u8>, EncodeError>;
+
+  /// Whether the payload is UTF-8 and should go out as a text frame rather than a binary one.
+  fn is_text(&self) -> bool {
+    false
+  }
+}
+
+/// Parses incoming frame payloads. Text frames are handed over as raw bytes too unless `decode_text` is overridden.
+pub trait MessageDecoder<M>: Send + Sync {
+  fn decode(&self, bytes: &[u8]) -> Result<M, DecodeError>;
+
+  fn decode_text(&self, text: &str) -> Result<M, DecodeError> {
+    self.decode(text.as_bytes())
+  }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonEncoder;
+
+impl<M: Serialize> MessageEncoder<M> for JsonEncoder {
+  fn encode(&self, msg: &M) -> Result<Vec<u8>, EncodeError> {
+    Ok(serde_json::to_vec(msg)?)
+  }
+
+  fn is_text(&self) -> bool {
+    true
+  }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonDecoder;
+
+impl<M: DeserializeOwned> MessageDecoder<M> for JsonDecoder {
+  fn decode(&self, bytes: &[u8]) -> Result<M, DecodeError> {
+    Ok(serde_json::from_slice(bytes)?)
+  }
+}
+
+/// Compact MessagePack encoding for high-rate feature streams.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BinaryEncoder;
+
+impl<M: Serialize> MessageEncoder<M> for BinaryEncoder {
+  fn encode(&self, msg: &M) -> Result<Vec<u8>, EncodeError> {
+    Ok(rmp_serde::to_vec(msg)?)
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BinaryDecoder;
+
+impl<M: DeserializeOwned> MessageDecoder<M> for BinaryDecoder {
+  fn decode(&self, bytes: &[u8]) -> Result<M, DecodeError> {
+    Ok(rmp_serde::from_slice(bytes)?)
+  }
+}
+
+/// Binary frames holding a 4-byte little-endian payload length followed by the MessagePack payload. Text frames are
+/// still read as JSON so peers that only send JSON keep working.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BinaryFramer;
+
+impl BinaryFramer {
+  const HEADER_LEN: usize = 4;
+
+  pub fn encode<M: Serialize>(msg: &M) -> Result<Vec<u8>, EncodeError> {
+    let payload = rmp_serde::to_vec(msg)?;
+    let mut bytes = Vec::with_capacity(Self::HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+  }
+
+  pub fn decode<M: DeserializeOwned>(bytes: &[u8]) -> Result<M, FrameError> {
+    if bytes.len() < Self::HEADER_LEN {
+      return Err(FrameError::MissingHeader(bytes.len()));
+    }
+    let (header, payload) = bytes.split_at(Self::HEADER_LEN);
+    let header = u32::from_le_bytes(header.try_into().unwrap()) as usize;
+    if header != payload.len() {
+      return Err(FrameError::Length {
+        header,
+        payload: payload.len(),
+      });
+    }
+    Ok(rmp_serde::from_slice(payload)?)
+  }
+}
+
+impl<M: Serialize> MessageEncoder<M> for BinaryFramer {
+  fn encode(&self, msg: &M) -> Result<Vec<u8>, EncodeError> {
+    BinaryFramer::encode(msg)
+  }
+}
+
+impl<M: DeserializeOwned> MessageDecoder<M> for BinaryFramer {
+  fn decode(&self, bytes: &[u8]) -> Result<M, DecodeError> {
+    Ok(BinaryFramer::decode(bytes)?)
+  }
+
+  fn decode_text(&self, text: &str) -> Result<M, DecodeError> {
+    Ok(serde_json::from_str(text)?)
+  }
+}
+
+/// Ping payload: the time it was sent as little-endian microseconds since the Unix epoch.
+fn ping_payload(now: SystemTime) -> Vec<u8> {
+  let micros = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+  micros.to_le_bytes().to_vec()
+}
+
+/// Microseconds since the ping carrying `payload` was sent, or `None` if it isn't one of ours.
+fn round_trip_micros(payload: &[u8], now: SystemTime) -> Option<u64> {
+  let sent = u64::from_le_bytes(payload.try_into().ok()?);
+  let now = now.duration_since(UNIX_EPOCH).ok()?.as_micros() as u64;
+  Some(now.saturating_sub(sent))
+}
+
+/// Data frames and their payload bytes passed through a `Client`, shared with its send and receive tasks. Pings and
+/// pongs aren't counted.
+#[derive(Debug, Default)]
+pub struct ClientStats {
+  pub messages_sent: AtomicU64,
+  pub messages_received: AtomicU64,
+  pub bytes_sent: AtomicU64,
+  pub bytes_received: AtomicU64,
+}
+
+/// `ClientStats` at one point in time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClientStatsSnapshot {
+  pub messages_sent: u64,
+  pub messages_received: u64,
+  pub bytes_sent: u64,
+  pub bytes_received: u64,
+}
+
+impl ClientStats {
+  fn count_sent(&self, message: &Message) {
+    self.messages_sent.fetch_add(1, Ordering::Relaxed);
+    self.bytes_sent.fetch_add(message.len() as u64, Ordering::Relaxed);
+  }
+
+  fn count_received(&self, message: &Message) {
+    self.messages_received.fetch_add(1, Ordering::Relaxed);
+    self.bytes_received.fetch_add(message.len() as u64, Ordering::Relaxed);
+  }
+
+  pub fn snapshot(&self) -> ClientStatsSnapshot {
+    ClientStatsSnapshot {
+      messages_sent: self.messages_sent.load(Ordering::Relaxed),
+      messages_received: self.messages_received.load(Ordering::Relaxed),
+      bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+      bytes_received: self.bytes_received.load(Ordering::Relaxed),
+    }
+  }
+
+  #[allow(dead_code)]
+  pub fn reset(&self) {
+    for counter in [
+      &self.messages_sent,
+      &self.messages_received,
+      &self.bytes_sent,
+      &self.bytes_received,
+    ] {
+      counter.store(0, Ordering::Relaxed);
+    }
+  }
+}
+
+pub struct Client<M, E, D> {
+  send_queue: UnboundedSender<M>,
+  receive_queue: Mutex<UnboundedReceiver<M>>,
+  connected: Arc<AtomicBool>,
+  /// Microseconds between the last answered ping and its pong
+  round_trip: Arc<AtomicU64>,
+  stats: Arc<ClientStats>,
+  _codec: PhantomData<(E, D)>,
+}
+
+#[allow(dead_code)]
+pub type JsonClient = Client<SimulatorMessage, JsonEncoder, JsonDecoder>;
+#[allow(dead_code)]
+pub type BinaryClient = Client<SimulatorMessage, BinaryEncoder, BinaryDecoder>;
+pub type FramedClient = Client<SimulatorMessage, BinaryFramer, BinaryFramer>;
+
+impl<M, E, D> Client<M, E, D>
+where
+  M: CommandMessage + Send + 'static,
+  E: MessageEncoder<M> + 'static,
+  D: MessageDecoder<M> + 'static,
+{
+  /// Connects to the robot. Commands it sends go straight to `commands` when given, so they arrive between frames even
+  /// while updates are paused, and are queued with the other messages otherwise.
+  #[allow(clippy::result_large_err)]
+  pub async fn new(encoder: E, decoder: D, commands: Option<EventLoopProxy<AppCommand>>) -> Result<Self, Error> {
+    let (ws_stream, _) = connect_async("ws://127.0.0.1:9001").await?;
+    Ok(Self::from_stream(ws_stream, encoder, decoder, commands))
+  }
+
+  /// Exchanges messages over an open websocket, pinging the peer every `PING_INTERVAL` while it is connected.
+  fn from_stream<S>(ws_stream: S, encoder: E, decoder: D, commands: Option<EventLoopProxy<AppCommand>>) -> Self
+  where
+    S: Stream<Item = Result<Message, Error>> + Sink<Message, Error = Error> + Send + 'static,
+  {
     let (send_tx, send_rx) = futures::channel::mpsc::unbounded();
     let (receive_tx, receive_rx) = futures::channel::mpsc::unbounded();
-    let (ws_stream, _) = connect_async("ws://127.0.0.1:9001").await?;
     let (write, read) = ws_stream.split();
+    let connected = Arc::new(AtomicBool::new(true));
+    let round_trip = Arc::new(AtomicU64::new(NO_ROUND_TRIP));
+    let stats = Arc::new(ClientStats::default());
 
+    let read_connected = connected.clone();
+    let read_round_trip = round_trip.clone();
+    let read_stats = stats.clone();
     async_std::task::spawn(async move {
-      read.map(|_msg| Ok(Message {})).forward(receive_tx).await.unwrap();
+      let result = read
+        .filter_map(|msg| {
+          if let Ok(msg @ (Message::Text(_) | Message::Binary(_))) = &msg {
+            read_stats.count_received(msg);
+          }
+          let decoded = match msg {
+            Ok(Message::Text(text)) => Some(decoder.decode_text(&text)),
+            Ok(Message::Binary(bytes)) => Some(decoder.decode(&bytes)),
+            Ok(Message::Pong(payload)) => {
+              if let Some(micros) = round_trip_micros(&payload, SystemTime::now()) {
+                read_round_trip.store(micros, Ordering::Relaxed);
+              }
+              None
+            }
+            _ => None,
+          };
+          let decoded =
+            decoded.and_then(|decoded| decoded.map_err(|err| eprintln!("invalid WS message: '{}'", err)).ok());
+          let message = match (decoded, &commands) {
+            (Some(decoded), Some(commands)) => match decoded.into_command() {
+              Ok(command) => {
+                if commands.send_event(command).is_err() {
+                  eprintln!("failed to pass on WS command: the event loop has exited");
+                }
+                None
+              }
+              Err(decoded) => Some(decoded),
+            },
+            (decoded, _) => decoded,
+          };
+          futures::future::ready(message)
+        })
+        .map(Ok)
+        .forward(receive_tx)
+        .await;
+      read_connected.store(false, Ordering::Relaxed);
+      result.unwrap();
     });
 
+    let ping_connected = connected.clone();
+    let send_stats = stats.clone();
     async_std::task::spawn(async move {
-      send_rx
-        .map(|_msg| Ok(tungstenite::Message::Text("hello world".into())))
-        .forward(write)
-        .await
-        .unwrap();
+      // Messages that fail to encode are skipped, so one bad message cannot close the connection
+      let messages = send_rx.filter_map(move |msg| {
+        let message = match encoder.encode(&msg) {
+          Ok(bytes) if encoder.is_text() => match String::from_utf8(bytes) {
+            Ok(text) => Some(Message::Text(text)),
+            Err(err) => {
+              eprintln!("failed to encode WS message as text: '{}'", err);
+              None
+            }
+          },
+          Ok(bytes) => Some(Message::Binary(bytes)),
+          Err(err) => {
+            eprintln!("failed to encode WS message: '{}'", err);
+            None
+          }
+        };
+        if let Some(message) = &message {
+          send_stats.count_sent(message);
+        }
+        futures::future::ready(message)
+      });
+      // The first ping goes out right away so latency is known shortly after connecting
+      let pings = futures::stream::unfold(true, move |first| {
+        let connected = ping_connected.clone();
+        async move {
+          if !first {
+            async_std::task::sleep(PING_INTERVAL).await;
+          }
+          if connected.load(Ordering::Relaxed) {
+            Some((Message::Ping(ping_payload(SystemTime::now())), false))
+          } else {
+            None
+          }
+        }
+      });
+      if let Err(err) = futures::stream::select(messages, pings).map(Ok).forward(write).await {
+        eprintln!("failed to send WS message: '{}'", err);
+      }
     });
 
-    Ok(Self {
-      _send_queue: send_tx,
+    Self {
+      send_queue: send_tx,
       receive_queue: Mutex::new(receive_rx),
-    })
+      connected,
+      round_trip,
+      stats,
+      _codec: PhantomData,
+    }
+  }
+
+  /// Whether the robot is still sending; the connection is never re-established once closed.
+  pub fn state(&self) -> ConnectionState {
+    if self.connected.load(Ordering::Relaxed) {
+      ConnectionState::Connected
+    } else {
+      ConnectionState::Disconnected
+    }
+  }
+
+  /// Half the last ping round trip in milliseconds, or zero before the first pong.
+  pub fn latency_ms(&self) -> f32 {
+    self
+      .round_trip_micros()
+      .map_or(0.0, |round_trip| round_trip as f32 / 2.0 / 1000.0)
+  }
+
+  fn round_trip_micros(&self) -> Option<u64> {
+    match self.round_trip.load(Ordering::Relaxed) {
+      NO_ROUND_TRIP => None,
+      round_trip => Some(round_trip),
+    }
+  }
+
+  pub fn stats(&self) -> ClientStatsSnapshot {
+    self.stats.snapshot()
   }
 
-  pub fn _send(&self, message: Message) {
-    self._send_queue.unbounded_send(message).unwrap();
+  /// Counters behind `stats`, for resetting them.
+  #[allow(dead_code)]
+  pub fn shared_stats(&self) -> Arc<ClientStats> {
+    self.stats.clone()
   }
 
-  pub fn stream(&self) -> MutexGuard<UnboundedReceiver<Message>> {
+  /// Queues `message` to be sent, failing with the message once the connection is gone and nothing sends anymore.
+  pub fn send(&self, message: M) -> Result<(), TrySendError<M>> {
+    self.send_queue.unbounded_send(message)
+  }
+
+  #[allow(dead_code)]
+  pub fn stream(&self) -> MutexGuard<'_, UnboundedReceiver<M>> {
     self.receive_queue.lock().unwrap()
   }
+
+  /// Every message received so far, taking the lock once.
+  #[allow(dead_code)]
+  pub fn drain_messages(&self) -> Vec<M> {
+    self.drain_messages_bounded(usize::MAX)
+  }
+
+  /// Up to `max` of the oldest messages received so far. The rest stay queued for the next call.
+  pub fn drain_messages_bounded(&self, max: usize) -> Vec<M> {
+    let mut queue = self.stream();
+    let mut messages = Vec::new();
+    while messages.len() < max {
+      match queue.try_next() {
+        Ok(Some(message)) => messages.push(message),
+        _ => break,
+      }
+    }
+    messages
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn feature_update_json_test() {
+    let json = r#"{
+      "type": "FeatureUpdate",
+      "data": [{
+        "id": 3, "n": 2, "age": 0,
+        "color": {"x": 255, "y": 0, "z": 0},
+        "position_mean": {"x": 1.0, "y": 2.0, "z": 3.0},
+        "position_deviation": {"x": 0.1, "y": 0.1, "z": 0.1},
+        "orientation_mean": {"x": 0.0, "y": 0.0, "z": 1.0},
+        "orientation_deviation": 0.0,
+        "radius_mean": 0.5,
+        "radius_deviation": 0.05,
+        "material": 1
+      }]
+    }"#;
+    let features = match serde_json::from_str(json).unwrap() {
+      SimulatorMessage::FeatureUpdate(features) => features,
+      other => panic!("unexpected message {:?}", other),
+    };
+    assert_eq!(features.len(), 1);
+    assert_eq!(features[0].id, 3);
+    assert_eq!(features[0].position_mean, (1.0, 2.0, 3.0).into());
+    assert_eq!(features[0].dataset, "default");
+  }
+
+  #[test]
+  fn path_update_json_test() {
+    let json = r#"{"type": "PathUpdate", "data": [[[0.0, 0.0, 0.0], [1.0, 0.0, 2.0]], []]}"#;
+    let paths = match serde_json::from_str(json).unwrap() {
+      SimulatorMessage::PathUpdate(paths) => paths,
+      other => panic!("unexpected message {:?}", other),
+    };
+    assert_eq!(paths, vec![vec![[0.0, 0.0, 0.0], [1.0, 0.0, 2.0]], vec![]]);
+  }
+
+  #[test]
+  fn feature_color_update_json_test() {
+    let json = r#"{"type": "FeatureColorUpdate", "data": {"id": 4, "r": 0, "g": 128, "b": 255}}"#;
+    assert!(matches!(
+      serde_json::from_str(json).unwrap(),
+      SimulatorMessage::FeatureColorUpdate {
+        id: 4,
+        r: 0,
+        g: 128,
+        b: 255
+      }
+    ));
+  }
+
+  #[test]
+  fn json_codec_round_trip_test() {
+    let message = SimulatorMessage::PathUpdate(vec![vec![[1.0, 2.0, 3.0]]]);
+    let bytes = JsonEncoder.encode(&message).unwrap();
+    assert!(std::str::from_utf8(&bytes)
+      .unwrap()
+      .starts_with(r#"{"type":"PathUpdate""#));
+    match JsonDecoder.decode(&bytes).unwrap() {
+      SimulatorMessage::PathUpdate(paths) => assert_eq!(paths, vec![vec![[1.0, 2.0, 3.0]]]),
+      other => panic!("unexpected message {:?}", other),
+    }
+  }
+
+  #[test]
+  fn binary_codec_round_trip_test() {
+    let message = SimulatorMessage::PathUpdate(vec![vec![[1.0, 2.0, 3.0]], vec![]]);
+    let bytes = BinaryEncoder.encode(&message).unwrap();
+    match BinaryDecoder.decode(&bytes).unwrap() {
+      SimulatorMessage::PathUpdate(paths) => assert_eq!(paths, vec![vec![[1.0, 2.0, 3.0]], vec![]]),
+      other => panic!("unexpected message {:?}", other),
+    }
+    let result: Result<SimulatorMessage, _> = BinaryDecoder.decode(&[0xff]);
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn sensor_data_round_trip_test() {
+    let readings = vec![SensorReading {
+      timestamp_us: 1_650_000_000_000_000,
+      sensor_id: 0,
+      range: 2.5,
+      bearing: -0.5,
+      elevation: 0.25,
+      intensity: 0.04,
+    }];
+    let message = SimulatorMessage::SensorData(readings.clone());
+    let json = String::from_utf8(JsonEncoder.encode(&message).unwrap()).unwrap();
+    assert!(json.starts_with(r#"{"type":"SensorData","data":[{"timestamp_us":1650000000000000,"#));
+    let decoded = [
+      JsonDecoder.decode(json.as_bytes()).unwrap(),
+      BinaryFramer::decode(&BinaryFramer::encode(&message).unwrap()).unwrap(),
+    ];
+    for decoded in decoded {
+      match decoded {
+        SimulatorMessage::SensorData(decoded) => assert_eq!(decoded, readings),
+        other => panic!("unexpected message {:?}", other),
+      }
+    }
+  }
+
+  #[test]
+  fn binary_framer_round_trip_test() {
+    let features: Vec<Feature> = (0..1000)
+      .map(|id| Feature {
+        id,
+        n: 1,
+        age: 0,
+        color: (255, 128, 0).into(),
+        position_mean: (id as f32, 0.0, -1.0).into(),
+        position_deviation: (0.1, 0.1, 0.1).into(),
+        orientation_mean: (0.0, 0.0, 1.0).into(),
+        orientation_deviation: 0.0,
+        radius_mean: 0.5,
+        radius_deviation: 0.05,
+        material: 1,
+        dataset: "default".into(),
+      })
+      .collect();
+    let bytes = BinaryFramer::encode(&SimulatorMessage::FeatureUpdate(features.clone())).unwrap();
+    assert_eq!(
+      u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize,
+      bytes.len() - 4
+    );
+    match BinaryFramer::decode(&bytes).unwrap() {
+      SimulatorMessage::FeatureUpdate(decoded) => assert_eq!(decoded, features),
+      other => panic!("unexpected message {:?}", other),
+    }
+
+    assert!(matches!(
+      BinaryFramer::decode::<SimulatorMessage>(&bytes[..3]),
+      Err(FrameError::MissingHeader(3))
+    ));
+    assert!(matches!(
+      BinaryFramer::decode::<SimulatorMessage>(&bytes[..bytes.len() - 1]),
+      Err(FrameError::Length { .. })
+    ));
+  }
+
+  #[test]
+  fn binary_framer_text_test() {
+    let message: SimulatorMessage = BinaryFramer
+      .decode_text(r#"{"type": "PathUpdate", "data": [[[1.0, 2.0, 3.0]]]}"#)
+      .unwrap();
+    assert!(matches!(message, SimulatorMessage::PathUpdate(paths) if paths == vec![vec![[1.0, 2.0, 3.0]]]));
+  }
+
+  #[test]
+  fn round_trip_micros_test() {
+    let sent = UNIX_EPOCH + Duration::from_micros(1_650_000_000_123_456);
+    let payload = ping_payload(sent);
+    assert_eq!(payload.len(), 8);
+    assert_eq!(
+      round_trip_micros(&payload, sent + Duration::from_micros(2500)),
+      Some(2500)
+    );
+    // Clock steps backwards never give a negative round trip
+    assert_eq!(round_trip_micros(&payload, sent - Duration::from_secs(1)), Some(0));
+    assert_eq!(round_trip_micros(b"pong", sent), None);
+  }
+
+  #[test]
+  fn latency_test() {
+    async_std::task::block_on(async {
+      let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      // The peer only reads, which answers every ping with a pong
+      async_std::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws_stream = async_tungstenite::accept_async(stream).await.unwrap();
+        while let Some(Ok(_)) = ws_stream.next().await {}
+      });
+      let (ws_stream, _) = connect_async(format!("ws://{}", address)).await.unwrap();
+      let client: JsonClient = Client::from_stream(ws_stream, JsonEncoder, JsonDecoder, None);
+      for _ in 0..200 {
+        if client.round_trip_micros().is_some() {
+          break;
+        }
+        async_std::task::sleep(Duration::from_millis(10)).await;
+      }
+      let round_trip = client.round_trip_micros().expect("pong within two seconds");
+      assert!(round_trip < 2_000_000);
+      assert_eq!(client.latency_ms(), round_trip as f32 / 2000.0);
+      assert_eq!(client.state(), ConnectionState::Connected);
+    });
+  }
+
+  #[test]
+  fn send_after_close_test() {
+    async_std::task::block_on(async {
+      let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      let server = async_std::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        async_tungstenite::accept_async(stream).await.unwrap()
+      });
+      let (ws_stream, _) = connect_async(format!("ws://{}", address)).await.unwrap();
+      let client: JsonClient = Client::from_stream(ws_stream, JsonEncoder, JsonDecoder, None);
+      drop(server.await);
+
+      let mut result = Ok(());
+      for _ in 0..200 {
+        result = client.send(SimulatorMessage::FeatureColorUpdate {
+          id: 0,
+          r: 0,
+          g: 0,
+          b: 0,
+        });
+        if result.is_err() {
+          break;
+        }
+        async_std::task::sleep(Duration::from_millis(10)).await;
+      }
+      let err = result.expect_err("sending to fail within two seconds of the server closing");
+      assert!(matches!(
+        err.into_inner(),
+        SimulatorMessage::FeatureColorUpdate { id: 0, .. }
+      ));
+    });
+  }
+
+  #[test]
+  fn drain_messages_bounded_test() {
+    async_std::task::block_on(async {
+      let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      let server = async_std::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let ws_stream = async_tungstenite::accept_async(stream).await.unwrap();
+        Client::from_stream(ws_stream, JsonEncoder, JsonDecoder, None)
+      });
+      let (ws_stream, _) = connect_async(format!("ws://{}", address)).await.unwrap();
+      let sender: JsonClient = Client::from_stream(ws_stream, JsonEncoder, JsonDecoder, None);
+      let receiver: JsonClient = server.await;
+
+      for id in 0..200 {
+        sender
+          .send(SimulatorMessage::FeatureColorUpdate { id, r: 0, g: 0, b: 0 })
+          .unwrap();
+      }
+      // Messages are counted just before they are queued, so all but the last are waiting once every one is counted
+      for _ in 0..200 {
+        if receiver.stats().messages_received == 200 {
+          break;
+        }
+        async_std::task::sleep(Duration::from_millis(10)).await;
+      }
+      let first = receiver.drain_messages_bounded(100);
+      assert_eq!(first.len(), 100);
+      assert!(matches!(first[0], SimulatorMessage::FeatureColorUpdate { id: 0, .. }));
+      let mut rest = Vec::new();
+      for _ in 0..200 {
+        rest.extend(receiver.drain_messages());
+        if rest.len() >= 100 {
+          break;
+        }
+        async_std::task::sleep(Duration::from_millis(10)).await;
+      }
+      assert_eq!(rest.len(), 100);
+      assert!(matches!(rest[0], SimulatorMessage::FeatureColorUpdate { id: 100, .. }));
+      assert!(receiver.drain_messages().is_empty());
+    });
+  }
+
+  #[test]
+  fn client_stats_test() {
+    async_std::task::block_on(async {
+      let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      let server = async_std::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let ws_stream = async_tungstenite::accept_async(stream).await.unwrap();
+        Client::from_stream(ws_stream, JsonEncoder, JsonDecoder, None)
+      });
+      let (ws_stream, _) = connect_async(format!("ws://{}", address)).await.unwrap();
+      let sender: JsonClient = Client::from_stream(ws_stream, JsonEncoder, JsonDecoder, None);
+      let receiver: JsonClient = server.await;
+
+      let message = SimulatorMessage::PathUpdate(vec![vec![[1.0, 2.0, 3.0]]]);
+      let len = JsonEncoder.encode(&message).unwrap().len() as u64;
+      sender.send(message).unwrap();
+      let mut received = None;
+      for _ in 0..200 {
+        if let Ok(Some(message)) = receiver.stream().try_next() {
+          received = Some(message);
+          break;
+        }
+        async_std::task::sleep(Duration::from_millis(10)).await;
+      }
+      assert!(matches!(received, Some(SimulatorMessage::PathUpdate(_))));
+
+      let expected = ClientStatsSnapshot {
+        messages_sent: 1,
+        bytes_sent: len,
+        ..ClientStatsSnapshot::default()
+      };
+      assert_eq!(sender.stats(), expected);
+      assert_eq!(
+        receiver.stats(),
+        ClientStatsSnapshot {
+          messages_received: 1,
+          bytes_received: len,
+          ..ClientStatsSnapshot::default()
+        }
+      );
+      sender.shared_stats().reset();
+      assert_eq!(sender.stats(), ClientStatsSnapshot::default());
+    });
+  }
+
+  /// JSON encoder failing on the color updates of feature 0.
+  struct FailingEncoder;
+
+  impl MessageEncoder<SimulatorMessage> for FailingEncoder {
+    fn encode(&self, msg: &SimulatorMessage) -> Result<Vec<u8>, EncodeError> {
+      match msg {
+        SimulatorMessage::FeatureColorUpdate { id: 0, .. } => Err(serde_json::from_str::<u8>("").unwrap_err().into()),
+        msg => JsonEncoder.encode(msg),
+      }
+    }
+
+    fn is_text(&self) -> bool {
+      true
+    }
+  }
+
+  #[test]
+  fn send_encode_error_test() {
+    async_std::task::block_on(async {
+      let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      let server = async_std::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let ws_stream = async_tungstenite::accept_async(stream).await.unwrap();
+        Client::from_stream(ws_stream, JsonEncoder, JsonDecoder, None)
+      });
+      let (ws_stream, _) = connect_async(format!("ws://{}", address)).await.unwrap();
+      let sender = Client::from_stream(ws_stream, FailingEncoder, JsonDecoder, None);
+      let receiver: JsonClient = server.await;
+
+      // The message that fails to encode is skipped and the connection stays up for the next one
+      for id in 0..2 {
+        sender
+          .send(SimulatorMessage::FeatureColorUpdate { id, r: 0, g: 0, b: 0 })
+          .unwrap();
+      }
+      let mut received = Vec::new();
+      for _ in 0..200 {
+        received.extend(receiver.drain_messages());
+        if !received.is_empty() {
+          break;
+        }
+        async_std::task::sleep(Duration::from_millis(10)).await;
+      }
+      assert!(matches!(
+        received[..],
+        [SimulatorMessage::FeatureColorUpdate { id: 1, .. }]
+      ));
+      assert_eq!(sender.stats().messages_sent, 1);
+      assert_eq!(sender.state(), ConnectionState::Connected);
+    });
+  }
 }
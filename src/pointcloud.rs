@@ -0,0 +1,142 @@
+use super::featuredb::Feature;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ExportError {
+  Io(std::io::Error),
+  Database(rusqlite::Error),
+}
+
+impl fmt::Display for ExportError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ExportError::Io(err) => write!(f, "failed to write point cloud: '{}'", err),
+      ExportError::Database(err) => write!(f, "failed to read features: '{}'", err),
+    }
+  }
+}
+
+impl From<std::io::Error> for ExportError {
+  fn from(other: std::io::Error) -> Self {
+    ExportError::Io(other)
+  }
+}
+
+impl From<rusqlite::Error> for ExportError {
+  fn from(other: rusqlite::Error) -> Self {
+    ExportError::Database(other)
+  }
+}
+
+/// Writes features as point clouds readable by tools such as CloudCompare or PCL. Each feature becomes one point at
+/// its mean position, with its orientation as the normal.
+pub struct PointCloudWriter<W: Write> {
+  writer: W,
+}
+
+impl PointCloudWriter<BufWriter<File>> {
+  pub fn create(path: &Path) -> Result<Self, ExportError> {
+    Ok(Self::new(BufWriter::new(File::create(path)?)))
+  }
+}
+
+impl<W: Write> PointCloudWriter<W> {
+  pub fn new(writer: W) -> Self {
+    Self { writer }
+  }
+
+  /// Writes an ASCII PCD v0.7 file.
+  pub fn write_pcd(&mut self, features: &[Feature]) -> Result<(), ExportError> {
+    writeln!(self.writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+    writeln!(self.writer, "VERSION 0.7")?;
+    writeln!(self.writer, "FIELDS x y z normal_x normal_y normal_z r g b")?;
+    writeln!(self.writer, "SIZE 4 4 4 4 4 4 1 1 1")?;
+    writeln!(self.writer, "TYPE F F F F F F U U U")?;
+    writeln!(self.writer, "COUNT 1 1 1 1 1 1 1 1 1")?;
+    writeln!(self.writer, "WIDTH {}", features.len())?;
+    writeln!(self.writer, "HEIGHT 1")?;
+    writeln!(self.writer, "VIEWPOINT 0 0 0 1 0 0 0")?;
+    writeln!(self.writer, "POINTS {}", features.len())?;
+    writeln!(self.writer, "DATA ascii")?;
+    for feature in features {
+      let position = feature.position_mean;
+      let normal = feature.orientation_mean;
+      let color = feature.color;
+      writeln!(
+        self.writer,
+        "{} {} {} {} {} {} {} {} {}",
+        position.x, position.y, position.z, normal.x, normal.y, normal.z, color.x, color.y, color.z
+      )?;
+    }
+    self.writer.flush()?;
+    Ok(())
+  }
+
+  /// Writes a binary little-endian PLY file.
+  pub fn write_ply(&mut self, features: &[Feature]) -> Result<(), ExportError> {
+    writeln!(self.writer, "ply")?;
+    writeln!(self.writer, "format binary_little_endian 1.0")?;
+    writeln!(self.writer, "element vertex {}", features.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz"] {
+      writeln!(self.writer, "property float {}", property)?;
+    }
+    for property in ["red", "green", "blue"] {
+      writeln!(self.writer, "property uchar {}", property)?;
+    }
+    writeln!(self.writer, "end_header")?;
+    for feature in features {
+      let position = feature.position_mean;
+      let normal = feature.orientation_mean;
+      for value in [position.x, position.y, position.z, normal.x, normal.y, normal.z] {
+        self.writer.write_all(&value.to_le_bytes())?;
+      }
+      self
+        .writer
+        .write_all(&[feature.color.x, feature.color.y, feature.color.z])?;
+    }
+    self.writer.flush()?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn feature() -> Feature {
+    Feature::mock()
+      .with_id(1)
+      .with_position((1.0, 2.0, 3.0))
+      .with_color((255, 128, 0))
+  }
+
+  #[test]
+  fn write_pcd_test() {
+    let mut output = Vec::new();
+    PointCloudWriter::new(&mut output).write_pcd(&[feature()]).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("POINTS 1\n"));
+    assert!(output.ends_with("DATA ascii\n1 2 3 0 0 1 255 128 0\n"));
+  }
+
+  #[test]
+  fn write_ply_test() {
+    let mut output = Vec::new();
+    PointCloudWriter::new(&mut output)
+      .write_ply(&[feature(), feature()])
+      .unwrap();
+    let header_end = b"end_header\n";
+    let body = output
+      .windows(header_end.len())
+      .position(|window| window == header_end)
+      .map(|idx| &output[idx + header_end.len()..])
+      .unwrap();
+    assert_eq!(body.len(), 2 * (6 * 4 + 3));
+    assert_eq!(&body[0..4], &1.0f32.to_le_bytes());
+    assert_eq!(&body[24..27], &[255, 128, 0]);
+  }
+}
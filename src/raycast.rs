@@ -1,5 +1,40 @@
 use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, MetricSpace, Point3, SquareMatrix, Vector3};
 use roots::Roots;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum SceneError {
+  Io(std::io::Error),
+  Json(serde_json::Error),
+}
+
+impl fmt::Display for SceneError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SceneError::Io(err) => write!(f, "failed to access scene: '{}'", err),
+      SceneError::Json(err) => write!(f, "invalid scene: '{}'", err),
+    }
+  }
+}
+
+impl From<std::io::Error> for SceneError {
+  fn from(other: std::io::Error) -> Self {
+    SceneError::Io(other)
+  }
+}
+
+impl From<serde_json::Error> for SceneError {
+  fn from(other: serde_json::Error) -> Self {
+    SceneError::Json(other)
+  }
+}
 
 #[derive(Debug, PartialEq)]
 pub enum Clipped<T: Sized> {
@@ -13,12 +48,10 @@ pub trait Clip: Sized {
 
 impl Clip for Point3<f32> {
   fn clip(self, plane: &Plane) -> Clipped<Point3<f32>> {
-    let v = self - plane.position;
-    let dist = v.dot(plane.normal);
-    if dist >= 0.0 {
+    if plane.signed_distance(self) >= 0.0 {
       Clipped::Inside(self)
     } else {
-      Clipped::Outside(self - dist * plane.normal)
+      Clipped::Outside(plane.project_onto(self))
     }
   }
 }
@@ -41,6 +74,24 @@ impl Ray {
   pub fn delta(&self) -> Vector3<f32> {
     self.target - self.eye
   }
+
+  /// Parameter `t` of the projection of `point` onto the ray, where `eye` is at 0 and `target` at 1.
+  pub fn parameter(&self, point: Point3<f32>) -> f32 {
+    let delta = self.delta();
+    (point - self.eye).dot(delta) / delta.dot(delta)
+  }
+
+  /// Distance from `point` to the line through `eye` and `target`.
+  pub fn distance_to_point(&self, point: Point3<f32>) -> f32 {
+    let delta = self.delta();
+    (point - self.eye).cross(delta).magnitude() / delta.magnitude()
+  }
+
+  /// Point on the ray nearest to `point`, which is `eye` for points behind it.
+  #[allow(dead_code)]
+  pub fn closest_point_to(&self, point: Point3<f32>) -> Point3<f32> {
+    self.eye + self.parameter(point).max(0.0) * self.delta()
+  }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -71,6 +122,63 @@ pub enum IntersectResult {
 }
 
 impl IntersectResult {
+  /// Applies `f` to every hit, keeping the variant.
+  #[allow(dead_code)]
+  pub fn map<F: Fn(Intersection) -> Intersection>(self, f: F) -> Self {
+    match self {
+      IntersectResult::Miss => IntersectResult::Miss,
+      IntersectResult::HitOnce(hit) => IntersectResult::HitOnce(f(hit)),
+      IntersectResult::HitTwice(first, second) => IntersectResult::HitTwice(f(first), f(second)),
+    }
+  }
+
+  /// Whether the ray hit anything.
+  #[allow(dead_code)]
+  pub fn any_hit(&self) -> bool {
+    !matches!(self, IntersectResult::Miss)
+  }
+
+  /// Number of hits, from 0 to 2.
+  #[allow(dead_code)]
+  pub fn count(&self) -> usize {
+    match self {
+      IntersectResult::Miss => 0,
+      IntersectResult::HitOnce(_) => 1,
+      IntersectResult::HitTwice(_, _) => 2,
+    }
+  }
+
+  /// The hits in order, closest first.
+  pub fn hits(&self) -> impl Iterator<Item = &Intersection> {
+    let (first, second) = match self {
+      IntersectResult::Miss => (None, None),
+      IntersectResult::HitOnce(hit) => (Some(hit), None),
+      IntersectResult::HitTwice(first, second) => (Some(first), Some(second)),
+    };
+    first.into_iter().chain(second)
+  }
+
+  /// Drops hits closer to the eye than `min_t` along `ray`.
+  #[allow(dead_code)]
+  pub fn filter_t(self, min_t: f32, ray: &Ray) -> Self {
+    self.filter_by_t(min_t, f32::INFINITY, ray)
+  }
+
+  /// Keeps only the hits whose parameter along `ray` is within `[min_t, max_t]`, where `eye` is at 0 and `target` at
+  /// 1. Hits behind the eye have negative `t`.
+  #[allow(dead_code)]
+  pub fn filter_by_t(self, min_t: f32, max_t: f32, ray: &Ray) -> Self {
+    let mut hits = self
+      .hits()
+      .filter(|hit| (min_t..=max_t).contains(&ray.parameter(hit.position)))
+      .copied();
+    match (hits.next(), hits.next()) {
+      (Some(first), Some(second)) => IntersectResult::HitTwice(first, second),
+      (Some(hit), None) => IntersectResult::HitOnce(hit),
+      _ => IntersectResult::Miss,
+    }
+  }
+
   pub fn closest(self) -> Option<Intersection> {
     match self {
       IntersectResult::HitOnce(hit) => Some(hit),
@@ -93,11 +201,30 @@ pub trait Intersect {
   fn intersect(&self, ray: &Ray) -> IntersectResult;
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Plane {
   pub position: Point3<f32>,
   pub normal: Vector3<f32>,
 }
 
+/// Methods measure along `normal` and assume it is unit length.
+impl Plane {
+  /// Distance from the plane, positive on the side `normal` points to.
+  pub fn signed_distance(&self, p: Point3<f32>) -> f32 {
+    (p - self.position).dot(self.normal)
+  }
+
+  #[allow(dead_code)]
+  pub fn contains_point(&self, p: Point3<f32>, epsilon: f32) -> bool {
+    self.signed_distance(p).abs() <= epsilon
+  }
+
+  /// Closest point on the plane to `p`.
+  pub fn project_onto(&self, p: Point3<f32>) -> Point3<f32> {
+    p - self.signed_distance(p) * self.normal
+  }
+}
+
 impl Intersect for Plane {
   fn intersect(&self, ray: &Ray) -> IntersectResult {
     let delta = ray.delta();
@@ -126,6 +253,120 @@ impl Intersect for Plane {
   }
 }
 
+/// Axis-aligned box between the corners `min` and `max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+  pub min: Point3<f32>,
+  pub max: Point3<f32>,
+}
+
+impl Aabb {
+  #[allow(dead_code)]
+  pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+    Self { min, max }
+  }
+
+  /// Corner farthest along `direction`.
+  fn support(&self, direction: Vector3<f32>) -> Point3<f32> {
+    Point3::new(
+      if direction.x >= 0.0 { self.max.x } else { self.min.x },
+      if direction.y >= 0.0 { self.max.y } else { self.min.y },
+      if direction.z >= 0.0 { self.max.z } else { self.min.z },
+    )
+  }
+}
+
+/// The volume a camera sees, bounded by six planes whose normals point inwards: left, right, bottom, top, near and
+/// far.
+#[derive(Debug, Clone)]
+pub struct Frustum {
+  pub planes: [Plane; 6],
+}
+
+impl Frustum {
+  /// Extracts the planes of a world to wgpu clip space matrix, with depth from 0 at the near plane to 1 at the far
+  /// one, by adding and subtracting its rows (Gribb and Hartmann).
+  #[allow(dead_code)]
+  pub fn from_view_proj(matrix: Matrix4<f32>) -> Frustum {
+    let (x, y, z, w) = (matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3));
+    // Every `a x + b y + c z + d >= 0` half space, scaled to a unit normal
+    let plane = |coefficients: cgmath::Vector4<f32>| {
+      let normal = coefficients.truncate();
+      let length = normal.magnitude();
+      Plane {
+        position: Point3::from_vec(-coefficients.w / length * normal / length),
+        normal: normal / length,
+      }
+    };
+    Frustum {
+      planes: [
+        plane(w + x),
+        plane(w - x),
+        plane(w + y),
+        plane(w - y),
+        plane(z),
+        plane(w - z),
+      ],
+    }
+  }
+
+  /// Whether any of the ball of `radius` around `center` may be inside. Balls just outside a corner, where two
+  /// planes meet, count as inside.
+  #[allow(dead_code)]
+  pub fn contains_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+    self.planes.iter().all(|plane| plane.signed_distance(center) >= -radius)
+  }
+
+  /// Whether any of `aabb` may be inside, with the same leniency at the corners as `contains_sphere`.
+  #[allow(dead_code)]
+  pub fn contains_aabb(&self, aabb: &Aabb) -> bool {
+    self
+      .planes
+      .iter()
+      .all(|plane| plane.signed_distance(aabb.support(plane.normal)) >= 0.0)
+  }
+}
+
+/// Hits where the ray enters the frustum in front of its eye, or where it leaves if the eye is already inside.
+impl Intersect for Frustum {
+  fn intersect(&self, ray: &Ray) -> IntersectResult {
+    let delta = ray.delta();
+    let (mut enter, mut exit) = ((f32::NEG_INFINITY, None), (f32::INFINITY, None));
+    for plane in &self.planes {
+      let distance = plane.signed_distance(ray.eye);
+      let rate = delta.dot(plane.normal);
+      if rate == 0.0 {
+        if distance < 0.0 {
+          return IntersectResult::Miss;
+        }
+        continue;
+      }
+      let t = -distance / rate;
+      if rate > 0.0 && t > enter.0 {
+        enter = (t, Some(plane));
+      } else if rate < 0.0 && t < exit.0 {
+        exit = (t, Some(plane));
+      }
+    }
+    if enter.0 > exit.0 || exit.0 < 0.0 {
+      return IntersectResult::Miss;
+    }
+    // Normals face the eye, as with `Plane`
+    match (enter, exit) {
+      ((t, Some(plane)), _) if t >= 0.0 => IntersectResult::HitOnce(Intersection {
+        position: ray.eye + t * delta,
+        normal: -plane.normal,
+      }),
+      (_, (t, Some(plane))) => IntersectResult::HitOnce(Intersection {
+        position: ray.eye + t * delta,
+        normal: plane.normal,
+      }),
+      _ => IntersectResult::Miss,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ball {
   radius: f32,
 }
@@ -171,12 +412,30 @@ impl Intersect for Ball {
   }
 }
 
+/// Serialized as the flat column-major affine matrix; the derived matrices are rebuilt on load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "[f32; 16]", into = "[f32; 16]")]
 pub struct Transform {
   affine: Matrix4<f32>,
   normal: Matrix3<f32>,
   inverse_affine: Matrix4<f32>,
 }
 
+impl TryFrom<[f32; 16]> for Transform {
+  type Error = String;
+
+  fn try_from(other: [f32; 16]) -> Result<Self, Self::Error> {
+    let matrix: &Matrix4<f32> = (&other).into();
+    Transform::new(*matrix).ok_or_else(|| "transform is not invertible".to_owned())
+  }
+}
+
+impl From<Transform> for [f32; 16] {
+  fn from(other: Transform) -> Self {
+    *AsRef::<[f32; 16]>::as_ref(&other.affine)
+  }
+}
+
 impl Transform {
   pub fn new(transform: Matrix4<f32>) -> Option<Self> {
     let normal = Matrix3 {
@@ -204,22 +463,163 @@ impl Transform {
       normal: (self.normal * intersection.normal).normalize(),
     }
   }
+
+  /// The plane moved by this transform.
+  pub fn apply_plane(&self, plane: &Plane) -> Plane {
+    Plane {
+      position: Point3::from_homogeneous(self.affine * plane.position.to_homogeneous()),
+      normal: (self.normal * plane.normal).normalize(),
+    }
+  }
+
+  /// `self` applied after `inner`.
+  pub fn compose(&self, inner: &Transform) -> Option<Transform> {
+    Transform::new(self.affine * inner.affine)
+  }
+}
+
+/// Concrete shapes a `Model` can be built from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PrimitiveKind {
+  Ball(Ball),
+  Plane(Plane),
+}
+
+impl Intersect for PrimitiveKind {
+  fn intersect(&self, ray: &Ray) -> IntersectResult {
+    match self {
+      PrimitiveKind::Ball(ball) => ball.intersect(ray),
+      PrimitiveKind::Plane(plane) => plane.intersect(ray),
+    }
+  }
 }
 
 #[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Model {
-  Object(Box<dyn Intersect>),
+  Primitive(PrimitiveKind),
   Scene(Vec<Model>),
   Transform(Transform, Box<Model>),
   Clip(Plane, Box<Model>),
   And(Box<Model>, Box<Model>),
   Or(Box<Model>, Box<Model>),
+  /// Solid `A` with solid `B` carved out of it. Both must be closed surfaces.
+  Difference(Box<Model>, Box<Model>),
+  /// Solid filling all of space; hit at the ray's eye with the normal facing back along the ray.
+  Infinite,
 }
 
 impl Model {
+  /// Writes the scene to `path` as JSON.
+  #[allow(dead_code)]
+  pub fn save(&self, path: &Path) -> Result<(), SceneError> {
+    serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+    Ok(())
+  }
+
+  #[allow(dead_code)]
+  pub fn load(path: &Path) -> Result<Model, SceneError> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+  }
+
+  /// Copy of the model moved by `matrix`, equivalent to `Model::Transform(matrix, model)` but without the extra
+  /// level. Planes and clip planes are moved directly, nested transforms are composed with `matrix` and balls,
+  /// which are always centred on the origin, get a transform of their own. `None` if `matrix` isn't invertible.
+  pub fn transform_in_place(&self, matrix: Matrix4<f32>) -> Option<Model> {
+    self.transform_by(&Transform::new(matrix)?)
+  }
+
+  fn transform_by(&self, transform: &Transform) -> Option<Model> {
+    let boxed = |model: &Model| model.transform_by(transform).map(Box::new);
+    Some(match self {
+      Model::Primitive(PrimitiveKind::Plane(plane)) => {
+        Model::Primitive(PrimitiveKind::Plane(transform.apply_plane(plane)))
+      }
+      Model::Primitive(primitive) => Model::Transform(transform.clone(), Box::new(Model::Primitive(primitive.clone()))),
+      Model::Scene(list) => Model::Scene(
+        list
+          .iter()
+          .map(|model| model.transform_by(transform))
+          .collect::<Option<_>>()?,
+      ),
+      Model::Transform(inner, model) => Model::Transform(transform.compose(inner)?, model.clone()),
+      Model::Clip(plane, model) => Model::Clip(transform.apply_plane(plane), boxed(model)?),
+      Model::And(a, b) => Model::And(boxed(a)?, boxed(b)?),
+      Model::Or(a, b) => Model::Or(boxed(a)?, boxed(b)?),
+      Model::Difference(a, b) => Model::Difference(boxed(a)?, boxed(b)?),
+      Model::Infinite => Model::Infinite,
+    })
+  }
+
+  /// Everything but this solid.
+  #[allow(dead_code)]
+  pub fn complement(self) -> Model {
+    Model::Difference(Box::new(Model::Infinite), Box::new(self))
+  }
+
+  /// Every surface crossing along the full line through `ray`, including behind the eye, ordered by
+  /// `Ray::parameter`. Counting crossings before a point gives whether it lies inside a closed solid.
+  pub fn intersect_all(&self, ray: &Ray) -> Vec<Intersection> {
+    let mut hits = match self {
+      Model::Primitive(primitive) => primitive.intersect(ray).hits().copied().collect(),
+      Model::Scene(list) => list.iter().flat_map(|model| model.intersect_all(ray)).collect(),
+      Model::Transform(transform, model) => {
+        let transformed = transform.apply_forward(ray);
+        model
+          .intersect_all(&transformed)
+          .iter()
+          .map(|hit| transform.apply_backward(hit))
+          .collect()
+      }
+      Model::Clip(plane, model) => model
+        .intersect_all(ray)
+        .into_iter()
+        .filter(|hit| matches!(hit.position.clip(plane), Clipped::Inside(_)))
+        .collect(),
+      Model::And(a, b) => {
+        let (a, b) = (a.intersect_all(ray), b.intersect_all(ray));
+        if a.is_empty() || b.is_empty() {
+          vec![]
+        } else {
+          a.into_iter().chain(b).collect()
+        }
+      }
+      Model::Or(a, b) => a.intersect_all(ray).into_iter().chain(b.intersect_all(ray)).collect(),
+      Model::Difference(a, b) => {
+        let (a, b) = (a.intersect_all(ray), b.intersect_all(ray));
+        let crossings_before =
+          |hits: &[Intersection], t: f32| hits.iter().filter(|hit| ray.parameter(hit.position) < t).count();
+        // Surface of A outside B, plus surface of B inside A facing the other way
+        let outside_b = a
+          .iter()
+          .filter(|hit| crossings_before(&b, ray.parameter(hit.position)) % 2 == 0)
+          .copied();
+        let inside_a = b
+          .iter()
+          .filter(|hit| crossings_before(&a, ray.parameter(hit.position)) % 2 == 1)
+          .map(|hit| Intersection {
+            position: hit.position,
+            normal: -hit.normal,
+          });
+        outside_b.chain(inside_a).collect()
+      }
+      Model::Infinite => vec![Intersection {
+        position: ray.eye,
+        normal: -ray.delta().normalize(),
+      }],
+    };
+    hits.sort_by(|a, b| {
+      ray
+        .parameter(a.position)
+        .partial_cmp(&ray.parameter(b.position))
+        .unwrap()
+    });
+    hits
+  }
+
   pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
     match self {
-      Model::Object(object) => object.intersect(ray).closest(),
+      Model::Primitive(primitive) => primitive.intersect(ray).closest(),
       Model::Scene(list) => list
         .iter()
         .filter_map(|model| model.intersect(ray))
@@ -259,10 +659,107 @@ impl Model {
           intersect1.or(intersect2)
         }
       }
+      Model::Difference(..) | Model::Infinite => self
+        .intersect_all(ray)
+        .into_iter()
+        .find(|hit| ray.parameter(hit.position) >= 0.0),
     }
   }
 }
 
+/// Models looked up by name. Removal swaps the last model into the gap, so the order of the models is not kept.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+  models: Vec<(String, Model)>,
+  /// Position of each name in `models`
+  index: HashMap<String, usize>,
+}
+
+impl Scene {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds `model` as `name`, replacing any model already called that.
+  pub fn add(&mut self, name: &str, model: Model) {
+    match self.index.get(name) {
+      Some(&idx) => self.models[idx].1 = model,
+      None => {
+        self.index.insert(name.into(), self.models.len());
+        self.models.push((name.into(), model));
+      }
+    }
+  }
+
+  #[allow(dead_code)]
+  pub fn remove(&mut self, name: &str) -> Option<Model> {
+    let idx = self.index.remove(name)?;
+    let (_, model) = self.models.swap_remove(idx);
+    if let Some((moved, _)) = self.models.get(idx) {
+      self.index.insert(moved.clone(), idx);
+    }
+    Some(model)
+  }
+
+  #[allow(dead_code)]
+  pub fn get(&self, name: &str) -> Option<&Model> {
+    self.index.get(name).map(|&idx| &self.models[idx].1)
+  }
+
+  #[allow(dead_code)]
+  pub fn len(&self) -> usize {
+    self.models.len()
+  }
+
+  #[allow(dead_code)]
+  pub fn is_empty(&self) -> bool {
+    self.models.is_empty()
+  }
+
+  /// Nearest hit in front of the ray's eye and the name of the model it belongs to.
+  pub fn intersect_named(&self, ray: &Ray) -> Option<(String, Intersection)> {
+    self
+      .models
+      .iter()
+      .filter_map(|(name, model)| {
+        let hit = model
+          .intersect_all(ray)
+          .into_iter()
+          .find(|hit| ray.parameter(hit.position) >= 0.0)?;
+        Some((name, hit))
+      })
+      .min_by(|(_, a), (_, b)| {
+        ray
+          .parameter(a.position)
+          .partial_cmp(&ray.parameter(b.position))
+          .unwrap()
+      })
+      .map(|(name, hit)| (name.clone(), hit))
+  }
+}
+
+impl From<Scene> for Model {
+  fn from(scene: Scene) -> Self {
+    Model::Scene(scene.models.into_iter().map(|(_, model)| model).collect())
+  }
+}
+
+/// The models of a `Model::Scene` are named by their position in it; any other model becomes a scene of one
+/// called "0".
+impl From<Model> for Scene {
+  fn from(model: Model) -> Self {
+    let models = match model {
+      Model::Scene(list) => list,
+      model => vec![model],
+    };
+    let mut scene = Scene::new();
+    for (idx, model) in models.into_iter().enumerate() {
+      scene.add(&idx.to_string(), model);
+    }
+    scene
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
@@ -292,6 +789,46 @@ mod test {
     assert_eq!(transformed.target, (1.0, 1.0, 6.0).into());
   }
 
+  #[test]
+  fn ray_point_distance_test() {
+    let ray = Ray {
+      eye: (0.0, 0.0, 0.0).into(),
+      target: (0.0, 0.0, 2.0).into(),
+    };
+    assert!((ray.distance_to_point((3.0, 4.0, 5.0).into()) - 5.0).abs() < 0.00001);
+    assert_eq!(ray.distance_to_point((0.0, 0.0, 7.0).into()), 0.0);
+    assert_eq!(ray.closest_point_to((3.0, 4.0, 5.0).into()), Point3::new(0.0, 0.0, 5.0));
+    // Behind the eye the ray ends, although the line does not
+    assert_eq!(ray.closest_point_to((1.0, 0.0, -5.0).into()), ray.eye);
+    assert!((ray.distance_to_point((1.0, 0.0, -5.0).into()) - 1.0).abs() < 0.00001);
+  }
+
+  #[test]
+  fn plane_point_test() {
+    let horizontal = Plane {
+      position: (0.0, 1.0, 0.0).into(),
+      normal: Vector3::unit_y(),
+    };
+    assert_eq!(horizontal.signed_distance((3.0, 4.0, -2.0).into()), 3.0);
+    assert_eq!(horizontal.signed_distance((3.0, -1.0, -2.0).into()), -2.0);
+    assert_eq!(
+      horizontal.project_onto((3.0, 4.0, -2.0).into()),
+      (3.0, 1.0, -2.0).into()
+    );
+    assert!(horizontal.contains_point((5.0, 1.0, 5.0).into(), 0.0));
+    assert!(horizontal.contains_point((5.0, 1.05, 5.0).into(), 0.1));
+    assert!(!horizontal.contains_point((5.0, 1.2, 5.0).into(), 0.1));
+
+    let oblique = Plane {
+      position: Point3::origin(),
+      normal: Vector3::new(1.0, 1.0, 0.0).normalize(),
+    };
+    let projected = oblique.project_onto((2.0, 0.0, 1.0).into());
+    assert!(projected.distance((1.0, -1.0, 1.0).into()) < 0.00001);
+    assert!(oblique.contains_point(projected, 0.00001));
+    assert!((oblique.signed_distance((2.0, 0.0, 1.0).into()) - 2.0f32.sqrt()).abs() < 0.00001);
+  }
+
   #[test]
   fn plane_intersect_test() {
     let plane = Plane {
@@ -316,6 +853,65 @@ mod test {
     );
   }
 
+  #[test]
+  fn carved_sphere_test() {
+    let carved = Model::Difference(
+      Box::new(Model::Primitive(PrimitiveKind::Ball(Ball::new(2.0)))),
+      Box::new(Model::Primitive(PrimitiveKind::Ball(Ball::new(1.0)))),
+    );
+    let ray = Ray {
+      eye: (0.0, 0.0, -10.0).into(),
+      target: Point3::origin(),
+    };
+    let hits = carved.intersect_all(&ray);
+    let positions: Vec<f32> = hits.iter().map(|hit| hit.position.z).collect();
+    assert_eq!(positions, vec![-2.0, -1.0, 1.0, 2.0]);
+    let normals: Vec<f32> = hits.iter().map(|hit| hit.normal.z).collect();
+    assert_eq!(normals, vec![-1.0, 1.0, -1.0, 1.0]);
+    assert_eq!(carved.intersect(&ray).unwrap().position, (0.0, 0.0, -2.0).into());
+
+    // From inside the cavity the first surface is the inner wall, facing the centre
+    let ray = Ray {
+      eye: Point3::origin(),
+      target: (0.0, 0.0, 1.0).into(),
+    };
+    assert_eq!(
+      carved.intersect(&ray),
+      Some(Intersection {
+        position: (0.0, 0.0, 1.0).into(),
+        normal: -Vector3::unit_z(),
+      })
+    );
+
+    // A ray through the shell alone misses the cavity
+    let ray = Ray {
+      eye: (1.5, 0.0, -10.0).into(),
+      target: (1.5, 0.0, 0.0).into(),
+    };
+    assert_eq!(carved.intersect_all(&ray).len(), 2);
+  }
+
+  #[test]
+  fn complement_test() {
+    let outside = Model::Primitive(PrimitiveKind::Ball(Ball::new(1.0))).complement();
+    let ray = Ray {
+      eye: (0.0, 0.0, -10.0).into(),
+      target: Point3::origin(),
+    };
+    assert_eq!(outside.intersect(&ray).unwrap().position, ray.eye);
+    let ray = Ray {
+      eye: Point3::origin(),
+      target: (0.0, 0.0, 1.0).into(),
+    };
+    assert_eq!(
+      outside.intersect(&ray),
+      Some(Intersection {
+        position: (0.0, 0.0, 1.0).into(),
+        normal: -Vector3::unit_z(),
+      })
+    );
+  }
+
   #[test]
   fn intersect_ball_test() {
     let ball = Ball { radius: 5.0 };
@@ -348,4 +944,262 @@ mod test {
       })
     );
   }
+
+  #[test]
+  fn intersect_result_helpers_test() {
+    let ray = Ray {
+      eye: (0.0, 0.0, -5.0).into(),
+      target: (0.0, 0.0, -4.0).into(),
+    };
+    let result = Ball::new(1.0).intersect(&ray);
+    assert_eq!(result.hits().count(), 2);
+    assert_eq!(IntersectResult::Miss.hits().count(), 0);
+
+    let shifted = Ball::new(1.0).intersect(&ray).map(|hit| Intersection {
+      position: hit.position + Vector3::unit_x(),
+      normal: hit.normal,
+    });
+    let xs: Vec<f32> = shifted.hits().map(|hit| hit.position.x).collect();
+    assert_eq!(xs, vec![1.0, 1.0]);
+
+    // Only the far side of the ball is at least 5 units away
+    let far = result.filter_t(5.0, &ray);
+    assert_eq!(far.hits().count(), 1);
+    assert!(far.closest().unwrap().position.distance((0.0, 0.0, 1.0).into()) < 0.00001);
+    let none = Ball::new(1.0).intersect(&ray).filter_t(7.0, &ray);
+    assert_eq!(none, IntersectResult::Miss);
+  }
+
+  #[test]
+  fn intersect_result_variants_test() {
+    let hit = |z: f32| Intersection {
+      position: (0.0, 0.0, z).into(),
+      normal: (0.0, 0.0, -1.0).into(),
+    };
+    let miss = IntersectResult::Miss;
+    let once = IntersectResult::HitOnce(hit(1.0));
+    let twice = IntersectResult::HitTwice(hit(1.0), hit(2.0));
+
+    assert!(!miss.any_hit());
+    assert!(once.any_hit());
+    assert!(twice.any_hit());
+    assert_eq!((miss.count(), once.count(), twice.count()), (0, 1, 2));
+
+    assert_eq!(miss.hits().count(), 0);
+    assert_eq!(once.hits().copied().collect::<Vec<_>>(), vec![hit(1.0)]);
+    assert_eq!(twice.hits().copied().collect::<Vec<_>>(), vec![hit(1.0), hit(2.0)]);
+
+    let forward = |hit: Intersection| Intersection {
+      position: hit.position + Vector3::unit_z(),
+      normal: hit.normal,
+    };
+    assert_eq!(miss.map(forward), IntersectResult::Miss);
+    assert_eq!(once.map(forward), IntersectResult::HitOnce(hit(2.0)));
+    assert_eq!(twice.map(forward), IntersectResult::HitTwice(hit(2.0), hit(3.0)));
+  }
+
+  #[test]
+  fn filter_by_t_test() {
+    // The eye is inside the ball, so one hit is behind it
+    let ray = Ray {
+      eye: (0.0, 0.0, 0.0).into(),
+      target: (0.0, 0.0, 0.5).into(),
+    };
+    let result = Ball::new(1.0).intersect(&ray);
+    assert_eq!(result.hits().count(), 2);
+
+    let ahead = Ball::new(1.0).intersect(&ray).filter_by_t(0.0, f32::INFINITY, &ray);
+    match ahead {
+      IntersectResult::HitOnce(hit) => assert!(hit.position.distance((0.0, 0.0, 1.0).into()) < 0.00001),
+      other => panic!("expected one hit, got {:?}", other),
+    }
+    assert_eq!(Ball::new(1.0).intersect(&ray).filter_by_t(-2.0, 2.0, &ray), result);
+    assert_eq!(
+      Ball::new(1.0).intersect(&ray).filter_by_t(0.0, 1.0, &ray),
+      IntersectResult::Miss
+    );
+    assert_eq!(
+      IntersectResult::Miss.filter_by_t(-1.0, 1.0, &ray),
+      IntersectResult::Miss
+    );
+  }
+
+  #[test]
+  fn scene_round_trip_test() {
+    let scene = Model::Transform(
+      Transform::new(Matrix4::from_translation((1.0, 2.0, 3.0).into())).unwrap(),
+      Box::new(Model::Primitive(PrimitiveKind::Ball(Ball::new(0.5)))),
+    );
+    let path = std::env::temp_dir().join("simulator_scene_test.json");
+    scene.save(&path).unwrap();
+    let loaded = Model::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let ray = Ray {
+      eye: (1.0, 2.0, -3.0).into(),
+      target: (1.0, 2.0, -2.0).into(),
+    };
+    let hit = loaded.intersect(&ray).unwrap();
+    assert!(hit.position.distance((1.0, 2.0, 2.5).into()) < 0.00001);
+    assert_eq!(hit, scene.intersect(&ray).unwrap());
+  }
+
+  #[test]
+  fn transform_in_place_test() {
+    let scene = Model::Scene(vec![
+      Model::Primitive(PrimitiveKind::Ball(Ball::new(1.0))),
+      Model::Clip(
+        Plane {
+          position: Point3::origin(),
+          normal: Vector3::unit_x(),
+        },
+        Box::new(Model::Transform(
+          Transform::new(Matrix4::from_translation((0.0, 3.0, 0.0).into())).unwrap(),
+          Box::new(Model::Primitive(PrimitiveKind::Ball(Ball::new(1.0)))),
+        )),
+      ),
+      Model::Primitive(PrimitiveKind::Plane(Plane {
+        position: (0.0, 0.0, 4.0).into(),
+        normal: -Vector3::unit_z(),
+      })),
+    ]);
+    let matrix = Matrix4::from_translation((1.0, 0.0, 0.0).into()) * Matrix4::from_nonuniform_scale(2.0, 1.0, 0.5);
+    let nested = Model::Transform(Transform::new(matrix).unwrap(), Box::new(scene.clone()));
+    let flat = scene.transform_in_place(matrix).unwrap();
+    assert!(matches!(&flat, Model::Scene(list) if list.len() == 3));
+
+    for (x, y) in [(1.0, 0.0), (2.5, 0.0), (1.5, 3.0), (0.5, 3.0), (5.0, 5.0)] {
+      let ray = Ray {
+        eye: (x, y, -10.0).into(),
+        target: (x, y, 0.0).into(),
+      };
+      match (flat.intersect(&ray), nested.intersect(&ray)) {
+        (Some(a), Some(b)) => {
+          assert!(a.position.distance(b.position) < 0.0001, "{:?} != {:?}", a, b);
+          assert!((a.normal - b.normal).magnitude() < 0.0001, "{:?} != {:?}", a, b);
+        }
+        (a, b) => assert_eq!(a, b),
+      }
+    }
+    assert!(scene.transform_in_place(Matrix4::from_scale(0.0)).is_none());
+  }
+
+  #[test]
+  fn named_scene_test() {
+    let ball = |x: f32| {
+      Model::Transform(
+        Transform::new(Matrix4::from_translation((x, 0.0, 0.0).into())).unwrap(),
+        Box::new(Model::Primitive(PrimitiveKind::Ball(Ball::new(0.5)))),
+      )
+    };
+    let mut scene = Scene::new();
+    scene.add("a", ball(0.0));
+    scene.add("b", ball(3.0));
+    scene.add("c", ball(6.0));
+    let ray = |x: f32| Ray {
+      eye: (x, 0.0, -5.0).into(),
+      target: (x, 0.0, 0.0).into(),
+    };
+    assert_eq!(scene.intersect_named(&ray(3.0)).map(|(name, _)| name), Some("b".into()));
+    assert!(scene.intersect_named(&ray(1.5)).is_none());
+
+    // Removing keeps the remaining names reachable
+    assert!(scene.remove("a").is_some());
+    assert!(scene.remove("a").is_none());
+    assert_eq!(scene.len(), 2);
+    assert!(scene.get("c").is_some());
+    assert_eq!(scene.intersect_named(&ray(6.0)).map(|(name, _)| name), Some("c".into()));
+    assert!(scene.intersect_named(&ray(0.0)).is_none());
+
+    // Adding an existing name replaces the model
+    scene.add("b", ball(9.0));
+    assert_eq!(scene.len(), 2);
+    assert_eq!(scene.intersect_named(&ray(9.0)).map(|(name, _)| name), Some("b".into()));
+
+    // Hits behind the eye don't count
+    let behind = Ray {
+      eye: (6.0, 0.0, 5.0).into(),
+      target: (6.0, 0.0, 10.0).into(),
+    };
+    assert!(scene.intersect_named(&behind).is_none());
+
+    let model = Model::from(scene);
+    assert!(matches!(&model, Model::Scene(list) if list.len() == 2));
+    let scene = Scene::from(model);
+    assert!(scene.get("0").is_some() && scene.get("1").is_some());
+    assert_eq!(Scene::from(ball(0.0)).len(), 1);
+  }
+
+  /// Camera at the origin looking down -z with a 90° field of view, seeing from 1 to 10 units away.
+  fn test_frustum() -> Frustum {
+    let view = Matrix4::look_at_rh(Point3::origin(), Point3::new(0.0, 0.0, -1.0), Vector3::unit_y());
+    let projection = cgmath::perspective(cgmath::Deg(90.0), 1.0, 1.0, 10.0);
+    Frustum::from_view_proj(crate::gfx::camera::OPENGL_TO_WGPU_MATRIX * projection * view)
+  }
+
+  #[test]
+  fn frustum_planes_test() {
+    let frustum = test_frustum();
+    let near = &frustum.planes[4];
+    assert!((near.normal - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+    assert!(near.contains_point(Point3::new(3.0, -2.0, -1.0), 1e-5));
+    let far = &frustum.planes[5];
+    assert!(far.contains_point(Point3::new(0.0, 0.0, -10.0), 1e-4));
+    // The side planes are 45° off the view direction
+    let left = &frustum.planes[0];
+    assert!((left.normal - Vector3::new(1.0, 0.0, -1.0).normalize()).magnitude() < 1e-5);
+    assert!(left.contains_point(Point3::new(-5.0, 0.0, -5.0), 1e-4));
+  }
+
+  #[test]
+  fn frustum_contains_test() {
+    let frustum = test_frustum();
+    assert!(frustum.contains_sphere(Point3::new(0.0, 0.0, -5.0), 0.1));
+    assert!(!frustum.contains_sphere(Point3::new(0.0, 0.0, 5.0), 1.0));
+    assert!(!frustum.contains_sphere(Point3::new(0.0, 0.0, -0.5), 0.4));
+    // Overlapping the near plane is enough
+    assert!(frustum.contains_sphere(Point3::new(0.0, 0.0, -0.5), 0.6));
+    assert!(!frustum.contains_sphere(Point3::new(8.0, 0.0, -5.0), 1.0));
+
+    let cube = |center: Point3<f32>, half: f32| {
+      Aabb::new(
+        center - Vector3::new(half, half, half),
+        center + Vector3::new(half, half, half),
+      )
+    };
+    assert!(frustum.contains_aabb(&cube(Point3::new(0.0, 0.0, -5.0), 0.5)));
+    assert!(frustum.contains_aabb(&cube(Point3::new(0.0, 0.0, -11.0), 1.5)));
+    assert!(!frustum.contains_aabb(&cube(Point3::new(0.0, 0.0, -12.0), 1.5)));
+    assert!(!frustum.contains_aabb(&cube(Point3::new(0.0, 9.0, -5.0), 1.0)));
+  }
+
+  #[test]
+  fn frustum_intersect_test() {
+    let frustum = test_frustum();
+    // Entering through the near plane
+    let ray = Ray {
+      eye: Point3::new(0.5, 0.0, 2.0),
+      target: Point3::new(0.5, 0.0, 1.0),
+    };
+    let hit = frustum.intersect(&ray).closest().unwrap();
+    assert!((hit.position - Point3::new(0.5, 0.0, -1.0)).magnitude() < 1e-4);
+    assert!((hit.normal - Vector3::unit_z()).magnitude() < 1e-5);
+    // From inside, hitting the far plane on the way out
+    let ray = Ray {
+      eye: Point3::new(0.0, 0.0, -5.0),
+      target: Point3::new(0.0, 0.0, -6.0),
+    };
+    let hit = frustum.intersect(&ray).closest().unwrap();
+    assert!((hit.position - Point3::new(0.0, 0.0, -10.0)).magnitude() < 1e-3);
+    // Passing beside the frustum and pointing away from it
+    let beside = Ray {
+      eye: Point3::new(20.0, 0.0, 2.0),
+      target: Point3::new(20.0, 0.0, 1.0),
+    };
+    assert_eq!(frustum.intersect(&beside), IntersectResult::Miss);
+    let away = Ray {
+      eye: Point3::new(0.0, 0.0, 2.0),
+      target: Point3::new(0.0, 0.0, 3.0),
+    };
+    assert_eq!(frustum.intersect(&away), IntersectResult::Miss);
+  }
 }
@@ -0,0 +1,141 @@
+use super::featuredb::Feature;
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug)]
+pub enum ReplayError {
+  Io(std::io::Error),
+  Json(serde_json::Error),
+}
+
+impl fmt::Display for ReplayError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ReplayError::Io(err) => write!(f, "failed to access recording: '{}'", err),
+      ReplayError::Json(err) => write!(f, "invalid recording: '{}'", err),
+    }
+  }
+}
+
+impl From<std::io::Error> for ReplayError {
+  fn from(other: std::io::Error) -> Self {
+    ReplayError::Io(other)
+  }
+}
+
+impl From<serde_json::Error> for ReplayError {
+  fn from(other: serde_json::Error) -> Self {
+    ReplayError::Json(other)
+  }
+}
+
+/// Snapshot of the features at `timestamp` seconds after recording started
+#[derive(Serialize, Deserialize)]
+struct Frame {
+  timestamp: f32,
+  features: Vec<Feature>,
+}
+
+pub struct FrameRecorder {
+  start: Instant,
+  frames: Vec<Frame>,
+}
+
+impl FrameRecorder {
+  pub fn new() -> Self {
+    Self {
+      start: Instant::now(),
+      frames: Vec::new(),
+    }
+  }
+
+  pub fn record_frame(&mut self, features: &[Feature]) {
+    self.frames.push(Frame {
+      timestamp: self.start.elapsed().as_secs_f32(),
+      features: features.to_vec(),
+    });
+  }
+
+  /// Writes the recorded frames as a JSON array of `{"timestamp": ..., "features": [...]}` objects.
+  pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+    serde_json::to_writer(BufWriter::new(File::create(path)?), &self.frames)?;
+    Ok(())
+  }
+}
+
+/// Plays back frames saved by `FrameRecorder`, advancing `playback_speed` frames per call to `next_frame`.
+pub struct FramePlayer {
+  frames: Vec<Frame>,
+  position: f32,
+  pub is_loop: bool,
+  pub playback_speed: f32,
+}
+
+impl FramePlayer {
+  pub fn load(path: &Path) -> Result<Self, ReplayError> {
+    let frames = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    Ok(Self {
+      frames,
+      position: 0.0,
+      is_loop: true,
+      playback_speed: 1.0,
+    })
+  }
+
+  pub fn next_frame(&mut self) -> Option<&[Feature]> {
+    let len = self.frames.len() as f32;
+    if self.position >= len {
+      if self.is_loop && len > 0.0 {
+        self.position %= len;
+      } else {
+        return None;
+      }
+    }
+    let index = self.position as usize;
+    self.position += self.playback_speed;
+    Some(&self.frames[index].features)
+  }
+
+  #[allow(dead_code)]
+  pub fn seek(&mut self, frame: usize) {
+    self.position = frame.min(self.frames.len()) as f32;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn feature(id: u32) -> Feature {
+    Feature::mock().with_id(id).with_position((id as f32, 0.0, 0.0))
+  }
+
+  #[test]
+  fn record_and_play_test() {
+    let path = std::env::temp_dir().join("simulator_replay_test.json");
+    let mut recorder = FrameRecorder::new();
+    recorder.record_frame(&[feature(1)]);
+    recorder.record_frame(&[feature(1), feature(2)]);
+    recorder.record_frame(&[]);
+    recorder.save(&path).unwrap();
+
+    let mut player = FramePlayer::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(player.next_frame().unwrap(), &[feature(1)]);
+    assert_eq!(player.next_frame().unwrap().len(), 2);
+    assert!(player.next_frame().unwrap().is_empty());
+    assert_eq!(player.next_frame().unwrap(), &[feature(1)]);
+
+    player.is_loop = false;
+    player.playback_speed = 2.0;
+    player.seek(1);
+    assert_eq!(player.next_frame().unwrap().len(), 2);
+    assert!(player.next_frame().is_none());
+  }
+}
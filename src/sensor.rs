@@ -0,0 +1,95 @@
+use super::gfx::camera::Camera;
+use super::gfx::shader::feature::FeatureInstance;
+
+use cgmath::InnerSpace;
+use serde::{Deserialize, Serialize};
+
+/// Sensor id of the readings made up from the camera
+pub const SIMULATED_SENSOR_ID: u8 = 0;
+
+/// One return of a range sensor, in the sensor's spherical coordinates. Angles are in radians.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SensorReading {
+  /// Microseconds since the Unix epoch
+  pub timestamp_us: u64,
+  pub sensor_id: u8,
+  /// Distance from the sensor
+  pub range: f32,
+  /// Angle to the right of straight ahead
+  pub bearing: f32,
+  /// Angle above straight ahead
+  pub elevation: f32,
+  /// Share of the sensor's beam the return fills, from 0 to 1
+  pub intensity: f32,
+}
+
+impl FeatureInstance {
+  /// Reading of the instance's center from a sensor at the camera eye, looking where the camera looks. The intensity
+  /// is the squared ratio of the instance radius to its range, so larger and closer features return more.
+  pub fn to_sensor_reading(self, camera: &Camera, timestamp_us: u64) -> SensorReading {
+    let offset = self.position() - camera.eye;
+    let (forward, right) = (camera.forward(), camera.right());
+    let up = right.cross(forward);
+    let (x, y, z) = (offset.dot(right), offset.dot(up), offset.dot(forward));
+    let range = offset.magnitude();
+    let intensity = if range > 0.0 {
+      (self.radius() / range).powi(2).min(1.0)
+    } else {
+      1.0
+    };
+    SensorReading {
+      timestamp_us,
+      sensor_id: SIMULATED_SENSOR_ID,
+      range,
+      bearing: x.atan2(z),
+      elevation: y.atan2(x.hypot(z)),
+      intensity,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use cgmath::Matrix4;
+  use std::f32::consts::FRAC_PI_4;
+
+  fn feature(position: (f32, f32, f32), radius: f32) -> FeatureInstance {
+    FeatureInstance::mock()
+      .with_model(Matrix4::from_scale(radius))
+      .with_position(position)
+  }
+
+  #[test]
+  fn to_sensor_reading_test() {
+    // Looking down +z from (0, 0, -1), so +x is to the left
+    let camera = Camera::mock();
+    let ahead = feature((0.0, 0.0, 1.0), 0.5).to_sensor_reading(&camera, 42);
+    assert_eq!(
+      ahead,
+      SensorReading {
+        timestamp_us: 42,
+        sensor_id: SIMULATED_SENSOR_ID,
+        range: 2.0,
+        bearing: 0.0,
+        elevation: 0.0,
+        intensity: 0.0625,
+      }
+    );
+
+    let right = feature((-1.0, 0.0, 0.0), 0.5).to_sensor_reading(&camera, 0);
+    assert!((right.bearing - FRAC_PI_4).abs() < 0.00001);
+    assert!(right.elevation.abs() < 0.00001);
+    assert!((right.range - 2f32.sqrt()).abs() < 0.00001);
+
+    let above = feature((0.0, 1.0, 0.0), 0.5).to_sensor_reading(&camera, 0);
+    assert!(above.bearing.abs() < 0.00001);
+    assert!((above.elevation - FRAC_PI_4).abs() < 0.00001);
+
+    // A feature around the eye fills the whole beam
+    assert_eq!(
+      feature((0.0, 0.0, -1.0), 0.5).to_sensor_reading(&camera, 0).intensity,
+      1.0
+    );
+  }
+}
@@ -0,0 +1,158 @@
+use super::net::ConnectionState;
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Averages the frame rate over the most recent frames.
+pub struct FrameTimer {
+  last: Instant,
+  frame_times: VecDeque<Duration>,
+  window: usize,
+}
+
+impl FrameTimer {
+  pub fn new(window: usize) -> Self {
+    Self {
+      last: Instant::now(),
+      frame_times: VecDeque::with_capacity(window),
+      window,
+    }
+  }
+
+  /// Marks the end of a frame.
+  pub fn tick(&mut self) {
+    let now = Instant::now();
+    self.record(now - self.last);
+    self.last = now;
+  }
+
+  fn record(&mut self, frame_time: Duration) {
+    if self.frame_times.len() == self.window {
+      self.frame_times.pop_front();
+    }
+    self.frame_times.push_back(frame_time);
+  }
+
+  /// Length of the most recently ticked frame.
+  pub fn last_frame_time(&self) -> Duration {
+    self.frame_times.back().copied().unwrap_or_default()
+  }
+
+  pub fn fps(&self) -> f32 {
+    let total: Duration = self.frame_times.iter().sum();
+    if total.is_zero() {
+      0.0
+    } else {
+      self.frame_times.len() as f32 / total.as_secs_f32()
+    }
+  }
+}
+
+/// Caps the frame rate by sleeping off whatever is left of each frame's time budget.
+pub struct FrameLimiter {
+  target: Duration,
+  last_frame: Instant,
+}
+
+impl FrameLimiter {
+  pub fn new(max_fps: f32) -> Self {
+    Self {
+      target: Duration::from_secs_f32(1.0 / max_fps),
+      last_frame: Instant::now(),
+    }
+  }
+
+  /// Time left in the budget of the frame that started at `last_frame`. Frames that are already overdue get
+  /// `None` rather than borrowing from the next frame, so a slow frame never makes the ones after it wait.
+  fn remaining(&self, now: Instant) -> Option<Duration> {
+    self
+      .target
+      .checked_sub(now.saturating_duration_since(self.last_frame))
+      .filter(|remaining| !remaining.is_zero())
+  }
+
+  /// Blocks until the current frame has used its budget and starts the next one.
+  pub fn wait(&mut self) {
+    if let Some(remaining) = self.remaining(Instant::now()) {
+      std::thread::sleep(remaining);
+    }
+    self.last_frame = Instant::now();
+  }
+}
+
+/// Rebuilds the window title every `interval` frames.
+pub struct TitleUpdater {
+  interval: u32,
+  frame: u32,
+}
+
+impl TitleUpdater {
+  pub fn new(interval: u32) -> Self {
+    Self { interval, frame: 0 }
+  }
+
+  /// Counts a frame, returning whether the title is due to be updated.
+  pub fn tick(&mut self) -> bool {
+    self.frame = (self.frame + 1) % self.interval;
+    self.frame == 0
+  }
+
+  /// `latency_ms` is shown after the connection state when known.
+  pub fn format(
+    &self,
+    feature_count: usize,
+    fps: f32,
+    connection_state: ConnectionState,
+    latency_ms: Option<f32>,
+  ) -> String {
+    let latency = latency_ms.map_or(String::new(), |latency| format!(" ({:.1} ms)", latency));
+    format!(
+      "Lawny Simulator | {} features | {:.1} FPS | {:?}{}",
+      feature_count, fps, connection_state, latency
+    )
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn frame_timer_test() {
+    let mut timer = FrameTimer::new(2);
+    assert_eq!(timer.fps(), 0.0);
+    timer.record(Duration::from_millis(100));
+    timer.record(Duration::from_millis(20));
+    timer.record(Duration::from_millis(30));
+    assert!((timer.fps() - 40.0).abs() < 0.001);
+  }
+
+  #[test]
+  fn frame_limiter_test() {
+    let limiter = FrameLimiter::new(50.0);
+    let start = limiter.last_frame;
+    assert_eq!(
+      limiter.remaining(start + Duration::from_millis(5)),
+      Some(Duration::from_millis(15))
+    );
+    assert_eq!(limiter.remaining(start + Duration::from_millis(20)), None);
+    assert_eq!(limiter.remaining(start + Duration::from_millis(45)), None);
+  }
+
+  #[test]
+  fn title_updater_test() {
+    let mut updater = TitleUpdater::new(3);
+    assert_eq!(
+      (0..6).map(|_| updater.tick()).collect::<Vec<_>>(),
+      vec![false, false, true, false, false, true]
+    );
+    assert_eq!(
+      updater.format(12, 59.94, ConnectionState::Connected, None),
+      "Lawny Simulator | 12 features | 59.9 FPS | Connected"
+    );
+    assert_eq!(
+      updater.format(12, 59.94, ConnectionState::Connected, Some(1.25)),
+      "Lawny Simulator | 12 features | 59.9 FPS | Connected (1.2 ms)"
+    );
+  }
+}
@@ -1,6 +1,17 @@
-use super::raycast::{Ball, Intersect, Model, Plane, Ray, Transform};
+use super::raycast::{Ball, Model, Plane, PrimitiveKind, Ray, Transform};
 
-use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector3};
+use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector2, Vector3};
+
+use std::f32::consts::PI;
+
+/// Where a ray hit the trackball surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackballHit {
+  pub position: Point3<f32>,
+  pub normal: Vector3<f32>,
+  /// Spherical coordinates of the hit around the trackball center, see `VirtualTrackball::world_to_uv`
+  pub uv: Vector2<f32>,
+}
 
 pub struct VirtualTrackball {
   position: Point3<f32>,
@@ -13,27 +24,51 @@ impl VirtualTrackball {
     VirtualTrackball {
       position,
       radius,
-      model: Model::Transform(
-        Transform::new(Matrix4::from_translation(position.to_homogeneous().truncate()))
-          .expect("ray transform to be valid"),
-        Box::new(Model::Object(Box::new(Ball::new(radius)))),
-      ),
+      model: Model::Transform(Self::transform(position), Box::new(Self::ball(radius))),
     }
   }
 
+  fn transform(position: Point3<f32>) -> Transform {
+    Transform::new(Matrix4::from_translation(position.to_homogeneous().truncate())).expect("ray transform to be valid")
+  }
+
+  fn ball(radius: f32) -> Model {
+    Model::Primitive(PrimitiveKind::Ball(Ball::new(radius)))
+  }
+
   pub fn position(&self) -> Point3<f32> {
     self.position
   }
 
+  /// Moves the trackball center, e.g. to a newly selected feature.
+  pub fn set_position(&mut self, position: Point3<f32>) {
+    self.position = position;
+    if let Model::Transform(transform, _) = &mut self.model {
+      *transform = Self::transform(position);
+    }
+  }
+
+  pub fn radius(&self) -> f32 {
+    self.radius
+  }
+
+  pub fn set_radius(&mut self, radius: f32) {
+    self.radius = radius;
+    if let Model::Transform(_, ball) = &mut self.model {
+      **ball = Self::ball(radius);
+    }
+  }
+
   fn intersect(&self, ray: &Ray) -> Point3<f32> {
     if let Some(intersect) = self.model.intersect(ray) {
       intersect.position
     } else {
+      // The ray passes the ball on the same side as its projection onto the plane facing the eye
       let plane = Plane {
         position: self.position,
         normal: (self.position - ray.eye).normalize(),
       };
-      let delta: Vector3<f32> = plane.intersect(ray).closest().unwrap().position - self.position;
+      let delta: Vector3<f32> = plane.project_onto(ray.target) - self.position;
       self.position + delta.normalize() * self.radius
     }
   }
@@ -53,6 +88,35 @@ impl VirtualTrackball {
   pub fn test(&self, ray: Ray) -> bool {
     self.model.intersect(&ray).is_some()
   }
+
+  /// Like `test`, but also returns where the ray hit.
+  #[allow(dead_code)]
+  pub fn hit_test_detailed(&self, ray: Ray) -> Option<TrackballHit> {
+    self.model.intersect(&ray).map(|intersect| TrackballHit {
+      position: intersect.position,
+      normal: intersect.normal,
+      uv: self.world_to_uv(intersect.position),
+    })
+  }
+
+  /// Spherical UV coordinates of the direction from the center to `point`: `u = atan2(z, x) / 2π + 0.5` goes around
+  /// the Y axis and `v = acos(y) / π` runs from the top pole to the bottom one.
+  pub fn world_to_uv(&self, point: Point3<f32>) -> Vector2<f32> {
+    let direction = (point - self.position).normalize();
+    Vector2::new(
+      direction.z.atan2(direction.x) / (2.0 * PI) + 0.5,
+      direction.y.clamp(-1.0, 1.0).acos() / PI,
+    )
+  }
+
+  /// Point on the trackball surface at `uv`, the inverse of `world_to_uv`.
+  #[allow(dead_code)]
+  pub fn uv_to_world(&self, uv: Vector2<f32>) -> Point3<f32> {
+    let azimuth = (uv.x - 0.5) * 2.0 * PI;
+    let polar = uv.y * PI;
+    let direction = Vector3::new(polar.sin() * azimuth.cos(), polar.cos(), polar.sin() * azimuth.sin());
+    self.position + direction * self.radius
+  }
 }
 
 #[cfg(test)]
@@ -75,4 +139,83 @@ mod test {
     assert!((axis - Vector3::unit_y()).magnitude() < 0.00001);
     assert_eq!(angle, Deg(45.0).into());
   }
+
+  #[test]
+  fn set_position_test() {
+    let mut trackball = VirtualTrackball::new((0.0, 0.0, 0.0).into(), 1.0);
+    let ray = Ray {
+      eye: (5.0, 0.0, -2.0).into(),
+      target: (5.0, 0.0, 0.0).into(),
+    };
+    assert!(!trackball.test(ray));
+    trackball.set_position((5.0, 0.0, 0.0).into());
+    assert_eq!(trackball.position(), Point3::new(5.0, 0.0, 0.0));
+    assert!(trackball.test(ray));
+    let hit = trackball.hit_test_detailed(ray).unwrap();
+    assert!((hit.position - Point3::new(5.0, 0.0, -1.0)).magnitude() < 0.00001);
+
+    // A ray passing 1.5 from the center only hits once the ball has grown
+    let offset = Ray {
+      eye: (6.5, 0.0, -2.0).into(),
+      target: (6.5, 0.0, 0.0).into(),
+    };
+    assert!(!trackball.test(offset));
+    trackball.set_radius(2.0);
+    assert_eq!(trackball.radius(), 2.0);
+    assert!(trackball.test(offset));
+  }
+
+  #[test]
+  fn missed_ray_test() {
+    let trackball = VirtualTrackball::new((0.0, 0.0, 0.0).into(), 1.0);
+    let ray = Ray {
+      eye: (0.0, 0.0, -2.0).into(),
+      target: (2.0, 0.0, 0.0).into(),
+    };
+    assert!(!trackball.test(ray));
+    assert!((trackball.intersect(&ray) - Point3::new(1.0, 0.0, 0.0)).magnitude() < 0.00001);
+  }
+
+  #[test]
+  fn hit_test_detailed_test() {
+    let trackball = VirtualTrackball::new((1.0, 2.0, 3.0).into(), 2.0);
+    let hit = trackball
+      .hit_test_detailed(Ray {
+        eye: (1.0, 2.0, -2.0).into(),
+        target: (1.0, 2.0, 3.0).into(),
+      })
+      .unwrap();
+    assert!((hit.position - Point3::new(1.0, 2.0, 1.0)).magnitude() < 0.00001);
+    assert!((hit.normal + Vector3::unit_z()).magnitude() < 0.00001);
+    assert!((hit.uv - Vector2::new(0.25, 0.5)).magnitude() < 0.00001);
+    assert_eq!(
+      trackball.hit_test_detailed(Ray {
+        eye: (1.0, 2.0, -2.0).into(),
+        target: (4.0, 2.0, 3.0).into(),
+      }),
+      None
+    );
+  }
+
+  #[test]
+  fn uv_roundtrip_test() {
+    let trackball = VirtualTrackball::new((1.0, 2.0, 3.0).into(), 2.0);
+    let directions = [
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(0.0, 0.0, -1.0),
+      Vector3::new(-1.0, 0.5, 0.5),
+      Vector3::new(0.3, -0.8, 0.2),
+      Vector3::new(-0.2, 0.1, -0.9),
+    ];
+    for direction in directions.iter() {
+      let point = trackball.position() + direction.normalize() * 2.0;
+      let roundtrip = trackball.uv_to_world(trackball.world_to_uv(point));
+      assert!(
+        (roundtrip - point).magnitude() < 0.0001,
+        "{:?} != {:?}",
+        roundtrip,
+        point
+      );
+    }
+  }
 }
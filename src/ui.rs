@@ -1,20 +1,54 @@
 use super::gfx::camera::Camera;
+use super::gfx::shader::feature::FeatureInstance;
 use super::raycast::Ray;
 
-use cgmath::{Deg, InnerSpace, Matrix4, Point3, Rad, Vector3};
+use cgmath::{Deg, InnerSpace, Matrix4, MetricSpace, Point3, Rad, Vector3};
 use winit::dpi::{PhysicalPosition, PhysicalSize};
 use winit::event::*;
 
 use std::collections::HashMap;
+use std::fmt;
+
+/// Smallest radius `UserInterface::frame` frames, in meters
+const MIN_FRAME_RADIUS: f32 = 1.0;
 
 #[allow(dead_code)]
 #[derive(Clone, Copy)]
 pub enum UIEvent {
   RotateAboutObject(Point3<f32>),
   FreeMoveCamera,
+  /// Measuring between the points of the next two clicks, set in turn as they are clicked
+  MeasureDistance {
+    from: Option<Point3<f32>>,
+    to: Option<Point3<f32>>,
+  },
   None,
 }
 
+/// Straight-line distance between two clicked points, in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+  pub start: Point3<f32>,
+  pub end: Point3<f32>,
+  pub distance: f32,
+}
+
+impl Measurement {
+  pub fn new(start: Point3<f32>, end: Point3<f32>) -> Self {
+    Measurement {
+      start,
+      end,
+      distance: start.distance(end),
+    }
+  }
+}
+
+impl fmt::Display for Measurement {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:.3} m", self.distance)
+  }
+}
+
 #[derive(Clone, Copy)]
 pub enum MouseEvent {
   None,
@@ -64,6 +98,30 @@ pub struct UIState {
   pub position: PhysicalPosition<f64>,
   pub size: PhysicalSize<u32>,
   pub event: UIEvent,
+  /// Mouse wheel lines scrolled since the last update, positive away from the user.
+  pub scroll: f32,
+  /// Cursor position when a mouse button last went down
+  pub click_position: PhysicalPosition<f64>,
+  /// How cursor movement turns into camera angles, copied from `UserInterface` on every update
+  pub look: MouseLook,
+}
+
+/// Turns cursor movement into camera angles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MouseLook {
+  /// Tilts the camera down when the cursor moves up, as in flight simulators
+  pub invert_y: bool,
+  /// Scales both angles
+  pub sensitivity: f32,
+}
+
+impl Default for MouseLook {
+  fn default() -> Self {
+    MouseLook {
+      invert_y: false,
+      sensitivity: 1.0,
+    }
+  }
 }
 
 impl Default for UIState {
@@ -76,12 +134,16 @@ impl Default for UIState {
       position: PhysicalPosition { x: 0.0, y: 0.0 },
       size: PhysicalSize { width: 0, height: 0 },
       event: UIEvent::None,
+      scroll: 0.0,
+      click_position: PhysicalPosition { x: 0.0, y: 0.0 },
+      look: MouseLook::default(),
     }
   }
 }
 
 impl UIState {
-  fn _ray(&self, camera: &Camera, size: PhysicalSize<u32>) -> Ray {
+  /// Ray from the camera through the cursor.
+  pub fn ray(&self, camera: &Camera, size: PhysicalSize<u32>) -> Ray {
     let fovy = camera.fovy;
     let fovx = fovy * size.width as f32 / size.height as f32;
     let Rad(xang) = Deg(-((self.position.x as f32 / size.width as f32) * fovx - fovx / 2.0)).into();
@@ -96,83 +158,113 @@ impl UIState {
     }
   }
 
-  fn key(&self, key: &VirtualKeyCode) -> KeyEvent {
+  pub fn key(&self, key: &VirtualKeyCode) -> KeyEvent {
     if let Some(value) = self.keys.get(key) {
       *value
     } else {
       KeyEvent::None
     }
   }
-}
 
-#[derive(Default)]
-pub struct UserInterface {
-  pub last_state: UIState,
-  pub current_state: UIState,
-}
+  /// Whether the cursor has moved more than `threshold` pixels since a mouse button went down.
+  pub fn is_dragging(&self, threshold: f32) -> bool {
+    let dx = self.position.x - self.click_position.x;
+    let dy = self.position.y - self.click_position.y;
+    (dx * dx + dy * dy).sqrt() > threshold as f64
+  }
 
-impl UserInterface {
-  pub fn new(size: PhysicalSize<u32>) -> Self {
-    let mut result = Self::default();
-    result.current_state.size = size;
-    result.last_state.size = size;
-    result
+  /// Whether `key` went down this update, for one-shot actions.
+  pub fn key_just_pressed(&self, key: VirtualKeyCode) -> bool {
+    matches!(self.key(&key), KeyEvent::Press)
   }
 
-  fn mouse_angle(&self, camera: &Camera) -> (Deg<f32>, Deg<f32>) {
-    let current = &self.current_state.position;
-    let last = &self.last_state.position;
-    let fovy = camera.fovy;
-    let fovx = fovy * self.current_state.size.width as f32 / self.current_state.size.height as f32;
-    let x_angle = Deg(((current.x - last.x) as f32 / (self.current_state.size.width as f32)) * fovx);
-    let y_angle = Deg(((current.y - last.y) as f32 / (self.current_state.size.height as f32)) * fovy);
-    (x_angle, y_angle)
+  #[allow(dead_code)]
+  pub fn key_just_released(&self, key: VirtualKeyCode) -> bool {
+    matches!(self.key(&key), KeyEvent::Release)
   }
 
-  pub fn _rotate_about_object(&self, position: Point3<f32>, camera: &mut Camera) {
-    let current = &self.current_state.position;
-    let last = &self.last_state.position;
-    if current != last {
-      let (x_angle, y_angle) = self.mouse_angle(camera);
-      let transform = Matrix4::from_angle_x(-y_angle) * Matrix4::from_angle_y(-x_angle);
-      let delta = (transform * (camera.eye - position).extend(0.0)).truncate();
-      camera.eye = position + delta;
-      camera.target = position;
-      camera.up = (transform * camera.up.extend(0.0)).truncate();
+  /// Whether `key` and every one of `mods` are held down.
+  #[allow(dead_code)]
+  pub fn key_chord(&self, mods: &[VirtualKeyCode], key: VirtualKeyCode) -> bool {
+    mods.iter().all(|modifier| self.key(modifier).is_down()) && self.key(&key).is_down()
+  }
+}
+
+/// Camera control driven by one mouse button or the scroll wheel.
+pub trait GestureRecognizer: Send {
+  /// Called every update while the gesture's input is active, with the state before and after the last input.
+  fn on_state(&mut self, current: &UIState, last: &UIState, camera: &mut Camera);
+}
+
+/// Input a `GestureRecognizer` is bound to. Button gestures are active while that button drags, the scroll gesture
+/// while the wheel has moved since the last update.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureSlot {
+  Left,
+  Right,
+  Middle,
+  Scroll,
+}
+
+impl GestureSlot {
+  fn is_active(self, state: &UIState) -> bool {
+    match self {
+      GestureSlot::Left => matches!(state.left, MouseEvent::Move),
+      GestureSlot::Right => matches!(state.right, MouseEvent::Move),
+      GestureSlot::Middle => matches!(state.middle, MouseEvent::Move),
+      GestureSlot::Scroll => state.scroll != 0.0,
     }
   }
+}
+
+/// Angles the cursor moved through between `last` and `current`, horizontally and vertically, adjusted by `look`.
+fn mouse_angle(current: &UIState, last: &UIState, camera: &Camera, look: MouseLook) -> (Deg<f32>, Deg<f32>) {
+  let fovy = camera.fovy;
+  let fovx = fovy * current.size.width as f32 / current.size.height as f32;
+  let x_angle = Deg(((current.position.x - last.position.x) as f32 / (current.size.width as f32)) * fovx);
+  let y_angle = Deg(((current.position.y - last.position.y) as f32 / (current.size.height as f32)) * fovy);
+  let y_sign = if look.invert_y { -1.0 } else { 1.0 };
+  (x_angle * look.sensitivity, y_angle * look.sensitivity * y_sign)
+}
+
+/// Moving speed while free moving or zooming, faster with shift held.
+fn move_speed(state: &UIState) -> f32 {
+  if state.key(&VirtualKeyCode::LShift).is_down() {
+    0.5
+  } else {
+    0.05
+  }
+}
 
-  pub fn free_move(&self, camera: &mut Camera) {
-    let current = &self.current_state.position;
-    let last = &self.last_state.position;
+/// Flies the camera with WASD, space and control, and looks around with the mouse.
+pub struct FreeMoveGesture;
 
+impl GestureRecognizer for FreeMoveGesture {
+  fn on_state(&mut self, current: &UIState, last: &UIState, camera: &mut Camera) {
     let mut move_relative: Vector3<f32> = (0.0, 0.0, 0.0).into();
 
-    if self.current_state.key(&VirtualKeyCode::W).is_down() {
+    if current.key(&VirtualKeyCode::W).is_down() {
       move_relative.z += 1.0;
     }
-    if self.current_state.key(&VirtualKeyCode::A).is_down() {
+    if current.key(&VirtualKeyCode::A).is_down() {
       move_relative.x -= 1.0;
     }
-    if self.current_state.key(&VirtualKeyCode::S).is_down() {
+    if current.key(&VirtualKeyCode::S).is_down() {
       move_relative.z -= 1.0;
     }
-    if self.current_state.key(&VirtualKeyCode::D).is_down() {
+    if current.key(&VirtualKeyCode::D).is_down() {
       move_relative.x += 1.0;
     }
-    if self.current_state.key(&VirtualKeyCode::Space).is_down() {
+    if current.key(&VirtualKeyCode::Space).is_down() {
       move_relative.y += 1.0;
     }
-    if self.current_state.key(&VirtualKeyCode::LControl).is_down() {
+    if current.key(&VirtualKeyCode::LControl).is_down() {
       move_relative.y -= 1.0;
     }
 
     if move_relative.magnitude() > 0.0 {
-      if self.current_state.key(&VirtualKeyCode::LShift).is_down() {
-        move_relative = move_relative.normalize() * 0.5;
-      } else {
-        move_relative = move_relative.normalize() * 0.05;
-      }
+      move_relative = move_relative.normalize() * move_speed(current);
     }
     let translation =
       move_relative.x * camera.right() + move_relative.y * camera.up.normalize() + move_relative.z * camera.forward();
@@ -180,8 +272,8 @@ impl UserInterface {
     camera.eye += translation;
     camera.target += translation;
 
-    if current != last {
-      let (x_angle, y_angle) = self.mouse_angle(camera);
+    if current.position != last.position {
+      let (x_angle, y_angle) = mouse_angle(current, last, camera, current.look);
       let transform =
         Matrix4::from_axis_angle(camera.right(), -y_angle) * Matrix4::from_axis_angle(camera.up, -x_angle);
       let delta = (transform * (camera.target - camera.eye).extend(0.0)).truncate();
@@ -191,10 +283,147 @@ impl UserInterface {
   }
 }
 
+/// Orbits the camera around `target`, or around what it is looking at when `None`.
+#[derive(Default)]
+pub struct OrbitGesture {
+  pub target: Option<Point3<f32>>,
+}
+
+impl GestureRecognizer for OrbitGesture {
+  fn on_state(&mut self, current: &UIState, last: &UIState, camera: &mut Camera) {
+    if current.position != last.position {
+      let (x_angle, y_angle) = mouse_angle(current, last, camera, current.look);
+      camera.orbit(self.target.unwrap_or(camera.target), -x_angle, -y_angle);
+    }
+  }
+}
+
+/// Slides the camera in its view plane so the point it looks at follows the cursor.
+pub struct PanGesture;
+
+impl GestureRecognizer for PanGesture {
+  fn on_state(&mut self, current: &UIState, last: &UIState, camera: &mut Camera) {
+    if current.size.height == 0 {
+      return;
+    }
+    let dx = (current.position.x - last.position.x) as f32;
+    let dy = (current.position.y - last.position.y) as f32;
+    // World units per pixel at the distance of the target
+    let distance = (camera.target - camera.eye).magnitude();
+    let scale = 2.0 * distance * (camera.fovy / 2.0).to_radians().tan() / current.size.height as f32;
+    let translation = (-dx * camera.right() + dy * camera.up.normalize()) * scale;
+    camera.eye += translation;
+    camera.target += translation;
+  }
+}
+
+/// Moves the camera along its view direction by the lines scrolled.
+pub struct ZoomGesture;
+
+impl GestureRecognizer for ZoomGesture {
+  fn on_state(&mut self, current: &UIState, _last: &UIState, camera: &mut Camera) {
+    let translation = camera.forward() * current.scroll * move_speed(current);
+    camera.eye += translation;
+    camera.target += translation;
+  }
+}
+
+pub struct UserInterface {
+  pub last_state: UIState,
+  pub current_state: UIState,
+  /// Pixels the cursor must move with a button down before a click becomes a drag
+  pub drag_threshold: f32,
+  /// Tilts the camera down when the cursor moves up
+  pub invert_y: bool,
+  /// Scales the camera angles the cursor turns through
+  pub mouse_sensitivity: f32,
+  gestures: Vec<(GestureSlot, Box<dyn GestureRecognizer>)>,
+}
+
+impl Default for UserInterface {
+  fn default() -> Self {
+    UserInterface {
+      last_state: UIState::default(),
+      current_state: UIState::default(),
+      drag_threshold: 3.0,
+      invert_y: false,
+      mouse_sensitivity: 1.0,
+      gestures: vec![
+        (GestureSlot::Left, Box::new(OrbitGesture::default())),
+        (GestureSlot::Right, Box::new(FreeMoveGesture)),
+        (GestureSlot::Middle, Box::new(PanGesture)),
+        (GestureSlot::Scroll, Box::new(ZoomGesture)),
+      ],
+    }
+  }
+}
+
+impl UserInterface {
+  pub fn new(size: PhysicalSize<u32>) -> Self {
+    let mut result = Self::default();
+    result.current_state.size = size;
+    result.last_state.size = size;
+    result
+  }
+
+  /// Binds `recognizer` to `slot`, replacing the gesture bound to it before.
+  #[allow(dead_code)]
+  pub fn set_gesture(&mut self, slot: GestureSlot, recognizer: Box<dyn GestureRecognizer>) {
+    match self.gestures.iter_mut().find(|(existing, _)| *existing == slot) {
+      Some((_, gesture)) => *gesture = recognizer,
+      None => self.gestures.push((slot, recognizer)),
+    }
+  }
+
+  fn look(&self) -> MouseLook {
+    MouseLook {
+      invert_y: self.invert_y,
+      sensitivity: self.mouse_sensitivity,
+    }
+  }
+
+  /// Runs the gestures whose input is active in the current state.
+  pub fn update(&mut self, camera: &mut Camera) {
+    self.current_state.look = self.look();
+    for (slot, gesture) in &mut self.gestures {
+      if slot.is_active(&self.current_state) {
+        gesture.on_state(&self.current_state, &self.last_state, camera);
+      }
+    }
+  }
+
+  pub fn _rotate_about_object(&self, position: Point3<f32>, camera: &mut Camera) {
+    if self.current_state.position != self.last_state.position {
+      let (x_angle, y_angle) = mouse_angle(&self.current_state, &self.last_state, camera, self.look());
+      camera.orbit(position, -x_angle, -y_angle);
+    }
+  }
+
+  /// Nearest instance to the eye, in front of it, whose sphere `ray` passes through.
+  pub fn pick_feature<'a>(
+    instances: impl Iterator<Item = &'a FeatureInstance>,
+    ray: &Ray,
+  ) -> Option<&'a FeatureInstance> {
+    instances
+      .map(|instance| (instance, instance.position()))
+      .filter(|&(instance, center)| ray.parameter(center) >= 0.0 && ray.distance_to_point(center) <= instance.radius())
+      .min_by(|(_, a), (_, b)| ray.parameter(*a).partial_cmp(&ray.parameter(*b)).unwrap())
+      .map(|(instance, _)| instance)
+  }
+
+  /// Points the camera at the sphere around `center` from twice its `radius` away, keeping the view direction. Radii
+  /// below `MIN_FRAME_RADIUS` are framed as that, so a single point doesn't put the eye on the target.
+  pub fn frame(center: Point3<f32>, radius: f32, camera: &mut Camera) {
+    let forward = camera.forward();
+    camera.target = center;
+    camera.eye = center - forward * 2.0 * radius.max(MIN_FRAME_RADIUS);
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
-  use cgmath::{EuclideanSpace, MetricSpace};
+  use cgmath::EuclideanSpace;
 
   #[test]
   fn rotate_about_object_test() {
@@ -212,4 +441,185 @@ mod test {
     assert_eq!(camera.target, (0.0, 0.0, 0.0).into());
     assert!(camera.eye.distance((1.0, 0.0, 0.0).into()) < 0.00001);
   }
+
+  #[test]
+  fn mouse_look_test() {
+    let camera = Camera::mock();
+    let size = PhysicalSize {
+      width: 100,
+      height: 100,
+    };
+    let last = UIState {
+      size,
+      ..Default::default()
+    };
+    let current = UIState {
+      size,
+      position: PhysicalPosition { x: 10.0, y: 20.0 },
+      ..Default::default()
+    };
+    let (x_angle, y_angle) = mouse_angle(&current, &last, &camera, MouseLook::default());
+    assert!(x_angle.0 > 0.0 && y_angle.0 > 0.0);
+
+    let inverted = MouseLook {
+      invert_y: true,
+      ..Default::default()
+    };
+    assert_eq!(mouse_angle(&current, &last, &camera, inverted), (x_angle, -y_angle));
+    let sensitive = MouseLook {
+      invert_y: false,
+      sensitivity: 2.0,
+    };
+    assert_eq!(
+      mouse_angle(&current, &last, &camera, sensitive),
+      (x_angle * 2.0, y_angle * 2.0)
+    );
+
+    // The settings reach the gestures through the current state
+    let mut user_interface = UserInterface::new(size);
+    user_interface.invert_y = true;
+    user_interface.mouse_sensitivity = 0.5;
+    let mut camera = Camera::mock();
+    user_interface.update(&mut camera);
+    assert_eq!(
+      user_interface.current_state.look,
+      MouseLook {
+        invert_y: true,
+        sensitivity: 0.5,
+      }
+    );
+  }
+
+  #[test]
+  fn measurement_test() {
+    let measurement = Measurement::new((1.0, 2.0, 3.0).into(), (4.0, 6.0, 3.0).into());
+    assert_eq!(measurement.distance, 5.0);
+    assert_eq!(measurement.to_string(), "5.000 m");
+  }
+
+  #[test]
+  fn scroll_zoom_test() {
+    let mut camera = Camera::mock();
+    let mut user_interface = UserInterface::new(PhysicalSize {
+      width: 100,
+      height: 100,
+    });
+    let eye = camera.eye;
+    user_interface.current_state.scroll = 2.0;
+    user_interface.update(&mut camera);
+    assert!((camera.eye - (eye + camera.forward() * 0.1)).magnitude() < 0.00001);
+  }
+
+  #[test]
+  fn pan_gesture_test() {
+    let mut camera = Camera::mock();
+    camera.fovy = 90.0;
+    let size = PhysicalSize {
+      width: 100,
+      height: 100,
+    };
+    let (eye, target) = (camera.eye, camera.target);
+    let last = UIState {
+      size,
+      ..Default::default()
+    };
+    let current = UIState {
+      size,
+      position: PhysicalPosition { x: 0.0, y: 50.0 },
+      ..Default::default()
+    };
+    PanGesture.on_state(&current, &last, &mut camera);
+    // Half the view height at the target's distance
+    let distance = (target - eye).magnitude();
+    assert!((camera.target - (target + camera.up.normalize() * distance)).magnitude() < 0.0001);
+    assert!((camera.eye - (eye + camera.up.normalize() * distance)).magnitude() < 0.0001);
+  }
+
+  #[test]
+  fn set_gesture_test() {
+    struct Count(std::sync::Arc<std::sync::atomic::AtomicU32>);
+    impl GestureRecognizer for Count {
+      fn on_state(&mut self, _: &UIState, _: &UIState, _: &mut Camera) {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+      }
+    }
+    let count = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+    let mut camera = Camera::mock();
+    let mut user_interface = UserInterface::new(PhysicalSize {
+      width: 100,
+      height: 100,
+    });
+    user_interface.set_gesture(GestureSlot::Right, Box::new(Count(count.clone())));
+    assert_eq!(user_interface.gestures.len(), 4);
+    user_interface.update(&mut camera);
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 0);
+    user_interface.current_state.right = MouseEvent::Move;
+    user_interface.update(&mut camera);
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 1);
+  }
+
+  #[test]
+  fn frame_test() {
+    let mut camera = Camera::mock();
+    let forward = camera.forward();
+    UserInterface::frame((1.0, 0.0, 0.0).into(), 4.0, &mut camera);
+    assert_eq!(camera.target, (1.0, 0.0, 0.0).into());
+    assert!((camera.eye.distance(camera.target) - 8.0).abs() < 0.00001);
+    assert!((camera.forward() - forward).magnitude() < 0.00001);
+    // A single point still leaves room between the eye and the target
+    UserInterface::frame((1.0, 0.0, 0.0).into(), 0.0, &mut camera);
+    assert!((camera.eye.distance(camera.target) - 2.0 * MIN_FRAME_RADIUS).abs() < 0.00001);
+  }
+
+  #[test]
+  fn pick_feature_test() {
+    let feature = |id: u32, position| {
+      FeatureInstance::mock()
+        .with_model(Matrix4::from_scale(0.5))
+        .with_position(position)
+        .with_id(id)
+    };
+    let ray = Ray {
+      eye: (0.0, 0.0, 0.0).into(),
+      target: (0.0, 0.0, 1.0).into(),
+    };
+    let features = [
+      feature(1, (0.3, 0.0, 6.0)),
+      feature(2, (0.0, 0.2, 3.0)),
+      feature(3, (0.0, 0.0, -2.0)),
+      feature(4, (1.0, 0.0, 1.0)),
+    ];
+    assert_eq!(
+      UserInterface::pick_feature(features.iter(), &ray).map(|f| f.id),
+      Some(2)
+    );
+    assert!(UserInterface::pick_feature(features[2..].iter(), &ray).is_none());
+  }
+
+  #[test]
+  fn key_helpers_test() {
+    let mut state = UIState::default();
+    state.keys.insert(VirtualKeyCode::LControl, KeyEvent::Hold);
+    state.keys.insert(VirtualKeyCode::S, KeyEvent::Press);
+    state.keys.insert(VirtualKeyCode::W, KeyEvent::Release);
+    assert!(state.key_just_pressed(VirtualKeyCode::S));
+    assert!(!state.key_just_pressed(VirtualKeyCode::LControl));
+    assert!(state.key_just_released(VirtualKeyCode::W));
+    assert!(!state.key_just_released(VirtualKeyCode::S));
+    assert!(state.key_chord(&[VirtualKeyCode::LControl], VirtualKeyCode::S));
+    assert!(!state.key_chord(&[VirtualKeyCode::LShift], VirtualKeyCode::S));
+    assert!(!state.key_chord(&[VirtualKeyCode::LControl], VirtualKeyCode::W));
+  }
+
+  #[test]
+  fn is_dragging_test() {
+    let mut state = UIState {
+      click_position: PhysicalPosition { x: 10.0, y: 10.0 },
+      position: PhysicalPosition { x: 12.0, y: 12.0 },
+      ..Default::default()
+    };
+    assert!(!state.is_dragging(3.0));
+    state.position = PhysicalPosition { x: 13.0, y: 14.0 };
+    assert!(state.is_dragging(3.0));
+  }
 }